@@ -2,7 +2,9 @@
 #define FUZZER_API
 
 #include <chrono>
+#include <cstdint>
 #include <cstdlib>
+#include <cstring>
 #include <filesystem>
 #include <iostream>
 #include <iomanip>
@@ -10,6 +12,7 @@
 #include <fstream>
 #include <string>
 
+#include <sys/shm.h>
 #include <unistd.h>
 
 #include "FuzzerCoverage.h"
@@ -60,6 +63,36 @@ inline std::string getFuzzingSavePath(std::string reason, std::string pathToTest
 }
 
 
+/// Tells the fuzzer that the upcoming crash is an intended report of a DUT
+/// issue and not a crash of the simulator itself.
+///
+/// The fuzzer sets FUZZING_ISSUE_MARKER to a file path before starting the
+/// target. Every crash without this file being created first is labeled as a
+/// simulator crash. Harnesses that don't use `reportFuzzingIssue` need to
+/// call this before aborting.
+__attribute__((no_sanitize("memory", "dataflow")))
+inline void markFuzzingIssue() {
+    if (const char *markerPath = std::getenv("FUZZING_ISSUE_MARKER")) {
+        std::ofstream marker(markerPath);
+    }
+}
+
+/// Exits the target with the given exit code and tells the fuzzer about it.
+///
+/// The forkserver doesn't pass the exit code on, so the fuzzer sets
+/// FUZZING_EXIT_CODE_FILE to a file path (only with `--crash-exit-code`) that
+/// this writes the code to. Harnesses that signal issues via their exit code
+/// need to exit through this for the fuzzer to see them.
+[[noreturn]]
+__attribute__((no_sanitize("memory", "dataflow")))
+inline void exitFuzzing(int code) {
+    if (const char *codePath = std::getenv("FUZZING_EXIT_CODE_FILE")) {
+        std::ofstream codeFile(codePath);
+        codeFile << code;
+    }
+    std::exit(code);
+}
+
 /// Saves the given test case and annotates it with the given reason string
 /// that will be displayed in the fuzzing interface.
 /// @param reason A string that will be displayed in the fuzzing interface.
@@ -68,6 +101,7 @@ inline std::string getFuzzingSavePath(std::string reason, std::string pathToTest
 __attribute__((no_sanitize("memory", "dataflow")))
 inline void reportFuzzingIssue(std::string reason, std::string pathToTestCase) {
     completedSimCallback();
+    markFuzzingIssue();
 
     std::cerr << "Found issue: " << reason << "\n";
     const char *causeDirVar = "FUZZING_CAUSE_DIR";
@@ -88,6 +122,38 @@ inline void reportFuzzingIssue(std::string reason, std::string pathToTestCase) {
     abort();
 }
 
+/// Reports the final integer registers of the DUT and of the reference model
+/// for co-simulating harnesses. The fuzzer saves inputs where they differ if
+/// it was started with `--register-divergence`.
+/// @param dut The registers x0 to x31 of the DUT.
+/// @param ref The registers x0 to x31 of the reference model.
+__attribute__((no_sanitize("memory", "dataflow")))
+inline void reportRegisterState(const std::uint64_t dut[32], const std::uint64_t ref[32]) {
+    // FUZZING_REGS_SHM_ID is only set if the fuzzer checks the registers.
+    const char *shmId = std::getenv("FUZZING_REGS_SHM_ID");
+    if (!shmId)
+        return;
+
+    // Only attach once per process, the region stays the same.
+    static char *region = nullptr;
+    if (!region) {
+        void *mapped = shmat(std::atoi(shmId), nullptr, 0);
+        if (mapped == reinterpret_cast<void *>(-1)) {
+            std::cerr << "Failed to attach register state shm: " << shmId << "\n";
+            abort();
+        }
+        region = static_cast<char *>(mapped);
+    }
+
+    // Layout: valid flag, DUT registers, reference registers (see cosim.rs).
+    const std::size_t dumpSize = 32 * sizeof(std::uint64_t);
+    std::memcpy(region + sizeof(std::uint64_t), dut, dumpSize);
+    std::memcpy(region + sizeof(std::uint64_t) + dumpSize, ref, dumpSize);
+    // Mark the dumps as valid only once they are fully written.
+    const std::uint64_t valid = 1;
+    std::memcpy(region, &valid, sizeof(valid));
+}
+
 /// Should be called on every executed fuzz input.
 /// Takes care of storing all inputs if requested by the fuzzer.
 /// @param path Path to the file containing the fuzzer input.
@@ -0,0 +1,184 @@
+//! The ABI names of the RISC-V registers (`ra`, `sp`, `a0`, ...), which make
+//! disassembly easier to read for people used to assembly listings.
+use crate::instructions::{Argument, InstructionCategory, InstructionTemplate};
+
+/// The ABI names of the integer registers `x0` to `x31`.
+pub const INT_ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+/// The ABI names of the floating point registers `f0` to `f31`.
+pub const FLOAT_ABI_NAMES: [&str; 32] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1", "fa0", "fa1", "fa2",
+    "fa3", "fa4", "fa5", "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9",
+    "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
+
+/// The operands that name a register.
+const REGISTER_OPERANDS: [&str; 4] = ["rd", "rs1", "rs2", "rs3"];
+
+/// The operands of compressed instructions that name a register. The `_p`
+/// ones only have 3 bits and name one of `x8` to `x15`.
+const COMPRESSED_REGISTER_OPERANDS: [&str; 9] = [
+    "rd_rs1_n0",
+    "rd_n0",
+    "rs1_n0",
+    "c_rs2_n0",
+    "c_rs2",
+    "rd_rs1_p",
+    "rs1_p",
+    "rd_p",
+    "rs2_p",
+];
+
+/// The conversion operand types (as in `fcvt.w.s`) that are integers.
+const INT_CONVERSION_TYPES: [&str; 4] = ["w", "wu", "l", "lu"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterFile {
+    Int,
+    Float,
+}
+
+impl RegisterFile {
+    /// The architectural name, e.g. `x1` or `f1`.
+    pub fn arch_name(self, reg: u32) -> String {
+        match self {
+            RegisterFile::Int => format!("x{}", reg),
+            RegisterFile::Float => format!("f{}", reg),
+        }
+    }
+
+    /// The ABI name, e.g. `ra` or `ft1`.
+    pub fn abi_name(self, reg: u32) -> &'static str {
+        match self {
+            RegisterFile::Int => INT_ABI_NAMES[reg as usize % 32],
+            RegisterFile::Float => FLOAT_ABI_NAMES[reg as usize % 32],
+        }
+    }
+}
+
+/// The register file an operand of the template refers to, or `None` if the
+/// operand isn't a register. Floating point instructions read or write
+/// integer registers if they move or convert values between the register
+/// files, compare or classify values, or access memory.
+pub fn register_file(template: &InstructionTemplate, operand: &str) -> Option<RegisterFile> {
+    if COMPRESSED_REGISTER_OPERANDS.contains(&operand) {
+        // The compressed floating point loads and stores aren't supported.
+        return Some(RegisterFile::Int);
+    }
+    if !REGISTER_OPERANDS.contains(&operand) {
+        return None;
+    }
+    let name = template.name();
+    let parts: Vec<&str> = name.split('.').collect();
+    let float = match template.category() {
+        // Only the floating point loads and stores have a float data register.
+        InstructionCategory::Mem if name.starts_with('f') && !name.starts_with("fence") => {
+            operand == "rd" || operand == "rs2"
+        }
+        InstructionCategory::Float => match (parts[0], operand) {
+            ("fmv", "rd") => parts.get(1) != Some(&"x"),
+            ("fmv", _) => parts.get(2) != Some(&"x"),
+            ("fcvt", "rd") => !parts
+                .get(1)
+                .map_or(false, |t| INT_CONVERSION_TYPES.contains(t)),
+            ("fcvt", _) => !parts
+                .get(2)
+                .map_or(false, |t| INT_CONVERSION_TYPES.contains(t)),
+            ("feq" | "flt" | "fle" | "fclass", "rd") => false,
+            _ => true,
+        },
+        _ => false,
+    };
+    Some(if float {
+        RegisterFile::Float
+    } else {
+        RegisterFile::Int
+    })
+}
+
+/// The number of the register the operand value names, which is only
+/// different from the value for the 3 bit registers of compressed
+/// instructions.
+pub fn register_number(operand: &str, value: u32) -> u32 {
+    if operand.ends_with("_p") {
+        value + 8
+    } else {
+        value
+    }
+}
+
+/// The value of the argument for humans: the register name for registers,
+/// the ABI name if `abi` is set, and hex for everything else.
+pub fn operand_string(template: &InstructionTemplate, arg: &Argument, abi: bool) -> String {
+    let name = arg.spec().name();
+    let reg = register_number(name, arg.value());
+    match register_file(template, name) {
+        Some(file) if abi => file.abi_name(reg).to_owned(),
+        Some(file) => file.arch_name(reg),
+        None => format!("{:#x}", arg.value()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{operand_string, register_file, RegisterFile, FLOAT_ABI_NAMES, INT_ABI_NAMES};
+    use crate::instructions::{sets, Argument, InstructionTemplate};
+
+    fn template(name: &str) -> &'static InstructionTemplate {
+        sets::all().into_iter().find(|t| t.name() == name).unwrap()
+    }
+
+    fn files(name: &str) -> Vec<Option<RegisterFile>> {
+        ["rd", "rs1", "rs2"]
+            .iter()
+            .map(|operand| register_file(template(name), operand))
+            .collect()
+    }
+
+    #[test]
+    fn abi_register_names() {
+        assert_eq!(INT_ABI_NAMES[0], "zero");
+        assert_eq!(INT_ABI_NAMES[2], "sp");
+        assert_eq!(INT_ABI_NAMES[10], "a0");
+        assert_eq!(INT_ABI_NAMES[31], "t6");
+        assert_eq!(FLOAT_ABI_NAMES[10], "fa0");
+        assert_eq!(FLOAT_ABI_NAMES[31], "ft11");
+
+        let (int, float) = (Some(RegisterFile::Int), Some(RegisterFile::Float));
+        assert_eq!(files("add"), [int, int, int]);
+        assert_eq!(files("fadd.d"), [float, float, float]);
+        assert_eq!(files("flw")[..2], [float, int]);
+        assert_eq!(files("fsd")[1..], [int, float]);
+        assert_eq!(files("feq.s"), [int, float, float]);
+        assert_eq!(files("fcvt.w.s")[..2], [int, float]);
+        assert_eq!(files("fcvt.d.lu")[..2], [float, int]);
+        assert_eq!(files("fcvt.s.d")[..2], [float, float]);
+        assert_eq!(files("fmv.x.w")[..2], [int, float]);
+        assert_eq!(files("fmv.d.x")[..2], [float, int]);
+        assert_eq!(register_file(template("addi"), "imm12"), None);
+
+        let addi = template("addi");
+        let ra = Argument::new(addi.op_with_name("rd".to_string()).unwrap(), 1);
+        let imm = Argument::new(addi.op_with_name("imm12".to_string()).unwrap(), 16);
+        assert_eq!(operand_string(addi, &ra, false), "x1");
+        assert_eq!(operand_string(addi, &ra, true), "ra");
+        assert_eq!(operand_string(addi, &imm, true), "0x10");
+        let fadd = template("fadd.s");
+        let fa0 = Argument::new(fadd.op_with_name("rd".to_string()).unwrap(), 10);
+        assert_eq!(operand_string(fadd, &fa0, false), "f10");
+        assert_eq!(operand_string(fadd, &fa0, true), "fa0");
+
+        // The 3 bit registers start at x8.
+        let lw = template("c.lw");
+        let s1 = Argument::new(lw.op_with_name("rs1_p".to_string()).unwrap(), 1);
+        assert_eq!(operand_string(lw, &s1, false), "x9");
+        assert_eq!(operand_string(lw, &s1, true), "s1");
+        let mv = template("c.mv");
+        let a5 = Argument::new(mv.op_with_name("rd_n0".to_string()).unwrap(), 15);
+        assert_eq!(operand_string(mv, &a5, true), "a5");
+    }
+}
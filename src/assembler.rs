@@ -1,12 +1,13 @@
 use crate::instructions::Instruction;
 
 /// Returns a list of instructions to their encoded machine code (in bytes).
+/// Compressed instructions take 2 bytes, all others 4.
 pub fn assemble_instructions(input: &Vec<Instruction>) -> Vec<u8> {
     let mut result = Vec::<u8>::new();
 
     for inst in input {
-        for byte in inst.encode().to_le_bytes() {
-            result.push(byte);
+        for byte in &inst.encode().to_le_bytes()[..inst.size()] {
+            result.push(*byte);
         }
     }
 
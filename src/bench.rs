@@ -0,0 +1,89 @@
+//! Throughput of the structural code that runs on every execution
+//! (instruction generation, encoding and decoding), measured without a
+//! target so it can be profiled on its own.
+use std::time::{Duration, Instant};
+
+use libafl::prelude::StdRand;
+
+use crate::{
+    assembler::assemble_instructions,
+    generator::InstGenerator,
+    instructions::{sets, Instruction, InstructionTemplate},
+    parser::parse_instructions,
+};
+
+/// The time one phase took for all programs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseThroughput {
+    pub phase: &'static str,
+    pub programs: u64,
+    pub insts: u64,
+    pub elapsed: Duration,
+}
+
+impl PhaseThroughput {
+    /// Programs per second, `inf` if the phase was too fast to measure.
+    pub fn programs_per_sec(&self) -> f64 {
+        self.programs as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn insts_per_sec(&self) -> f64 {
+        self.insts as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Generates `iters` programs of `len` instructions from `isa`, then encodes
+/// and decodes all of them. Every phase is timed separately.
+pub fn run_benchmark(
+    isa: &Vec<&'static InstructionTemplate>,
+    iters: u64,
+    len: u32,
+    seed: u64,
+) -> Vec<PhaseThroughput> {
+    let mut rand = StdRand::with_seed(seed);
+    let generator = InstGenerator::new();
+    let phase = |phase, programs: &[Vec<Instruction>], elapsed| PhaseThroughput {
+        phase,
+        programs: programs.len() as u64,
+        insts: programs.iter().map(|p| p.len() as u64).sum(),
+        elapsed,
+    };
+
+    let start = Instant::now();
+    let programs: Vec<Vec<Instruction>> = (0..iters)
+        .map(|_| generator.generate_instructions(&mut rand, isa, len))
+        .collect();
+    let generate = phase("generate", &programs, start.elapsed());
+
+    let start = Instant::now();
+    let encoded: Vec<Vec<u8>> = programs.iter().map(assemble_instructions).collect();
+    let encode = phase("encode", &programs, start.elapsed());
+
+    let all = sets::all();
+    let start = Instant::now();
+    let decoded: Vec<Vec<Instruction>> = encoded
+        .iter()
+        .map(|bytes| parse_instructions(bytes, &all).expect("Failed to decode a generated program"))
+        .collect();
+    let decode = phase("decode", &decoded, start.elapsed());
+
+    vec![generate, encode, decode]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run_benchmark;
+    use crate::instructions::sets;
+
+    #[test]
+    fn benchmark_phases() {
+        let results = run_benchmark(&sets::riscv_g(), 20, 8, 0);
+        let phases: Vec<_> = results.iter().map(|r| r.phase).collect();
+        assert_eq!(phases, ["generate", "encode", "decode"]);
+        for result in results {
+            assert_eq!(result.programs, 20);
+            assert_eq!(result.insts, 160);
+            assert!(result.insts_per_sec() >= result.programs_per_sec());
+        }
+    }
+}
@@ -13,7 +13,7 @@ fn read_lines(filename: String) -> io::Lines<BufReader<File>> {
 }
 
 fn find_template(name: String) -> Result<&'static InstructionTemplate, String> {
-    for inst in instructions::riscv::all() {
+    for inst in instructions::sets::all() {
         if inst.name() == name {
             return Ok(inst);
         }
@@ -61,12 +61,12 @@ fn parse_arg(inst: &'static InstructionTemplate, arg_str: String) -> Result<Argu
     }
     let value = value_or_err.unwrap();
 
-    if value > spec.max_value() {
+    if value > spec.mask() {
         return Err(format!(
             "Too large value {} for field {} which only allows up to {}",
             value,
             spec.name(),
-            spec.max_value()
+            spec.mask()
         ));
     }
 
@@ -1,6 +1,7 @@
 use clap::Parser;
 use colored::Colorize;
 use crossterm::style::Stylize;
+use riscv_mutator::abi::operand_string;
 use riscv_mutator::instructions::Instruction;
 use riscv_mutator::program_input::ProgramInput;
 use riscv_mutator::{instructions, parser};
@@ -12,6 +13,10 @@ struct Args {
     input: Vec<String>,
     #[arg(long, default_value_t = false)]
     raw: bool,
+    /// Print registers with their ABI names (e.g. 'ra', 'a0', 'fa0') instead
+    /// of 'x1', 'x10' and 'f10'.
+    #[arg(long, default_value_t = false)]
+    abi_names: bool,
 }
 
 fn main() {
@@ -29,15 +34,14 @@ fn main() {
         let program: Vec<Instruction>;
 
         if args.raw {
-            let program_or_err =
-                parser::parse_instructions(&buffer, &instructions::sets::riscv_g());
+            let program_or_err = parser::parse_instructions(&buffer, &instructions::sets::all());
             if program_or_err.is_err() {
                 eprintln!("Failed to decode raw instructions.");
                 continue;
             }
             program = program_or_err.unwrap();
         } else {
-            let input = postcard::from_bytes::<ProgramInput>(buffer.as_slice());
+            let input = ProgramInput::from_bytes(buffer.as_slice());
             program = input.unwrap().insts().to_vec();
         }
 
@@ -47,7 +51,7 @@ fn main() {
                 print!(
                     " {}={}",
                     Colorize::cyan(op.spec().name()),
-                    format!("{:#x}", op.value()).red()
+                    operand_string(inst.template(), op, args.abi_names).red()
                 );
             }
             println!("");
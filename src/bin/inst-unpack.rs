@@ -15,7 +15,7 @@ fn main() {
 
     for filename in args.input {
         let buffer = fs::read(filename.clone()).expect("Failed to read file");
-        let input = postcard::from_bytes::<ProgramInput>(buffer.as_slice());
+        let input = ProgramInput::from_bytes(buffer.as_slice());
 
         if input.is_err() {
             eprintln!("Note: File not in internal serialized format.");
@@ -0,0 +1,43 @@
+use clap::Parser;
+use riscv_mutator::disasm::disassemble;
+use riscv_mutator::instructions;
+use riscv_mutator::program_input::ProgramInput;
+use std::fs;
+use std::process::ExitCode;
+
+/// Prints a corpus file (e.g. a crash from the output directory) as assembly.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    input: String,
+    /// The file contains encoded instructions instead of a serialized
+    /// program.
+    #[arg(long, default_value_t = false)]
+    raw: bool,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let buffer = match fs::read(&args.input) {
+        Ok(buffer) => buffer,
+        Err(err) => {
+            eprintln!("Failed to read {}: {}", args.input, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if args.raw {
+        print!("{}", disassemble(&buffer, &instructions::sets::all()));
+        return ExitCode::SUCCESS;
+    }
+    match ProgramInput::from_bytes(&buffer) {
+        Ok(input) => print!("{}", input.to_asm()),
+        Err(err) => {
+            eprintln!("Failed to deserialize {}: {}", args.input, err);
+            eprintln!("Use --raw for files with encoded instructions.");
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
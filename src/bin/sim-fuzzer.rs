@@ -1,100 +1,284 @@
-use core::time::Duration;
+use core::{marker::PhantomData, ops::Range, time::Duration};
 use std::{
-    collections::HashMap,
-    fs::{self, OpenOptions},
-    io::Write,
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
     path::PathBuf,
-    process,
+    process::{self, ExitCode},
     sync::{Arc, Mutex},
 };
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use libafl::prelude::{CoreId, CorpusId, StdRand};
 use libafl::{
     bolts::{
+        core_affinity::get_core_ids,
         current_nanos,
-        rands::StdRand,
-        shmem::{ShMem, ShMemProvider, UnixShMemProvider},
-        tuples::tuple_list,
+        shmem::{ShMemProvider, UnixShMemProvider},
+        tuples::{tuple_list, MatchName},
         AsMutSlice,
     },
-    corpus::{OnDiskCorpus},
-    executors::forkserver::{ForkserverExecutor, TimeoutForkserverExecutor},
-    feedback_or,
-    feedbacks::{CrashFeedback, MaxMapFeedback, TimeFeedback},
+    corpus::Corpus,
+    events::{Event, EventFirer, EventRestarter},
+    executors::{ExitKind, HasObservers},
     fuzzer::{Fuzzer, StdFuzzer},
+    inputs::Input,
+    monitors::UserStats,
     mutators::StdScheduledMutator,
-    observers::{HitcountsMapObserver, StdMapObserver, TimeObserver},
+    observers::TimeObserver,
     prelude::current_time,
-    schedulers::{
-        powersched::PowerSchedule, IndexesLenTimeMinimizerScheduler, StdWeightedScheduler,
-    },
+    schedulers::powersched::PowerSchedule,
     stages::power::StdPowerMutationalStage,
-    state::StdState,
+    state::{HasCorpus, HasMetadata, HasRand, HasSolutions},
     Error, Evaluator,
 };
 use libafl::{
     events::ProgressReporter,
     prelude::{Cores, EventConfig, Launcher, LlmpRestartingEventManager},
 };
-use libafl::{
-    prelude::{ondisk::OnDiskMetadataFormat, CoreId},
+use nix::{
+    sys::signal::Signal,
+    unistd::{sysconf, SysconfVar},
 };
-use nix::sys::signal::Signal;
 use riscv_mutator::{
-    calibration::DummyCalibration,
-    causes::{list_causes, FUZZING_CAUSE_DIR_VAR},
+    bench::run_benchmark,
+    bundle::{CrashBundleConfig, CrashBundleFeedback},
+    calibration::{SkipEvicted, UnstableEntriesMetadata, STABILITY_STAT_NAME},
+    causes::{
+        client_cause_dir, list_causes, CauseFeedback, CausesFormat, FUZZING_CAUSE_DIR_VAR,
+        FUZZING_EXIT_CODE_FILE_VAR, FUZZING_ISSUE_MARKER_VAR,
+    },
+    classes::{ProgramClassMetadata, CLASSES_STAT_NAME},
+    config::DriverConfig,
+    cosim::{create_register_map, RegisterStateObserver, REGISTER_STATE_SIZE},
+    depths::{DepthHistogram, DEPTHS_STAT_NAME},
+    driver::{
+        build_executor, build_feedback, build_objective, build_scheduler, build_state,
+        create_coverage_map, edges_observer, map_size_warning, parse_env_var, parse_map_size,
+        resolve_core_spec, resolve_map_size, EdgesObserver, FeedbackOptions, ObjectiveOptions,
+        RestartAction, RestartPolicy, TargetConfig, DEFAULT_MAP_SIZE, EDGES_OBSERVER_NAME,
+        FAILURES_BEFORE_RESTART,
+    },
+    executors::auto_timeout,
+    feedbacks::{
+        parse_known_signatures, CrashOrigin, DuplicateCrashFilter, ExitCodeFeedback,
+        KnownCrashFilter, LowCoverageFilter, SignatureStore, TraceWriter,
+    },
     fuzz_ui::FuzzUI,
-    instructions::{
-        riscv::{
-            args,
-            rv_i::{ADDI},
-        },
-        Argument, Instruction,
+    generator::{
+        parse_arg_ranges, parse_categories, parse_category_weights, parse_template_weights,
+        parse_u64, preview_programs, DataflowRamp, InstGenerator, ScratchRegion,
     },
+    hooks::CrashHook,
+    instructions::{sets, Instruction},
+    lengths::{LengthHistogramMetadata, LENGTHS_STAT_NAME},
+    manifest::{client_seed, RunManifest, Shard, FUZZED_ISA},
     monitor::HWFuzzMonitor,
-    mutator::{RiscvScheduledMutator, all_riscv_mutations},
+    mutator::{all_riscv_mutations, MutationWeights, RiscvScheduledMutator},
+    observers::{
+        check_map_region, map_density, parse_map_region, BucketTable, MAP_DENSITY_STAT_NAME,
+    },
+    pack::{pack, read_corpus_dir, unpack},
     program_input::ProgramInput,
+    report::write_findings_report,
+    seeds::{fallback_seed, read_seed_dir, SeedDirFormat},
+    shutdown::{install_shutdown_handler, shutdown_requested},
+    single_run::{run_one, SingleRunner},
+    snapshot::{snapshot_path, spawn_snapshot, SNAPSHOTS_DIR_NAME},
+    stability::unstable_entries,
+    throughput::{CategoryTimeMetadata, CATEGORY_TIMES_STAT_NAME},
+    tmin::minimize,
 };
 
 use log::{LevelFilter, Metadata, Record};
+use serde::Serialize;
 
+/// Writes the records of every process to its own `fuzzer-pid_<pid>.log`.
+///
+/// Every thread buffers its records separately, as a lock held by another
+/// thread during a fork would never be released in the child. Warnings and
+/// errors are flushed right away, they often come right before a client
+/// dies.
 struct FuzzLogger;
 
+thread_local! {
+    /// The log of this thread, opened by the process with the given pid. The
+    /// clients are forked after the logger is set up, so they open their own
+    /// log on their first record.
+    static LOG: RefCell<Option<(u32, BufWriter<File>)>> = RefCell::new(None);
+}
+
 pub const FUZZING_LOG_DIR_VAR: &'static str = "FUZZING_LOG_DIR";
 
+/// The number of instructions of the programs printed by --dry-run.
+const DRY_RUN_LEN: u32 = 32;
+
+/// How often --timeout-auto runs every seed.
+const CALIBRATION_RUNS: usize = 3;
+
+fn open_log(pid: u32) -> BufWriter<File> {
+    let log_dir = std::env::var(FUZZING_LOG_DIR_VAR).unwrap_or(".".to_owned());
+    let logfile = format!("{}/fuzzer-pid_{}.log", log_dir, pid);
+    let file = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(logfile)
+        .expect("Failed to open log");
+    BufWriter::new(file)
+}
+
 impl log::Log for FuzzLogger {
     fn enabled(&self, _metadata: &Metadata) -> bool {
         true
     }
 
     fn log(&self, record: &Record) {
-        let log_dir = std::env::var(FUZZING_LOG_DIR_VAR).unwrap_or(".".to_owned());
-        let logfile = format!("{}/fuzzer-pid_{}.log", log_dir, process::id());
-        let mut dd = OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(logfile)
-            .expect("Failed to open log");
-        dd.write_all(format!("{:?}\n", record).as_bytes())
-            .expect("Failed to write log");
-    }
-
-    fn flush(&self) {}
+        let pid = process::id();
+        // Whole lines, so the records of the threads don't interleave.
+        let line = format!("{:?}\n", record);
+        LOG.with(|log| {
+            let mut file = log.borrow_mut();
+            if file.as_ref().map_or(true, |(owner, _)| *owner != pid) {
+                // The buffer of a parent's log was copied by the fork and is
+                // written by the parent, so drop it without flushing.
+                if let Some((_, inherited)) = file.take() {
+                    drop(inherited.into_parts());
+                }
+                *file = Some((pid, open_log(pid)));
+            }
+            let (_, writer) = file.as_mut().unwrap();
+            writer
+                .write_all(line.as_bytes())
+                .expect("Failed to write log");
+            if record.level() <= log::Level::Warn {
+                writer.flush().expect("Failed to write log");
+            }
+        });
+    }
+
+    /// Only flushes the records of the calling thread, the others are
+    /// flushed when their thread exits.
+    fn flush(&self) {
+        LOG.with(|log| {
+            if let Some((pid, writer)) = log.borrow_mut().as_mut() {
+                if *pid == process::id() {
+                    writer.flush().expect("Failed to write log");
+                }
+            }
+        });
+    }
 }
 static LOGGER: FuzzLogger = FuzzLogger;
 
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+    #[command(flatten)]
+    fuzz: Args,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Runs a single saved input once through the target and reports the result.
+    Reproduce(ReproduceArgs),
+    /// Packs all inputs in a corpus directory into a single archive.
+    Pack(PackArgs),
+    /// Extracts all inputs from an archive created by 'pack'.
+    Unpack(PackArgs),
+    /// Finds the instructions of an input that make its coverage differ
+    /// between runs.
+    Unstable(UnstableArgs),
+    /// Measures how fast programs are generated, encoded and decoded,
+    /// without running a target.
+    Bench(BenchArgs),
+}
+
+#[derive(clap::Args, Debug)]
+struct BenchArgs {
+    /// The number of programs per phase.
+    #[arg(long, default_value_t = 10000)]
+    iters: u64,
+    /// The number of instructions per program.
+    #[arg(long, default_value_t = 32)]
+    len: u32,
+    /// The instruction set the programs are generated from.
+    #[arg(long, default_value = FUZZED_ISA)]
+    isa: String,
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+#[derive(clap::Args, Debug)]
+struct UnstableArgs {
+    /// How often every candidate program is run to measure its stability.
+    /// More runs find rarer nondeterminism.
+    #[arg(long, default_value_t = 8)]
+    runs: usize,
+    /// Write the minimal unstable program to this file.
+    #[arg(long)]
+    out: Option<PathBuf>,
+    #[command(flatten)]
+    run: ReproduceArgs,
+}
+
+#[derive(clap::Args, Debug)]
+struct PackArgs {
+    /// The corpus directory ('pack') or archive ('unpack').
+    #[arg(long = "in")]
+    input: PathBuf,
+    /// The archive ('pack') or corpus directory ('unpack').
+    #[arg(long)]
+    out: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct ReproduceArgs {
+    /// The serialized program (e.g. a file from the 'found' directory).
+    #[arg(short, long)]
+    input: String,
+    #[arg(short, long, default_value_t = 60000)]
+    timeout: u64,
+    #[arg(long, value_parser = parse_map_size)]
+    map_size: Option<usize>,
+    /// Extra environment variable passed to the target. Can be repeated.
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_var)]
+    envs: Vec<(String, String)>,
+    /// The target command line.
+    #[arg(last = true, required = true)]
+    arguments: Vec<String>,
+}
+
+#[derive(clap::Args, Serialize, Debug)]
 struct Args {
+    /// The target command line. '@@' (also as part of an argument, e.g.
+    /// '+input=@@') is replaced with the file containing the current input.
     arguments: Vec<String>,
     #[arg(short, long, default_value = "in")]
     input: String,
+    /// How the files in the input directory are read: 'native' for
+    /// serialized programs or machine code, 'raw' for 32 bit little-endian
+    /// words, which keeps unknown words as '.word' placeholders.
+    #[arg(long, default_value = "native", value_parser = SeedDirFormat::parse)]
+    seed_format: SeedDirFormat,
     #[arg(short, long, default_value = "out")]
     out: String,
     #[arg(short, long, default_value_t = 60000)]
     timeout: u64,
+    /// Run the seeds a few times before fuzzing and time out inputs after five
+    /// times the slowest of them instead of after --timeout, which stays
+    /// the upper bound.
+    #[arg(long, default_value_t = false)]
+    timeout_auto: bool,
+    /// The cores to fuzz on, e.g. '0-3,6', 'all' or 'all/2' for half of them.
     #[arg(short, long, default_value = "all")]
     cores: String,
+    /// Only fuzz on this fraction (0-1] of all cores, leaving the rest to the
+    /// target's own threads. Takes precedence over --cores.
+    #[arg(long)]
+    core_fraction: Option<f64>,
     #[arg(long, default_value_t = false)]
     log: bool,
     #[arg(long, default_value_t = false)]
@@ -107,12 +291,818 @@ struct Args {
     mutations: String,
     #[arg(long, default_value_t = 0)]
     port: u16,
+    /// Size of the coverage map. Without it, the size the target reports is
+    /// used, or a default if it doesn't report one.
+    #[arg(long, value_parser = parse_map_size)]
+    map_size: Option<usize>,
+    /// Chance (0-1) of scheduling a uniformly random corpus entry.
+    #[arg(long, default_value_t = 0.0)]
+    schedule_epsilon: f64,
+    /// Chance (0-1) of scheduling a corpus entry that covers globally rare
+    /// edges.
+    #[arg(long, default_value_t = 0.0)]
+    rarity_chance: f64,
+    /// Chance (0-1) of scheduling a corpus entry whose program class (the
+    /// multiset of its instruction categories) has few members.
+    #[arg(long, default_value_t = 0.0)]
+    class_chance: f64,
+    /// Generate immediates close to the ones of saved crashes.
+    #[arg(long, default_value_t = false)]
+    crash_immediates: bool,
+    /// Save inputs that time out as solutions ('hangs').
+    #[arg(long, default_value_t = false)]
+    save_hangs: bool,
+    /// Save every crash. By default, crashes whose program has the same
+    /// instruction mnemonics as a saved one (e.g. only renamed registers) are
+    /// dropped. Unlike --dedup-crashes, this looks at the program and not at
+    /// the coverage.
+    #[arg(long, default_value_t = false)]
+    no_crash_dedup: bool,
+    /// Also copy every solution to 'by_label/<label>' in the output directory
+    /// for each condition that fired (crash, simcrash, hang, divergence,
+    /// exitcode).
+    #[arg(long, default_value_t = false)]
+    objectives_by_label: bool,
+    /// With --save-hangs, how often a timed-out input is re-run before it is
+    /// saved. Timeouts that go away on a re-run (e.g. due to host load) are
+    /// not saved.
+    #[arg(long, default_value_t = 1)]
+    timeout_reruns: u32,
+    /// Time out inputs after their parent's calibrated execution time times
+    /// this factor instead of after --timeout, which stays the upper bound.
+    /// Saves waiting on mutants of fast inputs that got stuck.
+    #[arg(long, value_name = "FACTOR")]
+    timeout_factor: Option<f64>,
+    /// Run every new corpus entry this many times during calibration and
+    /// report the share of map entries that were the same in all runs as
+    /// 'stability'. One run disables the measurement.
+    #[arg(long, value_name = "RUNS", default_value_t = 1)]
+    stability_runs: usize,
+    /// Give the power schedule execution times estimated from the program
+    /// length instead of measured ones, so schedules don't depend on the
+    /// load of the machine.
+    #[arg(long, default_value_t = false)]
+    estimate_exec_time: bool,
+    /// Number of executions during which the corpus is scheduled round-robin
+    /// before the power schedule takes over.
+    #[arg(long, default_value_t = 0)]
+    warmup_execs: usize,
+    /// Stack more mutations after this many executions in a row without a
+    /// new corpus entry, instead of spending simulator runs on more of the
+    /// same rejected mutants. Goes back to normal once the corpus grows.
+    #[arg(long, value_name = "EXECS")]
+    stall_threshold: Option<u64>,
+    /// How likely every mutation is picked relative to the others, as
+    /// '<mutation>=<weight>' for all of add, remove, replace-arg,
+    /// flip-arg-bit, replace, repeat, swap, splice and snippet. Uniform by
+    /// default.
+    #[arg(long, value_name = "WEIGHTS", value_parser = MutationWeights::parse)]
+    mutator_weights: Option<MutationWeights>,
+    /// Also consider inputs interesting that hit a known map entry with a new
+    /// hit count bucket. Makes the corpus grow faster.
+    #[arg(long, default_value_t = false)]
+    hitcount_feedback: bool,
+    /// Also consider inputs interesting whose program length is rare in the
+    /// corpus.
+    #[arg(long, default_value_t = false)]
+    length_feedback: bool,
+    /// Extra environment variable passed to the target. Can be repeated.
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_var)]
+    envs: Vec<(String, String)>,
+    /// How hit counts are bucketed: 'afl' (classic AFL buckets), 'raw' (no
+    /// bucketing) or a list of up to 8 ascending thresholds, e.g. '1,4,16'.
+    #[arg(long, default_value = "afl", value_parser = BucketTable::parse)]
+    buckets: BucketTable,
+    /// Save inputs that hit a map entry at most this many times short of a
+    /// bucket no input reached yet to 'frontier' in the output directory.
+    /// These are good starting points to get past coverage plateaus.
+    #[arg(long, value_name = "HITS", default_value_t = 0)]
+    near_miss: u8,
+    /// Warn once if this many different inputs in a row produce the same
+    /// coverage, which hints at broken instrumentation or a harness that
+    /// ignores its input. Zero (the default) disables the check, 1000 is a
+    /// good window to enable it with.
+    #[arg(long, value_name = "INPUTS", default_value_t = 0)]
+    constant_coverage_window: usize,
+    /// Periodically write the histogram of corpus mutation depths to
+    /// 'depths/<core>' in the output directory.
+    #[arg(long, default_value_t = false)]
+    dump_depths: bool,
+    /// Write a line per execution (program length, exit kind, execution time
+    /// and whether it was interesting or a solution) to 'trace/<core>' in the output
+    /// directory. Slows down fuzzing, meant for debugging harnesses.
+    #[arg(long, default_value_t = false)]
+    trace: bool,
+    /// Never let the execution time decide whether an input is added to the
+    /// corpus, only coverage. Execution times are still recorded for
+    /// scheduling.
+    #[arg(long, default_value_t = false)]
+    no_time_feedback: bool,
+    /// Comma separated contents of the crash bundles written to 'bundles' in
+    /// the output directory for every crash: raw, disasm, script, coverage,
+    /// metadata or all. No bundles are written by default.
+    #[arg(long, default_value = "", value_parser = CrashBundleConfig::parse)]
+    crash_bundles: CrashBundleConfig,
+    /// How the causes the harness reports are written to 'causes' in the
+    /// output directory: 'text' keeps only the harness's files, 'json' adds
+    /// a '<cause file>.json' with the triggering program, the exit signal
+    /// and a timestamp.
+    #[arg(long, value_name = "FORMAT", default_value = "text", value_parser = CausesFormat::parse)]
+    causes_format: CausesFormat,
+    /// The master seed all client RNGs are seeded from. Random by default,
+    /// the used seed is recorded in 'run.json' in the output directory.
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Run as machine INDEX of COUNT fuzzing the same target, e.g. '2/8'.
+    /// Every shard seeds its clients from a disjoint range. Give every shard
+    /// its own output directory and restart shards with the 'queue' of the
+    /// others as input directory to share inputs between machines.
+    #[arg(long, value_name = "INDEX/COUNT", value_parser = Shard::parse)]
+    shard: Option<Shard>,
+    /// With --shard, generate instructions of a different category on every
+    /// shard more often (shard 0 arith, 1 mem, 2 branch, ...).
+    #[arg(long, default_value_t = false)]
+    shard_emphasis: bool,
+    /// Restricts generated argument values, e.g. 'imm12=0-64,rs1=0x1-0x3'.
+    #[arg(long)]
+    arg_ranges: Option<String>,
+    /// The most instructions a generated or mutated program may have,
+    /// without the register fill prologue. Mutations drop the instructions
+    /// past it. Defaults to $PHANTOM_TRAILS_MAX_PROGRAM_LEN or 1024.
+    #[arg(long, value_name = "INSTS", alias = "max-insts")]
+    max_program_len: Option<usize>,
+    /// Start address of the memory the harness maps for the generated loads
+    /// and stores, e.g. '0x80000000'. Needs --scratch-size.
+    #[arg(long, value_name = "ADDR")]
+    scratch_base: Option<String>,
+    /// Size of the scratch memory at --scratch-base in bytes.
+    #[arg(long, value_name = "BYTES")]
+    scratch_size: Option<String>,
+    /// Start every program with a prologue that points x31 to the middle of
+    /// the scratch region, which must have at least 4 KiB, and address most
+    /// generated loads and stores relative to x31. Mutations never write
+    /// x31.
+    #[arg(long, default_value_t = false)]
+    mem_base: bool,
+    /// Don't generate instructions with reserved encodings (e.g. reserved
+    /// rounding modes) that only trap as illegal instructions.
+    #[arg(long, default_value_t = false)]
+    avoid_reserved: bool,
+    /// Start the initial inputs with a prologue that writes all registers
+    /// x1-x31, so the mutated body runs on diverse register contents. The
+    /// prologue itself is never mutated.
+    #[arg(long, default_value_t = false)]
+    register_fill: bool,
+    /// Remember the last K destination registers of generated instructions
+    /// and read them in source operands, which builds short def-use chains.
+    #[arg(long, value_name = "K")]
+    recent_regs: Option<usize>,
+    /// The chance (0-100) of reading one of the --recent-regs in a source
+    /// operand.
+    #[arg(long, default_value_t = 50)]
+    recent_reg_chance: u64,
+    /// Raise the chance of inserted instructions reading registers written
+    /// before them from START to MAX percent over the first SECS seconds,
+    /// e.g. '10:90:3600', to favor longer dependency chains as the campaign
+    /// matures. Defaults to $PHANTOM_TRAILS_DATAFLOW_RAMP.
+    #[arg(long, value_name = "START:MAX:SECS")]
+    dataflow_ramp: Option<String>,
+    /// The chance (0-100) of reusing an argument value of the program for a
+    /// generated argument. Defaults to $PHANTOM_TRAILS_REUSE_CHANCE, or 50 (0
+    /// if $PHANTOM_TRAILS_NO_ARG_REUSE is set).
+    #[arg(long, value_name = "CHANCE")]
+    reuse_chance: Option<u64>,
+    /// The chance (0-100) of choosing a power of two for a generated
+    /// argument. Defaults to $PHANTOM_TRAILS_POW2_CHANCE, or 50 (0 if
+    /// $PHANTOM_TRAILS_NO_ARG_REUSE is set).
+    #[arg(long, value_name = "CHANCE")]
+    pow2_chance: Option<u64>,
+    /// The chance (0-100) of generating a store immediately followed by a
+    /// load from the same address instead of a single instruction, which
+    /// exercises store-to-load forwarding. Defaults to
+    /// $PHANTOM_TRAILS_PAIRED_MEM_CHANCE or 0.
+    #[arg(long, value_name = "CHANCE")]
+    paired_mem_chance: Option<u64>,
+    /// The chance (0-100) of using an immediate of a saved crash for a
+    /// generated argument with --crash-immediates. Defaults to
+    /// $PHANTOM_TRAILS_CRASH_CHANCE or 50.
+    #[arg(long, value_name = "CHANCE")]
+    crash_chance: Option<u64>,
+    /// The instruction set new instructions are generated from: rv64g,
+    /// rv64i, rv64imfd, rv64g_zbb, rv64gc, rv32g, rv32i, rv32imfd or rv32gc.
+    /// The imfd sets are G without the atomics, the gc sets include the
+    /// compressed instructions, see also --compressed. Defaults to
+    /// $PHANTOM_TRAILS_ISA or rv64g.
+    #[arg(long)]
+    isa: Option<String>,
+    /// Comma separated instruction categories that are never generated,
+    /// e.g. 'branch,system'. Categories: arith, mem, branch, system, float
+    /// and atomic.
+    #[arg(long, value_name = "CATEGORIES")]
+    exclude_category: Option<String>,
+    /// How often instructions are generated relative to the default weight
+    /// of 100, e.g. 'addi=1000,ecall=0'. Overrides --category-weights.
+    #[arg(long, value_name = "WEIGHTS")]
+    template_weights: Option<String>,
+    /// Weights of all instructions of a category, e.g. 'mem=300,float=0'.
+    #[arg(long, value_name = "WEIGHTS")]
+    category_weights: Option<String>,
+    /// The core implements the C extension, so generated branch and jump
+    /// targets only need to be 2-byte instead of 4-byte aligned.
+    #[arg(long, default_value_t = false)]
+    compressed: bool,
+    /// Also generate the privileged system instructions (mret, sret, wfi and
+    /// sfence.vma). They trap or wait on most harnesses and so often end the
+    /// program early.
+    #[arg(long, default_value_t = false)]
+    privileged: bool,
+    /// Also generate the CSR instructions (csrrw, csrrs, csrrc and their
+    /// immediate variants), mostly with the addresses of CSRs that exist.
+    #[arg(long, default_value_t = false)]
+    csr: bool,
+    /// File with crash signatures (one hex value per line, as printed by
+    /// 'reproduce') of known bugs. Matching crashes are not reported.
+    #[arg(long)]
+    known_crashes: Option<PathBuf>,
+    /// Save crashes matching a known signature to 'known' in the output
+    /// directory instead of dropping them.
+    #[arg(long, default_value_t = false)]
+    save_known: bool,
+    /// Only save crashes that cover at least this many map entries. Crashes
+    /// below are written to 'low_coverage' in the output directory instead.
+    /// Filters harness artifacts, but also real bugs that trigger early.
+    #[arg(long, default_value_t = 0)]
+    min_crash_coverage: usize,
+    /// Only save the first crash of every coverage signature (the covered
+    /// map entries). The signatures are kept in 'signatures' in the output
+    /// directory, so this also holds across client restarts and when
+    /// resuming a campaign in the same directory. Independent of the
+    /// instruction dedup that --no-crash-dedup turns off.
+    #[arg(long, default_value_t = false)]
+    dedup_crashes: bool,
+    /// Every this many seconds, hardlink the corpus into
+    /// 'snapshots/<unix time>' in the output directory.
+    #[arg(long, value_name = "SECONDS")]
+    snapshot_interval: Option<u64>,
+    /// Only use the coverage in the map entries START (inclusive) to END
+    /// (exclusive) as feedback, e.g. to focus on a single module.
+    #[arg(long, value_name = "START-END", value_parser = parse_map_region)]
+    map_region: Option<Range<usize>>,
+    /// Treat inputs as solutions whose final registers differ from the ones
+    /// of the reference model, as reported by the harness via
+    /// 'reportRegisterState'.
+    #[arg(long, default_value_t = false)]
+    register_divergence: bool,
+    /// Treat inputs as solutions ('exitcode') if the target exits with this
+    /// code. Can be repeated. Needs the harness to exit via 'exitFuzzing'.
+    #[arg(long = "crash-exit-code", value_name = "CODE")]
+    crash_exit_codes: Vec<i32>,
+    /// How often the target is restarted in a row when it keeps failing
+    /// before the client gives up.
+    #[arg(long, default_value_t = 5)]
+    max_executor_restarts: u32,
+    /// Shell command run in the background for every saved crash, with the
+    /// path of the crash file as its last argument.
+    #[arg(long, value_name = "CMD")]
+    on_crash: Option<String>,
+    /// Write a JSON summary of the unique objectives (category, signature,
+    /// first file, first-seen time and count) to this file on shutdown.
+    #[arg(long, value_name = "PATH")]
+    findings_report: Option<PathBuf>,
+    /// Append the executions, exec/sec, corpus size, objectives and elapsed
+    /// time as one JSON object per line to this file every second, e.g. for
+    /// graphing headless runs.
+    #[arg(long, value_name = "PATH")]
+    stats_file: Option<PathBuf>,
+    /// Don't print the startup messages (map size, seeds, port, ...).
+    /// Errors are still printed.
+    #[arg(short, long, default_value_t = false)]
+    quiet: bool,
+    /// Print the resolved configuration as JSON and exit without fuzzing or
+    /// creating anything in the output directory. Implies --quiet. As the
+    /// target isn't started, this shows the --map-size (or the default)
+    /// instead of the size the target reports, and the --timeout instead of
+    /// the --timeout-auto one.
+    #[arg(long, default_value_t = false)]
+    print_config: bool,
+    /// Print the disassembly of N programs generated with the configuration
+    /// and the --seed, and exit without starting the target or creating
+    /// anything in the output directory. Implies --quiet.
+    #[arg(long, value_name = "N")]
+    dry_run: Option<usize>,
+}
+
+/// What `--print-config` prints: the options after applying the defaults
+/// and the values resolved from them, as they would end up in 'run.json'.
+#[derive(Serialize)]
+struct PrintedConfig<'a> {
+    options: &'a Args,
+    resolved: &'a RunManifest,
+}
+
+/// Delta-debugs which instructions of the input cause its unstable map
+/// entries and prints the minimal program that still has some of them.
+fn find_unstable(args: UnstableArgs) -> ExitCode {
+    let buffer = fs::read(&args.run.input).expect("Failed to read input file");
+    let input = match ProgramInput::from_bytes(buffer.as_slice()) {
+        Ok(input) => input,
+        Err(err) => {
+            eprintln!("Failed to deserialize {}: {}", args.run.input, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    if args.runs < 2 {
+        eprintln!("At least 2 runs are needed to compare coverage.");
+        return ExitCode::FAILURE;
+    }
+
+    let target = TargetConfig::single_runs(
+        &args.run.arguments,
+        args.run.envs.clone(),
+        Duration::from_millis(args.run.timeout),
+        Signal::SIGKILL,
+        args.run.map_size,
+    );
+
+    let mut runner = SingleRunner::new(&target).expect("Failed to create the executor.");
+
+    // The map entries that differ between runs of the program.
+    let mut measure = |insts: &[Instruction]| {
+        let program = ProgramInput::new(insts.to_vec());
+        let maps: Vec<Vec<u8>> = (0..args.runs)
+            .map(|_| runner.run(&program).expect("Failed to run target").map)
+            .collect();
+        unstable_entries(&maps)
+    };
+
+    let unstable = measure(input.insts());
+    if unstable.is_empty() {
+        println!("No unstable map entries in {} runs.", args.runs);
+        return ExitCode::FAILURE;
+    }
+    println!(
+        "{} unstable map entries in {} runs, minimizing {} instructions...",
+        unstable.len(),
+        args.runs,
+        input.insts().len()
+    );
+
+    // Programs that still show any of the original unstable entries.
+    let minimal = minimize(input.insts(), |insts| {
+        !measure(insts).is_disjoint(&unstable)
+    });
+    if minimal.is_empty() {
+        println!("The empty program is unstable too, the harness is nondeterministic.");
+    } else {
+        println!("Minimal unstable program ({} instructions):", minimal.len());
+        for inst in &minimal {
+            println!("  {}", inst);
+        }
+    }
+    if let Some(out) = &args.out {
+        let bytes = ProgramInput::new(minimal)
+            .to_bytes()
+            .expect("Failed to serialize input");
+        if let Err(err) = fs::write(out, bytes) {
+            eprintln!("Failed to write {:?}: {}", out, err);
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Runs the given input once and prints what happened.
+/// Returns a failure exit code if the target didn't crash.
+fn reproduce(args: ReproduceArgs) -> ExitCode {
+    let buffer = fs::read(&args.input).expect("Failed to read input file");
+    let input = match ProgramInput::from_bytes(buffer.as_slice()) {
+        Ok(input) => input,
+        Err(err) => {
+            eprintln!("Failed to deserialize {}: {}", args.input, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let marker = std::env::temp_dir().join(format!("sim-fuzzer-marker-{}", process::id()));
+    let mut envs = args.envs;
+    envs.push((
+        FUZZING_ISSUE_MARKER_VAR.to_owned(),
+        marker.to_string_lossy().into_owned(),
+    ));
+    let target = TargetConfig::single_runs(
+        &args.arguments,
+        envs,
+        Duration::from_millis(args.timeout),
+        Signal::SIGKILL,
+        args.map_size,
+    );
+
+    let result = run_one(&input, &target).expect("Failed to run target");
+    let exit_kind = result.exit_kind;
+
+    println!("Exit kind: {:?}", exit_kind);
+    match (exit_kind, result.signal) {
+        (ExitKind::Timeout, Some(signal)) => println!("Exit signal: {} (timeout)", signal),
+        (_, Some(signal)) => println!("Exit signal: {}", signal),
+        (ExitKind::Crash, None) => println!("Exit signal: unknown"),
+        _ => println!("Exit signal: none"),
+    }
+    let marker_exists = fs::remove_file(&marker).is_ok();
+    if exit_kind == ExitKind::Crash {
+        println!(
+            "Crash origin: {:?}",
+            CrashOrigin::from_marker(marker_exists)
+        );
+        println!("Crash signature: {:016x}", result.coverage.signature);
+    }
+    if let Some(time) = result.exec_time {
+        println!("Execution time: {:?}", time);
+    }
+    let coverage = &result.coverage;
+    println!(
+        "Coverage: {}/{} ({}%)",
+        coverage.covered,
+        coverage.total,
+        coverage.percent()
+    );
+
+    if exit_kind == ExitKind::Crash {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
 }
 
-pub fn main() {
-    let args = Args::parse();
-    let out_dir = PathBuf::from(args.out);
+/// Writes all inputs in the corpus directory to one archive.
+fn pack_corpus(args: PackArgs) -> ExitCode {
+    let (inputs, failed) = match read_corpus_dir(&args.input) {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("Failed to read {:?}: {}", args.input, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    if failed != 0 {
+        eprintln!("Skipped {} empty or unreadable files", failed);
+    }
+    if let Err(err) = fs::write(&args.out, pack(&inputs)) {
+        eprintln!("Failed to write {:?}: {}", args.out, err);
+        return ExitCode::FAILURE;
+    }
+    println!("Packed {} inputs into {:?}", inputs.len(), args.out);
+    ExitCode::SUCCESS
+}
+
+/// Writes all inputs in the archive to the output directory.
+fn unpack_corpus(args: PackArgs) -> ExitCode {
+    let archive = match fs::read(&args.input) {
+        Ok(archive) => archive,
+        Err(err) => {
+            eprintln!("Failed to read {:?}: {}", args.input, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let unpacked = unpack(&archive);
+    if unpacked.corrupt != 0 {
+        eprintln!("Skipped {} corrupt entries", unpacked.corrupt);
+    }
+    if unpacked.truncated {
+        eprintln!("Archive is truncated, the last entry is missing");
+    }
+    fs::create_dir_all(&args.out).expect("Failed to create output directory");
+    for (idx, input) in unpacked.inputs.iter().enumerate() {
+        let path = args.out.join(input.generate_name(idx));
+        fs::write(path, input.to_bytes().unwrap()).expect("Failed to write input");
+    }
+    println!(
+        "Unpacked {} inputs into {:?}",
+        unpacked.inputs.len(),
+        args.out
+    );
+    ExitCode::SUCCESS
+}
+
+/// Prints the throughput of every phase of [`run_benchmark`].
+fn bench(args: BenchArgs) -> ExitCode {
+    let isa = match sets::by_name(&args.isa) {
+        Some(isa) => isa,
+        None => {
+            eprintln!(
+                "Unknown instruction set {:?}. Supported: {:?}",
+                args.isa,
+                sets::NAMES
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    if args.iters == 0 || args.len == 0 {
+        eprintln!("--iters and --len must be at least 1");
+        return ExitCode::FAILURE;
+    }
+    println!(
+        "{} programs of {} instructions from {}",
+        args.iters, args.len, args.isa
+    );
+    for result in run_benchmark(&isa, args.iters, args.len, args.seed) {
+        println!(
+            "{:<8} {:>12.0} programs/s {:>14.0} insts/s ({:?})",
+            result.phase,
+            result.programs_per_sec(),
+            result.insts_per_sec(),
+            result.elapsed
+        );
+    }
+    ExitCode::SUCCESS
+}
+
+pub fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Commands::Reproduce(repro_args)) => return reproduce(repro_args),
+        Some(Commands::Pack(pack_args)) => return pack_corpus(pack_args),
+        Some(Commands::Unpack(pack_args)) => return unpack_corpus(pack_args),
+        Some(Commands::Unstable(unstable_args)) => return find_unstable(unstable_args),
+        Some(Commands::Bench(bench_args)) => return bench(bench_args),
+        None => {}
+    }
+
+    let args = cli.fuzz;
+    let out_dir = PathBuf::from(&args.out);
+    let quiet = args.quiet || args.print_config || args.dry_run.is_some();
+
+    // The env vars are the base, the command line overrides them.
+    let mut config = match DriverConfig::from_env() {
+        Ok(config) => config,
+        Err(err) => {
+            println!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    match (&args.scratch_base, &args.scratch_size) {
+        (None, None) => {}
+        (Some(base), Some(size)) => {
+            let region = match (parse_u64(base), parse_u64(size)) {
+                (Some(base), Some(size)) => ScratchRegion::new(base, size),
+                _ => Err(format!("Invalid number in '{}' or '{}'", base, size)),
+            };
+            match region {
+                Ok(region) => config.scratch = Some(region),
+                Err(err) => {
+                    println!("Invalid scratch region: {}", err);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        _ => {
+            println!("--scratch-base and --scratch-size must be given together.");
+            return ExitCode::FAILURE;
+        }
+    }
+    config.mem_base |= args.mem_base;
+    if let Some(region) = &config.scratch {
+        if !quiet {
+            println!(
+                "Scratch region: {:#x}-{:#x} ({} bytes)",
+                region.base,
+                region.end(),
+                region.size
+            );
+        }
+    }
+
+    if let Some(ranges) = &args.arg_ranges {
+        config.arg_ranges = match parse_arg_ranges(ranges) {
+            Ok(ranges) => ranges,
+            Err(err) => {
+                println!("Invalid --arg-ranges: {}", err);
+                return ExitCode::FAILURE;
+            }
+        };
+    }
+    if let Some(isa) = &args.isa {
+        if sets::by_name(isa).is_none() {
+            println!(
+                "Unknown instruction set {:?}. Supported: {:?}",
+                isa,
+                sets::NAMES
+            );
+            return ExitCode::FAILURE;
+        }
+        config.isa = isa.clone();
+    }
+    if let Some(list) = &args.exclude_category {
+        config.excluded_categories = match parse_categories(list) {
+            Ok(excluded) => excluded,
+            Err(err) => {
+                println!("Invalid --exclude-category: {}", err);
+                return ExitCode::FAILURE;
+            }
+        };
+        if config.instructions().is_empty() {
+            println!(
+                "--exclude-category {} leaves no instructions in {}",
+                list, config.isa
+            );
+            return ExitCode::FAILURE;
+        }
+    }
+    if let Some(list) = &args.template_weights {
+        config.template_weights = match parse_template_weights(list) {
+            Ok(weights) => weights,
+            Err(err) => {
+                println!("Invalid --template-weights: {}", err);
+                return ExitCode::FAILURE;
+            }
+        };
+    }
+    if let Some(list) = &args.category_weights {
+        config.category_weights = match parse_category_weights(list) {
+            Ok(weights) => weights,
+            Err(err) => {
+                println!("Invalid --category-weights: {}", err);
+                return ExitCode::FAILURE;
+            }
+        };
+    }
+    config.avoid_reserved |= args.avoid_reserved;
+    config.register_fill |= args.register_fill;
+    config.compressed |= args.compressed;
+    config.privileged |= args.privileged;
+    config.csr |= args.csr;
+    if let Some(len) = args.max_program_len {
+        config.max_program_len = len;
+    }
+    if let Some(recent_regs) = args.recent_regs {
+        if args.recent_reg_chance > 100 {
+            println!(
+                "Invalid --recent-reg-chance {}. Must be between 0 and 100.",
+                args.recent_reg_chance
+            );
+            return ExitCode::FAILURE;
+        }
+        config.recent_regs = Some((recent_regs, args.recent_reg_chance));
+    }
+    if let Some(ramp) = &args.dataflow_ramp {
+        config.dataflow_ramp = match DataflowRamp::parse(ramp) {
+            Ok(ramp) => Some(ramp),
+            Err(err) => {
+                println!("Invalid --dataflow-ramp: {}", err);
+                return ExitCode::FAILURE;
+            }
+        };
+    }
+    config.reuse_chance = args.reuse_chance.or(config.reuse_chance);
+    config.pow2_chance = args.pow2_chance.or(config.pow2_chance);
+    if let Some(chance) = args.paired_mem_chance {
+        config.paired_mem_chance = chance;
+    }
+    if let Some(chance) = args.crash_chance {
+        config.crash_chance = chance;
+    }
+
+    let shard = args.shard.unwrap_or_default();
+    if args.shard_emphasis {
+        if args.shard.is_none() {
+            println!("--shard-emphasis needs --shard");
+            return ExitCode::FAILURE;
+        }
+        config.emphasis = Some(shard.emphasis());
+    }
+    if let Err(err) = config.validate() {
+        println!("{}", err);
+        return ExitCode::FAILURE;
+    }
+
+    if let Some(count) = args.dry_run {
+        // The programs of the client on the first core.
+        let seed = client_seed(args.seed.unwrap_or_else(current_nanos), shard, 0);
+        for (i, program) in preview_programs(&config, seed, count, DRY_RUN_LEN)
+            .iter()
+            .enumerate()
+        {
+            println!("# program {} ({} instructions)", i, program.insts().len());
+            print!("{}", program.to_asm());
+        }
+        return ExitCode::SUCCESS;
+    }
 
+    let in_dir = PathBuf::from(&args.input);
+    if !in_dir.is_dir() {
+        println!("In dir at {:?} is not a valid directory!", &in_dir);
+        return ExitCode::FAILURE;
+    }
+
+    let timeout = Duration::from_millis(args.timeout);
+    let Some(executable) = args.arguments.first() else {
+        println!("Missing the target executable, pass the target command line after the options.");
+        return ExitCode::FAILURE;
+    };
+    let debug_child = false;
+    let simple_ui = args.simple_ui;
+    let num_cores = get_core_ids().map(|ids| ids.len()).unwrap_or(1);
+    let core_spec = match resolve_core_spec(&args.cores, args.core_fraction, num_cores) {
+        Ok(spec) => spec,
+        Err(err) => {
+            println!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let cores = Cores::from_cmdline(&core_spec).expect("Failed to parse --cores arg");
+    let signal = str::parse::<Signal>("SIGKILL").unwrap();
+    let arguments = args.arguments[1..].to_vec();
+
+    let scheduler_map: HashMap<String, PowerSchedule> = HashMap::from([
+        ("explore".to_owned(), PowerSchedule::EXPLORE),
+        ("fast".to_owned(), PowerSchedule::FAST),
+        ("exploit".to_owned(), PowerSchedule::EXPLOIT),
+    ]);
+    let scheduler = scheduler_map.get(&args.scheduler);
+    if scheduler.is_none() {
+        println!(
+            "Unknown scheduler {:?}. Supported schedulers: {:?}",
+            args.scheduler,
+            scheduler_map.keys()
+        );
+        return ExitCode::FAILURE;
+    }
+
+    let port = if args.port == 0 {
+        None
+    } else {
+        Some(args.port)
+    };
+
+    if !(0.0..=1.0).contains(&args.schedule_epsilon) {
+        println!(
+            "Invalid --schedule-epsilon {}. Must be between 0 and 1.",
+            args.schedule_epsilon
+        );
+        return ExitCode::FAILURE;
+    }
+    if !(0.0..=1.0).contains(&args.rarity_chance) {
+        println!(
+            "Invalid --rarity-chance {}. Must be between 0 and 1.",
+            args.rarity_chance
+        );
+        return ExitCode::FAILURE;
+    }
+    if !(0.0..=1.0).contains(&args.class_chance) {
+        println!(
+            "Invalid --class-chance {}. Must be between 0 and 1.",
+            args.class_chance
+        );
+        return ExitCode::FAILURE;
+    }
+    if let Some(factor) = args.timeout_factor {
+        if factor.is_nan() || factor < 1.0 {
+            println!("Invalid --timeout-factor {}. Must be at least 1.", factor);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    // The map size and the timeout are measured once the output directory
+    // exists, --print-config shows the configured ones.
+    let mut target = TargetConfig {
+        executable: executable.clone(),
+        arguments,
+        envs: args.envs.clone(),
+        debug_child,
+        timeout,
+        // Without hang solutions, nobody looks at the timeouts anyway.
+        timeout_reruns: if args.save_hangs {
+            args.timeout_reruns
+        } else {
+            0
+        },
+        timeout_factor: args.timeout_factor,
+        signal,
+        map_size: args.map_size.unwrap_or(DEFAULT_MAP_SIZE),
+    };
+    let master_seed = args.seed.unwrap_or_else(current_nanos);
+    let start_secs = current_time().as_secs();
+    let manifest = |target: &TargetConfig| {
+        RunManifest::new(
+            &config.isa,
+            master_seed,
+            shard,
+            &in_dir,
+            target,
+            cores.ids.iter().map(|id| id.0).collect(),
+            start_secs,
+        )
+    };
+    if args.print_config {
+        let manifest = manifest(&target);
+        let config = PrintedConfig {
+            options: &args,
+            resolved: &manifest,
+        };
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&config).expect("Failed to serialize the config")
+        );
+        return ExitCode::SUCCESS;
+    }
     let mut log_dir = out_dir.clone();
     log_dir.push("logs");
     std::fs::create_dir_all(log_dir.clone()).expect("Failed to create 'logs' directory.");
@@ -130,7 +1120,7 @@ pub fn main() {
     if fs::create_dir(&out_dir).is_err() {
         if !out_dir.is_dir() {
             println!("Out dir at {:?} is not a valid directory!", &out_dir);
-            return;
+            return ExitCode::FAILURE;
         }
     }
     let mut crashes = out_dir.clone();
@@ -159,77 +1149,363 @@ pub fn main() {
     let mut queue_dir = out_dir.clone();
     queue_dir.push("queue");
 
-    let in_dir = PathBuf::from(args.input);
-    if !in_dir.is_dir() {
-        println!("In dir at {:?} is not a valid directory!", &in_dir);
-        return;
-    }
+    let depths_dir = if args.dump_depths {
+        let mut depths_dir = out_dir.clone();
+        depths_dir.push("depths");
+        std::fs::create_dir_all(depths_dir.clone()).expect("Failed to create 'depths' directory.");
+        Some(depths_dir)
+    } else {
+        None
+    };
 
-    let timeout = Duration::from_millis(args.timeout);
-    let executable = args.arguments.first().unwrap();
-    let debug_child = false;
-    let simple_ui = args.simple_ui;
-    let cores = Cores::from_cmdline(&args.cores.to_string()).expect("Failed to parse --cores arg");
-    let signal = str::parse::<Signal>("SIGKILL").unwrap();
-    let arguments = &args.arguments[1..];
+    let trace_dir = if args.trace {
+        let mut trace_dir = out_dir.clone();
+        trace_dir.push("trace");
+        std::fs::create_dir_all(trace_dir.clone()).expect("Failed to create 'trace' directory.");
+        Some(trace_dir)
+    } else {
+        None
+    };
 
-    let scheduler_map: HashMap<String, PowerSchedule> = HashMap::from([
-        ("explore".to_owned(), PowerSchedule::EXPLORE),
-        ("fast".to_owned(), PowerSchedule::FAST),
-        ("exploit".to_owned(), PowerSchedule::EXPLOIT),
-    ]);
-    let scheduler = scheduler_map.get(&args.scheduler);
-    if scheduler.is_none() {
-        println!(
-            "Unknown scheduler {:?}. Supported schedulers: {:?}",
-            args.scheduler,
-            scheduler_map.keys()
-        );
-        return;
-    }
+    let frontier_dir = if args.near_miss > 0 {
+        let mut frontier_dir = out_dir.clone();
+        frontier_dir.push("frontier");
+        std::fs::create_dir_all(frontier_dir.clone())
+            .expect("Failed to create 'frontier' directory.");
+        Some(frontier_dir)
+    } else {
+        None
+    };
 
-    let port = if args.port == 0 {
+    let label_dir = if args.objectives_by_label {
+        let mut label_dir = out_dir.clone();
+        label_dir.push("by_label");
+        std::fs::create_dir_all(label_dir.clone()).expect("Failed to create 'by_label' directory.");
+        Some(label_dir)
+    } else {
         None
+    };
+
+    let known_crashes = match &args.known_crashes {
+        Some(path) => {
+            let list = fs::read_to_string(path).expect("Failed to read known crashes file");
+            match parse_known_signatures(&list) {
+                Ok(known) => {
+                    if !quiet {
+                        println!(
+                            "Loaded {} known crash signatures from {:?}",
+                            known.len(),
+                            path
+                        );
+                    }
+                    known
+                }
+                Err(err) => {
+                    println!("Invalid --known-crashes file: {}", err);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        None => HashSet::new(),
+    };
+    let known_dir = if args.save_known {
+        let mut known_dir = out_dir.clone();
+        known_dir.push("known");
+        std::fs::create_dir_all(known_dir.clone()).expect("Failed to create 'known' directory.");
+        Some(known_dir)
     } else {
-        Some(args.port)
+        None
+    };
+
+    let signature_dir = if args.dedup_crashes {
+        let mut signature_dir = out_dir.clone();
+        signature_dir.push("signatures");
+        std::fs::create_dir_all(signature_dir.clone())
+            .expect("Failed to create 'signatures' directory.");
+        Some(signature_dir)
+    } else {
+        None
+    };
+
+    let low_coverage_dir = if args.min_crash_coverage > 0 {
+        let mut low_coverage_dir = out_dir.clone();
+        low_coverage_dir.push("low_coverage");
+        std::fs::create_dir_all(low_coverage_dir.clone())
+            .expect("Failed to create 'low_coverage' directory.");
+        Some(low_coverage_dir)
+    } else {
+        None
     };
 
-    fuzz(
+    let snapshots = args.snapshot_interval.map(|interval| {
+        let mut snapshots_dir = out_dir.clone();
+        snapshots_dir.push(SNAPSHOTS_DIR_NAME);
+        std::fs::create_dir_all(snapshots_dir.clone())
+            .expect("Failed to create 'snapshots' directory.");
+        (snapshots_dir, Duration::from_secs(interval.max(1)))
+    });
+
+    let mut marker_dir = out_dir.clone();
+    marker_dir.push("markers");
+    std::fs::create_dir_all(marker_dir.clone()).expect("Failed to create 'markers' directory.");
+
+    let mut bundle_dir = out_dir.clone();
+    bundle_dir.push("bundles");
+    if !args.crash_bundles.is_empty() {
+        std::fs::create_dir_all(bundle_dir.clone()).expect("Failed to create 'bundles' directory.");
+    }
+
+    // Prefer the user's map size, then the one the target reports.
+    let map_size = resolve_map_size(executable, args.map_size);
+    target.map_size = map_size;
+    if simple_ui && !quiet {
+        println!("Using map size: {}", map_size);
+    }
+    let page_size = sysconf(SysconfVar::PAGE_SIZE)
+        .ok()
+        .flatten()
+        .unwrap_or(4096) as usize;
+    if let Some(warning) = map_size_warning(map_size, page_size) {
+        if !quiet {
+            println!("Warning: {}", warning);
+        }
+    }
+    if let Some(region) = &args.map_region {
+        if let Err(err) = check_map_region(region, map_size) {
+            println!("Invalid --map-region: {}", err);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let seeds = match read_seed_dir(&in_dir, args.seed_format) {
+        Ok(seeds) => seeds,
+        Err(err) => {
+            println!("Failed to read the seeds in {:?}: {}", in_dir, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    if simple_ui && !quiet {
+        println!("Loaded {} seeds from {:?}", seeds.len(), in_dir);
+    }
+
+    if args.timeout_auto {
+        let programs = if seeds.is_empty() {
+            vec![fallback_seed(&config.isa())]
+        } else {
+            seeds.clone()
+        };
+        target.timeout = match calibrate_timeout(&target, &config, master_seed, &programs) {
+            Ok(timeout) => timeout,
+            Err(err) => {
+                println!("Failed to measure the seeds for --timeout-auto: {}", err);
+                return ExitCode::FAILURE;
+            }
+        };
+        if !quiet {
+            println!("Using timeout: {:?}", target.timeout);
+        }
+    }
+
+    let manifest = manifest(&target);
+    manifest
+        .write_to(&out_dir)
+        .expect("Failed to write the run manifest.");
+
+    fuzz(FuzzOptions {
         out_dir,
-        queue_dir,
-        crashes,
-        &in_dir,
-        timeout,
-        executable,
-        debug_child,
-        signal,
-        &arguments,
+        base_corpus_dir: queue_dir,
+        base_objective_dir: crashes,
+        seeds,
+        target: &target,
         cores,
         simple_ui,
-        scheduler.copied(),
+        schedule: scheduler.copied(),
         port,
-    )
+        schedule_epsilon: args.schedule_epsilon,
+        rarity_chance: args.rarity_chance,
+        class_chance: args.class_chance,
+        warmup_execs: args.warmup_execs,
+        stability_runs: args.stability_runs,
+        estimate_exec_time: args.estimate_exec_time,
+        stall_threshold: args.stall_threshold.unwrap_or(0),
+        mutator_weights: args.mutator_weights.unwrap_or_default(),
+        config,
+        crash_immediates: args.crash_immediates,
+        save_hangs: args.save_hangs,
+        crash_dedup: !args.no_crash_dedup,
+        label_dir,
+        time_feedback: !args.no_time_feedback,
+        hitcount_feedback: args.hitcount_feedback,
+        length_feedback: args.length_feedback,
+        buckets: args.buckets,
+        near_miss: args.near_miss,
+        frontier_dir,
+        constant_coverage_window: args.constant_coverage_window,
+        depths_dir,
+        trace_dir,
+        crash_bundles: args.crash_bundles,
+        bundle_dir,
+        causes_format: args.causes_format,
+        cause_dir,
+        manifest: &manifest,
+        marker_dir,
+        known_crashes,
+        known_dir,
+        signature_dir,
+        min_crash_coverage: args.min_crash_coverage,
+        low_coverage_dir,
+        snapshots,
+        crash_hook: args.on_crash.map(CrashHook::new),
+        findings_report: args.findings_report,
+        stats_file: args.stats_file,
+        map_region: args.map_region,
+        max_executor_restarts: args.max_executor_restarts,
+        register_divergence: args.register_divergence,
+        crash_exit_codes: args.crash_exit_codes,
+        quiet,
+    })
     .expect("An error occurred while fuzzing");
+
+    ExitCode::SUCCESS
 }
 
-/// The actual fuzzer
-fn fuzz(
+/// Runs every seed [`CALIBRATION_RUNS`] times with the register fill the
+/// clients add to it and returns the timeout for --timeout-auto (see
+/// [`auto_timeout`]), or the timeout of `target` if nothing was measured.
+/// Seeds the clients would skip are skipped here as well.
+fn calibrate_timeout(
+    target: &TargetConfig,
+    config: &DriverConfig,
+    seed: u64,
+    seeds: &[ProgramInput],
+) -> Result<Duration, Error> {
+    let generator = InstGenerator::with_config(config);
+    let mut rand = StdRand::with_seed(seed);
+    let mut runner = SingleRunner::new(target)?;
+    let mut exec_times = vec![];
+    for seed in seeds {
+        let insts = generator.add_register_fill(&mut rand, seed.insts().to_vec());
+        let Ok(program) = ProgramInput::try_new(insts) else {
+            continue;
+        };
+        for _ in 0..CALIBRATION_RUNS {
+            exec_times.extend(runner.run(&program)?.exec_time);
+        }
+    }
+    Ok(auto_timeout(exec_times, target.timeout).unwrap_or(target.timeout))
+}
+
+/// The settings of a fuzzing run, resolved from the [`Args`] in `main`.
+struct FuzzOptions<'a> {
     out_dir: PathBuf,
     base_corpus_dir: PathBuf,
     base_objective_dir: PathBuf,
-    _seed_dir: &PathBuf, // Currently unused because seed parsing not implemented.
-    timeout: Duration,
-    executable: &String,
-    debug_child: bool,
-    signal: Signal,
-    arguments: &[String],
+    seeds: Vec<ProgramInput>,
+    target: &'a TargetConfig,
     cores: Cores,
     simple_ui: bool,
     schedule: Option<PowerSchedule>,
     port: Option<u16>,
-) -> Result<(), Error> {
+    schedule_epsilon: f64,
+    rarity_chance: f64,
+    class_chance: f64,
+    warmup_execs: usize,
+    stability_runs: usize,
+    estimate_exec_time: bool,
+    stall_threshold: u64,
+    mutator_weights: MutationWeights,
+    config: DriverConfig,
+    crash_immediates: bool,
+    save_hangs: bool,
+    crash_dedup: bool,
+    label_dir: Option<PathBuf>,
+    time_feedback: bool,
+    hitcount_feedback: bool,
+    length_feedback: bool,
+    buckets: BucketTable,
+    near_miss: u8,
+    frontier_dir: Option<PathBuf>,
+    constant_coverage_window: usize,
+    depths_dir: Option<PathBuf>,
+    trace_dir: Option<PathBuf>,
+    crash_bundles: CrashBundleConfig,
+    bundle_dir: PathBuf,
+    causes_format: CausesFormat,
+    cause_dir: PathBuf,
+    manifest: &'a RunManifest,
+    marker_dir: PathBuf,
+    known_crashes: HashSet<u64>,
+    known_dir: Option<PathBuf>,
+    signature_dir: Option<PathBuf>,
+    min_crash_coverage: usize,
+    low_coverage_dir: Option<PathBuf>,
+    snapshots: Option<(PathBuf, Duration)>,
+    crash_hook: Option<CrashHook>,
+    findings_report: Option<PathBuf>,
+    stats_file: Option<PathBuf>,
+    map_region: Option<Range<usize>>,
+    max_executor_restarts: u32,
+    register_divergence: bool,
+    crash_exit_codes: Vec<i32>,
+    quiet: bool,
+}
+
+/// The actual fuzzer
+fn fuzz(options: FuzzOptions<'_>) -> Result<(), Error> {
+    let FuzzOptions {
+        out_dir,
+        base_corpus_dir,
+        base_objective_dir,
+        seeds,
+        target,
+        cores,
+        simple_ui,
+        schedule,
+        port,
+        schedule_epsilon,
+        rarity_chance,
+        class_chance,
+        warmup_execs,
+        stability_runs,
+        estimate_exec_time,
+        stall_threshold,
+        mutator_weights,
+        config,
+        crash_immediates,
+        save_hangs,
+        crash_dedup,
+        label_dir,
+        time_feedback,
+        hitcount_feedback,
+        length_feedback,
+        buckets,
+        near_miss,
+        frontier_dir,
+        constant_coverage_window,
+        depths_dir,
+        trace_dir,
+        crash_bundles,
+        bundle_dir,
+        causes_format,
+        cause_dir,
+        manifest,
+        marker_dir,
+        known_crashes,
+        known_dir,
+        signature_dir,
+        min_crash_coverage,
+        low_coverage_dir,
+        snapshots,
+        crash_hook,
+        findings_report,
+        stats_file,
+        map_region,
+        max_executor_restarts,
+        register_divergence,
+        crash_exit_codes,
+        quiet,
+    } = options;
     let ui: Arc<Mutex<FuzzUI>> = Arc::new(Mutex::new(FuzzUI::new(simple_ui)));
-    const MAP_SIZE: usize = 2_621_440;
     let start_time = current_time();
 
     let monitor = HWFuzzMonitor::new(
@@ -238,42 +1514,66 @@ fn fuzz(
             .to_str()
             .expect("Out dir is not valid utf-8?")
             .to_owned(),
-    );
+    )
+    .with_stats_file(stats_file);
 
     let shmem_provider = UnixShMemProvider::new().expect("Failed to init shared memory");
     let mut shmem_provider_client = shmem_provider.clone();
 
     let mut run_client =
         |_state: Option<_>, mut mgr: LlmpRestartingEventManager<_, _>, core_id: CoreId| {
-            // The coverage map shared between observer and executor
-            let mut shmem = shmem_provider_client.new_shmem(MAP_SIZE).unwrap();
-
-            // let the forkserver know the shmid
-            shmem.write_to_env("__AFL_SHM_ID").unwrap();
-            let shmem_buf = shmem.as_mut_slice();
-
-            // To let know the AFL++ binary that we have a big map
-            std::env::set_var("AFL_MAP_SIZE", format!("{}", MAP_SIZE));
+            install_shutdown_handler()
+                .map_err(|err| Error::unknown(format!("Failed to handle SIGINT: {}", err)))?;
+            let mut shmem = create_coverage_map(&mut shmem_provider_client, target.map_size);
+            let map_ptr = shmem.as_mut_slice().as_mut_ptr();
+            let map_len = shmem.as_mut_slice().len();
+            // The executor is rebuilt with new observers on the same map if
+            // the target keeps failing.
+            let make_edges_observer = || {
+                // SAFETY: The map lives as long as the client and only the
+                // observer of the current executor accesses it, the old
+                // executor is dropped before a new observer is created.
+                let map = unsafe { std::slice::from_raw_parts_mut(map_ptr, map_len) };
+                edges_observer(map, buckets.clone(), map_region.clone()).with_near_miss(near_miss)
+            };
+            let edges_observer = make_edges_observer();
 
-            // Create an observation channel using the hitcounts map of AFL++
-            let edges_observer =
-                unsafe { HitcountsMapObserver::new(StdMapObserver::new("shared_mem", shmem_buf)) };
+            // The register state exported by co-simulating harnesses.
+            let mut regs_shmem =
+                register_divergence.then(|| create_register_map(&mut shmem_provider_client));
+            let regs_ptr = regs_shmem
+                .as_mut()
+                .map(|shmem| shmem.as_mut_slice().as_mut_ptr());
+            let make_registers_observer = || match regs_ptr {
+                // SAFETY: Same as for the coverage map.
+                Some(ptr) => RegisterStateObserver::new(unsafe {
+                    std::slice::from_raw_parts_mut(ptr, REGISTER_STATE_SIZE)
+                }),
+                None => RegisterStateObserver::disabled(),
+            };
 
             // Create an observation channel to keep track of the execution time
             let time_observer = TimeObserver::new("time");
 
-            let map_feedback = MaxMapFeedback::tracking(&edges_observer, true, false);
-
-            let calibration = DummyCalibration::new(&map_feedback);
-
-            // Feedback to rate the interestingness of an input
-            // This one is composed by two Feedbacks in OR
-            let mut feedback = feedback_or!(
-                // New maximization map feedback linked to the edges observer and the feedback state
-                map_feedback,
-                // Time feedback, this one does not need a feedback state
-                TimeFeedback::with_observer(&time_observer)
-            );
+            let trace = trace_dir.as_ref().map(|dir| {
+                TraceWriter::new(
+                    fs::File::create(dir.join(format!("{}", core_id.0)))
+                        .expect("Failed to create the trace file."),
+                )
+            });
+            let feedback_options = FeedbackOptions {
+                time_feedback,
+                hitcount_feedback,
+                length_feedback,
+                trace: trace.clone(),
+                frontier_dir: frontier_dir.clone(),
+                constant_window: constant_coverage_window,
+            };
+            let (mut feedback, calibration) =
+                build_feedback(&edges_observer, &time_observer, feedback_options);
+            let calibration = calibration
+                .with_stability_runs(stability_runs)
+                .with_estimated_time(estimate_exec_time);
 
             // Create client specific directories to avoid race conditions when
             // writing the corpus to disk.
@@ -282,81 +1582,181 @@ fn fuzz(
             let mut objective_dir = base_objective_dir.clone();
             objective_dir.push(format!("{}", core_id.0));
 
-            // A feedback to choose if an input is a solution or not
-            let mut objective = CrashFeedback::new();
+            let mut bundles = CrashBundleFeedback::new(
+                crash_bundles,
+                bundle_dir.clone(),
+                target.clone(),
+                core_id.0,
+                &edges_observer,
+            );
+            bundles.set_run_manifest(manifest.to_json());
+            // Every client gets its own marker as the targets run in parallel.
+            let mut marker = marker_dir.clone();
+            marker.push(format!("{}", core_id.0));
+            let mut client_target = target.clone();
+            client_target.envs.push((
+                FUZZING_ISSUE_MARKER_VAR.to_owned(),
+                marker.to_string_lossy().into_owned(),
+            ));
+            // The harness only reports exit codes if someone looks at them.
+            let exit_code_file = (!crash_exit_codes.is_empty()).then(|| {
+                let file = marker.with_extension("exitcode");
+                client_target.envs.push((
+                    FUZZING_EXIT_CODE_FILE_VAR.to_owned(),
+                    file.to_string_lossy().into_owned(),
+                ));
+                file
+            });
+            // The same goes for the causes, see `CauseFeedback`.
+            let client_causes = client_cause_dir(&cause_dir, core_id.0);
+            std::fs::create_dir_all(&client_causes)
+                .expect("Failed to create the client 'causes' directory.");
+            client_target.envs.push((
+                FUZZING_CAUSE_DIR_VAR.to_owned(),
+                client_causes.to_string_lossy().into_owned(),
+            ));
+
+            let known =
+                KnownCrashFilter::new(&edges_observer, known_crashes.clone(), known_dir.clone());
+            let low_coverage = LowCoverageFilter::new(
+                &edges_observer,
+                min_crash_coverage,
+                low_coverage_dir.clone(),
+            );
+            let signatures = signature_dir.as_ref().map(|dir| {
+                SignatureStore::open(dir, core_id.0).expect("Failed to load the crash signatures")
+            });
+            let duplicates = DuplicateCrashFilter::new(&edges_observer, signatures);
+            let objective_options = ObjectiveOptions {
+                core_id: core_id.0,
+                issue_marker: Some(marker),
+                crash_immediates,
+                save_hangs,
+                crash_dedup,
+                known_crashes: known,
+                low_coverage,
+                duplicates,
+                bundles,
+                causes: CauseFeedback::new(causes_format, client_causes, target.signal),
+                exit_codes: ExitCodeFeedback::new(exit_code_file, crash_exit_codes.clone()),
+                label_dir: label_dir.clone(),
+                trace,
+            };
+            let mut objective = build_objective(&time_observer, objective_options);
+
+            let client_corpus_dir = corpus_dir.clone();
 
             // Create the fuzz state.
-            let mut state = StdState::new(
-                StdRand::with_seed(current_nanos()),
-                OnDiskCorpus::<ProgramInput>::with_meta_format(
-                    corpus_dir,
-                    OnDiskMetadataFormat::Postcard,
-                )
-                .unwrap(),
-                OnDiskCorpus::new(objective_dir).unwrap(),
+            let mut state = build_state(
+                client_seed(manifest.master_seed, manifest.shard, core_id.0),
+                corpus_dir,
+                objective_dir,
                 &mut feedback,
                 &mut objective,
             )
             .unwrap();
 
-            let mutator = RiscvScheduledMutator::new(all_riscv_mutations());
+            let mutator = RiscvScheduledMutator::new(all_riscv_mutations(&config))
+                .with_snippets(config.snippets)
+                .with_stall_threshold(stall_threshold)
+                .with_weights(&mutator_weights);
 
             let power = StdPowerMutationalStage::new(mutator);
 
-            // A minimization+queue policy to get testcasess from the corpus
-            let scheduler = IndexesLenTimeMinimizerScheduler::new(
-                StdWeightedScheduler::with_schedule(&mut state, &edges_observer, schedule),
+            let scheduler = build_scheduler(
+                &mut state,
+                &edges_observer,
+                schedule,
+                schedule_epsilon,
+                rarity_chance,
+                class_chance,
+                warmup_execs,
             );
 
             // A fuzzer with feedbacks and a corpus scheduler
             let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
 
-            let forkserver = ForkserverExecutor::builder()
-                .program(executable.clone())
-                .debug_child(debug_child)
-                .parse_afl_cmdline(arguments)
-                .coverage_map_size(MAP_SIZE)
-                .is_persistent(false)
-                .is_deferred_frksrv(true)
-                .build_dynamic_map(edges_observer, tuple_list!(time_observer))
-                .unwrap();
-
-            let mut executor = TimeoutForkserverExecutor::with_signal(forkserver, timeout, signal)
-                .expect("Failed to create the executor.");
-
-            // Load the initial seeds from the user directory.
-            // state
-            //     .load_initial_inputs(&mut fuzzer, &mut executor, &mut mgr, &[seed_dir.clone()])
-            //     .unwrap_or_else(|_| {
-            //         println!("Failed to load initial corpus at {:?}", &seed_dir);
-            //         process::exit(0);
-            //     });
-
-            let nop = Instruction::new(
-                &ADDI,
-                vec![
-                    Argument::new(&args::RD, 0u32),
-                    Argument::new(&args::RS1, 0u32),
-                    Argument::new(&args::IMM12, 0u32),
-                ],
-            );
+            let mut executor = build_executor(
+                &client_target,
+                edges_observer,
+                time_observer,
+                make_registers_observer(),
+            )
+            .expect("Failed to create the executor.");
 
-            let init = ProgramInput::new([nop].to_vec());
-            fuzzer
-                .add_input(&mut state, &mut executor, &mut mgr, init)
-                .expect("Failed to load initial inputs");
+            // Start from the seeds in the user directory, or a single nop of
+            // the selected instruction set if there are none.
+            let inits = if seeds.is_empty() {
+                vec![fallback_seed(&config.isa())]
+            } else {
+                seeds.clone()
+            };
+            let generator = InstGenerator::with_config(&config);
+            for init in inits {
+                let insts = generator.add_register_fill(state.rand_mut(), init.insts().to_vec());
+                let init = match ProgramInput::try_new(insts) {
+                    Ok(init) => init,
+                    Err(err) => {
+                        log::warn!("Skipping seed: {}", err);
+                        continue;
+                    }
+                };
+                fuzzer
+                    .add_input(&mut state, &mut executor, &mut mgr, init)
+                    .expect("Failed to load initial inputs");
+            }
 
-            // First calibrate the initial seed and then mutate.
-            let mut stages = tuple_list!(calibration, power);
+            // First calibrate the initial seeds and then mutate the ones that
+            // survived calibration.
+            let mut stages = tuple_list!(calibration, SkipEvicted::new(power));
 
             // Main fuzzing loop.
             let mut last = current_time();
+            let mut last_depths = current_time();
             let monitor_timeout = Duration::from_secs(1);
+            let mut last_snapshot = current_time();
+            let mut last_solution: Option<CorpusId> = None;
+            let mut restarts = RestartPolicy::new(max_executor_restarts);
+            let mut last_fuzz_err = String::new();
 
-            loop {
+            // Inputs are written to the corpus directories as soon as they are
+            // added, so nothing is lost by stopping between two executions.
+            while !shutdown_requested() {
                 let fuzz_err = fuzzer.fuzz_one(&mut stages, &mut executor, &mut state, &mut mgr);
-                if fuzz_err.is_err() {
-                    log::error!("fuzz_one error: {}", fuzz_err.err().unwrap());
+                if let Err(err) = &fuzz_err {
+                    log::error!("fuzz_one error: {}", err);
+                    last_fuzz_err = err.to_string();
+                }
+                match restarts.record(fuzz_err.is_ok()) {
+                    RestartAction::Continue => {}
+                    RestartAction::Restart(backoff) => {
+                        log::error!(
+                            "Restarting the executor in {:?} after {} failures, last: {}",
+                            backoff,
+                            FAILURES_BEFORE_RESTART,
+                            last_fuzz_err
+                        );
+                        std::thread::sleep(backoff);
+                        // Dropping the executor stops the old forkserver and
+                        // releases its observers before new ones access the
+                        // same maps.
+                        drop(executor);
+                        executor = build_executor(
+                            &client_target,
+                            make_edges_observer(),
+                            TimeObserver::new("time"),
+                            make_registers_observer(),
+                        )
+                        .map_err(|err| {
+                            Error::illegal_state(format!("Failed to restart the executor: {}", err))
+                        })?;
+                    }
+                    RestartAction::Abort => {
+                        return Err(Error::illegal_state(format!(
+                            "Executor still failing after {} restarts: {}",
+                            max_executor_restarts, last_fuzz_err
+                        )));
+                    }
                 }
                 let last_err = mgr.maybe_report_progress(&mut state, last, monitor_timeout);
                 if last_err.is_err() {
@@ -365,12 +1765,169 @@ fn fuzz(
                     last = last_err.ok().unwrap()
                 }
 
+                if current_time() - last_depths > monitor_timeout {
+                    last_depths = current_time();
+                    match DepthHistogram::from_corpus(state.corpus()) {
+                        Ok(depths) => {
+                            if let Some(dir) = &depths_dir {
+                                let mut path = dir.clone();
+                                path.push(format!("{}", core_id.0));
+                                if let Err(err) = fs::write(path, depths.to_text()) {
+                                    log::error!("Failed to write depths: {}", err);
+                                }
+                            }
+                            let fire_err = mgr.fire(
+                                &mut state,
+                                Event::UpdateUserStats {
+                                    name: DEPTHS_STAT_NAME.to_string(),
+                                    value: UserStats::String(depths.summary()),
+                                    phantom: PhantomData,
+                                },
+                            );
+                            if fire_err.is_err() {
+                                log::error!("depths error: {}", fire_err.err().unwrap());
+                            }
+                        }
+                        Err(err) => log::error!("Failed to collect depths: {}", err),
+                    }
+
+                    let lengths = state
+                        .metadata_map()
+                        .get::<LengthHistogramMetadata>()
+                        .map(|hist| hist.summary());
+                    if let Some(lengths) = lengths {
+                        let fire_err = mgr.fire(
+                            &mut state,
+                            Event::UpdateUserStats {
+                                name: LENGTHS_STAT_NAME.to_string(),
+                                value: UserStats::String(lengths),
+                                phantom: PhantomData,
+                            },
+                        );
+                        if fire_err.is_err() {
+                            log::error!("lengths error: {}", fire_err.err().unwrap());
+                        }
+                    }
+
+                    let classes = state
+                        .metadata_map()
+                        .get::<ProgramClassMetadata>()
+                        .map(|classes| classes.summary());
+                    if let Some(classes) = classes {
+                        let fire_err = mgr.fire(
+                            &mut state,
+                            Event::UpdateUserStats {
+                                name: CLASSES_STAT_NAME.to_string(),
+                                value: UserStats::String(classes),
+                                phantom: PhantomData,
+                            },
+                        );
+                        if fire_err.is_err() {
+                            log::error!("classes error: {}", fire_err.err().unwrap());
+                        }
+                    }
+
+                    let category_times = state
+                        .metadata_map()
+                        .get::<CategoryTimeMetadata>()
+                        .map(|times| times.summary());
+                    if let Some(category_times) = category_times {
+                        let fire_err = mgr.fire(
+                            &mut state,
+                            Event::UpdateUserStats {
+                                name: CATEGORY_TIMES_STAT_NAME.to_string(),
+                                value: UserStats::String(category_times),
+                                phantom: PhantomData,
+                            },
+                        );
+                        if fire_err.is_err() {
+                            log::error!("category times error: {}", fire_err.err().unwrap());
+                        }
+                    }
+
+                    let density = executor
+                        .observers()
+                        .match_name::<EdgesObserver>(EDGES_OBSERVER_NAME)
+                        .map(map_density);
+                    if let Some((filled, size)) = density {
+                        let fire_err = mgr.fire(
+                            &mut state,
+                            Event::UpdateUserStats {
+                                name: MAP_DENSITY_STAT_NAME.to_string(),
+                                value: UserStats::Ratio(filled, size),
+                                phantom: PhantomData,
+                            },
+                        );
+                        if fire_err.is_err() {
+                            log::error!("map density error: {}", fire_err.err().unwrap());
+                        }
+                    }
+
+                    // The calibration stage reports it too, but only when new
+                    // entries come in.
+                    let stability = state
+                        .metadata_map()
+                        .get::<UnstableEntriesMetadata>()
+                        .map(|meta| (meta.stable_count(), meta.filled_entries().len()))
+                        .filter(|(_, filled)| *filled != 0);
+                    if let Some((stable, filled)) = stability {
+                        let fire_err = mgr.fire(
+                            &mut state,
+                            Event::UpdateUserStats {
+                                name: STABILITY_STAT_NAME.to_string(),
+                                value: UserStats::Ratio(stable as u64, filled as u64),
+                                phantom: PhantomData,
+                            },
+                        );
+                        if fire_err.is_err() {
+                            log::error!("stability error: {}", fire_err.err().unwrap());
+                        }
+                    }
+                }
+
+                // Only run the hook once the crash is on disk.
+                if let Some(hook) = &crash_hook {
+                    loop {
+                        let solutions = state.solutions();
+                        let next = match last_solution {
+                            Some(id) => solutions.next(id),
+                            None => solutions.first(),
+                        };
+                        let Some(id) = next else { break };
+                        last_solution = Some(id);
+                        match solutions.get(id) {
+                            Ok(testcase) => match testcase.borrow().file_path() {
+                                Some(path) => hook.run(path),
+                                None => log::error!("Crash {} has no file", id),
+                            },
+                            Err(err) => log::error!("Failed to get crash {}: {}", id, err),
+                        }
+                    }
+                }
+
+                if let Some((snapshots_dir, interval)) = &snapshots {
+                    let now = current_time();
+                    if now - last_snapshot > *interval {
+                        last_snapshot = now;
+                        // Round down so all clients end up in the same directory.
+                        let slot = now.as_secs() - now.as_secs() % interval.as_secs();
+                        let dest = snapshot_path(snapshots_dir, slot, &format!("{}", core_id.0));
+                        spawn_snapshot(client_corpus_dir.clone(), dest);
+                    }
+                }
+
                 // If we have a simple UI, we need to manually list all causes
                 // to check if we found all bugs.
                 if simple_ui {
                     list_causes(start_time);
                 }
             }
+            log::info!("Client {} stopping after SIGINT", core_id.0);
+            log::logger().flush();
+            // Tell the restarter not to start this client again. Dropping the
+            // executor afterwards stops the forkserver and its child.
+            mgr.send_exiting()?;
+            Ok(())
         };
 
     let conf = EventConfig::from_build_id();
@@ -378,7 +1935,7 @@ fn fuzz(
     let random_port = 8000u16 + cores.ids.first().unwrap().0 as u16;
     let actual_port = port.or(Some(random_port)).unwrap();
 
-    if simple_ui {
+    if simple_ui && !quiet {
         println!("Using Port: {:#?}", actual_port);
     }
 
@@ -395,6 +1952,10 @@ fn fuzz(
     launcher_log_file.push("launch_log");
 
     let launcher = launcher.stdout_file(Some(launcher_log_file.to_str().unwrap()));
+    // The clients are forked from this process and also return from launch.
+    // Nothing buffered should be left for them to drop.
+    log::logger().flush();
+    let broker_pid = std::process::id();
     match launcher.build().launch() {
         Ok(()) => (),
         Err(Error::ShuttingDown) => {
@@ -402,5 +1963,11 @@ fn fuzz(
         }
         Err(err) => panic!("Fuzzer error: {err:?}"),
     }
+    if let Some(report) = findings_report.filter(|_| std::process::id() == broker_pid) {
+        match write_findings_report(&report, &base_objective_dir, &bundle_dir) {
+            Ok(count) => println!("Wrote {} findings to {}", count, report.display()),
+            Err(err) => println!("Failed to write the findings report: {}", err),
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,118 @@
+use clap::Parser;
+use core::time::Duration;
+use nix::sys::signal::Signal;
+use riscv_mutator::{
+    driver::{parse_env_var, parse_map_size, parse_signal, TargetConfig},
+    instructions::Instruction,
+    program_input::ProgramInput,
+    single_run::SingleRunner,
+    tmin::{is_crash, minimize, same_crash},
+};
+use std::{fs, path::PathBuf, process::ExitCode};
+
+/// Minimizes a crashing program: removes instructions and reruns the target
+/// as long as it still crashes the same way.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// The serialized program (e.g. a file from the 'found' directory).
+    #[arg(short, long)]
+    input: PathBuf,
+    /// Where the minimized program is written. Defaults to the input with a
+    /// '.min' suffix.
+    #[arg(short, long)]
+    out: Option<PathBuf>,
+    #[arg(short, long, default_value_t = 60000)]
+    timeout: u64,
+    /// The signal a timed out target is killed with, e.g. one the simulator
+    /// handles to shut down cleanly.
+    #[arg(long, value_parser = parse_signal, default_value = "SIGKILL")]
+    signal: Signal,
+    #[arg(long, value_parser = parse_map_size)]
+    map_size: Option<usize>,
+    /// Also keep the crash signature of the coverage map, so the program
+    /// isn't minimized into a different crash.
+    #[arg(long, default_value_t = false)]
+    same_signature: bool,
+    /// Extra environment variable passed to the target. Can be repeated.
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_var)]
+    envs: Vec<(String, String)>,
+    /// The target command line.
+    #[arg(last = true, required = true)]
+    arguments: Vec<String>,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let buffer = match fs::read(&args.input) {
+        Ok(buffer) => buffer,
+        Err(err) => {
+            eprintln!("Failed to read {:?}: {}", args.input, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let input = match ProgramInput::from_bytes(&buffer) {
+        Ok(input) => input,
+        Err(err) => {
+            eprintln!("Failed to deserialize {:?}: {}", args.input, err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let target = TargetConfig::single_runs(
+        &args.arguments,
+        args.envs.clone(),
+        Duration::from_millis(args.timeout),
+        args.signal,
+        args.map_size,
+    );
+
+    let mut runner = SingleRunner::new(&target).expect("Failed to create the executor.");
+    let original = runner.run(&input).expect("Failed to run target");
+    if !is_crash(&original) {
+        eprintln!(
+            "The input doesn't crash the target (exit kind {:?}).",
+            original.exit_kind
+        );
+        return ExitCode::FAILURE;
+    }
+    println!(
+        "Exit kind {:?}, minimizing {} instructions...",
+        original.exit_kind,
+        input.insts().len()
+    );
+
+    let mut runs = 0;
+    let minimal = minimize(input.insts(), |insts: &[Instruction]| {
+        runs += 1;
+        let result = runner
+            .run(&ProgramInput::new(insts.to_vec()))
+            .expect("Failed to run target");
+        same_crash(&original, &result, args.same_signature)
+    });
+    println!(
+        "Minimal program ({} of {} instructions, {} runs):",
+        minimal.len(),
+        input.insts().len(),
+        runs
+    );
+    for inst in &minimal {
+        println!("  {}", inst);
+    }
+
+    let out = args.out.unwrap_or_else(|| {
+        let mut out = args.input.clone().into_os_string();
+        out.push(".min");
+        out.into()
+    });
+    let bytes = ProgramInput::new(minimal)
+        .to_bytes()
+        .expect("Failed to serialize input");
+    if let Err(err) = fs::write(&out, bytes) {
+        eprintln!("Failed to write {:?}: {}", out, err);
+        return ExitCode::FAILURE;
+    }
+    println!("Written to {:?}", out);
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,296 @@
+use core::{fmt::Debug, marker::PhantomData};
+use std::{fs, path::PathBuf};
+
+use libafl::{
+    bolts::tuples::{MatchName, Named},
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::UsesInput,
+    observers::{MapObserver, ObserversTuple},
+    prelude::current_time,
+    state::{HasClientPerfMonitor, HasMetadata},
+    Error,
+};
+use serde::Serialize;
+
+use crate::{
+    driver::TargetConfig, feedbacks::CrashOriginMetadata, manifest::RUN_MANIFEST_NAME,
+    program_input::ProgramInput, triage::CrashTriageMetadata,
+};
+
+/// Which files are written into a crash bundle.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CrashBundleConfig {
+    /// The encoded instructions as they were passed to the target.
+    pub raw: bool,
+    /// The program in the `inst-assembler` format.
+    pub disasm: bool,
+    /// A shell script reproducing the crash via `sim-fuzzer reproduce`.
+    pub script: bool,
+    /// The non-zero coverage map entries as `INDEX VALUE` lines.
+    pub coverage: bool,
+    /// A `metadata.json` describing the crash and the run's `run.json`.
+    pub metadata: bool,
+}
+
+impl CrashBundleConfig {
+    /// Parses a comma separated list of bundle contents, e.g. `raw,disasm`.
+    /// `all` enables everything, an empty string nothing.
+    pub fn parse(list: &str) -> Result<Self, String> {
+        let mut result = Self::default();
+        for part in list.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match part {
+                "raw" => result.raw = true,
+                "disasm" => result.disasm = true,
+                "script" => result.script = true,
+                "coverage" => result.coverage = true,
+                "metadata" => result.metadata = true,
+                "all" => {
+                    result = Self {
+                        raw: true,
+                        disasm: true,
+                        script: true,
+                        coverage: true,
+                        metadata: true,
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "Unknown bundle content '{}'. Supported: raw, disasm, script, coverage, metadata, all",
+                        part
+                    ))
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The contents of `metadata.json` in a crash bundle.
+#[derive(Serialize, Debug)]
+struct CrashMetadata {
+    exit_kind: String,
+    /// The `CrashOrigin` label, if known.
+    origin: Option<String>,
+    unix_time: u64,
+    core: usize,
+    hash: String,
+    num_insts: usize,
+    /// The instruction category the crash is likely caused by.
+    trigger: String,
+    last_inst: Option<String>,
+    covered_entries: usize,
+}
+
+/// Writes a self-contained directory for every saved objective that can be
+/// handed to someone else for reproducing the crash.
+///
+/// Like [`crate::feedbacks::ObjectiveNameFeedback`] this never considers
+/// anything interesting on its own and should be combined with the actual
+/// objective via `feedback_or!` (after the name feedback, so the bundle is
+/// named after the saved file).
+#[derive(Debug)]
+pub struct CrashBundleFeedback<O> {
+    config: CrashBundleConfig,
+    bundle_dir: PathBuf,
+    target: TargetConfig,
+    core_id: usize,
+    observer_name: String,
+    /// The state of the last execution, needed once the testcase is saved.
+    last_exit_kind: Option<ExitKind>,
+    last_coverage: Vec<(usize, u8)>,
+    /// The serialized run manifest copied into every bundle.
+    run_manifest: Option<String>,
+    phantom: PhantomData<O>,
+}
+
+impl<O> CrashBundleFeedback<O>
+where
+    O: MapObserver<Entry = u8>,
+{
+    pub fn new(
+        config: CrashBundleConfig,
+        bundle_dir: PathBuf,
+        target: TargetConfig,
+        core_id: usize,
+        map_observer: &O,
+    ) -> Self {
+        Self {
+            config,
+            bundle_dir,
+            target,
+            core_id,
+            observer_name: map_observer.name().to_string(),
+            last_exit_kind: None,
+            last_coverage: vec![],
+            run_manifest: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the run manifest (as JSON) that is included with the metadata.
+    pub fn set_run_manifest(&mut self, manifest: String) {
+        self.run_manifest = Some(manifest);
+    }
+
+    /// The shell script that reruns the crashing input against the target.
+    fn repro_script(&self) -> String {
+        let mut cmd = format!(
+            "sim-fuzzer reproduce --input \"$(dirname \"$0\")/input\" --timeout {}",
+            self.target.timeout.as_millis()
+        );
+        for (key, value) in &self.target.envs {
+            cmd += &format!(" --env '{}={}'", key, value);
+        }
+        cmd += &format!(" -- '{}'", self.target.executable);
+        for arg in &self.target.arguments {
+            cmd += &format!(" '{}'", arg);
+        }
+        format!("#!/bin/sh\n{}\n", cmd)
+    }
+
+    fn write_bundle(
+        &self,
+        name: &str,
+        input: &ProgramInput,
+        origin: Option<&str>,
+    ) -> Result<(), Error> {
+        let mut dir = self.bundle_dir.clone();
+        dir.push(name);
+        fs::create_dir_all(&dir)?;
+
+        // The reproduce subcommand reads the serialized input.
+        if self.config.script {
+            fs::write(dir.join("input"), input.to_bytes()?)?;
+            fs::write(dir.join("repro.sh"), self.repro_script())?;
+        }
+        if self.config.raw {
+            fs::write(dir.join("input.insts"), input.encode_program())?;
+        }
+        if self.config.disasm {
+            let mut disasm = String::new();
+            for inst in input.insts() {
+                disasm += &format!("{}\n", inst);
+            }
+            fs::write(dir.join("input.asm"), disasm)?;
+        }
+        if self.config.coverage {
+            let mut coverage = String::new();
+            for (idx, value) in &self.last_coverage {
+                coverage += &format!("{} {}\n", idx, value);
+            }
+            fs::write(dir.join("coverage"), coverage)?;
+        }
+        if self.config.metadata {
+            let triage = CrashTriageMetadata::classify(input.insts());
+            let meta = CrashMetadata {
+                exit_kind: format!("{:?}", self.last_exit_kind.unwrap_or(ExitKind::Crash)),
+                origin: origin.map(str::to_owned),
+                unix_time: current_time().as_secs(),
+                core: self.core_id,
+                hash: format!("{:016x}", input.content_hash()),
+                num_insts: input.insts().len(),
+                trigger: triage.family.name().to_owned(),
+                last_inst: triage.last_inst,
+                covered_entries: self.last_coverage.len(),
+            };
+            let json =
+                serde_json::to_string_pretty(&meta).map_err(|e| Error::serialize(e.to_string()))?;
+            fs::write(dir.join("metadata.json"), json)?;
+            if let Some(manifest) = &self.run_manifest {
+                fs::write(dir.join(RUN_MANIFEST_NAME), manifest)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<O> Named for CrashBundleFeedback<O> {
+    fn name(&self) -> &str {
+        "CrashBundleFeedback"
+    }
+}
+
+impl<O, S> Feedback<S> for CrashBundleFeedback<O>
+where
+    O: MapObserver<Entry = u8> + Debug,
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &ProgramInput,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        if self.config.is_empty() {
+            return Ok(false);
+        }
+        self.last_exit_kind = Some(*exit_kind);
+        // Only crashing inputs end up in a bundle, so don't bother copying
+        // the map for the others.
+        self.last_coverage.clear();
+        if self.config.coverage && *exit_kind != ExitKind::Ok {
+            let map = observers
+                .match_name::<O>(&self.observer_name)
+                .ok_or_else(|| Error::key_not_found("MapObserver not found".to_string()))?;
+            for idx in 0..map.usable_count() {
+                let value = *map.get(idx);
+                if value != 0 {
+                    self.last_coverage.push((idx, value));
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        if self.config.is_empty() {
+            return Ok(());
+        }
+        let origin = testcase
+            .metadata_map()
+            .get::<CrashOriginMetadata>()
+            .map(|meta| meta.origin.label());
+        if let Some(input) = testcase.input() {
+            let name = testcase
+                .filename()
+                .clone()
+                .unwrap_or_else(|| format!("{:016x}", input.content_hash()));
+            if let Err(err) = self.write_bundle(&name, input, origin) {
+                log::error!("Failed to write crash bundle {}: {}", name, err);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CrashBundleConfig;
+
+    #[test]
+    fn parse_bundle_config() {
+        assert!(CrashBundleConfig::parse("").unwrap().is_empty());
+        let config = CrashBundleConfig::parse("raw, metadata").unwrap();
+        assert!(config.raw && config.metadata);
+        assert!(!config.disasm && !config.script && !config.coverage);
+        assert!(!CrashBundleConfig::parse("all").unwrap().is_empty());
+        assert!(CrashBundleConfig::parse("raw,bogus").is_err());
+    }
+}
@@ -9,28 +9,41 @@ use serde::{Deserialize, Serialize};
 use libafl::{
     bolts::{tuples::Named, AsIter},
     corpus::{Corpus, CorpusId, SchedulerTestcaseMetadata},
-    events::{EventFirer, LogSeverity},
+    events::{Event, EventFirer, LogSeverity},
     executors::{Executor, ExitKind, HasObservers},
     feedbacks::HasObserverName,
-    fuzzer::Evaluator,
+    fuzzer::{Evaluator, HasScheduler},
     inputs::UsesInput,
-    observers::{MapObserver, ObserversTuple, UsesObserver},
-    schedulers::powersched::SchedulerMetadata,
+    monitors::UserStats,
+    observers::{MapObserver, ObserversTuple, TimeObserver, UsesObserver},
+    schedulers::{powersched::SchedulerMetadata, Scheduler},
     stages::Stage,
     state::{HasClientPerfMonitor, HasCorpus, HasMetadata, HasNamedMetadata, UsesState},
     Error,
 };
 
-use crate::program_input::ProgramInput;
+use crate::{program_input::ProgramInput, stability::unstable_entries};
+
+/// How often a new corpus entry is run before it is given up on. Anything
+/// that doesn't finish once in these runs is removed from the corpus.
+pub const CALIBRATION_ATTEMPTS: usize = 3;
+
+/// The name of the user stat with the stability, as stable out of filled
+/// map entries.
+pub const STABILITY_STAT_NAME: &str = "stability";
 
 libafl::impl_serdeany!(UnstableEntriesMetadata);
 /// The metadata to keep unstable entries
 /// In libafl, the stability is the number of the unstable entries divided by the size of the map
 /// This is different from AFL++, which shows the number of the unstable entries divided by the number of filled entries.
+/// [`UnstableEntriesMetadata::stability`] follows AFL++.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct UnstableEntriesMetadata {
     unstable_entries: HashSet<usize>,
     map_len: usize,
+    /// The entries set by any calibrated input.
+    #[serde(default)]
+    filled_entries: HashSet<usize>,
 }
 
 impl UnstableEntriesMetadata {
@@ -40,9 +53,50 @@ impl UnstableEntriesMetadata {
         Self {
             unstable_entries: entries,
             map_len,
+            filled_entries: HashSet::new(),
         }
     }
 
+    /// Adds the unstable and filled entries of one more calibrated input.
+    /// Entries past the map length are ignored, so the sets never outgrow
+    /// the map.
+    pub fn record(
+        &mut self,
+        unstable: impl IntoIterator<Item = usize>,
+        filled: impl IntoIterator<Item = usize>,
+    ) {
+        let map_len = self.map_len;
+        self.unstable_entries
+            .extend(unstable.into_iter().filter(|idx| *idx < map_len));
+        self.filled_entries
+            .extend(filled.into_iter().filter(|idx| *idx < map_len));
+    }
+
+    /// Getter
+    #[must_use]
+    pub fn filled_entries(&self) -> &HashSet<usize> {
+        &self.filled_entries
+    }
+
+    /// The number of filled entries that were stable in every calibration.
+    #[must_use]
+    pub fn stable_count(&self) -> usize {
+        self.filled_entries
+            .iter()
+            .filter(|idx| !self.unstable_entries.contains(*idx))
+            .count()
+    }
+
+    /// The share (0-1) of the filled entries that were stable in every
+    /// calibration, or `None` if nothing was filled yet.
+    #[must_use]
+    pub fn stability(&self) -> Option<f64> {
+        if self.filled_entries.is_empty() {
+            return None;
+        }
+        Some(self.stable_count() as f64 / self.filled_entries.len() as f64)
+    }
+
     /// Getter
     #[must_use]
     pub fn unstable_entries(&self) -> &HashSet<usize> {
@@ -56,10 +110,31 @@ impl UnstableEntriesMetadata {
     }
 }
 
+libafl::impl_serdeany!(MeasuredTimeMetadata);
+/// The execution time of a corpus entry as measured during calibration. The
+/// executor uses it to tighten the timeout for the mutants of the entry (see
+/// [`crate::executors::AdaptiveTimeoutExecutor`]).
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeasuredTimeMetadata {
+    pub exec_time: Duration,
+}
+
 /// The calibration stage will measure the average exec time and the target's stability for this input.
+/// Entries that crash or time out on every attempt are evicted from the corpus, later stages should
+/// be wrapped in [`SkipEvicted`].
+///
+/// By default, every entry runs once. With more stability runs, the entries whose value differs
+/// between the runs are collected in the [`UnstableEntriesMetadata`] of the state and the
+/// stability is reported as [`STABILITY_STAT_NAME`].
+///
+/// The power schedule gets the execution time measured by the time observer. With estimated times,
+/// it gets one second per instruction instead, which doesn't depend on the load of the machine.
 #[derive(Clone, Debug)]
 pub struct DummyCalibration<O, OT, S> {
     map_observer_name: String,
+    time_observer_name: String,
+    stability_runs: usize,
+    estimate_time: bool,
     phantom: PhantomData<(O, OT, S)>,
 }
 
@@ -78,7 +153,7 @@ where
     for<'de> <O as MapObserver>::Entry: Serialize + Deserialize<'de> + 'static,
     OT: ObserversTuple<E::State>,
     E::State: HasCorpus + HasMetadata + HasClientPerfMonitor + HasNamedMetadata,
-    Z: Evaluator<E, EM, State = E::State>,
+    Z: Evaluator<E, EM, State = E::State> + HasScheduler,
     ProgramInput: From<<<E as UsesState>::State as UsesInput>::Input>,
 {
     fn perform(
@@ -98,7 +173,7 @@ where
             }
         }
 
-        // We only ran our program once.
+        // We only ran our program once for the timing.
         let iter = 1;
 
         let input = state
@@ -108,24 +183,63 @@ where
             .load_input(state.corpus())?
             .clone();
 
-        executor.observers_mut().pre_exec_all(state, &input)?;
+        let mut attempts = 0;
+        let mut measured = None;
+        loop {
+            executor.observers_mut().pre_exec_all(state, &input)?;
+
+            let exit_kind = executor.run_target(fuzzer, state, mgr, &input)?;
 
-        let exit_kind = executor.run_target(fuzzer, state, mgr, &input)?;
-        if exit_kind != ExitKind::Ok {
+            executor
+                .observers_mut()
+                .post_exec_all(state, &input, &exit_kind)?;
+
+            attempts += 1;
+            if exit_kind == ExitKind::Ok {
+                measured = executor
+                    .observers()
+                    .match_name::<TimeObserver>(&self.time_observer_name)
+                    .and_then(|observer| *observer.last_runtime());
+                break;
+            }
             mgr.log(
                 state,
                 LogSeverity::Warn,
-                "Corpus entry errored on execution!".into(),
+                format!("Corpus entry errored on execution ({:?})!", exit_kind),
             )?;
-        };
+            if attempts == CALIBRATION_ATTEMPTS {
+                // Otherwise the scheduler keeps picking the entry and every
+                // mutant of it crashes the same way.
+                let removed = state.corpus_mut().remove(corpus_idx)?;
+                fuzzer
+                    .scheduler_mut()
+                    .on_remove(state, corpus_idx, &Some(removed))?;
+                mgr.log(
+                    state,
+                    LogSeverity::Warn,
+                    format!(
+                        "Evicted corpus entry {} after {} failed executions",
+                        corpus_idx, attempts
+                    ),
+                )?;
+                return Ok(());
+            }
+        }
+
+        if let Some(exec_time) = measured {
+            state
+                .corpus()
+                .get(corpus_idx)?
+                .borrow_mut()
+                .add_metadata(MeasuredTimeMetadata { exec_time });
+        }
 
-        executor
-            .observers_mut()
-            .post_exec_all(state, &input, &exit_kind)?;
+        if self.stability_runs > 1 {
+            self.measure_stability(fuzzer, executor, state, mgr, &input)?;
+        }
 
-        // Estimate duration based on number of instructions.
         let program: ProgramInput = input.into();
-        let total_time = Duration::from_secs((program.insts().len() + 1) as u64);
+        let total_time = schedule_time(measured, self.estimate_time, program.insts().len());
 
         // If weighted scheduler or powerscheduler is used, update it
         if state.has_metadata::<SchedulerMetadata>() {
@@ -193,14 +307,176 @@ where
     S: HasCorpus + HasMetadata + HasNamedMetadata,
 {
     #[must_use]
-    pub fn new<F>(map_feedback: &F) -> Self
+    pub fn new<F>(map_feedback: &F, time_observer: &TimeObserver) -> Self
     where
         F: HasObserverName + Named + UsesObserver<S, Observer = O>,
         for<'it> O: AsIter<'it, Item = O::Entry>,
     {
         Self {
             map_observer_name: map_feedback.observer_name().to_string(),
+            time_observer_name: time_observer.name().to_string(),
+            stability_runs: 1,
+            estimate_time: false,
             phantom: PhantomData,
         }
     }
+
+    /// Runs every new entry `runs` times to find unstable map entries, see
+    /// [`DummyCalibration`]. Zero or one run disables this.
+    #[must_use]
+    pub fn with_stability_runs(mut self, runs: usize) -> Self {
+        self.stability_runs = runs;
+        self
+    }
+
+    /// Gives the power schedule execution times estimated from the program
+    /// length instead of measured ones, see [`DummyCalibration`].
+    #[must_use]
+    pub fn with_estimated_time(mut self, estimate: bool) -> Self {
+        self.estimate_time = estimate;
+        self
+    }
+
+    /// Runs the input `stability_runs - 1` more times and records the map
+    /// entries that differ from the run that is still in the observer. Runs
+    /// that crash or time out are skipped, their maps stop early and would
+    /// make every later entry look unstable.
+    fn measure_stability<E, EM, Z>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        mgr: &mut EM,
+        input: &S::Input,
+    ) -> Result<(), Error>
+    where
+        E: Executor<EM, Z, State = S> + HasObservers<Observers = OT>,
+        EM: EventFirer<State = S>,
+        Z: UsesState<State = S>,
+    {
+        let map_of = |executor: &E| {
+            executor
+                .observers()
+                .match_name::<O>(&self.map_observer_name)
+                .map(|map| (map.to_vec(), map.usable_count(), map.initial()))
+                .ok_or_else(|| Error::key_not_found("MapObserver not found".to_string()))
+        };
+        let (first, map_len, initial) = map_of(executor)?;
+        let mut maps = vec![first];
+        for _ in 1..self.stability_runs {
+            executor.observers_mut().pre_exec_all(state, input)?;
+            let exit_kind = executor.run_target(fuzzer, state, mgr, input)?;
+            executor
+                .observers_mut()
+                .post_exec_all(state, input, &exit_kind)?;
+            if exit_kind == ExitKind::Ok {
+                maps.push(map_of(executor)?.0);
+            }
+        }
+        let filled: Vec<usize> = (0..map_len)
+            .filter(|idx| maps.iter().any(|map| map[*idx] != initial))
+            .collect();
+
+        if !state.has_metadata::<UnstableEntriesMetadata>() {
+            state.add_metadata(UnstableEntriesMetadata::new(HashSet::new(), map_len));
+        }
+        let meta = state
+            .metadata_map_mut()
+            .get_mut::<UnstableEntriesMetadata>()
+            .unwrap();
+        meta.record(unstable_entries(&maps), filled);
+        let (stable, filled) = (meta.stable_count(), meta.filled_entries().len());
+        mgr.fire(
+            state,
+            Event::UpdateUserStats {
+                name: STABILITY_STAT_NAME.to_string(),
+                value: UserStats::Ratio(stable as u64, filled as u64),
+                phantom: PhantomData,
+            },
+        )
+    }
+}
+
+/// The execution time the power schedule gets for an entry: the measured one,
+/// or one estimated from the number of instructions if estimating or if
+/// nothing was measured.
+fn schedule_time(measured: Option<Duration>, estimate: bool, insts: usize) -> Duration {
+    match measured {
+        Some(exec_time) if !estimate => exec_time,
+        _ => Duration::from_secs((insts + 1) as u64),
+    }
+}
+
+/// Runs the wrapped stage only if the corpus entry still exists, i.e. wasn't
+/// evicted by [`DummyCalibration`] before.
+#[derive(Clone, Debug)]
+pub struct SkipEvicted<ST> {
+    inner: ST,
+}
+
+impl<ST> SkipEvicted<ST> {
+    pub fn new(inner: ST) -> Self {
+        Self { inner }
+    }
+}
+
+impl<ST> UsesState for SkipEvicted<ST>
+where
+    ST: UsesState,
+{
+    type State = ST::State;
+}
+
+impl<E, EM, ST, Z> Stage<E, EM, Z> for SkipEvicted<ST>
+where
+    ST: Stage<E, EM, Z>,
+    E: UsesState<State = ST::State>,
+    EM: UsesState<State = ST::State>,
+    Z: UsesState<State = ST::State>,
+    ST::State: HasCorpus,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut ST::State,
+        mgr: &mut EM,
+        corpus_idx: CorpusId,
+    ) -> Result<(), Error> {
+        if state.corpus().get(corpus_idx).is_err() {
+            return Ok(());
+        }
+        self.inner.perform(fuzzer, executor, state, mgr, corpus_idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::{schedule_time, UnstableEntriesMetadata};
+
+    #[test]
+    fn estimated_time_only_without_measurement() {
+        let measured = Some(Duration::from_micros(250));
+        assert_eq!(
+            schedule_time(measured, false, 9),
+            Duration::from_micros(250)
+        );
+        // Without a measurement, the estimate is the fallback.
+        assert_eq!(schedule_time(None, false, 9), Duration::from_secs(10));
+        assert_eq!(schedule_time(measured, true, 9), Duration::from_secs(10));
+        assert_eq!(schedule_time(None, true, 0), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn entries_are_capped_at_the_map_len() {
+        let mut meta = UnstableEntriesMetadata::new(Default::default(), 4);
+        assert_eq!(meta.stability(), None);
+        meta.record([1, 7], [0, 1, 2, 9]);
+        assert_eq!(meta.filled_entries().len(), 3);
+        assert_eq!(meta.unstable_entries().len(), 1);
+        assert_eq!(meta.stable_count(), 2);
+        assert_eq!(meta.stability(), Some(2.0 / 3.0));
+    }
 }
@@ -1,14 +1,273 @@
+use core::fmt::Debug;
 use std::{
     collections::HashSet,
     fs::File,
     io::{BufRead, BufReader, Write},
-    path::Path,
+    path::{Path, PathBuf},
     process::Command,
     time::{Duration, UNIX_EPOCH},
 };
 
+use libafl::{
+    bolts::tuples::{MatchName, Named},
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::UsesInput,
+    observers::ObserversTuple,
+    prelude::current_time,
+    state::HasClientPerfMonitor,
+    Error,
+};
+use nix::sys::signal::Signal;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    disasm::inst_to_asm,
+    observers::{ExitStatusObserver, EXIT_STATUS_OBSERVER_NAME},
+    program_input::ProgramInput,
+};
+
 pub const FUZZING_CAUSE_DIR_VAR: &'static str = "FUZZING_CAUSE_DIR";
 pub const FUZZING_EXPECTED_LIST_VAR: &'static str = "FUZZING_EXPECTED_LIST";
+/// The file the harness creates right before an intended crash. See
+/// `markFuzzingIssue` in FuzzerAPI.h.
+pub const FUZZING_ISSUE_MARKER_VAR: &'static str = "FUZZING_ISSUE_MARKER";
+/// The file the harness writes its exit code to. See `exitFuzzing` in
+/// FuzzerAPI.h.
+pub const FUZZING_EXIT_CODE_FILE_VAR: &'static str = "FUZZING_EXIT_CODE_FILE";
+
+/// The extension of the [`CauseRecord`] written next to a cause file.
+pub const CAUSE_RECORD_EXTENSION: &str = "json";
+
+/// How the causes the harness reports are recorded, see `--causes-format`.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CausesFormat {
+    /// Only the files the harness writes.
+    #[default]
+    Text,
+    /// Also a [`CauseRecord`] for every cause file, see [`CauseFeedback`].
+    Json,
+}
+
+impl CausesFormat {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim() {
+            "text" => Ok(CausesFormat::Text),
+            "json" => Ok(CausesFormat::Json),
+            _ => Err(format!(
+                "Unknown causes format '{}'. Supported: text, json",
+                s
+            )),
+        }
+    }
+}
+
+/// A cause in the JSON format, written to `<cause file>.json`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CauseRecord {
+    /// The cause as listed by [`list_causes`].
+    pub cause: String,
+    /// The file name of the saved crash in the 'found' directory.
+    pub solution: Option<String>,
+    /// The triggering program, one instruction per entry (see
+    /// [`crate::disasm`]).
+    pub disassembly: Vec<String>,
+    pub exit_kind: String,
+    /// The signal the target exited with, see [`exit_signal`]. `None` if
+    /// the target wasn't signaled or the forkserver reported no status.
+    pub signal: Option<String>,
+    pub unix_time: u64,
+}
+
+/// The cause reported in a cause file of the harness, which is named
+/// `<cause>%<anything>` with underscores for spaces.
+pub fn cause_name(file_name: &str) -> String {
+    file_name
+        .split('%')
+        .next()
+        .unwrap_or("Bad cause name")
+        .replace('_', " ")
+}
+
+/// The signal a target exited with: the one in the exit status the
+/// forkserver reported (see [`ExitStatusObserver`]) or, for timeouts without
+/// a status, the signal the executor kills timed out targets with.
+pub fn exit_signal(
+    exit_kind: ExitKind,
+    status_signal: Option<Signal>,
+    timeout_signal: Signal,
+) -> Option<Signal> {
+    match exit_kind {
+        ExitKind::Ok => None,
+        ExitKind::Timeout => status_signal.or(Some(timeout_signal)),
+        _ => status_signal,
+    }
+}
+
+/// The cause directory of a client. Every client gets its own so the cause
+/// files its target writes can't be attributed to runs of another client.
+pub fn client_cause_dir(cause_dir: &Path, client: usize) -> PathBuf {
+    cause_dir.join(format!("{}", client))
+}
+
+fn is_cause_record(file_name: &str) -> bool {
+    Path::new(file_name)
+        .extension()
+        .map_or(false, |ext| ext == CAUSE_RECORD_EXTENSION)
+}
+
+/// Writes a [`CauseRecord`] next to the cause files the harness writes while
+/// running a saved crash.
+///
+/// The cause directory belongs to this client (see [`client_cause_dir`]) and
+/// only crashing runs write cause files, so new cause files belong to the
+/// crash this client just finished. Cause files of crashes that aren't saved
+/// (e.g. known ones) are skipped. Like
+/// [`crate::bundle::CrashBundleFeedback`] this never considers anything
+/// interesting and goes after the objectives in `feedback_or!`.
+#[derive(Debug)]
+pub struct CauseFeedback {
+    format: CausesFormat,
+    cause_dir: PathBuf,
+    /// The signal a timed out target is killed with.
+    timeout_signal: Signal,
+    /// Cause files that are recorded or were skipped.
+    seen: HashSet<String>,
+    /// The exit kind of the last run and the signal it exited with.
+    last_exit: Option<(ExitKind, Option<Signal>)>,
+}
+
+impl CauseFeedback {
+    /// Cause files that are already in the directory (e.g. of a previous
+    /// run) never get a record.
+    pub fn new(format: CausesFormat, cause_dir: PathBuf, timeout_signal: Signal) -> Self {
+        let mut result = Self {
+            format,
+            cause_dir,
+            timeout_signal,
+            seen: HashSet::new(),
+            last_exit: None,
+        };
+        if format == CausesFormat::Json {
+            result.new_cause_files();
+        }
+        result
+    }
+
+    /// The cause files without a record that weren't seen before. They are
+    /// seen afterwards.
+    fn new_cause_files(&mut self) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(&self.cause_dir) else {
+            return vec![];
+        };
+        let mut result = vec![];
+        for entry in entries.flatten() {
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if is_cause_record(&name) || self.seen.contains(&name) {
+                continue;
+            }
+            self.seen.insert(name.clone());
+            // Recorded by a previous run.
+            if !self.record_path(&name).exists() {
+                result.push(name);
+            }
+        }
+        result.sort();
+        result
+    }
+
+    fn record_path(&self, cause_file: &str) -> PathBuf {
+        self.cause_dir
+            .join(format!("{}.{}", cause_file, CAUSE_RECORD_EXTENSION))
+    }
+
+    fn write_record(&self, cause_file: &str, record: &CauseRecord) -> Result<(), Error> {
+        let json =
+            serde_json::to_string_pretty(record).map_err(|e| Error::serialize(e.to_string()))?;
+        std::fs::write(self.record_path(cause_file), json)?;
+        Ok(())
+    }
+}
+
+impl Named for CauseFeedback {
+    fn name(&self) -> &str {
+        "CauseFeedback"
+    }
+}
+
+impl<S> Feedback<S> for CauseFeedback
+where
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &ProgramInput,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        if self.format == CausesFormat::Json {
+            let status_signal = observers
+                .match_name::<ExitStatusObserver>(EXIT_STATUS_OBSERVER_NAME)
+                .and_then(ExitStatusObserver::signal);
+            let signal = exit_signal(*exit_kind, status_signal, self.timeout_signal);
+            self.last_exit = Some((*exit_kind, signal));
+        }
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        let Some((exit_kind, signal)) = self.last_exit.take() else {
+            return Ok(());
+        };
+        let Some(input) = testcase.input() else {
+            return Ok(());
+        };
+        for cause_file in self.new_cause_files() {
+            let record = CauseRecord {
+                cause: cause_name(&cause_file),
+                solution: testcase.filename().clone(),
+                disassembly: input.insts().iter().map(inst_to_asm).collect(),
+                exit_kind: format!("{:?}", exit_kind),
+                signal: signal.map(|signal| signal.as_str().to_owned()),
+                unix_time: current_time().as_secs(),
+            };
+            if let Err(err) = self.write_record(&cause_file, &record) {
+                log::error!(
+                    "Failed to write the record of cause {}: {}",
+                    cause_file,
+                    err
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &ProgramInput) -> Result<(), Error> {
+        // Only crashes make the harness write a cause.
+        if self
+            .last_exit
+            .take()
+            .map_or(false, |(kind, _)| kind != ExitKind::Ok)
+        {
+            self.new_cause_files();
+        }
+        Ok(())
+    }
+}
 
 pub struct TestCaseData {
     pub cause: String,
@@ -38,28 +297,40 @@ pub struct CausesList {
     pub still_missing: Vec<String>,
 }
 
+/// The cause files in the cause directory and the client directories in it
+/// (see [`client_cause_dir`]).
+fn cause_entries(cause_dir: &Path) -> Vec<std::fs::DirEntry> {
+    let mut result = vec![];
+    let causes = std::fs::read_dir(cause_dir).expect("Failed to read causes dir");
+    for cause_or_err in causes {
+        let cause = cause_or_err.unwrap();
+        if !cause.file_type().unwrap().is_dir() {
+            result.push(cause);
+            continue;
+        }
+        let client_causes = std::fs::read_dir(cause.path()).expect("Failed to read causes dir");
+        result.extend(client_causes.map(|cause| cause.unwrap()));
+    }
+    result
+}
+
 pub fn list_causes(start_time: std::time::Duration) -> CausesList {
     let cause_dir =
         std::env::var(FUZZING_CAUSE_DIR_VAR).expect("Driver failed to set cause env var?");
 
-    let causes = std::fs::read_dir(Path::new(&cause_dir)).expect("Failed to read causes dir");
-
     let mut expected = get_expected();
 
     let mut case_list = Vec::<TestCaseData>::new();
-    for cause_or_err in causes {
-        let cause = cause_or_err.unwrap();
+    for cause in cause_entries(Path::new(&cause_dir)) {
         let creation_time = cause.metadata().unwrap().created().unwrap();
         let creation_unix_time = creation_time.duration_since(UNIX_EPOCH).unwrap();
         let diff_time = creation_unix_time - start_time;
 
         let filename = cause.file_name().into_string().unwrap();
-        let cause_str = filename
-            .split("%")
-            .nth(0)
-            .or(Some("Bad cause name"))
-            .unwrap();
-        let display_str = cause_str.replace("_", " ");
+        if is_cause_record(&filename) {
+            continue;
+        }
+        let display_str = cause_name(&filename);
 
         expected.remove(&display_str);
 
@@ -104,3 +375,93 @@ pub fn list_causes(start_time: std::time::Duration) -> CausesList {
         still_missing: missing,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use libafl::executors::ExitKind;
+    use nix::sys::signal::Signal;
+
+    use super::{
+        cause_entries, cause_name, client_cause_dir, exit_signal, CauseFeedback, CauseRecord,
+        CausesFormat,
+    };
+
+    #[test]
+    fn parse_causes_format() {
+        assert_eq!(CausesFormat::parse("text"), Ok(CausesFormat::Text));
+        assert_eq!(CausesFormat::parse("json"), Ok(CausesFormat::Json));
+        assert!(CausesFormat::parse("yaml").is_err());
+        assert_eq!(cause_name("Wrong_result%1234"), "Wrong result");
+    }
+
+    #[test]
+    fn exit_signals() {
+        assert_eq!(exit_signal(ExitKind::Ok, None, Signal::SIGKILL), None);
+        assert_eq!(
+            exit_signal(ExitKind::Crash, Some(Signal::SIGSEGV), Signal::SIGKILL),
+            Some(Signal::SIGSEGV)
+        );
+        // Without a status, the signal of a crash is unknown.
+        assert_eq!(exit_signal(ExitKind::Crash, None, Signal::SIGKILL), None);
+        assert_eq!(
+            exit_signal(ExitKind::Timeout, None, Signal::SIGUSR2),
+            Some(Signal::SIGUSR2)
+        );
+    }
+
+    #[test]
+    fn client_cause_dirs_are_listed() {
+        let dir = std::env::temp_dir().join(format!("client-causes-test-{}", std::process::id()));
+        std::fs::create_dir_all(client_cause_dir(&dir, 0)).unwrap();
+        std::fs::create_dir_all(client_cause_dir(&dir, 1)).unwrap();
+        std::fs::write(dir.join("Manual_cause%1"), "").unwrap();
+        std::fs::write(client_cause_dir(&dir, 1).join("Client_cause%2"), "").unwrap();
+
+        let mut names: Vec<_> = cause_entries(&dir)
+            .iter()
+            .map(|entry| entry.file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["Client_cause%2".to_owned(), "Manual_cause%1".to_owned()]
+        );
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn cause_records() {
+        let dir = std::env::temp_dir().join(format!("causes-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Old_cause%1"), "").unwrap();
+
+        let mut feedback = CauseFeedback::new(CausesFormat::Json, dir.clone(), Signal::SIGKILL);
+        assert!(feedback.new_cause_files().is_empty());
+
+        std::fs::write(dir.join("New_cause%2"), "").unwrap();
+        assert_eq!(feedback.new_cause_files(), vec!["New_cause%2".to_owned()]);
+        assert!(feedback.new_cause_files().is_empty());
+
+        let record = CauseRecord {
+            cause: cause_name("New_cause%2"),
+            solution: Some("crash_1".to_owned()),
+            disassembly: vec!["add x2, x0, x0".to_owned()],
+            exit_kind: "Crash".to_owned(),
+            signal: None,
+            unix_time: 1,
+        };
+        feedback.write_record("New_cause%2", &record).unwrap();
+        let json = std::fs::read_to_string(dir.join("New_cause%2.json")).unwrap();
+        assert_eq!(serde_json::from_str::<CauseRecord>(&json).unwrap(), record);
+
+        // Records aren't causes, and causes recorded by a previous run are
+        // skipped. In the text format, nothing is seen up front.
+        std::fs::write(dir.join("Other_cause%3"), "").unwrap();
+        std::fs::write(dir.join("Other_cause%3.json"), "{}").unwrap();
+        let mut other = CauseFeedback::new(CausesFormat::Text, dir.clone(), Signal::SIGKILL);
+        assert_eq!(other.new_cause_files(), vec!["Old_cause%1".to_owned()]);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
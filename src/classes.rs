@@ -0,0 +1,113 @@
+//! Structural equivalence classes of programs. Two programs are in the same
+//! class if they consist of the same multiset of instruction categories,
+//! regardless of their order or operands.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::instructions::{Instruction, InstructionCategory};
+
+/// Name of the user stat with the most populated classes.
+pub const CLASSES_STAT_NAME: &str = "classes";
+
+/// How many classes the monitor summary lists.
+const SUMMARY_CLASSES: usize = 3;
+
+/// The class of the program, e.g. `arith2-mem1` for two arithmetic and one
+/// memory instruction. Categories are listed in the order of
+/// [`InstructionCategory::ALL`], unknown instructions last.
+pub fn program_class(insts: &[Instruction]) -> String {
+    let mut counts = BTreeMap::<InstructionCategory, usize>::new();
+    for inst in insts {
+        *counts.entry(inst.template().category()).or_insert(0) += 1;
+    }
+    if counts.is_empty() {
+        return "empty".to_owned();
+    }
+    InstructionCategory::ALL
+        .iter()
+        .chain([InstructionCategory::Unknown].iter())
+        .filter_map(|category| {
+            counts
+                .get(category)
+                .map(|count| format!("{}{}", category.name(), count))
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+libafl::impl_serdeany!(ProgramClassMetadata);
+/// Number of corpus entries per program class, kept in the state.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProgramClassMetadata {
+    counts: BTreeMap<String, u64>,
+}
+
+impl ProgramClassMetadata {
+    pub fn add(&mut self, class: String) {
+        *self.counts.entry(class).or_insert(0) += 1;
+    }
+
+    pub fn remove(&mut self, class: &str) {
+        if let Some(count) = self.counts.get_mut(class) {
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(class);
+            }
+        }
+    }
+
+    /// How many corpus entries are in the given class.
+    pub fn count(&self, class: &str) -> u64 {
+        self.counts.get(class).copied().unwrap_or(0)
+    }
+
+    /// The most populated classes for the monitor, e.g.
+    /// `12 classes, top: arith3 (40), arith2-mem1 (12)`.
+    pub fn summary(&self) -> String {
+        let mut classes: Vec<_> = self.counts.iter().collect();
+        // Most entries first, ties by name to keep the summary stable.
+        classes.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let top = classes
+            .iter()
+            .take(SUMMARY_CLASSES)
+            .map(|(class, count)| format!("{} ({})", class, count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{} classes, top: {}", self.counts.len(), top)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{program_class, ProgramClassMetadata};
+    use crate::instructions::{
+        riscv::rv_i::{ADD, BEQ, LW},
+        test_insts::inst,
+    };
+
+    #[test]
+    fn classes_of_programs() {
+        assert_eq!(program_class(&[]), "empty");
+        let program = [inst(&LW), inst(&ADD), inst(&LW)];
+        assert_eq!(program_class(&program), "arith1-mem2");
+        // The order doesn't matter, only the categories.
+        let reordered = [inst(&LW), inst(&LW), inst(&ADD)];
+        assert_eq!(program_class(&program), program_class(&reordered));
+        assert_ne!(program_class(&program), program_class(&program[..2]));
+        assert_eq!(program_class(&[inst(&BEQ)]), "branch1");
+
+        let mut classes = ProgramClassMetadata::default();
+        classes.add(program_class(&program));
+        classes.add(program_class(&reordered));
+        classes.add(program_class(&[inst(&BEQ)]));
+        assert_eq!(classes.count("arith1-mem2"), 2);
+        assert_eq!(
+            classes.summary(),
+            "2 classes, top: arith1-mem2 (2), branch1 (1)"
+        );
+        classes.remove("branch1");
+        assert_eq!(classes.count("branch1"), 0);
+        assert_eq!(classes.summary(), "1 classes, top: arith1-mem2 (2)");
+    }
+}
@@ -0,0 +1,651 @@
+//! The settings of the instruction generator and the mutators. They are
+//! passed explicitly as a [`DriverConfig`], which the fuzzer fills from its
+//! command line and other tools from the `PHANTOM_TRAILS_*` env vars.
+use std::env;
+
+use crate::{
+    generator::{
+        parse_arg_ranges, parse_categories, parse_category_weights, parse_recent_regs,
+        parse_template_weights, without_categories, DataflowRamp, InstGenerator, ScratchRegion,
+        MEM_BASE_SPAN,
+    },
+    instructions::{sets, ArgumentSpec, InstructionCategory, InstructionTemplate},
+};
+
+/// Env var with value ranges for arguments, e.g. `imm12=0-64,rd=1-7`.
+pub const ARG_RANGES_VAR: &str = "PHANTOM_TRAILS_ARG_RANGES";
+
+/// Env var with the name of the instruction set new instructions are
+/// generated from (see [`sets::NAMES`]). Defaults to `rv64g`.
+pub const ISA_VAR: &str = "PHANTOM_TRAILS_ISA";
+
+/// Env var with a comma separated list of instruction categories (see
+/// [`InstructionCategory::name`]) that are removed from the instruction set.
+pub const EXCLUDED_CATEGORIES_VAR: &str = "PHANTOM_TRAILS_EXCLUDED_CATEGORIES";
+
+/// Env var that adds the privileged instructions of [`sets::riscv_system`]
+/// to the instruction set if set.
+pub const PRIVILEGED_VAR: &str = "PHANTOM_TRAILS_PRIVILEGED";
+
+/// Env var that adds the CSR instructions of [`sets::riscv_zicsr`] to the
+/// instruction set if set.
+pub const CSR_VAR: &str = "PHANTOM_TRAILS_CSR";
+
+/// Env var that makes the generator avoid reserved encodings if set.
+pub const AVOID_RESERVED_VAR: &str = "PHANTOM_TRAILS_AVOID_RESERVED";
+
+/// Env var that makes the generator start programs with a register fill
+/// prologue if set (see
+/// [`crate::generator::InstGenerator::generate_register_fill`]).
+pub const REGISTER_FILL_VAR: &str = "PHANTOM_TRAILS_REGISTER_FILL";
+
+/// Env var that tells the generator that the core implements the C
+/// extension if set, so branch and jump targets only have to be aligned to
+/// [`crate::instructions::COMPRESSED_ALIGNMENT`].
+pub const COMPRESSED_VAR: &str = "PHANTOM_TRAILS_COMPRESSED";
+
+/// Env var in the `K:CHANCE` format that makes the generator remember the
+/// last `K` destination registers and use one of them for a source register
+/// with a chance of `CHANCE` percent (see [`parse_recent_regs`]).
+pub const RECENT_REGS_VAR: &str = "PHANTOM_TRAILS_RECENT_REGS";
+
+/// Env var with an instruction category (see [`InstructionCategory::name`])
+/// that new instructions are taken from with a chance of
+/// [`crate::generator::EMPHASIS_CHANCE`] percent, e.g. to give every shard
+/// of a campaign a different focus.
+pub const EMPHASIS_VAR: &str = "PHANTOM_TRAILS_EMPHASIS";
+
+/// Env var with the memory region generated loads and stores access, in the
+/// `BASE+SIZE` format (see [`ScratchRegion`]).
+pub const SCRATCH_REGION_VAR: &str = "PHANTOM_TRAILS_SCRATCH_REGION";
+
+/// Env var with the chance (0-100) of generating a store followed by a load
+/// from the same address instead of a single instruction (see
+/// [`crate::generator::InstGenerator::generate_memory_pair`]).
+pub const PAIRED_MEM_CHANCE_VAR: &str = "PHANTOM_TRAILS_PAIRED_MEM_CHANCE";
+
+/// Env var with the chance (0-100) of using an immediate of a saved crash for
+/// a matching argument (see [`crate::immediates`]).
+pub const CRASH_CHANCE_VAR: &str = "PHANTOM_TRAILS_CRASH_CHANCE";
+
+/// Env var that makes generated programs point a base register into the
+/// scratch region for their loads and stores if set (see
+/// [`crate::generator::InstGenerator::set_mem_base`]).
+pub const MEM_BASE_VAR: &str = "PHANTOM_TRAILS_MEM_BASE";
+
+/// Env var with weights of instruction templates relative to
+/// [`crate::generator::DEFAULT_WEIGHT`] for choosing one to generate, e.g.
+/// `addi=1000,ecall=0` (see [`parse_template_weights`]).
+pub const TEMPLATE_WEIGHTS_VAR: &str = "PHANTOM_TRAILS_TEMPLATE_WEIGHTS";
+
+/// Env var with weights of all templates of instruction categories, e.g.
+/// `mem=300,float=0`. Template weights take precedence.
+pub const CATEGORY_WEIGHTS_VAR: &str = "PHANTOM_TRAILS_CATEGORY_WEIGHTS";
+
+/// Env var that stops the generator from reusing the argument values of the
+/// program and from preferring powers of two if set.
+pub const NO_ARG_REUSE_VAR: &str = "PHANTOM_TRAILS_NO_ARG_REUSE";
+
+/// Env var with the chance (0-100) of reusing an argument value of the
+/// program (see [`DriverConfig::reuse_chance`]).
+pub const REUSE_CHANCE_VAR: &str = "PHANTOM_TRAILS_REUSE_CHANCE";
+
+/// Env var with the chance (0-100) of choosing a power of two (see
+/// [`DriverConfig::pow2_chance`]).
+pub const POW2_CHANCE_VAR: &str = "PHANTOM_TRAILS_POW2_CHANCE";
+
+/// Env var that stops the generator from reading registers written earlier
+/// in a generated sequence more often if set.
+pub const NO_DATAFLOW_VAR: &str = "PHANTOM_TRAILS_NO_DATAFLOW";
+
+/// Env var in the `START:MAX:SECS` format that raises the chance of reading
+/// those registers from `START` to `MAX` percent over the first `SECS`
+/// seconds of a campaign (see [`DataflowRamp`]).
+pub const DATAFLOW_RAMP_VAR: &str = "PHANTOM_TRAILS_DATAFLOW_RAMP";
+
+/// Env var that stops the mutator from inserting call/return snippets if
+/// set.
+pub const NO_SNIPPET_VAR: &str = "PHANTOM_TRAILS_NO_SNIPPET";
+
+/// Env var with the most instructions a generated or mutated program may
+/// have, without the register fill prologue.
+pub const MAX_PROGRAM_LEN_VAR: &str = "PHANTOM_TRAILS_MAX_PROGRAM_LEN";
+
+/// The name of the default instruction set.
+const DEFAULT_ISA: &str = "rv64g";
+
+/// The default of [`DriverConfig::crash_chance`].
+pub const DEFAULT_CRASH_CHANCE: u64 = 50;
+
+/// The default of [`DriverConfig::max_program_len`].
+pub const DEFAULT_MAX_PROGRAM_LEN: usize = 1024;
+
+/// How programs are generated and mutated. The default generates from
+/// RV64G with all mutations and no restrictions.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DriverConfig {
+    /// One of [`sets::NAMES`].
+    pub isa: String,
+    /// Also generate the privileged instructions of [`sets::riscv_system`].
+    pub privileged: bool,
+    /// Also generate the CSR instructions of [`sets::riscv_zicsr`].
+    pub csr: bool,
+    pub excluded_categories: Vec<InstructionCategory>,
+    /// Reuse the argument values of the program and prefer powers of two.
+    pub arg_reuse: bool,
+    /// The chance (0-100) of reusing an argument value of the program, if
+    /// it differs from the default of [`DriverConfig::arg_reuse`].
+    pub reuse_chance: Option<u64>,
+    /// The chance (0-100) of choosing a power of two, if it differs from the
+    /// default of [`DriverConfig::arg_reuse`].
+    pub pow2_chance: Option<u64>,
+    /// Read registers written earlier in a generated sequence more often.
+    pub dataflow: bool,
+    /// Raise the chance of reading them over time instead of keeping it at
+    /// 50.
+    pub dataflow_ramp: Option<DataflowRamp>,
+    /// Let the mutator insert call/return snippets.
+    pub snippets: bool,
+    pub avoid_reserved: bool,
+    pub register_fill: bool,
+    pub scratch: Option<ScratchRegion>,
+    /// The chance (0-100) of generating a store/load pair sharing an
+    /// address instead of a single instruction.
+    pub paired_mem_chance: u64,
+    /// The chance (0-100) of using an immediate of a saved crash for a
+    /// matching argument, once there are some.
+    pub crash_chance: u64,
+    /// Point the base register of loads and stores into the scratch region,
+    /// which needs at least [`MEM_BASE_SPAN`] bytes.
+    pub mem_base: bool,
+    pub compressed: bool,
+    /// The number of remembered destination registers and the chance
+    /// (0-100) of reading one of them.
+    pub recent_regs: Option<(usize, u64)>,
+    pub arg_ranges: Vec<(&'static ArgumentSpec, u32, u32)>,
+    pub emphasis: Option<InstructionCategory>,
+    /// The weights of templates and categories for choosing a template to
+    /// generate, see [`InstGenerator::weight`].
+    pub template_weights: Vec<(&'static InstructionTemplate, u64)>,
+    pub category_weights: Vec<(InstructionCategory, u64)>,
+    /// The most instructions of a generated or mutated program, without the
+    /// register fill prologue. Mutations drop the instructions past it.
+    pub max_program_len: usize,
+}
+
+impl Default for DriverConfig {
+    fn default() -> Self {
+        Self {
+            isa: DEFAULT_ISA.to_owned(),
+            privileged: false,
+            csr: false,
+            excluded_categories: vec![],
+            arg_reuse: true,
+            reuse_chance: None,
+            pow2_chance: None,
+            dataflow: true,
+            dataflow_ramp: None,
+            snippets: true,
+            avoid_reserved: false,
+            register_fill: false,
+            scratch: None,
+            paired_mem_chance: 0,
+            crash_chance: DEFAULT_CRASH_CHANCE,
+            mem_base: false,
+            compressed: false,
+            recent_regs: None,
+            arg_ranges: vec![],
+            emphasis: None,
+            template_weights: vec![],
+            category_weights: vec![],
+            max_program_len: DEFAULT_MAX_PROGRAM_LEN,
+        }
+    }
+}
+
+impl DriverConfig {
+    /// Reads the config from the `PHANTOM_TRAILS_*` env vars. Unset vars
+    /// keep their default.
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    /// Like [`DriverConfig::from_env`], with the variables looked up by `var`.
+    pub fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Result<Self, String> {
+        let invalid = |name: &str, err: String| format!("Invalid {}: {}", name, err);
+        let mut config = Self::default();
+        if let Some(isa) = var(ISA_VAR) {
+            config.isa = isa;
+        }
+        config.privileged = var(PRIVILEGED_VAR).is_some();
+        config.csr = var(CSR_VAR).is_some();
+        if let Some(list) = var(EXCLUDED_CATEGORIES_VAR) {
+            config.excluded_categories =
+                parse_categories(&list).map_err(|err| invalid(EXCLUDED_CATEGORIES_VAR, err))?;
+        }
+        config.arg_reuse = var(NO_ARG_REUSE_VAR).is_none();
+        for (name, chance) in [
+            (REUSE_CHANCE_VAR, &mut config.reuse_chance),
+            (POW2_CHANCE_VAR, &mut config.pow2_chance),
+        ] {
+            if let Some(value) = var(name) {
+                *chance = Some(
+                    value
+                        .trim()
+                        .parse()
+                        .map_err(|_| invalid(name, format!("not a number: '{}'", value)))?,
+                );
+            }
+        }
+        config.dataflow = var(NO_DATAFLOW_VAR).is_none();
+        if let Some(ramp) = var(DATAFLOW_RAMP_VAR) {
+            config.dataflow_ramp =
+                Some(DataflowRamp::parse(&ramp).map_err(|err| invalid(DATAFLOW_RAMP_VAR, err))?);
+        }
+        config.snippets = var(NO_SNIPPET_VAR).is_none();
+        config.avoid_reserved = var(AVOID_RESERVED_VAR).is_some();
+        config.register_fill = var(REGISTER_FILL_VAR).is_some();
+        if let Some(region) = var(SCRATCH_REGION_VAR) {
+            config.scratch = Some(
+                ScratchRegion::parse(&region).map_err(|err| invalid(SCRATCH_REGION_VAR, err))?,
+            );
+        }
+        if let Some(chance) = var(PAIRED_MEM_CHANCE_VAR) {
+            config.paired_mem_chance = chance.trim().parse().map_err(|_| {
+                invalid(PAIRED_MEM_CHANCE_VAR, format!("not a number: '{}'", chance))
+            })?;
+        }
+        if let Some(chance) = var(CRASH_CHANCE_VAR) {
+            config.crash_chance = chance
+                .trim()
+                .parse()
+                .map_err(|_| invalid(CRASH_CHANCE_VAR, format!("not a number: '{}'", chance)))?;
+        }
+        config.mem_base = var(MEM_BASE_VAR).is_some();
+        config.compressed = var(COMPRESSED_VAR).is_some();
+        if let Some(recent_regs) = var(RECENT_REGS_VAR) {
+            config.recent_regs =
+                Some(parse_recent_regs(&recent_regs).map_err(|err| invalid(RECENT_REGS_VAR, err))?);
+        }
+        if let Some(ranges) = var(ARG_RANGES_VAR) {
+            config.arg_ranges =
+                parse_arg_ranges(&ranges).map_err(|err| invalid(ARG_RANGES_VAR, err))?;
+        }
+        if let Some(name) = var(EMPHASIS_VAR) {
+            config.emphasis =
+                Some(InstructionCategory::from_name(&name).ok_or_else(|| {
+                    invalid(EMPHASIS_VAR, format!("unknown category '{}'", name))
+                })?);
+        }
+        if let Some(list) = var(TEMPLATE_WEIGHTS_VAR) {
+            config.template_weights =
+                parse_template_weights(&list).map_err(|err| invalid(TEMPLATE_WEIGHTS_VAR, err))?;
+        }
+        if let Some(list) = var(CATEGORY_WEIGHTS_VAR) {
+            config.category_weights =
+                parse_category_weights(&list).map_err(|err| invalid(CATEGORY_WEIGHTS_VAR, err))?;
+        }
+        if let Some(len) = var(MAX_PROGRAM_LEN_VAR) {
+            config.max_program_len = len
+                .trim()
+                .parse()
+                .map_err(|_| invalid(MAX_PROGRAM_LEN_VAR, format!("not a number: '{}'", len)))?;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The env vars that [`DriverConfig::from_env`] reads this config from.
+    pub fn to_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = vec![(ISA_VAR, self.isa.clone())];
+        let flags = [
+            (PRIVILEGED_VAR, self.privileged),
+            (CSR_VAR, self.csr),
+            (NO_ARG_REUSE_VAR, !self.arg_reuse),
+            (NO_DATAFLOW_VAR, !self.dataflow),
+            (NO_SNIPPET_VAR, !self.snippets),
+            (AVOID_RESERVED_VAR, self.avoid_reserved),
+            (REGISTER_FILL_VAR, self.register_fill),
+            (MEM_BASE_VAR, self.mem_base),
+            (COMPRESSED_VAR, self.compressed),
+        ];
+        vars.extend(
+            flags
+                .into_iter()
+                .filter(|(_, set)| *set)
+                .map(|(name, _)| (name, "1".to_owned())),
+        );
+        if !self.excluded_categories.is_empty() {
+            let names: Vec<_> = self.excluded_categories.iter().map(|c| c.name()).collect();
+            vars.push((EXCLUDED_CATEGORIES_VAR, names.join(",")));
+        }
+        if let Some(region) = &self.scratch {
+            vars.push((SCRATCH_REGION_VAR, region.to_string()));
+        }
+        if let Some(ramp) = &self.dataflow_ramp {
+            vars.push((DATAFLOW_RAMP_VAR, ramp.to_string()));
+        }
+        if let Some(chance) = self.reuse_chance {
+            vars.push((REUSE_CHANCE_VAR, chance.to_string()));
+        }
+        if let Some(chance) = self.pow2_chance {
+            vars.push((POW2_CHANCE_VAR, chance.to_string()));
+        }
+        if self.paired_mem_chance != 0 {
+            vars.push((PAIRED_MEM_CHANCE_VAR, self.paired_mem_chance.to_string()));
+        }
+        if self.crash_chance != DEFAULT_CRASH_CHANCE {
+            vars.push((CRASH_CHANCE_VAR, self.crash_chance.to_string()));
+        }
+        if let Some((len, chance)) = self.recent_regs {
+            vars.push((RECENT_REGS_VAR, format!("{}:{}", len, chance)));
+        }
+        if !self.arg_ranges.is_empty() {
+            let ranges: Vec<_> = self
+                .arg_ranges
+                .iter()
+                .map(|(spec, min, max)| format!("{}={}-{}", spec.name(), min, max))
+                .collect();
+            vars.push((ARG_RANGES_VAR, ranges.join(",")));
+        }
+        if let Some(category) = self.emphasis {
+            vars.push((EMPHASIS_VAR, category.name().to_owned()));
+        }
+        if !self.template_weights.is_empty() {
+            // All templates with a name share its weight.
+            let mut weights: Vec<String> = vec![];
+            for (template, weight) in &self.template_weights {
+                let weight = format!("{}={}", template.name(), weight);
+                if !weights.contains(&weight) {
+                    weights.push(weight);
+                }
+            }
+            vars.push((TEMPLATE_WEIGHTS_VAR, weights.join(",")));
+        }
+        if !self.category_weights.is_empty() {
+            let weights: Vec<_> = self
+                .category_weights
+                .iter()
+                .map(|(category, weight)| format!("{}={}", category.name(), weight))
+                .collect();
+            vars.push((CATEGORY_WEIGHTS_VAR, weights.join(",")));
+        }
+        if self.max_program_len != DEFAULT_MAX_PROGRAM_LEN {
+            vars.push((MAX_PROGRAM_LEN_VAR, self.max_program_len.to_string()));
+        }
+        vars
+    }
+
+    /// Checks that the instruction set exists and that there are
+    /// instructions left after removing the excluded categories.
+    pub fn validate(&self) -> Result<(), String> {
+        if sets::by_name(&self.isa).is_none() {
+            return Err(format!(
+                "Unknown instruction set {:?}. Supported: {:?}",
+                self.isa,
+                sets::NAMES
+            ));
+        }
+        if self.max_program_len == 0 {
+            return Err("The maximum program length must not be zero".to_owned());
+        }
+        if let Some((_, chance)) = self.recent_regs {
+            if chance > 100 {
+                return Err(format!(
+                    "Invalid recent register chance {}, must be 0-100",
+                    chance
+                ));
+            }
+        }
+        for (name, chance) in [
+            ("reuse", self.reuse_chance),
+            ("power of two", self.pow2_chance),
+        ] {
+            match chance {
+                Some(chance) if chance > 100 => {
+                    return Err(format!("Invalid {} chance {}, must be 0-100", name, chance));
+                }
+                _ => {}
+            }
+        }
+        if let Some(ramp) = self.dataflow_ramp {
+            if !self.dataflow {
+                return Err("A dataflow ramp needs the dataflow bias".to_owned());
+            }
+            DataflowRamp::new(ramp.start, ramp.max, ramp.secs)
+                .map_err(|err| format!("Invalid dataflow ramp: {}", err))?;
+        }
+        if self.paired_mem_chance > 100 {
+            return Err(format!(
+                "Invalid paired memory chance {}, must be 0-100",
+                self.paired_mem_chance
+            ));
+        }
+        if self.crash_chance > 100 {
+            return Err(format!(
+                "Invalid crash chance {}, must be 0-100",
+                self.crash_chance
+            ));
+        }
+        if self.mem_base
+            && self
+                .scratch
+                .map_or(true, |region| region.size < MEM_BASE_SPAN)
+        {
+            return Err(format!(
+                "A memory base needs a scratch region of at least {} bytes",
+                MEM_BASE_SPAN
+            ));
+        }
+        let instructions = self.instructions();
+        if instructions.is_empty() {
+            let names: Vec<_> = self.excluded_categories.iter().map(|c| c.name()).collect();
+            return Err(format!(
+                "Excluding {} leaves no instructions in {}",
+                names.join(","),
+                self.isa
+            ));
+        }
+        let generator = InstGenerator::with_config(self);
+        if instructions
+            .iter()
+            .all(|template| generator.weight(template) == 0)
+        {
+            return Err(format!(
+                "The weights leave no instructions of {} to generate",
+                self.isa
+            ));
+        }
+        Ok(())
+    }
+
+    /// The selected instruction set, with the system instructions if
+    /// `privileged` is set and the CSR instructions if `csr` is set. Falls
+    /// back to RV64G for unknown names.
+    pub fn isa(&self) -> Vec<&'static InstructionTemplate> {
+        let mut isa = sets::by_name(&self.isa).unwrap_or_else(sets::riscv_g);
+        if self.privileged {
+            isa = sets::with_system(isa);
+        }
+        if self.csr {
+            isa = sets::with_zicsr(isa);
+        }
+        isa
+    }
+
+    /// Whether the [`DriverConfig::isa`] is an RV64 one, i.e. has
+    /// instructions that only exist on RV64.
+    pub fn is_rv64(&self) -> bool {
+        self.isa()
+            .iter()
+            .any(|template| sets::is_rv64_only(template))
+    }
+
+    /// The instructions new ones are generated from: the [`DriverConfig::isa`]
+    /// without the excluded categories.
+    pub fn instructions(&self) -> Vec<&'static InstructionTemplate> {
+        without_categories(self.isa(), &self.excluded_categories)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{
+        DriverConfig, ARG_RANGES_VAR, CATEGORY_WEIGHTS_VAR, COMPRESSED_VAR, CRASH_CHANCE_VAR,
+        DATAFLOW_RAMP_VAR, EXCLUDED_CATEGORIES_VAR, ISA_VAR, MAX_PROGRAM_LEN_VAR, MEM_BASE_VAR,
+        NO_DATAFLOW_VAR, NO_SNIPPET_VAR, PAIRED_MEM_CHANCE_VAR, POW2_CHANCE_VAR, RECENT_REGS_VAR,
+        REUSE_CHANCE_VAR, SCRATCH_REGION_VAR, TEMPLATE_WEIGHTS_VAR,
+    };
+    use crate::{
+        generator::{parse_template_weights, DataflowRamp, ScratchRegion},
+        instructions::{riscv::args, InstructionCategory},
+    };
+
+    fn from_vars(vars: &[(&str, &str)]) -> Result<DriverConfig, String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        DriverConfig::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn config_from_vars() {
+        assert_eq!(from_vars(&[]).unwrap(), DriverConfig::default());
+
+        let config = from_vars(&[
+            (ISA_VAR, "rv32i"),
+            (EXCLUDED_CATEGORIES_VAR, "branch"),
+            (NO_SNIPPET_VAR, "1"),
+            (COMPRESSED_VAR, "1"),
+            (SCRATCH_REGION_VAR, "0x80000000+4096"),
+            (RECENT_REGS_VAR, "4:30"),
+            (ARG_RANGES_VAR, "imm12=0-64"),
+        ])
+        .unwrap();
+        // The same config built field by field, as from the command line.
+        let expected = DriverConfig {
+            isa: "rv32i".to_owned(),
+            excluded_categories: vec![InstructionCategory::Branch],
+            snippets: false,
+            compressed: true,
+            scratch: Some(ScratchRegion::new(0x8000_0000, 4096).unwrap()),
+            recent_regs: Some((4, 30)),
+            arg_ranges: vec![(&args::IMM12, 0, 64)],
+            ..DriverConfig::default()
+        };
+        assert_eq!(config, expected);
+        assert!(config
+            .instructions()
+            .iter()
+            .all(|t| t.category() != InstructionCategory::Branch));
+
+        assert!(from_vars(&[(ISA_VAR, "rv32e")]).is_err());
+        assert!(from_vars(&[(RECENT_REGS_VAR, "4")]).is_err());
+        assert!(from_vars(&[(MAX_PROGRAM_LEN_VAR, "0")]).is_err());
+        assert!(from_vars(&[(MAX_PROGRAM_LEN_VAR, "many")]).is_err());
+        assert!(from_vars(&[(PAIRED_MEM_CHANCE_VAR, "101")]).is_err());
+        assert!(from_vars(&[(CRASH_CHANCE_VAR, "101")]).is_err());
+        assert_eq!(
+            from_vars(&[(CRASH_CHANCE_VAR, "80")]).unwrap().crash_chance,
+            80
+        );
+        assert!(from_vars(&[(TEMPLATE_WEIGHTS_VAR, "addi=x")]).is_err());
+        // Without arithmetic, rv32i still has e.g. loads and branches.
+        assert!(from_vars(&[(ISA_VAR, "rv32i"), (CATEGORY_WEIGHTS_VAR, "arith=0")]).is_ok());
+        // Nothing left to choose from.
+        let none: Vec<_> = InstructionCategory::ALL
+            .iter()
+            .map(|category| format!("{}=0", category.name()))
+            .collect();
+        assert_eq!(
+            from_vars(&[(CATEGORY_WEIGHTS_VAR, none.join(",").as_str())]),
+            Err("The weights leave no instructions of rv64g to generate".to_owned())
+        );
+        assert!(!from_vars(&[(ISA_VAR, "rv32gc")]).unwrap().is_rv64());
+        assert!(from_vars(&[(ISA_VAR, "rv64i")]).unwrap().is_rv64());
+        let region = (SCRATCH_REGION_VAR, "0x80000000+4096");
+        assert!(from_vars(&[(MEM_BASE_VAR, "1"), region]).unwrap().mem_base);
+        // Without a region, or with one too small for all offsets.
+        assert!(from_vars(&[(MEM_BASE_VAR, "1")]).is_err());
+        let small = (SCRATCH_REGION_VAR, "0x80000000+2048");
+        assert!(from_vars(&[(MEM_BASE_VAR, "1"), small]).is_err());
+        let all: Vec<_> = InstructionCategory::ALL.iter().map(|c| c.name()).collect();
+        assert!(from_vars(&[(EXCLUDED_CATEGORIES_VAR, all.join(",").as_str())]).is_err());
+    }
+
+    #[test]
+    fn arg_chances_are_validated() {
+        let config = |reuse_chance, pow2_chance| DriverConfig {
+            reuse_chance,
+            pow2_chance,
+            ..DriverConfig::default()
+        };
+        assert!(config(Some(0), Some(100)).validate().is_ok());
+        assert_eq!(
+            config(Some(101), None).validate(),
+            Err("Invalid reuse chance 101, must be 0-100".to_owned())
+        );
+        assert!(config(None, Some(200)).validate().is_err());
+        assert_eq!(
+            from_vars(&[(REUSE_CHANCE_VAR, "30")]).unwrap().reuse_chance,
+            Some(30)
+        );
+        assert!(from_vars(&[(POW2_CHANCE_VAR, "often")]).is_err());
+        assert!(from_vars(&[(POW2_CHANCE_VAR, "101")]).is_err());
+    }
+
+    #[test]
+    fn dataflow_ramp_from_vars() {
+        let ramp = from_vars(&[(DATAFLOW_RAMP_VAR, "10:90:3600")])
+            .unwrap()
+            .dataflow_ramp;
+        assert_eq!(ramp, Some(DataflowRamp::new(10, 90, 3600).unwrap()));
+        assert!(from_vars(&[(DATAFLOW_RAMP_VAR, "10:90")]).is_err());
+        assert!(from_vars(&[(DATAFLOW_RAMP_VAR, "10:101:60")]).is_err());
+        assert!(from_vars(&[(DATAFLOW_RAMP_VAR, "90:10:60")]).is_err());
+        // Nothing to ramp up without the bias.
+        let no_dataflow = (NO_DATAFLOW_VAR, "1");
+        assert!(from_vars(&[no_dataflow, (DATAFLOW_RAMP_VAR, "10:90:60")]).is_err());
+
+        let config = DriverConfig {
+            dataflow_ramp: ramp,
+            ..DriverConfig::default()
+        };
+        let vars = config.to_vars();
+        let vars: Vec<(&str, &str)> = vars.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        assert_eq!(from_vars(&vars).unwrap(), config);
+    }
+
+    #[test]
+    fn config_round_trips_through_vars() {
+        let config = DriverConfig {
+            isa: "rv64g_zbb".to_owned(),
+            privileged: true,
+            csr: true,
+            excluded_categories: vec![InstructionCategory::Float, InstructionCategory::Atomic],
+            arg_reuse: false,
+            reuse_chance: Some(30),
+            pow2_chance: Some(0),
+            dataflow: false,
+            avoid_reserved: true,
+            register_fill: true,
+            scratch: Some(ScratchRegion::new(0x1000, 4096).unwrap()),
+            paired_mem_chance: 25,
+            crash_chance: 10,
+            mem_base: true,
+            recent_regs: Some((2, 100)),
+            arg_ranges: vec![(&args::RD, 1, 7), (&args::IMM12, 0, 16)],
+            emphasis: Some(InstructionCategory::Mem),
+            template_weights: parse_template_weights("slli=5,addi=0").unwrap(),
+            category_weights: vec![(InstructionCategory::Float, 300)],
+            max_program_len: 64,
+            ..DriverConfig::default()
+        };
+        let vars = config.to_vars();
+        let vars: Vec<(&str, &str)> = vars.iter().map(|(k, v)| (*k, v.as_str())).collect();
+        assert_eq!(from_vars(&vars).unwrap(), config);
+    }
+}
@@ -0,0 +1,261 @@
+//! Differential checking of the architectural register state of the DUT
+//! against a reference model (e.g. Spike) that runs alongside it.
+//!
+//! The harness writes both final register files into a shared memory region
+//! whose id the fuzzer passes in [`REGS_SHM_ID_VAR`] (see
+//! `reportRegisterState` in `FuzzerAPI.h`). All values are little-endian:
+//!
+//! | Offset | Size     | Contents                                         |
+//! |--------|----------|--------------------------------------------------|
+//! | 0      | 8        | Valid flag, set to 1 after both files are written |
+//! | 8      | 32 * 8   | DUT registers `x0` to `x31`                      |
+//! | 264    | 32 * 8   | Reference registers `x0` to `x31`                |
+//!
+//! The fuzzer clears the valid flag before every execution, so runs where the
+//! harness didn't report anything are never considered diverging.
+use libafl::{
+    bolts::{
+        ownedref::OwnedMutSlice,
+        shmem::{ShMem, ShMemProvider, UnixShMem, UnixShMemProvider},
+        tuples::{MatchName, Named},
+        AsMutSlice, AsSlice,
+    },
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::UsesInput,
+    observers::{Observer, ObserversTuple},
+    state::{HasClientPerfMonitor, HasMetadata},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::program_input::ProgramInput;
+
+/// Env var with the id of the shared memory holding the register state.
+pub const REGS_SHM_ID_VAR: &str = "FUZZING_REGS_SHM_ID";
+
+/// Name of the [`RegisterStateObserver`] in the observer tuple.
+pub const REGISTERS_OBSERVER_NAME: &str = "registers";
+
+/// Number of integer registers in a register dump.
+pub const NUM_REGISTERS: usize = 32;
+
+const VALID_SIZE: usize = 8;
+const DUMP_SIZE: usize = NUM_REGISTERS * 8;
+
+/// Size of the shared memory region with the register state.
+pub const REGISTER_STATE_SIZE: usize = VALID_SIZE + 2 * DUMP_SIZE;
+
+/// Creates the shared memory for the register state and exports its id to
+/// the target.
+pub fn create_register_map(provider: &mut UnixShMemProvider) -> UnixShMem {
+    let shmem = provider.new_shmem(REGISTER_STATE_SIZE).unwrap();
+    shmem.write_to_env(REGS_SHM_ID_VAR).unwrap();
+    shmem
+}
+
+fn read_dump(bytes: &[u8]) -> Vec<u64> {
+    bytes
+        .chunks_exact(8)
+        .map(|reg| u64::from_le_bytes(reg.try_into().unwrap()))
+        .collect()
+}
+
+/// Parses the register region. Returns the DUT and the reference registers
+/// if the harness marked them as valid.
+pub fn parse_register_state(region: &[u8]) -> Option<(Vec<u64>, Vec<u64>)> {
+    if region.len() < REGISTER_STATE_SIZE || region[..VALID_SIZE] != 1u64.to_le_bytes() {
+        return None;
+    }
+    let dut = read_dump(&region[VALID_SIZE..VALID_SIZE + DUMP_SIZE]);
+    let reference = read_dump(&region[VALID_SIZE + DUMP_SIZE..REGISTER_STATE_SIZE]);
+    Some((dut, reference))
+}
+
+/// Reads the register state the harness exported after every execution.
+/// Without a region (i.e. a disabled observer) nothing is ever reported.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RegisterStateObserver<'a> {
+    name: String,
+    region: OwnedMutSlice<'a, u8>,
+    /// The DUT and reference registers of the last execution, if valid.
+    last: Option<(Vec<u64>, Vec<u64>)>,
+}
+
+impl<'a> RegisterStateObserver<'a> {
+    pub fn new(region: &'a mut [u8]) -> Self {
+        Self {
+            name: REGISTERS_OBSERVER_NAME.to_string(),
+            region: OwnedMutSlice::from(region),
+            last: None,
+        }
+    }
+
+    /// An observer without a region for runs without a reference model.
+    pub fn disabled() -> Self {
+        Self {
+            name: REGISTERS_OBSERVER_NAME.to_string(),
+            region: OwnedMutSlice::from(vec![]),
+            last: None,
+        }
+    }
+
+    pub fn last(&self) -> Option<&(Vec<u64>, Vec<u64>)> {
+        self.last.as_ref()
+    }
+}
+
+impl<'a> Named for RegisterStateObserver<'a> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<'a, S> Observer<S> for RegisterStateObserver<'a>
+where
+    S: UsesInput,
+{
+    fn pre_exec(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
+        self.last = None;
+        let region = self.region.as_mut_slice();
+        if region.len() >= VALID_SIZE {
+            region[..VALID_SIZE].fill(0);
+        }
+        Ok(())
+    }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _input: &S::Input,
+        _exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        self.last = parse_register_state(self.region.as_slice());
+        Ok(())
+    }
+}
+
+libafl::impl_serdeany!(RegisterDivergenceMetadata);
+/// Both register dumps of a diverging input, saved with the objective.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RegisterDivergenceMetadata {
+    pub dut: Vec<u64>,
+    pub reference: Vec<u64>,
+    /// The indexes of the registers that differ.
+    pub diverging: Vec<usize>,
+}
+
+impl RegisterDivergenceMetadata {
+    /// Returns the metadata if the register files differ.
+    pub fn compare(dut: &[u64], reference: &[u64]) -> Option<Self> {
+        let diverging: Vec<usize> = (0..NUM_REGISTERS)
+            .filter(|reg| dut.get(*reg) != reference.get(*reg))
+            .collect();
+        if diverging.is_empty() {
+            return None;
+        }
+        Some(Self {
+            dut: dut.to_vec(),
+            reference: reference.to_vec(),
+            diverging,
+        })
+    }
+}
+
+/// An objective that fires if the DUT's final registers differ from the ones
+/// of the reference model.
+#[derive(Debug, Default)]
+pub struct DivergenceFeedback {
+    last: Option<RegisterDivergenceMetadata>,
+}
+
+impl DivergenceFeedback {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Named for DivergenceFeedback {
+    fn name(&self) -> &str {
+        "DivergenceFeedback"
+    }
+}
+
+impl<S> Feedback<S> for DivergenceFeedback
+where
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &ProgramInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let observer = observers
+            .match_name::<RegisterStateObserver>(REGISTERS_OBSERVER_NAME)
+            .ok_or_else(|| Error::key_not_found("RegisterStateObserver not found".to_string()))?;
+        self.last = observer
+            .last()
+            .and_then(|(dut, reference)| RegisterDivergenceMetadata::compare(dut, reference));
+        Ok(self.last.is_some())
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        if let Some(meta) = self.last.take() {
+            testcase.add_metadata(meta);
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &ProgramInput) -> Result<(), Error> {
+        self.last = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_register_state, RegisterDivergenceMetadata, REGISTER_STATE_SIZE};
+
+    fn region(valid: u64, dut: &[u64; 32], reference: &[u64; 32]) -> Vec<u8> {
+        let mut result = valid.to_le_bytes().to_vec();
+        for reg in dut.iter().chain(reference.iter()) {
+            result.extend_from_slice(&reg.to_le_bytes());
+        }
+        assert_eq!(result.len(), REGISTER_STATE_SIZE);
+        result
+    }
+
+    #[test]
+    fn register_state_layout() {
+        let mut dut = [0u64; 32];
+        dut[5] = 0xdead_beef;
+        let mut reference = dut;
+        let (parsed_dut, parsed_ref) = parse_register_state(&region(1, &dut, &reference)).unwrap();
+        assert_eq!(parsed_dut[5], 0xdead_beef);
+        assert_eq!(parsed_dut, parsed_ref);
+        assert!(RegisterDivergenceMetadata::compare(&parsed_dut, &parsed_ref).is_none());
+
+        // Not written by the harness.
+        assert!(parse_register_state(&region(0, &dut, &reference)).is_none());
+        assert!(parse_register_state(&[]).is_none());
+
+        reference[31] = 1;
+        let (parsed_dut, parsed_ref) = parse_register_state(&region(1, &dut, &reference)).unwrap();
+        let meta = RegisterDivergenceMetadata::compare(&parsed_dut, &parsed_ref).unwrap();
+        assert_eq!(meta.diverging, vec![31]);
+        assert_eq!(meta.reference[31], 1);
+    }
+}
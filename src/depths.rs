@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+
+use libafl::{
+    corpus::{Corpus, SchedulerTestcaseMetadata},
+    state::HasMetadata,
+    Error,
+};
+
+/// Name of the user stat with the depth summary reported to the monitor.
+pub const DEPTHS_STAT_NAME: &str = "depths";
+
+/// Histogram of mutation depths (number of ancestors) of the corpus entries.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DepthHistogram {
+    counts: BTreeMap<u64, u64>,
+}
+
+impl DepthHistogram {
+    /// Collects the depths recorded in the testcase metadata of the corpus.
+    /// Entries that haven't been calibrated yet don't have a depth and are
+    /// skipped.
+    pub fn from_corpus<C: Corpus>(corpus: &C) -> Result<Self, Error> {
+        let mut result = Self::default();
+        let mut id = corpus.first();
+        while let Some(cur) = id {
+            if let Some(meta) = corpus
+                .get(cur)?
+                .borrow()
+                .metadata_map()
+                .get::<SchedulerTestcaseMetadata>()
+            {
+                result.add(meta.depth());
+            }
+            id = corpus.next(cur);
+        }
+        Ok(result)
+    }
+
+    pub fn add(&mut self, depth: u64) {
+        *self.counts.entry(depth).or_insert(0) += 1;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    pub fn max_depth(&self) -> u64 {
+        self.counts.keys().last().copied().unwrap_or(0)
+    }
+
+    pub fn mean_depth(&self) -> f64 {
+        let total = self.total();
+        if total == 0 {
+            return 0.0;
+        }
+        let sum: u64 = self.counts.iter().map(|(depth, count)| depth * count).sum();
+        sum as f64 / total as f64
+    }
+
+    /// A short one-line summary for the monitor.
+    pub fn summary(&self) -> String {
+        format!("max {} mean {:.1}", self.max_depth(), self.mean_depth())
+    }
+
+    /// The full histogram with one `DEPTH COUNT` pair per line.
+    pub fn to_text(&self) -> String {
+        let mut result = String::new();
+        for (depth, count) in &self.counts {
+            result += &format!("{} {}\n", depth, count);
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DepthHistogram;
+
+    #[test]
+    fn histogram_stats() {
+        let mut hist = DepthHistogram::default();
+        assert_eq!(hist.summary(), "max 0 mean 0.0");
+
+        for depth in [0, 1, 1, 4] {
+            hist.add(depth);
+        }
+        assert_eq!(hist.total(), 4);
+        assert_eq!(hist.max_depth(), 4);
+        assert_eq!(hist.mean_depth(), 1.5);
+        assert_eq!(hist.to_text(), "0 1\n1 2\n4 1\n");
+    }
+}
@@ -0,0 +1,151 @@
+//! Assembly listings of programs in the usual `add x2, x0, x0` syntax, for
+//! reading crashes without decoding the corpus files by hand.
+use crate::{
+    abi::operand_string,
+    instructions::{Instruction, InstructionTemplate},
+    parser::{encoded_len, split_encodings},
+};
+
+/// The instruction in assembly syntax. Operands are in the order of the
+/// template, registers are named `x0` to `x31` (`f0` to `f31` for floating
+/// point registers) and immediates are in hex.
+pub fn inst_to_asm(inst: &Instruction) -> String {
+    let template = inst.template();
+    let operands: Vec<String> = template
+        .operands()
+        .filter_map(|spec| inst.arguments().iter().find(|arg| arg.spec() == *spec))
+        .map(|arg| operand_string(template, arg, false))
+        .collect();
+    if operands.is_empty() {
+        template.name().to_owned()
+    } else {
+        format!("{} {}", template.name(), operands.join(", "))
+    }
+}
+
+/// One line per instruction, see [`inst_to_asm`].
+pub fn insts_to_asm(insts: &[Instruction]) -> String {
+    insts.iter().map(|inst| inst_to_asm(inst) + "\n").collect()
+}
+
+/// Decodes machine code with the given templates. Words that none of them
+/// match are listed as `.word` (`.half` for compressed encodings) with a
+/// comment, and bytes after the last whole instruction as `.byte`, so every
+/// byte of the input shows up.
+pub fn disassemble(bytes: &[u8], isa: &[&'static InstructionTemplate]) -> String {
+    let mut result = String::new();
+    let (encodings, rest) = split_encodings(bytes);
+    for data in encodings {
+        match isa.iter().find_map(|template| template.decode(data)) {
+            Some(inst) => result.push_str(&inst_to_asm(&inst)),
+            None if encoded_len(data as u16) == 2 => {
+                result.push_str(&format!(".half {:#06x}  # unknown instruction", data))
+            }
+            None => result.push_str(&format!(".word {:#010x}  # unknown instruction", data)),
+        }
+        result.push('\n');
+    }
+    if !rest.is_empty() {
+        let rest: Vec<_> = rest.iter().map(|byte| format!("{:#04x}", byte)).collect();
+        result.push_str(&format!(".byte {}  # trailing bytes\n", rest.join(", ")));
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::prelude::Xoshiro256StarRand;
+
+    use super::{disassemble, inst_to_asm, insts_to_asm};
+    use crate::{
+        assembler::assemble_instructions,
+        generator::InstGenerator,
+        instructions::{
+            riscv::{
+                args,
+                rv_i::{ADD, ADDI, ECALL},
+            },
+            sets, Argument, Instruction,
+        },
+        parser::parse_instructions,
+    };
+
+    #[test]
+    fn assembly_syntax() {
+        let add = Instruction::new(
+            &ADD,
+            vec![
+                Argument::new(&args::RD, 2),
+                Argument::new(&args::RS1, 0),
+                Argument::new(&args::RS2, 0),
+            ],
+        );
+        assert_eq!(inst_to_asm(&add), "add x2, x0, x0");
+
+        // The operand order doesn't depend on the argument order.
+        let mut addi = Instruction::new(
+            &ADDI,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 1),
+                Argument::new(&args::IMM12, 0),
+            ],
+        );
+        addi.set_arg(Argument::new(&args::RD, 5));
+        assert_eq!(inst_to_asm(&addi), "addi x5, x1, 0x0");
+
+        let ecall = Instruction::new(&ECALL, vec![]);
+        assert_eq!(insts_to_asm(&[add, ecall]), "add x2, x0, x0\necall\n");
+
+        let fadd = sets::all()
+            .into_iter()
+            .find(|t| t.name() == "fadd.d")
+            .unwrap();
+        let args: Vec<_> = fadd
+            .operands()
+            .map(|spec| Argument::new(*spec, 1))
+            .collect();
+        assert!(inst_to_asm(&Instruction::new(fadd, args)).starts_with("fadd.d f1, f1, f1"));
+    }
+
+    #[test]
+    fn unknown_words_are_listed() {
+        let ecall = Instruction::new(&ECALL, vec![]);
+        let mut bytes = assemble_instructions(&vec![ecall]);
+        bytes.extend([0xff, 0xff, 0xff, 0xff, 0x12]);
+        assert_eq!(
+            disassemble(&bytes, &sets::all()),
+            "ecall\n.word 0xffffffff  # unknown instruction\n.byte 0x12  # trailing bytes\n"
+        );
+        assert_eq!(disassemble(&[], &sets::all()), "");
+
+        // This funct3 is reserved in the first quadrant of the C extension.
+        assert_eq!(
+            disassemble(&[0x00, 0x80, 0xff], &sets::all()),
+            ".half 0x8000  # unknown instruction\n.byte 0xff  # trailing bytes\n"
+        );
+    }
+
+    #[test]
+    fn compressed_round_trip() {
+        let mut generator = InstGenerator::new();
+        generator.set_avoid_reserved(true);
+        let mut rng = Xoshiro256StarRand::default();
+        for template in sets::riscv_c() {
+            for _ in 0..100 {
+                let inst = generator.generate_instruction(&mut rng, &vec![template]);
+                let bytes = assemble_instructions(&vec![inst.clone()]);
+                assert_eq!(bytes.len(), 2, "{}", inst);
+                assert_eq!(disassemble(&bytes, &sets::all()), inst_to_asm(&inst) + "\n");
+                assert_eq!(parse_instructions(&bytes, &sets::all()).unwrap(), [inst]);
+            }
+        }
+
+        // c.lw x8, 4(x9) between two 32 bit instructions.
+        let bytes = [0x73, 0x00, 0x00, 0x00, 0xc0, 0x40, 0x73, 0x00, 0x10, 0x00];
+        assert_eq!(
+            disassemble(&bytes, &sets::all()),
+            "ecall\nc.lw x8, x9, 0x2, 0x0\nebreak\n"
+        );
+    }
+}
@@ -0,0 +1,897 @@
+//! Construction of the fuzzing components (executor, feedbacks, state and
+//! scheduler) shared by the different binaries.
+use core::{ops::Range, time::Duration};
+use std::{
+    io::Read,
+    path::PathBuf,
+    process::{Command, Stdio},
+    time::Instant,
+};
+
+use libafl::{
+    bolts::{
+        fs::get_unique_std_input_file,
+        rands::StdRand,
+        shmem::{ShMem, ShMemProvider, UnixShMem, UnixShMemProvider},
+        tuples::{tuple_list, tuple_list_type},
+    },
+    corpus::{ondisk::OnDiskMetadataFormat, OnDiskCorpus},
+    executors::forkserver::{ForkserverExecutor, TimeoutForkserverExecutor},
+    feedback_and_fast, feedback_or,
+    feedbacks::{
+        CrashFeedback, EagerOrFeedback, FastAndFeedback, MaxMapFeedback, TimeFeedback,
+        TimeoutFeedback,
+    },
+    inputs::UsesInput,
+    observers::{StdMapObserver, TimeObserver},
+    schedulers::{
+        powersched::PowerSchedule, IndexesLenTimeMinimizerScheduler, StdWeightedScheduler,
+    },
+    state::StdState,
+    Error,
+};
+use nix::sys::signal::Signal;
+
+use crate::{
+    bundle::CrashBundleFeedback,
+    calibration::DummyCalibration,
+    causes::{CauseFeedback, FUZZING_EXIT_CODE_FILE_VAR, FUZZING_ISSUE_MARKER_VAR},
+    cosim::{DivergenceFeedback, RegisterStateObserver},
+    executors::{AdaptiveTimeoutExecutor, TimeoutRerunExecutor},
+    feedbacks::{
+        CategoryTimeFeedback, ConstantCoverageFeedback, CrashImmediatesFeedback,
+        CrashOriginFeedback, DuplicateCrashFilter, ExitCodeFeedback, FrontierFeedback,
+        HitcountBucketFeedback, InstSignatureFilter, KnownCrashFilter, LabeledObjective,
+        LengthDiversityFeedback, LowCoverageFilter, ObjectiveNameFeedback, ToggleFeedback,
+        TraceFeedback, TraceWriter, CRASH_LABEL, EXIT_CODE_LABEL,
+    },
+    observers::{BucketTable, BucketedMapObserver, ExitStatusObserver, IssueMarkerObserver},
+    program_input::ProgramInput,
+    scheduler::{ClassScheduler, EpsilonScheduler, RarityScheduler, WarmupScheduler},
+};
+
+/// The coverage map size used if neither the target nor the user provide one.
+pub const DEFAULT_MAP_SIZE: usize = 2_621_440;
+
+/// The name of the observer for the coverage map.
+pub const EDGES_OBSERVER_NAME: &str = "shared_mem";
+
+/// The observer for the AFL++ coverage map of the target.
+pub type EdgesObserver<'a> = BucketedMapObserver<StdMapObserver<'a, u8, false>>;
+
+/// All observers attached to the executor.
+pub type FuzzObservers<'a> = tuple_list_type!(
+    EdgesObserver<'a>,
+    TimeObserver,
+    RegisterStateObserver<'a>,
+    IssueMarkerObserver,
+    ExitStatusObserver
+);
+
+/// The executor that runs the target via the AFL++ forkserver.
+pub type TargetExecutor<'a, S> = TimeoutRerunExecutor<
+    AdaptiveTimeoutExecutor<
+        TimeoutForkserverExecutor<ForkserverExecutor<FuzzObservers<'a>, S, UnixShMemProvider>>,
+    >,
+>;
+
+/// The state of a fuzzing client.
+pub type FuzzState =
+    StdState<ProgramInput, OnDiskCorpus<ProgramInput>, StdRand, OnDiskCorpus<ProgramInput>>;
+
+/// The feedback deciding whether an input is added to the corpus.
+pub type FuzzFeedback<'a> = TraceFeedback<
+    EagerOrFeedback<
+        MaxMapFeedback<EdgesObserver<'a>, FuzzState, u8>,
+        EagerOrFeedback<
+            ToggleFeedback<TimeFeedback>,
+            EagerOrFeedback<
+                HitcountBucketFeedback<EdgesObserver<'a>, FuzzState>,
+                EagerOrFeedback<
+                    LengthDiversityFeedback,
+                    EagerOrFeedback<
+                        CategoryTimeFeedback,
+                        EagerOrFeedback<
+                            FrontierFeedback<StdMapObserver<'a, u8, false>>,
+                            ConstantCoverageFeedback<EdgesObserver<'a>>,
+                            FuzzState,
+                        >,
+                        FuzzState,
+                    >,
+                    FuzzState,
+                >,
+                FuzzState,
+            >,
+            FuzzState,
+        >,
+        FuzzState,
+    >,
+>;
+
+/// The feedback deciding whether an input is a solution.
+pub type FuzzObjective<'a> = TraceFeedback<
+    EagerOrFeedback<
+        LabeledObjective<
+            FastAndFeedback<
+                CrashFeedback,
+                FastAndFeedback<
+                    KnownCrashFilter<EdgesObserver<'a>>,
+                    FastAndFeedback<
+                        LowCoverageFilter<EdgesObserver<'a>>,
+                        FastAndFeedback<
+                            InstSignatureFilter,
+                            DuplicateCrashFilter<EdgesObserver<'a>>,
+                            FuzzState,
+                        >,
+                        FuzzState,
+                    >,
+                    FuzzState,
+                >,
+                FuzzState,
+            >,
+        >,
+        EagerOrFeedback<
+            LabeledObjective<ToggleFeedback<TimeoutFeedback>>,
+            EagerOrFeedback<
+                LabeledObjective<DivergenceFeedback>,
+                EagerOrFeedback<
+                    LabeledObjective<ExitCodeFeedback>,
+                    EagerOrFeedback<
+                        CrashOriginFeedback,
+                        EagerOrFeedback<
+                            CrashImmediatesFeedback,
+                            EagerOrFeedback<
+                                ObjectiveNameFeedback,
+                                EagerOrFeedback<
+                                    CrashBundleFeedback<EdgesObserver<'a>>,
+                                    CauseFeedback,
+                                    FuzzState,
+                                >,
+                                FuzzState,
+                            >,
+                            FuzzState,
+                        >,
+                        FuzzState,
+                    >,
+                    FuzzState,
+                >,
+                FuzzState,
+            >,
+            FuzzState,
+        >,
+        FuzzState,
+    >,
+>;
+
+/// The calibration stage for new corpus entries.
+pub type FuzzCalibration<'a> = DummyCalibration<EdgesObserver<'a>, FuzzObservers<'a>, FuzzState>;
+
+/// The corpus scheduler.
+pub type FuzzScheduler<'a> = WarmupScheduler<
+    EpsilonScheduler<
+        ClassScheduler<
+            RarityScheduler<
+                IndexesLenTimeMinimizerScheduler<
+                    StdWeightedScheduler<EdgesObserver<'a>, FuzzState>,
+                >,
+                EdgesObserver<'a>,
+                FuzzState,
+            >,
+            FuzzState,
+        >,
+        FuzzState,
+    >,
+    FuzzState,
+>;
+
+/// Everything needed to start the target.
+#[derive(Clone, Debug)]
+pub struct TargetConfig {
+    pub executable: String,
+    pub arguments: Vec<String>,
+    /// Extra environment variables for the target.
+    pub envs: Vec<(String, String)>,
+    pub debug_child: bool,
+    pub timeout: Duration,
+    /// How often a timed-out input is re-run before the timeout is reported
+    /// (see [`TimeoutRerunExecutor`]).
+    pub timeout_reruns: u32,
+    /// Derive the timeout of every input from the calibrated execution time
+    /// of its parent times this factor, with `timeout` as the upper bound
+    /// (see [`AdaptiveTimeoutExecutor`]). `None` always uses `timeout`.
+    pub timeout_factor: Option<f64>,
+    /// The signal used to kill the target on a timeout.
+    pub signal: Signal,
+    pub map_size: usize,
+}
+
+impl TargetConfig {
+    /// The target for running single inputs outside of fuzzing, e.g. to
+    /// reproduce or minimize them. `command` is the target command line.
+    /// Timeouts are reported on the first run, and the map size is detected
+    /// unless given (see [`resolve_map_size`]).
+    pub fn single_runs(
+        command: &[String],
+        envs: Vec<(String, String)>,
+        timeout: Duration,
+        signal: Signal,
+        map_size: Option<usize>,
+    ) -> Self {
+        let executable = command
+            .first()
+            .expect("The target command line is empty")
+            .clone();
+        let map_size = resolve_map_size(&executable, map_size);
+        Self {
+            arguments: command[1..].to_vec(),
+            executable,
+            envs,
+            debug_child: false,
+            timeout,
+            timeout_reruns: 0,
+            timeout_factor: None,
+            signal,
+            map_size,
+        }
+    }
+}
+
+/// How long [`detect_map_size`] waits for the target to print its map size.
+pub const MAP_SIZE_PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Asks the AFL++ instrumented target for the size of its coverage map.
+///
+/// AFL++ binaries print their map size and exit when `AFL_DUMP_MAP_SIZE` is
+/// set, which is the same mechanism `afl-fuzz` uses. Returns `None` if the
+/// target doesn't support this (e.g. old instrumentation) or doesn't exit
+/// within `timeout`, in which case it is killed.
+pub fn detect_map_size(executable: &str, timeout: Duration) -> Option<usize> {
+    let mut child = Command::new(executable)
+        .env("AFL_DUMP_MAP_SIZE", "1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let start = Instant::now();
+    while child.try_wait().ok()?.is_none() {
+        if start.elapsed() >= timeout {
+            child.kill().ok();
+            child.wait().ok();
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let mut stdout = String::new();
+    child.stdout.take()?.read_to_string(&mut stdout).ok()?;
+    let size = stdout.trim().parse::<usize>().ok()?;
+    if size == 0 {
+        return None;
+    }
+    Some(size)
+}
+
+/// The coverage map size to use: the one given by the user, else the one
+/// the target reports (see [`detect_map_size`]), else [`DEFAULT_MAP_SIZE`].
+pub fn resolve_map_size(executable: &str, user: Option<usize>) -> usize {
+    user.or_else(|| detect_map_size(executable, MAP_SIZE_PROBE_TIMEOUT))
+        .unwrap_or(DEFAULT_MAP_SIZE)
+}
+
+/// Parses a coverage map size given by the user, which must not be zero.
+pub fn parse_map_size(arg: &str) -> Result<usize, String> {
+    match arg.trim().parse::<usize>() {
+        Ok(0) => Err("The coverage map size must not be zero".to_owned()),
+        Ok(size) => Ok(size),
+        Err(_) => Err(format!("Invalid map size '{}'", arg)),
+    }
+}
+
+/// A warning if the map size isn't a multiple of the page size, as the
+/// shared memory is allocated in whole pages anyway.
+pub fn map_size_warning(map_size: usize, page_size: usize) -> Option<String> {
+    if page_size == 0 || map_size % page_size == 0 {
+        return None;
+    }
+    Some(format!(
+        "Map size {} is not a multiple of the page size {}, {} bytes are wasted",
+        map_size,
+        page_size,
+        page_size - map_size % page_size
+    ))
+}
+
+/// Parses the name of a signal, e.g. `SIGKILL`.
+pub fn parse_signal(arg: &str) -> Result<Signal, String> {
+    arg.trim()
+        .parse::<Signal>()
+        .map_err(|_| format!("Unknown signal '{}'", arg))
+}
+
+/// Parses a `KEY=VALUE` environment variable assignment.
+pub fn parse_env_var(arg: &str) -> Result<(String, String), String> {
+    let (key, value) = arg
+        .split_once('=')
+        .ok_or(format!("Not in KEY=VALUE format: '{}'", arg))?;
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return Err(format!("Invalid environment variable name: '{}'", key));
+    }
+    Ok((key.to_owned(), value.to_owned()))
+}
+
+/// Resolves the `all/N` shorthand of `--cores` and the `--core-fraction`
+/// option into a core list that `Cores::from_cmdline` understands. Given a
+/// fraction, it takes precedence over `spec`. Other specs are returned as is.
+///
+/// At least one core is always used, so `all/64` on a 16 core machine still
+/// uses core 0.
+pub fn resolve_core_spec(
+    spec: &str,
+    fraction: Option<f64>,
+    num_cores: usize,
+) -> Result<String, String> {
+    let count = if let Some(fraction) = fraction {
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            return Err(format!("Core fraction must be in (0, 1], got {}", fraction));
+        }
+        (num_cores as f64 * fraction) as usize
+    } else if let Some(divisor) = spec.trim().strip_prefix("all/") {
+        let divisor = divisor
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid core divisor in '{}'", spec))?;
+        if divisor == 0 {
+            return Err(format!("Invalid core divisor in '{}'", spec));
+        }
+        num_cores / divisor
+    } else {
+        return Ok(spec.to_owned());
+    };
+    Ok(format!("0-{}", count.clamp(1, num_cores.max(1)) - 1))
+}
+
+/// Allocates the coverage map and tells the target where to find it.
+pub fn create_coverage_map(provider: &mut UnixShMemProvider, map_size: usize) -> UnixShMem {
+    // The coverage map shared between observer and executor
+    let shmem = provider.new_shmem(map_size).unwrap();
+
+    // let the forkserver know the shmid
+    shmem.write_to_env("__AFL_SHM_ID").unwrap();
+
+    // To let know the AFL++ binary that we have a big map
+    std::env::set_var("AFL_MAP_SIZE", format!("{}", map_size));
+    shmem
+}
+
+/// Creates an observation channel using the hitcounts map of AFL++. The hit
+/// counts are classified with the given buckets. If a region is given, only
+/// the map entries within it are observed.
+pub fn edges_observer(
+    map: &mut [u8],
+    buckets: BucketTable,
+    region: Option<Range<usize>>,
+) -> EdgesObserver {
+    unsafe {
+        BucketedMapObserver::new(StdMapObserver::new(EDGES_OBSERVER_NAME, map), buckets)
+            .with_region(region)
+    }
+}
+
+/// The placeholder for the path of the input file in the target arguments.
+pub const INPUT_PATH_PLACEHOLDER: &str = "@@";
+
+/// Replaces `@@` inside of target arguments (e.g. `+input=@@`) with the path
+/// of the input file. Arguments that are exactly `@@` are left to
+/// `parse_afl_cmdline`, which also stops feeding the input via stdin.
+///
+/// The forkserver writes the encoded program (the target bytes, not the
+/// serialized corpus format) to the same file before every execution, so the
+/// path stays valid for the whole run.
+pub fn substitute_input_path(arguments: &[String], input_path: &str) -> Vec<String> {
+    arguments
+        .iter()
+        .map(|arg| {
+            if arg == INPUT_PATH_PLACEHOLDER {
+                arg.clone()
+            } else {
+                arg.replace(INPUT_PATH_PLACEHOLDER, input_path)
+            }
+        })
+        .collect()
+}
+
+/// The absolute path of the file the forkserver writes the inputs to.
+pub fn forkserver_input_path() -> String {
+    let path = PathBuf::from(get_unique_std_input_file());
+    std::env::current_dir()
+        .map(|dir| dir.join(&path))
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Creates the forkserver executor that runs the target with our observers.
+/// The issue marker and the exit code file the target gets in its
+/// environment (see [`FUZZING_ISSUE_MARKER_VAR`] and
+/// [`FUZZING_EXIT_CODE_FILE_VAR`]) are removed before every run and the
+/// exit status of every run is kept in an [`ExitStatusObserver`].
+pub fn build_executor<'a, S>(
+    config: &TargetConfig,
+    edges_observer: EdgesObserver<'a>,
+    time_observer: TimeObserver,
+    registers_observer: RegisterStateObserver<'a>,
+) -> Result<TargetExecutor<'a, S>, Error>
+where
+    S: UsesInput<Input = ProgramInput>,
+{
+    let markers = config
+        .envs
+        .iter()
+        .filter(|(key, _)| key == FUZZING_ISSUE_MARKER_VAR || key == FUZZING_EXIT_CODE_FILE_VAR)
+        .map(|(_, path)| PathBuf::from(path))
+        .collect();
+    let forkserver = ForkserverExecutor::builder()
+        .program(config.executable.clone())
+        .debug_child(config.debug_child)
+        .parse_afl_cmdline(substitute_input_path(
+            &config.arguments,
+            &forkserver_input_path(),
+        ))
+        .envs(config.envs.iter().cloned())
+        .coverage_map_size(config.map_size)
+        .is_persistent(false)
+        .is_deferred_frksrv(true)
+        .build_dynamic_map(
+            edges_observer,
+            tuple_list!(
+                time_observer,
+                registers_observer,
+                IssueMarkerObserver::new(markers),
+                ExitStatusObserver::new()
+            ),
+        )?;
+
+    let executor =
+        TimeoutForkserverExecutor::with_signal(forkserver, config.timeout, config.signal)?;
+    let executor = AdaptiveTimeoutExecutor::new(executor, config.timeout_factor, config.timeout);
+    Ok(TimeoutRerunExecutor::new(executor, config.timeout_reruns))
+}
+
+/// Consecutive failed fuzzing iterations after which the executor is rebuilt.
+pub const FAILURES_BEFORE_RESTART: u32 = 10;
+
+/// The wait before the first executor restart. Doubles with every further
+/// restart in a row.
+const RESTART_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+
+/// What to do after a fuzzing iteration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestartAction {
+    Continue,
+    /// Rebuild the executor (and so the forkserver) after waiting this long.
+    Restart(Duration),
+    /// Too many restarts without a single successful iteration in between.
+    Abort,
+}
+
+/// Decides when to rebuild an executor whose target keeps failing, e.g.
+/// because the simulator died and the forkserver can't fork anymore.
+#[derive(Clone, Debug)]
+pub struct RestartPolicy {
+    max_restarts: u32,
+    failures: u32,
+    restarts: u32,
+}
+
+impl RestartPolicy {
+    pub fn new(max_restarts: u32) -> Self {
+        Self {
+            max_restarts,
+            failures: 0,
+            restarts: 0,
+        }
+    }
+
+    /// Records the outcome of an iteration and returns what to do next.
+    pub fn record(&mut self, success: bool) -> RestartAction {
+        if success {
+            self.failures = 0;
+            self.restarts = 0;
+            return RestartAction::Continue;
+        }
+        self.failures += 1;
+        if self.failures < FAILURES_BEFORE_RESTART {
+            return RestartAction::Continue;
+        }
+        if self.restarts >= self.max_restarts {
+            return RestartAction::Abort;
+        }
+        let backoff = RESTART_BACKOFF
+            .saturating_mul(1 << self.restarts.min(16))
+            .min(MAX_RESTART_BACKOFF);
+        self.failures = 0;
+        self.restarts += 1;
+        RestartAction::Restart(backoff)
+    }
+}
+
+/// The optional parts of the corpus feedback, see [`build_feedback`].
+#[derive(Default)]
+pub struct FeedbackOptions {
+    /// Let the execution time make an input interesting. It is still
+    /// recorded for scheduling otherwise.
+    pub time_feedback: bool,
+    /// Novel hit count buckets make an input interesting.
+    pub hitcount_feedback: bool,
+    /// Rare program lengths make an input interesting.
+    pub length_feedback: bool,
+    /// Every execution is written here if given (see [`TraceFeedback`]).
+    pub trace: Option<TraceWriter>,
+    /// The near misses of the edges observer are exported here if given (see
+    /// [`FrontierFeedback`]).
+    pub frontier_dir: Option<PathBuf>,
+    /// A warning is logged if this many different inputs in a row have the
+    /// same coverage (see [`ConstantCoverageFeedback`]), zero disables this.
+    pub constant_window: usize,
+}
+
+/// Creates the corpus feedback and the matching calibration stage.
+pub fn build_feedback<'a>(
+    edges_observer: &EdgesObserver<'a>,
+    time_observer: &TimeObserver,
+    options: FeedbackOptions,
+) -> (FuzzFeedback<'a>, FuzzCalibration<'a>) {
+    let FeedbackOptions {
+        time_feedback,
+        hitcount_feedback,
+        length_feedback,
+        trace,
+        frontier_dir,
+        constant_window,
+    } = options;
+    let map_feedback = MaxMapFeedback::tracking(edges_observer, true, false);
+
+    let calibration = DummyCalibration::new(&map_feedback, time_observer);
+
+    // Feedback to rate the interestingness of an input
+    // This one is composed by several Feedbacks in OR
+    let feedback = feedback_or!(
+        // New maximization map feedback linked to the edges observer and the feedback state
+        map_feedback,
+        // Time feedback, this one does not need a feedback state
+        ToggleFeedback::new(TimeFeedback::with_observer(time_observer), time_feedback),
+        // Novel hit count buckets (only if enabled)
+        HitcountBucketFeedback::new(edges_observer, hitcount_feedback),
+        // Rare program lengths (only if enabled)
+        LengthDiversityFeedback::new(length_feedback),
+        // Execution time per instruction category (never interesting)
+        CategoryTimeFeedback::new(time_observer),
+        // Inputs that almost reached a new bucket (never interesting)
+        FrontierFeedback::new(edges_observer, frontier_dir),
+        // The same coverage for many different inputs (never interesting)
+        ConstantCoverageFeedback::new(edges_observer, constant_window)
+    );
+    (
+        TraceFeedback::new(feedback, time_observer, trace),
+        calibration,
+    )
+}
+
+/// The parts of the objective, see [`build_objective`].
+pub struct ObjectiveOptions<'a> {
+    /// The core solutions are found on, part of their name.
+    pub core_id: usize,
+    /// The file the harness creates before reporting an issue, see
+    /// [`CrashOriginFeedback`].
+    pub issue_marker: Option<PathBuf>,
+    /// Collect the immediates of saved solutions for the mutators (see
+    /// [`crate::immediates`]).
+    pub crash_immediates: bool,
+    /// Inputs that still time out after the re-runs of the executor are
+    /// solutions too.
+    pub save_hangs: bool,
+    /// Crashes with the instruction signature of an already saved one are
+    /// not solutions (see [`crate::feedbacks::inst_signature`]).
+    pub crash_dedup: bool,
+    /// Crashes matching a known signature are not solutions.
+    pub known_crashes: KnownCrashFilter<EdgesObserver<'a>>,
+    /// Crashes with too little coverage are not solutions.
+    pub low_coverage: LowCoverageFilter<EdgesObserver<'a>>,
+    /// Crashes with the signature of an already saved one are not solutions
+    /// if it has a store.
+    pub duplicates: DuplicateCrashFilter<EdgesObserver<'a>>,
+    /// Saved solutions get a crash bundle with their name, if any bundle
+    /// contents are enabled.
+    pub bundles: CrashBundleFeedback<EdgesObserver<'a>>,
+    /// Records the causes the harness reported for saved solutions.
+    pub causes: CauseFeedback,
+    /// Inputs it fires on are solutions (see [`ExitCodeFeedback`]).
+    pub exit_codes: ExitCodeFeedback,
+    /// Every solution is also copied to the directory of each of its labels
+    /// in here if given.
+    pub label_dir: Option<PathBuf>,
+    /// Solutions are written here if given, like all other executions by the
+    /// corpus feedback (see [`TraceFeedback`]).
+    pub trace: Option<TraceWriter>,
+}
+
+/// Creates the feedback to choose if an input is a solution or not. Saved
+/// solutions are named after the conditions that fired (`crash` or its
+/// origin, `hang`, `divergence`, `exitcode`, see
+/// [`crate::feedbacks::objective_label`]) and the time and core they were
+/// found on. Inputs whose final registers differ from the reference model
+/// are solutions as well (see [`crate::cosim`]).
+pub fn build_objective<'a>(
+    time_observer: &TimeObserver,
+    options: ObjectiveOptions<'a>,
+) -> FuzzObjective<'a> {
+    let ObjectiveOptions {
+        core_id,
+        issue_marker,
+        crash_immediates,
+        save_hangs,
+        crash_dedup,
+        known_crashes,
+        low_coverage,
+        duplicates,
+        bundles,
+        causes,
+        exit_codes,
+        label_dir,
+        trace,
+    } = options;
+    let crash = feedback_and_fast!(
+        CrashFeedback::new(),
+        known_crashes,
+        low_coverage,
+        InstSignatureFilter::new(crash_dedup),
+        duplicates
+    );
+    // The name depends on the labels and the origin and the bundles depend
+    // on the name.
+    let objective = feedback_or!(
+        LabeledObjective::new(crash, CRASH_LABEL),
+        LabeledObjective::new(
+            ToggleFeedback::new(TimeoutFeedback::new(), save_hangs),
+            "hang"
+        ),
+        LabeledObjective::new(DivergenceFeedback::new(), "divergence"),
+        LabeledObjective::new(exit_codes, EXIT_CODE_LABEL),
+        CrashOriginFeedback::new(issue_marker),
+        CrashImmediatesFeedback::new(crash_immediates),
+        ObjectiveNameFeedback::new(core_id).with_label_dir(label_dir),
+        bundles,
+        causes
+    );
+    TraceFeedback::solutions(objective, time_observer, trace)
+}
+
+/// Creates the fuzz state with the corpus and solutions stored on disk.
+pub fn build_state(
+    seed: u64,
+    corpus_dir: PathBuf,
+    objective_dir: PathBuf,
+    feedback: &mut FuzzFeedback,
+    objective: &mut FuzzObjective,
+) -> Result<FuzzState, Error> {
+    StdState::new(
+        StdRand::with_seed(seed),
+        OnDiskCorpus::<ProgramInput>::with_meta_format(corpus_dir, OnDiskMetadataFormat::Postcard)?,
+        OnDiskCorpus::new(objective_dir)?,
+        feedback,
+        objective,
+    )
+}
+
+/// Creates a minimization+queue policy to get testcases from the corpus with
+/// an occasional pick of an entry with rare edges, of a rare program class or
+/// a random one. The first `warmup_execs` executions go round-robin through
+/// the corpus.
+pub fn build_scheduler<'a>(
+    state: &mut FuzzState,
+    edges_observer: &EdgesObserver<'a>,
+    schedule: Option<PowerSchedule>,
+    schedule_epsilon: f64,
+    rarity_chance: f64,
+    class_chance: f64,
+    warmup_execs: usize,
+) -> FuzzScheduler<'a> {
+    WarmupScheduler::new(
+        EpsilonScheduler::new(
+            ClassScheduler::new(
+                RarityScheduler::new(
+                    IndexesLenTimeMinimizerScheduler::new(StdWeightedScheduler::with_schedule(
+                        state,
+                        edges_observer,
+                        schedule,
+                    )),
+                    edges_observer,
+                    rarity_chance,
+                ),
+                class_chance,
+            ),
+            schedule_epsilon,
+        ),
+        warmup_execs,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use libafl::{corpus::Corpus, fuzzer::StdFuzzer, observers::TimeObserver, state::HasCorpus};
+    use nix::sys::signal::Signal;
+
+    use super::{
+        build_feedback, build_objective, build_scheduler, build_state, detect_map_size,
+        edges_observer, map_size_warning, parse_map_size, parse_signal, resolve_core_spec,
+        resolve_map_size, substitute_input_path, FeedbackOptions, ObjectiveOptions, RestartAction,
+        RestartPolicy, TargetConfig, FAILURES_BEFORE_RESTART,
+    };
+    use crate::{
+        bundle::{CrashBundleConfig, CrashBundleFeedback},
+        causes::{CauseFeedback, CausesFormat},
+        feedbacks::{DuplicateCrashFilter, ExitCodeFeedback, KnownCrashFilter, LowCoverageFilter},
+        observers::BucketTable,
+    };
+
+    #[test]
+    fn construct_components() {
+        let mut map = vec![0u8; 1024];
+        let edges = edges_observer(map.as_mut_slice(), BucketTable::afl(), None);
+        let time = TimeObserver::new("time");
+
+        let options = FeedbackOptions {
+            time_feedback: true,
+            hitcount_feedback: true,
+            length_feedback: true,
+            constant_window: 100,
+            ..FeedbackOptions::default()
+        };
+        let (mut feedback, _calibration) = build_feedback(&edges, &time, options);
+        let base_dir = std::env::temp_dir().join(format!("driver-test-{}", std::process::id()));
+        let target = TargetConfig {
+            executable: "true".to_string(),
+            arguments: vec![],
+            envs: vec![],
+            debug_child: false,
+            timeout: Duration::from_secs(1),
+            timeout_reruns: 1,
+            timeout_factor: None,
+            signal: Signal::SIGKILL,
+            map_size: 1024,
+        };
+        let bundles = CrashBundleFeedback::new(
+            CrashBundleConfig::default(),
+            base_dir.join("bundles"),
+            target,
+            0,
+            &edges,
+        );
+        let known = KnownCrashFilter::new(&edges, Default::default(), None);
+        let low_coverage = LowCoverageFilter::new(&edges, 0, None);
+        let duplicates = DuplicateCrashFilter::new(&edges, None);
+        let causes =
+            CauseFeedback::new(CausesFormat::Text, base_dir.join("causes"), Signal::SIGKILL);
+        let options = ObjectiveOptions {
+            core_id: 0,
+            issue_marker: None,
+            crash_immediates: true,
+            save_hangs: false,
+            crash_dedup: true,
+            known_crashes: known,
+            low_coverage,
+            duplicates,
+            bundles,
+            causes,
+            exit_codes: ExitCodeFeedback::new(None, vec![]),
+            label_dir: None,
+            trace: None,
+        };
+        let mut objective = build_objective(&time, options);
+
+        let mut state = build_state(
+            0,
+            base_dir.join("queue"),
+            base_dir.join("found"),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let scheduler = build_scheduler(&mut state, &edges, None, 0.1, 0.1, 0.1, 100);
+        let _fuzzer = StdFuzzer::new(scheduler, feedback, objective);
+
+        assert_eq!(state.corpus().count(), 0);
+        std::fs::remove_dir_all(base_dir).ok();
+    }
+
+    #[test]
+    fn restart_after_failures() {
+        let mut policy = RestartPolicy::new(2);
+        let fail = |policy: &mut RestartPolicy| {
+            for _ in 1..FAILURES_BEFORE_RESTART {
+                assert_eq!(policy.record(false), RestartAction::Continue);
+            }
+            policy.record(false)
+        };
+        assert_eq!(
+            fail(&mut policy),
+            RestartAction::Restart(Duration::from_millis(500))
+        );
+        assert_eq!(
+            fail(&mut policy),
+            RestartAction::Restart(Duration::from_secs(1))
+        );
+        assert_eq!(fail(&mut policy), RestartAction::Abort);
+
+        // A successful iteration resets the backoff.
+        let mut policy = RestartPolicy::new(2);
+        fail(&mut policy);
+        assert_eq!(policy.record(true), RestartAction::Continue);
+        assert_eq!(
+            fail(&mut policy),
+            RestartAction::Restart(Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn core_spec_fractions() {
+        assert_eq!(resolve_core_spec("all", None, 16).unwrap(), "all");
+        assert_eq!(resolve_core_spec("1,3-4", None, 16).unwrap(), "1,3-4");
+        assert_eq!(resolve_core_spec("all/2", None, 16).unwrap(), "0-7");
+        assert_eq!(resolve_core_spec("all/3", None, 16).unwrap(), "0-4");
+        assert_eq!(resolve_core_spec("all/64", None, 16).unwrap(), "0-0");
+        assert!(resolve_core_spec("all/0", None, 16).is_err());
+        assert!(resolve_core_spec("all/x", None, 16).is_err());
+        // The fraction wins over the spec.
+        assert_eq!(resolve_core_spec("all/2", Some(0.25), 16).unwrap(), "0-3");
+        assert_eq!(resolve_core_spec("all", Some(1.0), 16).unwrap(), "0-15");
+        assert!(resolve_core_spec("all", Some(0.0), 16).is_err());
+        assert!(resolve_core_spec("all", Some(1.5), 16).is_err());
+    }
+
+    #[test]
+    fn map_sizes() {
+        assert_eq!(parse_map_size("65536"), Ok(65536));
+        assert!(parse_map_size("0").is_err());
+        assert!(parse_map_size("-1").is_err());
+        assert!(parse_map_size("lots").is_err());
+        assert_eq!(map_size_warning(65536, 4096), None);
+        assert_eq!(map_size_warning(super::DEFAULT_MAP_SIZE, 4096), None);
+        assert!(map_size_warning(1000, 4096).unwrap().contains("3096 bytes"));
+
+        // The user's size wins, targets that don't report one get the default.
+        assert_eq!(resolve_map_size("true", Some(4096)), 4096);
+        assert_eq!(resolve_map_size("true", None), super::DEFAULT_MAP_SIZE);
+        assert_eq!(
+            detect_map_size("/nonexistent", Duration::from_secs(1)),
+            None
+        );
+    }
+
+    #[test]
+    fn single_run_targets() {
+        let command = ["true", "+input=@@"].map(String::from);
+        let timeout = Duration::from_secs(1);
+        let target =
+            TargetConfig::single_runs(&command, vec![], timeout, Signal::SIGUSR2, Some(64));
+        assert_eq!(target.executable, "true");
+        assert_eq!(target.arguments, ["+input=@@"]);
+        assert_eq!(target.timeout_reruns, 0);
+        assert_eq!(target.signal, Signal::SIGUSR2);
+        assert_eq!(target.map_size, 64);
+
+        assert_eq!(parse_signal("SIGTERM"), Ok(Signal::SIGTERM));
+        assert!(parse_signal("SIGNOPE").is_err());
+    }
+
+    #[test]
+    fn input_path_substitution() {
+        let args = ["@@", "+input=@@", "--trace", "a@@b@@"].map(String::from);
+        assert_eq!(
+            substitute_input_path(&args, "/tmp/in"),
+            ["@@", "+input=/tmp/in", "--trace", "a/tmp/inb/tmp/in"].map(String::from)
+        );
+    }
+}
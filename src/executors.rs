@@ -0,0 +1,482 @@
+//! Executor wrappers around the forkserver executor.
+use core::{fmt::Debug, time::Duration};
+
+use libafl::{
+    bolts::tuples::MatchName,
+    corpus::Corpus,
+    executors::{
+        forkserver::{HasForkserver, TimeoutForkserverExecutor},
+        Executor, ExitKind, HasObservers,
+    },
+    observers::{ObserversTuple, UsesObservers},
+    state::{HasCorpus, HasMetadata, UsesState},
+    Error,
+};
+
+use crate::{
+    calibration::MeasuredTimeMetadata,
+    observers::{ExitStatusObserver, EXIT_STATUS_OBSERVER_NAME},
+};
+
+/// The lower bound of adaptive timeouts, so process startup jitter doesn't
+/// turn fast inputs into timeouts.
+pub const MIN_ADAPTIVE_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Executors whose timeout can be changed between runs.
+pub trait HasTimeout {
+    fn set_timeout(&mut self, timeout: Duration);
+}
+
+impl<E> HasTimeout for TimeoutForkserverExecutor<E> {
+    fn set_timeout(&mut self, timeout: Duration) {
+        forkserver::set_timeout(self, timeout);
+    }
+}
+
+/// Executors that know how the target process of the last run ended.
+pub trait HasExitStatus {
+    /// The raw `waitpid` status of the target in the last run, if known.
+    fn exit_status(&self) -> Option<i32>;
+}
+
+impl<E: HasForkserver> HasExitStatus for TimeoutForkserverExecutor<E> {
+    fn exit_status(&self) -> Option<i32> {
+        Some(forkserver::status(self))
+    }
+}
+
+mod forkserver {
+    use core::time::Duration;
+
+    use libafl::executors::forkserver::{HasForkserver, TimeoutForkserverExecutor};
+
+    /// The status the forkserver read for the last child, which the
+    /// executor also checks for signals.
+    pub(super) fn status<E: HasForkserver>(executor: &TimeoutForkserverExecutor<E>) -> i32 {
+        executor.executor().forkserver().status()
+    }
+
+    /// Calls the inherent method of the forkserver executor. [`HasTimeout`]
+    /// isn't in scope here, so this can't resolve to the trait method and
+    /// recurse.
+    ///
+    /// [`HasTimeout`]: super::HasTimeout
+    pub(super) fn set_timeout<E>(executor: &mut TimeoutForkserverExecutor<E>, timeout: Duration) {
+        TimeoutForkserverExecutor::<E>::set_timeout(executor, timeout);
+    }
+}
+
+/// The timeout for an input whose parent ran in `measured`: `measured`
+/// times `factor`, but at least [`MIN_ADAPTIVE_TIMEOUT`] and at most `max`.
+pub fn adaptive_timeout(measured: Duration, factor: f64, max: Duration) -> Duration {
+    measured
+        .mul_f64(factor)
+        .clamp(MIN_ADAPTIVE_TIMEOUT.min(max), max)
+}
+
+/// The factor applied to the slowest seed for an automatic timeout, as in
+/// AFL++.
+pub const AUTO_TIMEOUT_FACTOR: f64 = 5.0;
+
+/// The timeout for a corpus whose seeds ran in `exec_times`: the slowest
+/// seed times [`AUTO_TIMEOUT_FACTOR`], with the same bounds as
+/// [`adaptive_timeout`] and `ceiling` as the upper one. `None` if nothing
+/// was measured.
+pub fn auto_timeout(
+    exec_times: impl IntoIterator<Item = Duration>,
+    ceiling: Duration,
+) -> Option<Duration> {
+    exec_times
+        .into_iter()
+        .max()
+        .map(|slowest| adaptive_timeout(slowest, AUTO_TIMEOUT_FACTOR, ceiling))
+}
+
+/// Runs every input with a timeout derived from the calibrated execution
+/// time of the corpus entry it was mutated from (see
+/// [`MeasuredTimeMetadata`]) instead of the blanket timeout. Inputs without
+/// a calibrated parent, e.g. seeds, get the blanket timeout `max`, which is
+/// also the upper bound for all others.
+///
+/// A mutant that times out under a lower adaptive timeout is run again with
+/// `max`, so only inputs that don't finish within the blanket timeout are
+/// reported as timeouts. Mutants that hang are killed early, and those that
+/// are only slower than their parent cost a second run.
+#[derive(Debug)]
+pub struct AdaptiveTimeoutExecutor<E> {
+    inner: E,
+    /// The factor applied to the measured time, `None` to always use `max`.
+    factor: Option<f64>,
+    max: Duration,
+    current: Duration,
+}
+
+impl<E> AdaptiveTimeoutExecutor<E>
+where
+    E: HasTimeout,
+{
+    pub fn new(inner: E, factor: Option<f64>, max: Duration) -> Self {
+        Self {
+            inner,
+            factor,
+            max,
+            current: max,
+        }
+    }
+
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+
+    /// The timeout of the last run.
+    pub fn current_timeout(&self) -> Duration {
+        self.current
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        if timeout != self.current {
+            self.inner.set_timeout(timeout);
+            self.current = timeout;
+        }
+    }
+}
+
+impl<E: HasExitStatus> HasExitStatus for AdaptiveTimeoutExecutor<E> {
+    fn exit_status(&self) -> Option<i32> {
+        self.inner.exit_status()
+    }
+}
+
+impl<E> UsesState for AdaptiveTimeoutExecutor<E>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E> UsesObservers for AdaptiveTimeoutExecutor<E>
+where
+    E: UsesObservers,
+{
+    type Observers = E::Observers;
+}
+
+impl<E> HasObservers for AdaptiveTimeoutExecutor<E>
+where
+    E: HasObservers,
+{
+    fn observers(&self) -> &Self::Observers {
+        self.inner.observers()
+    }
+
+    fn observers_mut(&mut self) -> &mut Self::Observers {
+        self.inner.observers_mut()
+    }
+}
+
+impl<E, EM, Z> Executor<EM, Z> for AdaptiveTimeoutExecutor<E>
+where
+    E: Executor<EM, Z> + HasObservers + HasTimeout + Debug,
+    E::State: HasCorpus,
+    EM: UsesState<State = E::State>,
+    Z: UsesState<State = E::State>,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut Self::State,
+        mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        let timeout = match self.factor {
+            Some(factor) => {
+                // The entry currently being fuzzed is the parent of the input.
+                let measured = (*state.corpus().current()).and_then(|idx| {
+                    let testcase = state.corpus().get(idx).ok()?.borrow();
+                    testcase
+                        .metadata_map()
+                        .get::<MeasuredTimeMetadata>()
+                        .map(|meta| meta.exec_time)
+                });
+                measured.map_or(self.max, |measured| {
+                    adaptive_timeout(measured, factor, self.max)
+                })
+            }
+            None => self.max,
+        };
+        self.set_timeout(timeout);
+        let exit_kind = self.inner.run_target(fuzzer, state, mgr, input)?;
+        if exit_kind != ExitKind::Timeout || timeout >= self.max {
+            return Ok(exit_kind);
+        }
+        self.set_timeout(self.max);
+        // Start the observers over, so they only see the re-run.
+        self.inner.observers_mut().pre_exec_all(state, input)?;
+        self.inner.run_target(fuzzer, state, mgr, input)
+    }
+}
+
+/// Re-runs inputs that timed out, as simulator timeouts are often caused by
+/// load spikes on the host and not by the input. Only timeouts that persist
+/// over all re-runs are reported as [`ExitKind::Timeout`], otherwise the exit
+/// kind and observations of the first re-run that finished are used.
+///
+/// The exit status of the reported run is recorded in the
+/// [`ExitStatusObserver`], if there is one.
+#[derive(Debug)]
+pub struct TimeoutRerunExecutor<E> {
+    inner: E,
+    reruns: u32,
+}
+
+impl<E> TimeoutRerunExecutor<E> {
+    pub fn new(inner: E, reruns: u32) -> Self {
+        Self { inner, reruns }
+    }
+
+    pub fn inner(&self) -> &E {
+        &self.inner
+    }
+}
+
+impl<E: HasExitStatus> HasExitStatus for TimeoutRerunExecutor<E> {
+    fn exit_status(&self) -> Option<i32> {
+        self.inner.exit_status()
+    }
+}
+
+impl<E> UsesState for TimeoutRerunExecutor<E>
+where
+    E: UsesState,
+{
+    type State = E::State;
+}
+
+impl<E> UsesObservers for TimeoutRerunExecutor<E>
+where
+    E: UsesObservers,
+{
+    type Observers = E::Observers;
+}
+
+impl<E> HasObservers for TimeoutRerunExecutor<E>
+where
+    E: HasObservers,
+{
+    fn observers(&self) -> &Self::Observers {
+        self.inner.observers()
+    }
+
+    fn observers_mut(&mut self) -> &mut Self::Observers {
+        self.inner.observers_mut()
+    }
+}
+
+impl<E, EM, Z> Executor<EM, Z> for TimeoutRerunExecutor<E>
+where
+    E: Executor<EM, Z> + HasObservers + HasExitStatus + Debug,
+    EM: UsesState<State = E::State>,
+    Z: UsesState<State = E::State>,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut Self::State,
+        mgr: &mut EM,
+        input: &Self::Input,
+    ) -> Result<ExitKind, Error> {
+        let mut exit_kind = self.inner.run_target(fuzzer, state, mgr, input)?;
+        for rerun in 1..=self.reruns {
+            if exit_kind != ExitKind::Timeout {
+                break;
+            }
+            // Start the observers over, so they only see the re-run.
+            self.inner.observers_mut().pre_exec_all(state, input)?;
+            exit_kind = self.inner.run_target(fuzzer, state, mgr, input)?;
+            if exit_kind != ExitKind::Timeout {
+                log::info!(
+                    "Timeout reclassified as {:?} after {} re-run(s)",
+                    exit_kind,
+                    rerun
+                );
+            }
+        }
+        let status = self.inner.exit_status();
+        if let Some(observer) = self
+            .inner
+            .observers_mut()
+            .match_name_mut::<ExitStatusObserver>(EXIT_STATUS_OBSERVER_NAME)
+        {
+            observer.record(status);
+        }
+        Ok(exit_kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use libafl::{
+        corpus::{Corpus, Testcase},
+        events::NopEventManager,
+        executors::{Executor, ExitKind, HasObservers},
+        fuzzer::NopFuzzer,
+        observers::UsesObservers,
+        state::{HasCorpus, HasMetadata, UsesState},
+        Error,
+    };
+
+    use super::{
+        adaptive_timeout, auto_timeout, AdaptiveTimeoutExecutor, HasTimeout, MIN_ADAPTIVE_TIMEOUT,
+    };
+    use crate::{
+        calibration::MeasuredTimeMetadata,
+        program_input::ProgramInput,
+        single_run::{single_run_state, SingleRunState},
+    };
+
+    /// Pretends to run every input for `runtime` and records the timeouts
+    /// it was run with.
+    #[derive(Debug)]
+    struct MockTarget {
+        observers: (),
+        runtime: Duration,
+        timeout: Duration,
+        runs: Vec<Duration>,
+    }
+
+    impl UsesState for MockTarget {
+        type State = SingleRunState;
+    }
+
+    impl UsesObservers for MockTarget {
+        type Observers = ();
+    }
+
+    impl HasObservers for MockTarget {
+        fn observers(&self) -> &() {
+            &self.observers
+        }
+
+        fn observers_mut(&mut self) -> &mut () {
+            &mut self.observers
+        }
+    }
+
+    impl HasTimeout for MockTarget {
+        fn set_timeout(&mut self, timeout: Duration) {
+            self.timeout = timeout;
+        }
+    }
+
+    impl<EM, Z> Executor<EM, Z> for MockTarget
+    where
+        EM: UsesState<State = SingleRunState>,
+        Z: UsesState<State = SingleRunState>,
+    {
+        fn run_target(
+            &mut self,
+            _fuzzer: &mut Z,
+            _state: &mut SingleRunState,
+            _mgr: &mut EM,
+            _input: &ProgramInput,
+        ) -> Result<ExitKind, Error> {
+            self.runs.push(self.timeout);
+            if self.runtime > self.timeout {
+                Ok(ExitKind::Timeout)
+            } else {
+                Ok(ExitKind::Ok)
+            }
+        }
+    }
+
+    #[test]
+    fn adaptive_timeouts() {
+        let max = Duration::from_secs(1);
+        assert_eq!(
+            adaptive_timeout(Duration::from_millis(100), 3.0, max),
+            Duration::from_millis(300)
+        );
+        // Slow inputs never get more than the blanket timeout.
+        assert_eq!(adaptive_timeout(Duration::from_millis(500), 3.0, max), max);
+        // Very fast ones still get some headroom.
+        assert_eq!(
+            adaptive_timeout(Duration::from_micros(10), 3.0, max),
+            MIN_ADAPTIVE_TIMEOUT
+        );
+        // The minimum doesn't exceed a blanket timeout below it.
+        let tiny = Duration::from_millis(10);
+        assert_eq!(adaptive_timeout(Duration::from_micros(10), 3.0, tiny), tiny);
+    }
+
+    #[test]
+    fn auto_timeouts() {
+        let ceiling = Duration::from_secs(60);
+        let times = [Duration::from_millis(200), Duration::from_secs(1)];
+        // The slowest seed decides.
+        assert_eq!(auto_timeout(times, ceiling), Some(Duration::from_secs(5)));
+        assert_eq!(
+            auto_timeout(times, Duration::from_secs(2)),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            auto_timeout([Duration::from_micros(10)], ceiling),
+            Some(MIN_ADAPTIVE_TIMEOUT)
+        );
+        assert_eq!(auto_timeout([], ceiling), None);
+    }
+
+    #[test]
+    fn adaptive_timeout_reruns_at_max() {
+        let max = Duration::from_secs(1);
+        let target = MockTarget {
+            observers: (),
+            runtime: Duration::ZERO,
+            timeout: max,
+            runs: vec![],
+        };
+        let mut executor = AdaptiveTimeoutExecutor::new(target, Some(3.0), max);
+        let mut state = single_run_state();
+        let mut fuzzer = NopFuzzer::new();
+        let mut mgr = NopEventManager::new();
+        let input = ProgramInput::new(vec![]);
+        let mut run = |executor: &mut AdaptiveTimeoutExecutor<MockTarget>,
+                       state: &mut SingleRunState,
+                       runtime: Duration| {
+            executor.inner.runtime = runtime;
+            executor.inner.runs.clear();
+            let exit_kind = executor
+                .run_target(&mut fuzzer, state, &mut mgr, &input)
+                .unwrap();
+            (exit_kind, executor.inner.runs.clone())
+        };
+
+        // Without a calibrated parent, inputs only run with the maximum.
+        let ms = Duration::from_millis;
+        assert_eq!(
+            run(&mut executor, &mut state, ms(500)),
+            (ExitKind::Ok, vec![max])
+        );
+
+        let mut parent = Testcase::new(ProgramInput::new(vec![]));
+        parent.add_metadata(MeasuredTimeMetadata { exec_time: ms(100) });
+        let idx = state.corpus_mut().add(parent).unwrap();
+        *state.corpus_mut().current_mut() = Some(idx);
+        assert_eq!(
+            run(&mut executor, &mut state, ms(200)),
+            (ExitKind::Ok, vec![ms(300)])
+        );
+        assert_eq!(executor.current_timeout(), ms(300));
+        // Slower than the adaptive timeout, but not than the maximum.
+        assert_eq!(
+            run(&mut executor, &mut state, ms(500)),
+            (ExitKind::Ok, vec![ms(300), max])
+        );
+        assert_eq!(executor.current_timeout(), max);
+        assert_eq!(
+            run(&mut executor, &mut state, ms(2000)),
+            (ExitKind::Timeout, vec![ms(300), max])
+        );
+    }
+}
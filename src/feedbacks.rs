@@ -0,0 +1,1924 @@
+extern crate alloc;
+use alloc::string::{String, ToString};
+use core::{
+    cell::RefCell,
+    fmt::Debug,
+    hash::{BuildHasher, Hasher},
+    marker::PhantomData,
+};
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::{LineWriter, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use ahash::RandomState;
+
+use libafl::{
+    bolts::{
+        tuples::{MatchName, Named},
+        AsIter, AsSlice,
+    },
+    corpus::Testcase,
+    events::EventFirer,
+    executors::ExitKind,
+    feedbacks::Feedback,
+    inputs::UsesInput,
+    observers::{MapObserver, ObserversTuple, TimeObserver},
+    prelude::current_time,
+    state::{HasClientPerfMonitor, HasMetadata},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    generator::register_fill_len,
+    immediates::{crash_neighbors, CrashImmediatesMetadata},
+    instructions::Instruction,
+    lengths::LengthHistogramMetadata,
+    observers::BucketedMapObserver,
+    program_input::ProgramInput,
+    throughput::{dominant_category, CategoryTimeMetadata},
+    triage::CrashTriageMetadata,
+};
+
+/// The label of objectives found by the crash feedback.
+pub const CRASH_LABEL: &str = "crash";
+
+/// Separates the labels of objectives that fired several conditions, e.g.
+/// `crash+divergence`.
+pub const LABEL_SEPARATOR: &str = "+";
+
+/// Returns true if the crash condition fired for a saved objective, i.e. it
+/// has the [`CRASH_LABEL`] of its [`LabeledObjective`].
+fn saved_as_crash(testcase: &Testcase<ProgramInput>) -> bool {
+    testcase
+        .metadata_map()
+        .get::<ObjectiveLabelsMetadata>()
+        .map_or(false, |meta| {
+            meta.labels.iter().any(|label| label == CRASH_LABEL)
+        })
+}
+
+/// The label of a saved objective: the labels of all [`LabeledObjective`]s
+/// that fired, joined by [`LABEL_SEPARATOR`]. The crash label is replaced by
+/// the [`CrashOrigin`] if it is known. Testcases without labels are crashes.
+pub fn objective_label(testcase: &Testcase<ProgramInput>) -> String {
+    let origin = testcase
+        .metadata_map()
+        .get::<CrashOriginMetadata>()
+        .map_or(CRASH_LABEL, |meta| meta.origin.label());
+    let labels = match testcase.metadata_map().get::<ObjectiveLabelsMetadata>() {
+        Some(meta) if !meta.labels.is_empty() => meta.labels.clone(),
+        _ => vec![CRASH_LABEL.to_owned()],
+    };
+    labels
+        .iter()
+        .map(|label| {
+            if label == CRASH_LABEL {
+                origin
+            } else {
+                label.as_str()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(LABEL_SEPARATOR)
+}
+
+/// Gives saved objectives a file name that can be correlated with external
+/// (e.g. simulator) logs: `<label>_<unixtime>_core<id>_<hash>`, with the
+/// [`objective_label`] of the testcase.
+///
+/// With a label directory, a copy of every objective is also written to
+/// `<label dir>/<label>/` for each of its labels, so every kind of objective
+/// can be looked at on its own.
+///
+/// This feedback never considers anything interesting on its own. Combine it
+/// with the actual objective feedback via `feedback_or!` so it only gets to
+/// name testcases that the other feedback decided to keep.
+#[derive(Debug, Clone)]
+pub struct ObjectiveNameFeedback {
+    core_id: usize,
+    label_dir: Option<PathBuf>,
+}
+
+impl ObjectiveNameFeedback {
+    pub fn new(core_id: usize) -> Self {
+        Self {
+            core_id,
+            label_dir: None,
+        }
+    }
+
+    /// Also writes every objective to the directories of its labels.
+    pub fn with_label_dir(mut self, label_dir: Option<PathBuf>) -> Self {
+        self.label_dir = label_dir;
+        self
+    }
+
+    /// The file name for the given input found at the current time.
+    pub fn file_name(&self, label: &str, input: &ProgramInput) -> String {
+        format!(
+            "{}_{}_core{}_{:016x}",
+            label,
+            current_time().as_secs(),
+            self.core_id,
+            input.content_hash()
+        )
+    }
+}
+
+impl Named for ObjectiveNameFeedback {
+    fn name(&self) -> &str {
+        "ObjectiveNameFeedback"
+    }
+}
+
+impl<S> Feedback<S> for ObjectiveNameFeedback
+where
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &ProgramInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        let label = objective_label(testcase);
+        let name = match testcase.input() {
+            Some(input) => self.file_name(&label, input),
+            None => return Ok(()),
+        };
+        if let (Some(dir), Some(input)) = (&self.label_dir, testcase.input()) {
+            for label in label.split(LABEL_SEPARATOR) {
+                let dir = dir.join(label);
+                fs::create_dir_all(&dir)?;
+                fs::write(dir.join(&name), input.to_bytes()?)?;
+            }
+        }
+        *testcase.filename_mut() = Some(name);
+        Ok(())
+    }
+}
+
+libafl::impl_serdeany!(ObjectiveLabelsMetadata);
+/// The labels of the [`LabeledObjective`]s that fired for a saved objective,
+/// in the order they are composed in.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ObjectiveLabelsMetadata {
+    pub labels: Vec<String>,
+}
+
+/// Wraps one condition of a composed objective and records its label in the
+/// [`ObjectiveLabelsMetadata`] of the testcase if it fired. Compose several
+/// with `feedback_or!`, which evaluates all of them, so an input that e.g.
+/// both crashes and diverges gets both labels.
+///
+/// The label must not contain `_` or [`LABEL_SEPARATOR`], as it is part of
+/// the objective file name.
+#[derive(Debug, Clone)]
+pub struct LabeledObjective<F> {
+    inner: F,
+    label: &'static str,
+    fired: bool,
+}
+
+impl<F> LabeledObjective<F> {
+    pub fn new(inner: F, label: &'static str) -> Self {
+        debug_assert!(!label.contains('_') && !label.contains(LABEL_SEPARATOR));
+        Self {
+            inner,
+            label,
+            fired: false,
+        }
+    }
+}
+
+impl<F: Named> Named for LabeledObjective<F> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+}
+
+impl<F, S> Feedback<S> for LabeledObjective<F>
+where
+    F: Feedback<S>,
+    S: UsesInput + HasClientPerfMonitor,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        self.inner.init_state(state)
+    }
+
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &S::Input,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.fired = self
+            .inner
+            .is_interesting(state, manager, input, observers, exit_kind)?;
+        Ok(self.fired)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error> {
+        if std::mem::take(&mut self.fired) {
+            if !testcase.has_metadata::<ObjectiveLabelsMetadata>() {
+                testcase.add_metadata(ObjectiveLabelsMetadata::default());
+            }
+            testcase
+                .metadata_map_mut()
+                .get_mut::<ObjectiveLabelsMetadata>()
+                .unwrap()
+                .labels
+                .push(self.label.to_owned());
+        }
+        self.inner.append_metadata(state, testcase)
+    }
+
+    fn discard_metadata(&mut self, state: &mut S, input: &S::Input) -> Result<(), Error> {
+        self.fired = false;
+        self.inner.discard_metadata(state, input)
+    }
+}
+
+/// What caused the target to crash.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrashOrigin {
+    /// The harness deliberately crashed to report an issue in the DUT.
+    Harness,
+    /// The simulator crashed without the harness reporting anything, which
+    /// is most likely a bug in the simulator and not in the DUT.
+    Simulator,
+}
+
+impl CrashOrigin {
+    /// Returns the origin given whether the harness created its marker.
+    pub fn from_marker(marker_exists: bool) -> Self {
+        if marker_exists {
+            CrashOrigin::Harness
+        } else {
+            CrashOrigin::Simulator
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CrashOrigin::Harness => "crash",
+            CrashOrigin::Simulator => "simcrash",
+        }
+    }
+}
+
+libafl::impl_serdeany!(CrashOriginMetadata);
+/// The origin of a saved crash.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CrashOriginMetadata {
+    pub origin: CrashOrigin,
+}
+
+/// Distinguishes crashes the harness reports on purpose from crashes of the
+/// simulator itself and stores the result as [`CrashOriginMetadata`]. Every
+/// saved objective is also tagged with its likely trigger as
+/// [`CrashTriageMetadata`].
+///
+/// The harness contract: before deliberately crashing, the harness creates
+/// the file named in `FUZZING_ISSUE_MARKER` (see `markFuzzingIssue` in
+/// FuzzerAPI.h). Crashes without that file are simulator crashes. Failing to
+/// talk to the forkserver at all is reported as an error by the executor
+/// and never reaches the objective. The executor removes the marker before
+/// every run (see [`crate::observers::IssueMarkerObserver`]), as not every
+/// run reaches the objective.
+///
+/// Like [`ObjectiveNameFeedback`] this never considers anything interesting
+/// on its own and needs to come before it in `feedback_or!`.
+#[derive(Debug, Clone)]
+pub struct CrashOriginFeedback {
+    /// The marker file of our target. No origin is recorded without it.
+    marker: Option<PathBuf>,
+    last_origin: Option<CrashOrigin>,
+}
+
+impl CrashOriginFeedback {
+    pub fn new(marker: Option<PathBuf>) -> Self {
+        Self {
+            marker,
+            last_origin: None,
+        }
+    }
+}
+
+impl Named for CrashOriginFeedback {
+    fn name(&self) -> &str {
+        "CrashOriginFeedback"
+    }
+}
+
+impl<S> Feedback<S> for CrashOriginFeedback
+where
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &ProgramInput,
+        _observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.last_origin = None;
+        if let Some(marker) = &self.marker {
+            // Always remove the marker so it can't leak into the next run.
+            let marker_exists = std::fs::remove_file(marker).is_ok();
+            if *exit_kind == ExitKind::Crash {
+                let origin = CrashOrigin::from_marker(marker_exists);
+                if origin == CrashOrigin::Simulator {
+                    log::error!("Target crashed without the harness reporting an issue");
+                }
+                self.last_origin = Some(origin);
+            }
+        }
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        if let Some(origin) = self.last_origin.take() {
+            testcase.add_metadata(CrashOriginMetadata { origin });
+        }
+        if let Some(input) = testcase.input() {
+            let triage = CrashTriageMetadata::classify(input.insts());
+            testcase.add_metadata(triage);
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &ProgramInput) -> Result<(), Error> {
+        self.last_origin = None;
+        Ok(())
+    }
+}
+
+/// The label of objectives found by [`ExitCodeFeedback`].
+pub const EXIT_CODE_LABEL: &str = "exitcode";
+
+libafl::impl_serdeany!(ExitCodeMetadata);
+/// The exit code of a solution found by [`ExitCodeFeedback`].
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ExitCodeMetadata {
+    pub code: i32,
+}
+
+/// An objective that fires if the target exits normally with one of the
+/// given exit codes, see `--crash-exit-code`. The found code is stored as
+/// [`ExitCodeMetadata`].
+///
+/// The forkserver doesn't report exit codes, so this relies on the harness
+/// exiting via `exitFuzzing` (see FuzzerAPI.h), which writes the code to the
+/// file named in `FUZZING_EXIT_CODE_FILE`. The executor removes that file
+/// before every run (see [`crate::observers::IssueMarkerObserver`]). Without
+/// a file or codes this never fires.
+#[derive(Debug, Clone)]
+pub struct ExitCodeFeedback {
+    /// The file the harness writes the exit code of our target to.
+    file: Option<PathBuf>,
+    codes: Vec<i32>,
+    last_code: Option<i32>,
+}
+
+impl ExitCodeFeedback {
+    pub fn new(file: Option<PathBuf>, codes: Vec<i32>) -> Self {
+        Self {
+            file,
+            codes,
+            last_code: None,
+        }
+    }
+}
+
+impl Named for ExitCodeFeedback {
+    fn name(&self) -> &str {
+        "ExitCodeFeedback"
+    }
+}
+
+impl<S> Feedback<S> for ExitCodeFeedback
+where
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &ProgramInput,
+        _observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.last_code = None;
+        if let Some(file) = &self.file {
+            let code = fs::read_to_string(file)
+                .ok()
+                .and_then(|code| code.trim().parse().ok());
+            // Always remove the file so it can't leak into the next run.
+            let _ = fs::remove_file(file);
+            if *exit_kind == ExitKind::Ok {
+                self.last_code = code.filter(|code| self.codes.contains(code));
+            }
+        }
+        Ok(self.last_code.is_some())
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        if let Some(code) = self.last_code.take() {
+            testcase.add_metadata(ExitCodeMetadata { code });
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &ProgramInput) -> Result<(), Error> {
+        self.last_code = None;
+        Ok(())
+    }
+}
+
+/// Collects the immediates of saved objectives and their neighbors into a
+/// [`CrashImmediatesMetadata`], which the mutators use to generate values
+/// close to crash-triggering constants.
+///
+/// Like [`ObjectiveNameFeedback`] this never considers anything interesting
+/// on its own. If disabled, nothing is collected.
+#[derive(Debug, Clone)]
+pub struct CrashImmediatesFeedback {
+    enabled: bool,
+}
+
+impl CrashImmediatesFeedback {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+impl Named for CrashImmediatesFeedback {
+    fn name(&self) -> &str {
+        "CrashImmediatesFeedback"
+    }
+}
+
+impl<S> Feedback<S> for CrashImmediatesFeedback
+where
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor + HasMetadata,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &ProgramInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if let Some(input) = testcase.input() {
+            let neighbors = crash_neighbors(input.insts());
+            if !state.has_metadata::<CrashImmediatesMetadata>() {
+                state.add_metadata(CrashImmediatesMetadata::default());
+            }
+            state
+                .metadata_map_mut()
+                .get_mut::<CrashImmediatesMetadata>()
+                .unwrap()
+                .add(&neighbors);
+        }
+        Ok(())
+    }
+}
+
+/// Wraps a feedback so it can be switched off without changing the type of
+/// the composed feedback.
+///
+/// A disabled feedback still observes every execution and annotates new
+/// testcases (e.g. `TimeFeedback` still records the execution time used for
+/// scheduling), but it never contributes to the decision whether an input is
+/// interesting.
+#[derive(Debug, Clone)]
+pub struct ToggleFeedback<F> {
+    inner: F,
+    enabled: bool,
+}
+
+impl<F> ToggleFeedback<F> {
+    pub fn new(inner: F, enabled: bool) -> Self {
+        Self { inner, enabled }
+    }
+}
+
+impl<F: Named> Named for ToggleFeedback<F> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+}
+
+impl<F, S> Feedback<S> for ToggleFeedback<F>
+where
+    F: Feedback<S>,
+    S: UsesInput + HasClientPerfMonitor,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        self.inner.init_state(state)
+    }
+
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &S::Input,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let interesting = self
+            .inner
+            .is_interesting(state, manager, input, observers, exit_kind)?;
+        Ok(self.enabled && interesting)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        testcase: &mut Testcase<S::Input>,
+    ) -> Result<(), Error> {
+        self.inner.append_metadata(state, testcase)
+    }
+
+    fn discard_metadata(&mut self, state: &mut S, input: &S::Input) -> Result<(), Error> {
+        self.inner.discard_metadata(state, input)
+    }
+}
+
+/// A trace file shared by the [`TraceFeedback`]s of the corpus feedback and
+/// the objective of one client.
+#[derive(Debug, Clone)]
+pub struct TraceWriter(Rc<RefCell<LineWriter<File>>>);
+
+impl TraceWriter {
+    pub fn new(file: File) -> Self {
+        Self(Rc::new(RefCell::new(LineWriter::new(file))))
+    }
+}
+
+/// Writes one line per execution to a trace file: the program length, the
+/// exit kind, the execution time in microseconds, whether the input was
+/// interesting and whether it was a solution, e.g.
+/// `len=12 exit=Ok time_us=830 new=false solution=false`.
+///
+/// The fuzzer evaluates the objective first and the corpus feedback only for
+/// inputs that are no solution. So the objective is wrapped with
+/// [`TraceFeedback::solutions`], which only writes the lines of solutions,
+/// and the corpus feedback with [`TraceFeedback::new`], which writes all
+/// others.
+///
+/// Meant for short diagnostic runs. Without a trace file this only forwards
+/// to the wrapped feedback.
+#[derive(Debug)]
+pub struct TraceFeedback<F> {
+    inner: F,
+    time_observer_name: String,
+    trace: Option<TraceWriter>,
+    solutions: bool,
+}
+
+impl<F> TraceFeedback<F> {
+    /// Wraps the corpus feedback.
+    pub fn new(inner: F, time_observer: &TimeObserver, trace: Option<TraceWriter>) -> Self {
+        Self {
+            inner,
+            time_observer_name: time_observer.name().to_string(),
+            trace,
+            solutions: false,
+        }
+    }
+
+    /// Wraps the objective.
+    pub fn solutions(inner: F, time_observer: &TimeObserver, trace: Option<TraceWriter>) -> Self {
+        Self {
+            solutions: true,
+            ..Self::new(inner, time_observer, trace)
+        }
+    }
+}
+
+impl<F: Named> Named for TraceFeedback<F> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+}
+
+impl<F, S> Feedback<S> for TraceFeedback<F>
+where
+    F: Feedback<S>,
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        self.inner.init_state(state)
+    }
+
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &ProgramInput,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let interesting = self
+            .inner
+            .is_interesting(state, manager, input, observers, exit_kind)?;
+        let Some(trace) = &self.trace else {
+            return Ok(interesting);
+        };
+        if !self.solutions || interesting {
+            let time_observer = observers
+                .match_name::<TimeObserver>(&self.time_observer_name)
+                .ok_or_else(|| Error::key_not_found("TimeObserver not found".to_string()))?;
+            let runtime = time_observer.last_runtime().unwrap_or_default();
+            if let Err(err) = writeln!(
+                trace.0.borrow_mut(),
+                "len={} exit={:?} time_us={} new={} solution={}",
+                input.insts().len(),
+                exit_kind,
+                runtime.as_micros(),
+                interesting && !self.solutions,
+                self.solutions
+            ) {
+                log::error!("Failed to write trace: {}", err);
+            }
+        }
+        Ok(interesting)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        self.inner.append_metadata(state, testcase)
+    }
+
+    fn discard_metadata(&mut self, state: &mut S, input: &ProgramInput) -> Result<(), Error> {
+        self.inner.discard_metadata(state, input)
+    }
+}
+
+/// Rewards inputs that hit an already covered map entry with a hit count
+/// bucket that was never seen for that entry before.
+///
+/// `MaxMapFeedback` only rewards new entries or higher counts. Some hardware
+/// bugs depend on how often something happens (e.g. loop counts), which this
+/// feedback also considers novel. The observed map must already be bucketed
+/// AFL-style (e.g., by a `BucketedMapObserver`) so each bucket is one bit.
+///
+/// Note that this lets the corpus grow considerably faster, as every map entry
+/// can contribute up to eight new corpus entries instead of one.
+#[derive(Debug, Clone)]
+pub struct HitcountBucketFeedback<O, S> {
+    observer_name: String,
+    enabled: bool,
+    /// For every map entry the bitmask of the buckets seen so far.
+    seen_buckets: Vec<u8>,
+    phantom: PhantomData<(O, S)>,
+}
+
+impl<O, S> HitcountBucketFeedback<O, S>
+where
+    O: MapObserver<Entry = u8>,
+{
+    /// Creates the feedback for the given map observer. A disabled feedback
+    /// never considers anything interesting, which allows composing it
+    /// unconditionally.
+    pub fn new(map_observer: &O, enabled: bool) -> Self {
+        Self {
+            observer_name: map_observer.name().to_string(),
+            enabled,
+            seen_buckets: vec![],
+            phantom: PhantomData,
+        }
+    }
+
+    /// Records the given bucketed map entries.
+    /// Returns true if any entry hit a bucket it never hit before.
+    pub fn record_buckets<I: Iterator<Item = u8>>(&mut self, entries: I) -> bool {
+        let mut interesting = false;
+        for (i, bucket) in entries.enumerate() {
+            if i >= self.seen_buckets.len() {
+                self.seen_buckets.resize(i + 1, 0);
+            }
+            if bucket & !self.seen_buckets[i] != 0 {
+                self.seen_buckets[i] |= bucket;
+                interesting = true;
+            }
+        }
+        interesting
+    }
+}
+
+impl<O, S> Named for HitcountBucketFeedback<O, S> {
+    fn name(&self) -> &str {
+        "HitcountBucketFeedback"
+    }
+}
+
+impl<O, S> Feedback<S> for HitcountBucketFeedback<O, S>
+where
+    O: MapObserver<Entry = u8>,
+    for<'it> O: AsIter<'it, Item = u8>,
+    S: UsesInput + HasClientPerfMonitor + Debug,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &S::Input,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        if !self.enabled {
+            return Ok(false);
+        }
+        let map = observers
+            .match_name::<O>(&self.observer_name)
+            .ok_or_else(|| Error::key_not_found("MapObserver not found".to_string()))?;
+        Ok(self.record_buckets(map.as_iter().copied()))
+    }
+}
+
+/// Corpus entries per length bucket below which a length counts as
+/// under-represented.
+const UNDERREPRESENTED_LENGTH_COUNT: u64 = 4;
+
+/// Rewards inputs whose program length is rare in the corpus, which keeps the
+/// corpus from converging on a single program length.
+///
+/// The length histogram of the corpus is kept in a [`LengthHistogramMetadata`]
+/// in the state. It is also updated when the feedback is disabled, in which
+/// case the feedback never considers anything interesting.
+#[derive(Debug, Clone)]
+pub struct LengthDiversityFeedback {
+    enabled: bool,
+}
+
+impl LengthDiversityFeedback {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+impl Named for LengthDiversityFeedback {
+    fn name(&self) -> &str {
+        "LengthDiversityFeedback"
+    }
+}
+
+impl<S> Feedback<S> for LengthDiversityFeedback
+where
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor + HasMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        if !state.has_metadata::<LengthHistogramMetadata>() {
+            state.add_metadata(LengthHistogramMetadata::default());
+        }
+        Ok(())
+    }
+
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        input: &ProgramInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        if !self.enabled {
+            return Ok(false);
+        }
+        let count = state
+            .metadata_map()
+            .get::<LengthHistogramMetadata>()
+            .map_or(0, |hist| hist.count(input.insts().len()));
+        Ok(count < UNDERREPRESENTED_LENGTH_COUNT)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        if let Some(input) = testcase.input() {
+            let len = input.insts().len();
+            self.init_state(state)?;
+            state
+                .metadata_map_mut()
+                .get_mut::<LengthHistogramMetadata>()
+                .unwrap()
+                .add(len);
+        }
+        Ok(())
+    }
+}
+
+/// Attributes the execution time of every input to its dominant instruction
+/// category in the [`CategoryTimeMetadata`] of the state. Never considers
+/// anything interesting.
+#[derive(Debug, Clone)]
+pub struct CategoryTimeFeedback {
+    time_observer_name: String,
+}
+
+impl CategoryTimeFeedback {
+    pub fn new(time_observer: &TimeObserver) -> Self {
+        Self {
+            time_observer_name: time_observer.name().to_string(),
+        }
+    }
+}
+
+impl Named for CategoryTimeFeedback {
+    fn name(&self) -> &str {
+        "CategoryTimeFeedback"
+    }
+}
+
+impl<S> Feedback<S> for CategoryTimeFeedback
+where
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor + HasMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        if !state.has_metadata::<CategoryTimeMetadata>() {
+            state.add_metadata(CategoryTimeMetadata::default());
+        }
+        Ok(())
+    }
+
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        input: &ProgramInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let runtime = observers
+            .match_name::<TimeObserver>(&self.time_observer_name)
+            .ok_or_else(|| Error::key_not_found("TimeObserver not found".to_string()))?
+            .last_runtime();
+        if let Some(runtime) = *runtime {
+            self.init_state(state)?;
+            state
+                .metadata_map_mut()
+                .get_mut::<CategoryTimeMetadata>()
+                .unwrap()
+                .record(dominant_category(input.insts()), runtime);
+        }
+        Ok(false)
+    }
+}
+
+/// Exports inputs that came close to new coverage to `frontier_dir`: inputs
+/// with a map entry a few hits short of a bucket no input reached so far (see
+/// [`BucketedMapObserver::with_near_miss`]). They are promising starting points
+/// for focused mutation to get past coverage plateaus. Every entry and bucket
+/// is only exported once, as `near_<index>_<bucket>_<hash>`.
+///
+/// Never considers anything interesting. Without a directory it does nothing.
+#[derive(Debug, Clone)]
+pub struct FrontierFeedback<M> {
+    observer_name: String,
+    frontier_dir: Option<PathBuf>,
+    /// For every map entry the highest bucket seen so far.
+    seen_buckets: Vec<u8>,
+    exported: HashSet<(usize, u8)>,
+    phantom: PhantomData<M>,
+}
+
+impl<M> FrontierFeedback<M>
+where
+    M: MapObserver<Entry = u8> + Serialize + serde::de::DeserializeOwned,
+{
+    pub fn new(map_observer: &BucketedMapObserver<M>, frontier_dir: Option<PathBuf>) -> Self {
+        Self {
+            observer_name: map_observer.name().to_string(),
+            frontier_dir,
+            seen_buckets: vec![],
+            exported: HashSet::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Records the bucketed map entries and returns the near misses that
+    /// would reach a bucket never seen before and weren't returned yet.
+    pub fn record_frontier(
+        &mut self,
+        entries: &[u8],
+        near_misses: &[(usize, u8)],
+    ) -> Vec<(usize, u8)> {
+        if self.seen_buckets.len() < entries.len() {
+            self.seen_buckets.resize(entries.len(), 0);
+        }
+        for (seen, bucket) in self.seen_buckets.iter_mut().zip(entries) {
+            *seen = (*seen).max(*bucket);
+        }
+        near_misses
+            .iter()
+            .filter(|(idx, bucket)| {
+                self.seen_buckets
+                    .get(*idx)
+                    .map_or(false, |seen| bucket > seen)
+            })
+            .filter(|near_miss| self.exported.insert(**near_miss))
+            .copied()
+            .collect()
+    }
+}
+
+impl<M> Named for FrontierFeedback<M> {
+    fn name(&self) -> &str {
+        "FrontierFeedback"
+    }
+}
+
+impl<M, S> Feedback<S> for FrontierFeedback<M>
+where
+    M: MapObserver<Entry = u8> + AsSlice<Entry = u8> + Serialize + serde::de::DeserializeOwned,
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        input: &ProgramInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        let dir = match &self.frontier_dir {
+            Some(dir) => dir.clone(),
+            None => return Ok(false),
+        };
+        let map = observers
+            .match_name::<BucketedMapObserver<M>>(&self.observer_name)
+            .ok_or_else(|| Error::key_not_found("MapObserver not found".to_string()))?;
+        let frontier = self.record_frontier(map.as_slice(), map.near_misses());
+        if let Some((idx, bucket)) = frontier.first() {
+            log::debug!("Near miss on entry {} ({} new)", idx, frontier.len());
+            let name = format!("near_{}_{}_{:016x}", idx, bucket, input.content_hash());
+            fs::write(dir.join(name), input.to_bytes()?)?;
+        }
+        Ok(false)
+    }
+}
+
+/// Warns once if the coverage map looked exactly the same (by its hash) for
+/// `window` structurally different inputs in a row. Programs made of different
+/// instructions practically never cover the same hit counts, so that usually
+/// means the instrumentation broke or the harness stopped reading the input,
+/// possibly partway through a campaign.
+///
+/// Like [`FrontierFeedback`] this never considers anything interesting. A
+/// window of zero disables the check.
+#[derive(Debug, Clone)]
+pub struct ConstantCoverageFeedback<O> {
+    observer_name: String,
+    window: usize,
+    /// The map hash of the last execution.
+    last_map_hash: Option<u64>,
+    /// The content hashes of the inputs that produced the last map hash.
+    inputs: HashSet<u64>,
+    warned: bool,
+    phantom: PhantomData<O>,
+}
+
+impl<O> ConstantCoverageFeedback<O>
+where
+    O: MapObserver,
+{
+    pub fn new(map_observer: &O, window: usize) -> Self {
+        Self {
+            observer_name: map_observer.name().to_string(),
+            window,
+            last_map_hash: None,
+            inputs: HashSet::new(),
+            warned: false,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Records the map hash of an execution of the input with the given
+    /// content hash. Returns true the first time the window is full.
+    pub fn record_coverage(&mut self, map_hash: u64, input_hash: u64) -> bool {
+        if self.window == 0 || self.warned {
+            return false;
+        }
+        if self.last_map_hash != Some(map_hash) {
+            self.last_map_hash = Some(map_hash);
+            self.inputs.clear();
+        }
+        self.inputs.insert(input_hash);
+        self.warned = self.inputs.len() >= self.window;
+        self.warned
+    }
+}
+
+impl<O> Named for ConstantCoverageFeedback<O> {
+    fn name(&self) -> &str {
+        "ConstantCoverageFeedback"
+    }
+}
+
+impl<O, S> Feedback<S> for ConstantCoverageFeedback<O>
+where
+    O: MapObserver,
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        input: &ProgramInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        if self.window == 0 || self.warned {
+            return Ok(false);
+        }
+        let map = observers
+            .match_name::<O>(&self.observer_name)
+            .ok_or_else(|| Error::key_not_found("MapObserver not found".to_string()))?;
+        if self.record_coverage(map.hash(), input.content_hash()) {
+            log::warn!(
+                "The last {} different inputs all had the same coverage. The instrumentation \
+                 may be broken or the harness may ignore its input.",
+                self.window
+            );
+        }
+        Ok(false)
+    }
+}
+
+/// Identifies a crash independently of the exact input: a hash of the set of
+/// covered map entries (ignoring hit counts). Two inputs crashing along the
+/// same path get the same signature.
+pub fn crash_signature<I: Iterator<Item = usize>>(covered: I) -> u64 {
+    let mut hasher = RandomState::with_seeds(0, 0, 0, 0).build_hasher();
+    for idx in covered {
+        hasher.write_usize(idx);
+    }
+    hasher.finish()
+}
+
+/// The signature of the crash observed in the given map.
+pub fn map_crash_signature<O: MapObserver>(map: &O) -> u64 {
+    let initial = map.initial();
+    crash_signature((0..map.usable_count()).filter(|idx| *map.get(*idx) != initial))
+}
+
+/// Parses a list of crash signatures, one hex value per line. Empty lines and
+/// lines starting with `#` are ignored.
+pub fn parse_known_signatures(list: &str) -> Result<HashSet<u64>, String> {
+    let mut result = HashSet::new();
+    for line in list.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let value = u64::from_str_radix(line.trim_start_matches("0x"), 16)
+            .map_err(|_| format!("Invalid crash signature '{}'", line))?;
+        result.insert(value);
+    }
+    Ok(result)
+}
+
+/// Filters out crashes with a known [`crash_signature`] so a campaign only
+/// reports novel bugs. Combine it with the crash feedback via
+/// `feedback_and_fast!`.
+///
+/// Known crashes are dropped, or saved to `known_dir` if one is given.
+#[derive(Debug, Clone)]
+pub struct KnownCrashFilter<O> {
+    observer_name: String,
+    known: HashSet<u64>,
+    known_dir: Option<PathBuf>,
+    phantom: PhantomData<O>,
+}
+
+impl<O> KnownCrashFilter<O>
+where
+    O: MapObserver,
+{
+    pub fn new(map_observer: &O, known: HashSet<u64>, known_dir: Option<PathBuf>) -> Self {
+        Self {
+            observer_name: map_observer.name().to_string(),
+            known,
+            known_dir,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<O> Named for KnownCrashFilter<O> {
+    fn name(&self) -> &str {
+        "KnownCrashFilter"
+    }
+}
+
+impl<O, S> Feedback<S> for KnownCrashFilter<O>
+where
+    O: MapObserver,
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        input: &ProgramInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        if self.known.is_empty() {
+            return Ok(true);
+        }
+        let map = observers
+            .match_name::<O>(&self.observer_name)
+            .ok_or_else(|| Error::key_not_found("MapObserver not found".to_string()))?;
+        let signature = map_crash_signature(map);
+        if !self.known.contains(&signature) {
+            return Ok(true);
+        }
+        log::info!("Known crash {:016x}", signature);
+        if let Some(dir) = &self.known_dir {
+            let name = format!("known_{:016x}_{:016x}", signature, input.content_hash());
+            fs::write(dir.join(name), input.to_bytes()?)?;
+        }
+        Ok(false)
+    }
+}
+
+/// The crash signatures saved by all clients of a campaign, persisted so
+/// deduplication survives client restarts. Every client appends to its own
+/// `core<id>` file in the directory and reads the files of all clients, in
+/// the same format as [`parse_known_signatures`].
+#[derive(Debug, Clone)]
+pub struct SignatureStore {
+    dir: PathBuf,
+    file: PathBuf,
+    seen: HashSet<u64>,
+}
+
+impl SignatureStore {
+    /// Opens the store in `dir` and loads the signatures of all clients.
+    pub fn open(dir: &Path, core_id: usize) -> Result<Self, Error> {
+        fs::create_dir_all(dir)?;
+        let mut result = Self {
+            dir: dir.to_path_buf(),
+            file: dir.join(format!("core{}", core_id)),
+            seen: HashSet::new(),
+        };
+        result.reload()?;
+        Ok(result)
+    }
+
+    /// Merges the files of all clients into the known signatures. A last line
+    /// without a newline is skipped, as another client might be in the
+    /// middle of writing it.
+    pub fn reload(&mut self) -> Result<(), Error> {
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                continue;
+            }
+            let content = fs::read_to_string(&path)?;
+            let complete = content.rsplit_once('\n').map_or("", |(lines, _)| lines);
+            for line in complete.lines() {
+                if let Ok(value) = u64::from_str_radix(line.trim(), 16) {
+                    self.seen.insert(value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns true if any client saved the signature. Rereads the files of
+    /// the other clients if it isn't known yet.
+    pub fn contains(&mut self, signature: u64) -> Result<bool, Error> {
+        if !self.seen.contains(&signature) {
+            self.reload()?;
+        }
+        Ok(self.seen.contains(&signature))
+    }
+
+    /// Records the signature and appends it to this client's file.
+    pub fn insert(&mut self, signature: u64) -> Result<(), Error> {
+        if !self.seen.insert(signature) {
+            return Ok(());
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.file)?;
+        // A single write, so readers see either nothing or the whole line.
+        file.write_all(format!("{:016x}\n", signature).as_bytes())?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+}
+
+/// Drops crashes with a [`crash_signature`] that was already saved, by this
+/// or any other client and also before a restart (see [`SignatureStore`]).
+/// Combine it with the crash feedback via `feedback_and_fast!` inside the
+/// [`LabeledObjective`] with [`CRASH_LABEL`]. The signature is only recorded
+/// if the saved testcase got that label, i.e. the whole crash condition
+/// fired and not only another objective. Without a store every crash is kept.
+#[derive(Debug, Clone)]
+pub struct DuplicateCrashFilter<O> {
+    observer_name: String,
+    store: Option<SignatureStore>,
+    /// The signature of the last crash, recorded once it was saved.
+    pending: Option<u64>,
+    phantom: PhantomData<O>,
+}
+
+impl<O> DuplicateCrashFilter<O>
+where
+    O: MapObserver,
+{
+    pub fn new(map_observer: &O, store: Option<SignatureStore>) -> Self {
+        Self {
+            observer_name: map_observer.name().to_string(),
+            store,
+            pending: None,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<O> Named for DuplicateCrashFilter<O> {
+    fn name(&self) -> &str {
+        "DuplicateCrashFilter"
+    }
+}
+
+impl<O, S> Feedback<S> for DuplicateCrashFilter<O>
+where
+    O: MapObserver,
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &ProgramInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.pending = None;
+        let store = match &mut self.store {
+            Some(store) => store,
+            None => return Ok(true),
+        };
+        let map = observers
+            .match_name::<O>(&self.observer_name)
+            .ok_or_else(|| Error::key_not_found("MapObserver not found".to_string()))?;
+        let signature = map_crash_signature(map);
+        if store.contains(signature)? {
+            log::info!("Duplicate crash {:016x}", signature);
+            return Ok(false);
+        }
+        self.pending = Some(signature);
+        Ok(true)
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        let pending = self.pending.take();
+        if let (Some(store), Some(signature)) = (
+            &mut self.store,
+            pending.filter(|_| saved_as_crash(testcase)),
+        ) {
+            store.insert(signature)?;
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &ProgramInput) -> Result<(), Error> {
+        self.pending = None;
+        Ok(())
+    }
+}
+
+/// Identifies a crash by its program independently of the operands: a hash
+/// of the mnemonics in order. Programs that only differ in registers or
+/// immediates get the same signature. The register fill prologue is ignored.
+pub fn inst_signature(insts: &[Instruction]) -> u64 {
+    let mut hasher = RandomState::with_seeds(0, 0, 0, 0).build_hasher();
+    for inst in &insts[register_fill_len(insts)..] {
+        hasher.write(inst.template().name().as_bytes());
+        // Keeps e.g. `add`,`i` apart from `addi`.
+        hasher.write_u8(0);
+    }
+    hasher.finish()
+}
+
+libafl::impl_serdeany!(InstSignaturesMetadata);
+/// The [`inst_signature`]s of the crashes saved by this client.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct InstSignaturesMetadata {
+    pub seen: HashSet<u64>,
+}
+
+/// Drops crashes with an [`inst_signature`] that was already saved, which
+/// are mostly the same bug with renamed registers. Combine it with the crash
+/// feedback via `feedback_and_fast!` inside the [`LabeledObjective`] with
+/// [`CRASH_LABEL`]. The signatures are kept in the state as
+/// [`InstSignaturesMetadata`] once a testcase with that label is saved, so
+/// crashes dropped by a later filter don't count. If disabled, every crash
+/// is kept.
+#[derive(Debug, Clone)]
+pub struct InstSignatureFilter {
+    enabled: bool,
+    /// The signature of the last crash, recorded once it was saved.
+    pending: Option<u64>,
+}
+
+impl InstSignatureFilter {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            pending: None,
+        }
+    }
+}
+
+impl Named for InstSignatureFilter {
+    fn name(&self) -> &str {
+        "InstSignatureFilter"
+    }
+}
+
+impl<S> Feedback<S> for InstSignatureFilter
+where
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor + HasMetadata,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        input: &ProgramInput,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        self.pending = None;
+        if !self.enabled {
+            return Ok(true);
+        }
+        let signature = inst_signature(input.insts());
+        let seen = state
+            .metadata_map()
+            .get::<InstSignaturesMetadata>()
+            .map_or(false, |meta| meta.seen.contains(&signature));
+        if seen {
+            log::info!("Crash with known instruction signature {:016x}", signature);
+            return Ok(false);
+        }
+        self.pending = Some(signature);
+        Ok(true)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        testcase: &mut Testcase<ProgramInput>,
+    ) -> Result<(), Error> {
+        let pending = self.pending.take();
+        if let Some(signature) = pending.filter(|_| saved_as_crash(testcase)) {
+            if !state.has_metadata::<InstSignaturesMetadata>() {
+                state.add_metadata(InstSignaturesMetadata::default());
+            }
+            let meta = state
+                .metadata_map_mut()
+                .get_mut::<InstSignaturesMetadata>()
+                .unwrap();
+            meta.seen.insert(signature);
+        }
+        Ok(())
+    }
+
+    fn discard_metadata(&mut self, _state: &mut S, _input: &ProgramInput) -> Result<(), Error> {
+        self.pending = None;
+        Ok(())
+    }
+}
+
+/// Drops crashes that cover fewer than `min_coverage` map entries, which are
+/// often artifacts of a flaky harness (e.g. the simulator dying during
+/// startup) rather than DUT bugs. Combine it with the crash feedback via
+/// `feedback_and_fast!`.
+///
+/// The trade-off: a real bug that triggers right at the start of a program
+/// can also cover very little and would be filtered as well. Such crashes are
+/// therefore saved to `low_coverage_dir` if one is given instead of being
+/// dropped. A threshold of zero keeps every crash.
+#[derive(Debug, Clone)]
+pub struct LowCoverageFilter<O> {
+    observer_name: String,
+    min_coverage: usize,
+    low_coverage_dir: Option<PathBuf>,
+    phantom: PhantomData<O>,
+}
+
+impl<O> LowCoverageFilter<O>
+where
+    O: MapObserver,
+{
+    pub fn new(map_observer: &O, min_coverage: usize, low_coverage_dir: Option<PathBuf>) -> Self {
+        Self {
+            observer_name: map_observer.name().to_string(),
+            min_coverage,
+            low_coverage_dir,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<O> Named for LowCoverageFilter<O> {
+    fn name(&self) -> &str {
+        "LowCoverageFilter"
+    }
+}
+
+impl<O, S> Feedback<S> for LowCoverageFilter<O>
+where
+    O: MapObserver,
+    S: UsesInput<Input = ProgramInput> + HasClientPerfMonitor,
+{
+    fn is_interesting<EM, OT>(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        input: &ProgramInput,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        EM: EventFirer<State = S>,
+        OT: ObserversTuple<S>,
+    {
+        if self.min_coverage == 0 {
+            return Ok(true);
+        }
+        let map = observers
+            .match_name::<O>(&self.observer_name)
+            .ok_or_else(|| Error::key_not_found("MapObserver not found".to_string()))?;
+        let covered = map.count_bytes() as usize;
+        if covered >= self.min_coverage {
+            return Ok(true);
+        }
+        log::info!("Crash with low coverage ({} entries)", covered);
+        if let Some(dir) = &self.low_coverage_dir {
+            let name = format!("low_{}_{:016x}", covered, input.content_hash());
+            fs::write(dir.join(name), input.to_bytes()?)?;
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::{
+        bolts::tuples::tuple_list,
+        corpus::{InMemoryCorpus, Testcase},
+        events::NopEventManager,
+        executors::ExitKind,
+        feedback_or,
+        feedbacks::{ConstFeedback, CrashFeedback, Feedback},
+        observers::StdMapObserver,
+        prelude::StdRand,
+        state::{HasMetadata, StdState},
+    };
+
+    use super::{
+        crash_signature, inst_signature, objective_label, parse_known_signatures,
+        ConstantCoverageFeedback, CrashOrigin, ExitCodeFeedback, ExitCodeMetadata,
+        FrontierFeedback, HitcountBucketFeedback, InstSignatureFilter, LabeledObjective,
+        ObjectiveLabelsMetadata, SignatureStore, CRASH_LABEL, EXIT_CODE_LABEL,
+    };
+    use crate::{
+        instructions::{
+            riscv::{
+                args,
+                rv_i::{ADD, SUB},
+            },
+            test_insts::inst_with,
+        },
+        program_input::ProgramInput,
+    };
+
+    type TestFeedback = HitcountBucketFeedback<StdMapObserver<'static, u8, false>, ()>;
+
+    fn feedback() -> TestFeedback {
+        TestFeedback {
+            observer_name: "map".to_string(),
+            enabled: true,
+            seen_buckets: vec![],
+            phantom: Default::default(),
+        }
+    }
+
+    #[test]
+    fn new_bucket_on_covered_entry_is_interesting() {
+        let mut feedback = feedback();
+        assert!(feedback.record_buckets([0u8, 1, 0].into_iter()));
+        // Same coverage again is not interesting.
+        assert!(!feedback.record_buckets([0u8, 1, 0].into_iter()));
+        // A lower bucket on the same entry is also novel.
+        assert!(feedback.record_buckets([0u8, 4, 0].into_iter()));
+        assert!(!feedback.record_buckets([0u8, 1, 0].into_iter()));
+        assert!(!feedback.record_buckets([0u8, 4, 0].into_iter()));
+    }
+
+    #[test]
+    fn frontier_of_near_misses() {
+        let mut frontier = FrontierFeedback::<StdMapObserver<'static, u8, false>> {
+            observer_name: "map".to_string(),
+            frontier_dir: None,
+            seen_buckets: vec![],
+            exported: Default::default(),
+            phantom: Default::default(),
+        };
+        assert_eq!(frontier.record_frontier(&[1, 4, 0], &[(1, 8)]), [(1, 8)]);
+        // Every near miss is only reported once.
+        assert!(frontier.record_frontier(&[1, 4, 0], &[(1, 8)]).is_empty());
+        // Buckets another input already reached are no frontier.
+        assert!(frontier.record_frontier(&[2, 0, 0], &[]).is_empty());
+        assert!(frontier.record_frontier(&[1, 0, 0], &[(0, 2)]).is_empty());
+        assert_eq!(
+            frontier.record_frontier(&[1, 0, 0], &[(0, 4), (2, 1)]),
+            [(0, 4), (2, 1)]
+        );
+    }
+
+    #[test]
+    fn constant_coverage_warns_once() {
+        let mut map = [0u8; 4];
+        let observer = unsafe { StdMapObserver::new("map", &mut map) };
+        let mut constant = ConstantCoverageFeedback::new(&observer, 3);
+        assert!(!constant.record_coverage(1, 10));
+        // Re-running the same input doesn't count.
+        assert!(!constant.record_coverage(1, 10));
+        assert!(!constant.record_coverage(1, 11));
+        // Different coverage restarts the window.
+        assert!(!constant.record_coverage(2, 12));
+        assert!(!constant.record_coverage(2, 13));
+        assert!(constant.record_coverage(2, 14));
+        // Only one warning.
+        assert!(!constant.record_coverage(2, 15));
+
+        let mut disabled = ConstantCoverageFeedback::new(&observer, 0);
+        assert!((0..100).all(|input| !disabled.record_coverage(1, input)));
+    }
+
+    #[test]
+    fn labels_of_simultaneous_objectives() {
+        let mut objective = feedback_or!(
+            LabeledObjective::new(CrashFeedback::new(), "crash"),
+            LabeledObjective::new(ConstFeedback::new(false), "never"),
+            LabeledObjective::new(ConstFeedback::new(true), "always")
+        );
+        let mut feedback = ();
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::<ProgramInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let mut mgr = NopEventManager::new();
+        let input = ProgramInput::new(vec![]);
+
+        let fired = objective
+            .is_interesting(
+                &mut state,
+                &mut mgr,
+                &input,
+                &tuple_list!(),
+                &ExitKind::Crash,
+            )
+            .unwrap();
+        assert!(fired);
+        let mut testcase = Testcase::new(input.clone());
+        objective
+            .append_metadata(&mut state, &mut testcase)
+            .unwrap();
+        let labels = &testcase
+            .metadata_map()
+            .get::<ObjectiveLabelsMetadata>()
+            .unwrap()
+            .labels;
+        assert_eq!(labels, &["crash", "always"]);
+        assert_eq!(objective_label(&testcase), "crash+always");
+
+        // Labels don't carry over to the next objective.
+        objective
+            .is_interesting(&mut state, &mut mgr, &input, &tuple_list!(), &ExitKind::Ok)
+            .unwrap();
+        let mut testcase = Testcase::new(input);
+        objective
+            .append_metadata(&mut state, &mut testcase)
+            .unwrap();
+        assert_eq!(objective_label(&testcase), "always");
+    }
+
+    #[test]
+    fn configured_exit_codes_are_objectives() {
+        let file = std::env::temp_dir().join(format!("exit-code-test-{}", std::process::id()));
+        let mut objective = feedback_or!(
+            LabeledObjective::new(CrashFeedback::new(), CRASH_LABEL),
+            LabeledObjective::new(
+                ExitCodeFeedback::new(Some(file.clone()), vec![3, 42]),
+                EXIT_CODE_LABEL
+            )
+        );
+        let mut feedback = ();
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::<ProgramInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let mut mgr = NopEventManager::new();
+        let input = ProgramInput::new(vec![]);
+        let mut run = |code: Option<&str>, exit_kind: ExitKind| {
+            if let Some(code) = code {
+                std::fs::write(&file, code).unwrap();
+            }
+            objective
+                .is_interesting(&mut state, &mut mgr, &input, &tuple_list!(), &exit_kind)
+                .unwrap()
+        };
+
+        assert!(!run(Some("0"), ExitKind::Ok));
+        assert!(!run(Some("1"), ExitKind::Ok));
+        // The file of the last run is gone.
+        assert!(!run(None, ExitKind::Ok));
+        // Garbage in the file is ignored.
+        assert!(!run(Some("forty-two"), ExitKind::Ok));
+        // Codes of timed out runs don't count.
+        assert!(!run(Some("42"), ExitKind::Timeout));
+        assert!(run(Some("42\n"), ExitKind::Ok));
+
+        let mut testcase = Testcase::new(input.clone());
+        objective
+            .append_metadata(&mut state, &mut testcase)
+            .unwrap();
+        assert_eq!(objective_label(&testcase), EXIT_CODE_LABEL);
+        assert_eq!(
+            testcase
+                .metadata_map()
+                .get::<ExitCodeMetadata>()
+                .unwrap()
+                .code,
+            42
+        );
+        assert!(!file.exists());
+
+        let mut disabled = ExitCodeFeedback::new(None, vec![0]);
+        let fired = disabled
+            .is_interesting(&mut state, &mut mgr, &input, &tuple_list!(), &ExitKind::Ok)
+            .unwrap();
+        assert!(!fired);
+    }
+
+    #[test]
+    fn crash_origin_labels() {
+        assert_eq!(CrashOrigin::from_marker(true), CrashOrigin::Harness);
+        assert_eq!(CrashOrigin::from_marker(false), CrashOrigin::Simulator);
+        assert_ne!(CrashOrigin::Harness.label(), CrashOrigin::Simulator.label());
+    }
+
+    #[test]
+    fn known_crash_signatures() {
+        assert_eq!(
+            crash_signature([1, 5, 9].into_iter()),
+            crash_signature([1, 5, 9].into_iter())
+        );
+        assert_ne!(
+            crash_signature([1, 5, 9].into_iter()),
+            crash_signature([1, 5].into_iter())
+        );
+
+        let sig = crash_signature([1, 5, 9].into_iter());
+        let list = format!("# known bugs\n\n{:016x}\n0x12\n", sig);
+        let known = parse_known_signatures(&list).unwrap();
+        assert_eq!(known.len(), 2);
+        assert!(known.contains(&sig));
+        assert!(known.contains(&0x12));
+        assert!(parse_known_signatures("xyz").is_err());
+    }
+
+    #[test]
+    fn crashes_deduplicated_by_inst_signature() {
+        let inst = |template, rd| {
+            inst_with(
+                template,
+                &[(&args::RD, rd), (&args::RS1, 1), (&args::RS2, 2)],
+            )
+        };
+        let crash = ProgramInput::new(vec![inst(&ADD, 3), inst(&SUB, 4)]);
+        let renamed = ProgramInput::new(vec![inst(&ADD, 5), inst(&SUB, 6)]);
+        let other = ProgramInput::new(vec![inst(&SUB, 3), inst(&ADD, 4)]);
+        assert_eq!(
+            inst_signature(crash.insts()),
+            inst_signature(renamed.insts())
+        );
+        assert_ne!(inst_signature(crash.insts()), inst_signature(other.insts()));
+
+        type TestState = StdState<
+            ProgramInput,
+            InMemoryCorpus<ProgramInput>,
+            StdRand,
+            InMemoryCorpus<ProgramInput>,
+        >;
+        let mut state = TestState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::new(),
+            InMemoryCorpus::new(),
+            &mut (),
+            &mut (),
+        )
+        .unwrap();
+        let is_new = |filter: &mut InstSignatureFilter, state: &mut TestState, input| {
+            filter
+                .is_interesting(
+                    state,
+                    &mut NopEventManager::new(),
+                    input,
+                    &tuple_list!(),
+                    &ExitKind::Crash,
+                )
+                .unwrap()
+        };
+
+        let mut filter = InstSignatureFilter::new(true);
+        // Only saved crashes count.
+        assert!(is_new(&mut filter, &mut state, &crash));
+        filter.discard_metadata(&mut state, &crash).unwrap();
+        assert!(is_new(&mut filter, &mut state, &crash));
+        // Saved for another objective, e.g. a later crash filter failed.
+        filter
+            .append_metadata(&mut state, &mut Testcase::new(crash.clone()))
+            .unwrap();
+        assert!(is_new(&mut filter, &mut state, &crash));
+        let mut testcase = Testcase::new(crash.clone());
+        testcase.add_metadata(ObjectiveLabelsMetadata {
+            labels: vec![CRASH_LABEL.to_string()],
+        });
+        filter.append_metadata(&mut state, &mut testcase).unwrap();
+        assert!(!is_new(&mut filter, &mut state, &renamed));
+        assert!(is_new(&mut filter, &mut state, &other));
+
+        let mut disabled = InstSignatureFilter::new(false);
+        assert!(is_new(&mut disabled, &mut state, &crash));
+    }
+
+    #[test]
+    fn signatures_persist_across_clients_and_restarts() {
+        let dir = std::env::temp_dir().join(format!("signatures-test-{}", std::process::id()));
+        let mut first = SignatureStore::open(&dir, 0).unwrap();
+        let mut second = SignatureStore::open(&dir, 1).unwrap();
+        assert!(first.is_empty());
+
+        first.insert(0x1234).unwrap();
+        first.insert(0x1234).unwrap();
+        // Other clients pick up new signatures when they look them up.
+        assert!(second.contains(0x1234).unwrap());
+        assert!(!second.contains(0x5678).unwrap());
+        second.insert(0x5678).unwrap();
+
+        // A restarted client starts with the signatures of all clients.
+        let restarted = SignatureStore::open(&dir, 0).unwrap();
+        assert_eq!(restarted.len(), 2);
+        // The files are valid known crash lists.
+        let own = std::fs::read_to_string(dir.join("core0")).unwrap();
+        assert_eq!(parse_known_signatures(&own).unwrap().len(), 1);
+
+        // Partially written lines are skipped.
+        std::fs::write(dir.join("core2"), "00000000000000ab\n0000").unwrap();
+        let mut reloaded = SignatureStore::open(&dir, 0).unwrap();
+        assert!(reloaded.contains(0xab).unwrap());
+        assert!(!reloaded.contains(0).unwrap());
+        assert_eq!(reloaded.len(), 3);
+
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
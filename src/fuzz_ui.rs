@@ -4,9 +4,11 @@ use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use libafl::prelude::{current_time, format_duration_hms};
+use nix::sys::signal::{signal, SigHandler, Signal};
 use std::{
     collections::{HashMap, HashSet, VecDeque},
-    io::{self, Stdout},
+    io::{self, IsTerminal, Stdout},
+    sync::atomic::{AtomicBool, Ordering},
     time::{Duration, Instant},
 };
 use tui::{
@@ -24,6 +26,33 @@ use crate::causes::list_causes;
 // Every nth corpus increase that should be logged.
 const EVERY_N_CORPUS: u64 = 1000;
 
+// How often the plain UI prints the per-client rows.
+const PLAIN_CLIENTS_INTERVAL: Duration = Duration::from_secs(10);
+
+// At most this many client rows are shown in the terminal UI.
+const MAX_CLIENT_ROWS: usize = 16;
+
+/// Set by the SIGWINCH handler, the next tick then redraws everything.
+static TERMINAL_RESIZED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_terminal_resize(_: i32) {
+    TERMINAL_RESIZED.store(true, Ordering::Relaxed);
+}
+
+/// Replaces control characters (e.g. escape sequences in target output or
+/// file names) that would garble the terminal.
+pub fn sanitize_for_display(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_control() {
+                char::REPLACEMENT_CHARACTER
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
 pub struct TimeData {
     // The time when this data point was created.
     time: f64,
@@ -39,6 +68,29 @@ pub struct FuzzUIData {
     time_since_last_find_group: f64,
     start_time: std::time::Duration,
     messages: VecDeque<String>,
+    // One `CATEGORY MEAN/EXECS` row per instruction category.
+    category_times: Vec<String>,
+    // One row with the execution speed and last find per client.
+    client_rows: Vec<String>,
+    // The filled entries and the size of the map in the last execution.
+    map_density: Option<(u64, u64)>,
+    // The stable and the filled entries over all calibrations.
+    stability: Option<(u64, u64)>,
+}
+
+/// A `PART/WHOLE (PERCENT%)` ratio for the map box, or `-` before the first
+/// report.
+fn format_ratio(name: &str, ratio: Option<(u64, u64)>) -> String {
+    match ratio {
+        Some((part, whole)) if whole != 0 => format!(
+            "{}: {}/{} ({:.2}%)",
+            name,
+            part,
+            whole,
+            part as f64 * 100.0 / whole as f64
+        ),
+        _ => format!("{}: -", name),
+    }
 }
 
 impl FuzzUIData {
@@ -76,7 +128,33 @@ impl FuzzUIData {
     }
 
     pub fn add_message(&mut self, value: String) {
-        self.messages.push_front(value);
+        self.messages.push_front(sanitize_for_display(&value));
+    }
+
+    /// Takes the summary of a `CategoryTimeMetadata`.
+    pub fn set_category_times(&mut self, summary: &str) {
+        self.category_times = summary.split(", ").map(sanitize_for_display).collect();
+    }
+
+    /// Takes one row per client, see `HWFuzzMonitor`.
+    pub fn set_client_rows(&mut self, rows: Vec<String>) {
+        self.client_rows = rows;
+    }
+
+    pub fn set_map_density(&mut self, filled: u64, size: u64) {
+        self.map_density = Some((filled, size));
+    }
+
+    pub fn set_stability(&mut self, stable: u64, filled: u64) {
+        self.stability = Some((stable, filled));
+    }
+
+    /// The rows of the map box: the density and the stability.
+    pub fn map_rows(&self) -> Vec<String> {
+        vec![
+            format_ratio("map density", self.map_density),
+            format_ratio("stability", self.stability),
+        ]
     }
 
     fn rel_time_secs(&self) -> f64 {
@@ -87,6 +165,7 @@ impl FuzzUIData {
 pub struct FuzzUI {
     terminal: Option<Terminal<CrosstermBackend<Stdout>>>,
     last_tick: Instant,
+    last_clients_print: Instant,
     data: FuzzUIData,
 }
 
@@ -98,6 +177,10 @@ impl FuzzUI {
             time_since_last_find_group: 0.0,
             start_time: current_time(),
             messages: VecDeque::<String>::new(),
+            category_times: vec![],
+            client_rows: vec![],
+            map_density: None,
+            stability: None,
         };
         data.time_since_last_find.push(TimeData {
             time: 0.0,
@@ -105,64 +188,110 @@ impl FuzzUI {
             rel_time: 0.0,
         });
 
-        if !simple_ui {
-            // setup terminal
-            enable_raw_mode().expect("Failed to enable raw terminal mode");
-            let mut stdout = io::stdout();
-            execute!(stdout, EnterAlternateScreen, EnableMouseCapture)
-                .expect("Failed to enable terminal mode");
-            let backend = CrosstermBackend::new(stdout);
-            let terminal = Terminal::new(backend).expect("Failed to create terminal wrapper");
-            FuzzUI {
-                terminal: Some(terminal),
-                last_tick: Instant::now(),
-                data,
-            }
+        // Without a terminal (e.g. when piping the output into a file) we
+        // must not emit any control sequences, so fall back to plain lines.
+        let terminal = if simple_ui || !io::stdout().is_terminal() {
+            None
         } else {
-            FuzzUI {
-                terminal: None,
-                last_tick: Instant::now(),
-                data,
+            match setup_terminal() {
+                Ok(terminal) => Some(terminal),
+                Err(err) => {
+                    restore_terminal(None);
+                    eprintln!(
+                        "Failed to set up the terminal UI, using plain output: {}",
+                        err
+                    );
+                    None
+                }
             }
+        };
+        FuzzUI {
+            terminal,
+            last_tick: Instant::now(),
+            last_clients_print: Instant::now(),
+            data,
         }
     }
 
+    /// Returns true if the UI only prints plain lines.
+    pub fn is_plain(&self) -> bool {
+        self.terminal.is_none()
+    }
+
     pub fn data(&mut self) -> &mut FuzzUIData {
         &mut self.data
     }
 
     fn on_tick(&mut self) {
         if let Some(term) = self.terminal.as_mut() {
-            term.draw(|f| ui(f, &self.data)).unwrap();
+            // After a resize the old screen contents can't be diffed
+            // against anymore, so start from a blank screen.
+            let resized = TERMINAL_RESIZED.swap(false, Ordering::Relaxed);
+            let result = if resized { term.clear() } else { Ok(()) }
+                .and_then(|_| term.draw(|f| ui(f, &self.data)).map(|_| ()));
+            if let Err(err) = result {
+                // E.g. the ssh connection is gone. Keep fuzzing without UI.
+                restore_terminal(self.terminal.take());
+                eprintln!("Terminal UI failed, using plain output: {}", err);
+            }
         } else {
             if !self.data.messages.is_empty() {
                 println!("{}", self.data.messages.front().unwrap());
             }
+            if self.last_clients_print.elapsed() >= PLAIN_CLIENTS_INTERVAL {
+                self.last_clients_print = Instant::now();
+                for row in &self.data.client_rows {
+                    println!("  {}", row);
+                }
+            }
         }
     }
 
     pub fn try_tick(&mut self) {
         let tick_rate = Duration::from_millis(250);
 
-        if self.last_tick.elapsed() >= tick_rate {
+        let resized = self.terminal.is_some() && TERMINAL_RESIZED.load(Ordering::Relaxed);
+        if resized || self.last_tick.elapsed() >= tick_rate {
             self.on_tick();
             self.last_tick = Instant::now();
         }
     }
 }
 
-impl Drop for FuzzUI {
-    fn drop(&mut self) {
-        if let Some(term) = self.terminal.as_mut() {
-            // restore terminal
-            disable_raw_mode().unwrap();
+fn setup_terminal() -> io::Result<Terminal<CrosstermBackend<Stdout>>> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    // SAFETY: The handler only stores to an atomic.
+    unsafe { signal(Signal::SIGWINCH, SigHandler::Handler(on_terminal_resize)) }
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    Terminal::new(CrosstermBackend::new(stdout))
+}
+
+/// Restores the terminal as far as possible. Errors are ignored as there is
+/// nothing left to do about them.
+fn restore_terminal(terminal: Option<Terminal<CrosstermBackend<Stdout>>>) {
+    disable_raw_mode().ok();
+    match terminal {
+        Some(mut term) => {
             execute!(
                 term.backend_mut(),
                 LeaveAlternateScreen,
                 DisableMouseCapture
             )
-            .unwrap();
-            term.show_cursor().unwrap();
+            .ok();
+            term.show_cursor().ok();
+        }
+        None => {
+            execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture).ok();
+        }
+    }
+}
+
+impl Drop for FuzzUI {
+    fn drop(&mut self) {
+        if self.terminal.is_some() {
+            restore_terminal(self.terminal.take());
         }
     }
 }
@@ -352,18 +481,55 @@ fn ui<B: Backend>(f: &mut Frame<B>, data: &FuzzUIData) {
 
     let findings: Vec<ListItem> = cause_list
         .iter()
+        .map(|i| sanitize_for_display(i))
         .map(|i| {
             if i.contains("Missing") {
-                ListItem::new(i.as_str()).style(Style::default().fg(Color::Red))
+                ListItem::new(i).style(Style::default().fg(Color::Red))
             } else {
-                ListItem::new(i.as_str()).style(Style::default())
+                ListItem::new(i).style(Style::default())
             }
         })
         .collect();
     let findings_list =
         List::new(findings).block(Block::default().borders(Borders::ALL).title("Findings"));
 
-    f.render_widget(findings_list, top_chunks[1]);
+    let client_rows = data.client_rows.len().min(MAX_CLIENT_ROWS) as u16;
+    let right_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(5),
+            Constraint::Length(4),
+            Constraint::Length(9),
+            Constraint::Length(client_rows + 2),
+        ])
+        .split(top_chunks[1]);
+
+    f.render_widget(findings_list, right_chunks[0]);
+
+    let map_rows: Vec<ListItem> = data.map_rows().into_iter().map(ListItem::new).collect();
+    let map_list = List::new(map_rows).block(Block::default().borders(Borders::ALL).title("Map"));
+    f.render_widget(map_list, right_chunks[1]);
+
+    let category_times: Vec<ListItem> = data
+        .category_times
+        .iter()
+        .map(|i| ListItem::new(i.as_str()).style(Style::default()))
+        .collect();
+    let category_list = List::new(category_times).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title("Mean time/execs per category"),
+    );
+    f.render_widget(category_list, right_chunks[2]);
+
+    let clients: Vec<ListItem> = data
+        .client_rows
+        .iter()
+        .map(|i| ListItem::new(i.as_str()).style(Style::default()))
+        .collect();
+    let client_list =
+        List::new(clients).block(Block::default().borders(Borders::ALL).title("Clients"));
+    f.render_widget(client_list, right_chunks[3]);
 
     // Iterate through all elements in the `items` app and append some debug text to it.
     let items: Vec<ListItem> = data
@@ -380,3 +546,31 @@ fn ui<B: Backend>(f: &mut Frame<B>, data: &FuzzUIData) {
     render_coverage(f, data, bottom_chunks[0]);
     render_time_between_findings(f, data, bottom_chunks[1]);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{format_ratio, sanitize_for_display};
+
+    #[test]
+    fn control_characters_are_replaced() {
+        assert_eq!(sanitize_for_display("crash in lw"), "crash in lw");
+        assert_eq!(
+            sanitize_for_display("\x1b[2Jboom\r\n"),
+            "\u{fffd}[2Jboom\u{fffd}\u{fffd}"
+        );
+    }
+
+    #[test]
+    fn ratios() {
+        assert_eq!(
+            format_ratio("stability", Some((99, 100))),
+            "stability: 99/100 (99.00%)"
+        );
+        assert_eq!(
+            format_ratio("map density", Some((1, 3))),
+            "map density: 1/3 (33.33%)"
+        );
+        assert_eq!(format_ratio("stability", Some((0, 0))), "stability: -");
+        assert_eq!(format_ratio("stability", None), "stability: -");
+    }
+}
@@ -1,42 +1,816 @@
-use crate::instructions::{Argument, ArgumentSpec, Instruction, InstructionTemplate};
-use std::env;
+use libafl::prelude::StdRand;
+
+use crate::{
+    abi::{register_file, RegisterFile},
+    config::DriverConfig,
+    instructions::{
+        riscv::{
+            args,
+            rv64_i::{LD, SD},
+            rv_i::{ADDI, AUIPC, LB, LH, LUI, LW, SB, SH, SW},
+        },
+        sets, zicsr, Argument, ArgumentSpec, Instruction, InstructionCategory, InstructionTemplate,
+        COMPRESSED_ALIGNMENT, INST_ALIGNMENT,
+    },
+    program_input::ProgramInput,
+};
+use std::{collections::HashMap, fmt, time::Duration};
+
+/// The chance (0-100) of generating an instruction of the emphasized
+/// category instead of one from the whole set.
+pub const EMPHASIS_CHANCE: u64 = 50;
+
+/// The chance (0-100) of using the address of one of the
+/// [`zicsr::KNOWN_CSRS`] for a CSR operand instead of a random one.
+pub const KNOWN_CSR_CHANCE: u64 = 90;
+
+/// The default chance (0-100) of reusing a known argument value, and of
+/// choosing a power of two, with [`DriverConfig::arg_reuse`] set.
+pub const DEFAULT_ARG_CHANCE: u64 = 50;
+
+/// The weight of templates when choosing one to generate, unless it is set
+/// in [`DriverConfig::template_weights`] or
+/// [`DriverConfig::category_weights`].
+pub const DEFAULT_WEIGHT: u64 = 100;
+
+/// The source register arguments biased toward recent destinations.
+const SOURCE_REGS: [&str; 2] = ["rs1", "rs2"];
+
+/// Number of instructions in a register fill prologue, one for each of
+/// `x1` to `x31`.
+pub const REGISTER_FILL_LEN: usize = 31;
+
+/// Alignment of the base and size of the scratch region, the width of the
+/// widest access.
+pub const SCRATCH_ALIGNMENT: u64 = 8;
+
+/// The scratch region must end below this address, so every address in it
+/// can be built from a `lui` and a 12 bit offset (see
+/// [`InstGenerator::scratch_address`]).
+const MAX_SCRATCH_END: u64 = 0xffff_f800;
+
+/// The register that points into the scratch region with a memory base (see
+/// [`InstGenerator::set_mem_base`]), `x31`.
+pub const MEM_BASE_REG: u32 = 31;
+
+/// The chance (0-100) that a generated memory access uses [`MEM_BASE_REG`]
+/// as its address if there is a memory base.
+pub const MEM_BASE_CHANCE: u64 = 75;
+
+/// The smallest scratch region for a memory base, so all addresses that
+/// [`MEM_BASE_REG`] plus a 12 bit offset can reach are inside.
+pub const MEM_BASE_SPAN: u64 = 4096;
+
+/// The memory region that the harness maps for the generated programs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScratchRegion {
+    pub base: u64,
+    pub size: u64,
+}
+
+impl ScratchRegion {
+    /// Validates the region: base and size must be aligned to
+    /// [`SCRATCH_ALIGNMENT`] and all addresses must fit into 32 bits.
+    pub fn new(base: u64, size: u64) -> Result<Self, String> {
+        if size == 0 {
+            return Err("The scratch region is empty".to_owned());
+        }
+        if base % SCRATCH_ALIGNMENT != 0 || size % SCRATCH_ALIGNMENT != 0 {
+            return Err(format!(
+                "Base {:#x} and size {} must be aligned to {} bytes",
+                base, size, SCRATCH_ALIGNMENT
+            ));
+        }
+        if base
+            .checked_add(size)
+            .map_or(true, |end| end > MAX_SCRATCH_END)
+        {
+            return Err(format!(
+                "The scratch region must end below {:#x}",
+                MAX_SCRATCH_END
+            ));
+        }
+        Ok(Self { base, size })
+    }
+
+    /// Parses the `BASE+SIZE` format of [`crate::config::SCRATCH_REGION_VAR`], e.g.
+    /// `0x80000000+4096`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let (base, size) = s
+            .split_once('+')
+            .ok_or(format!("Not in BASE+SIZE format: '{}'", s))?;
+        let parse = |s: &str| parse_u64(s).ok_or(format!("Invalid number '{}'", s));
+        Self::new(parse(base)?, parse(size)?)
+    }
+
+    pub fn end(&self) -> u64 {
+        self.base + self.size
+    }
+}
+
+impl fmt::Display for ScratchRegion {
+    /// The [`crate::config::SCRATCH_REGION_VAR`] format.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}+{}", self.base, self.size)
+    }
+}
+
+/// Raises the dataflow chance (see [`InstGenerator::set_dataflow_chance`])
+/// linearly from `start` to `max` over the first `secs` seconds of a
+/// campaign, so mutations build longer def-use chains as it matures.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DataflowRamp {
+    pub start: u64,
+    pub max: u64,
+    pub secs: u64,
+}
+
+impl DataflowRamp {
+    /// Validates the chances: both are 0-100 and the ramp doesn't go down.
+    pub fn new(start: u64, max: u64, secs: u64) -> Result<Self, String> {
+        if max > 100 {
+            return Err(format!("Invalid chance {}, must be 0-100", max));
+        }
+        if start > max {
+            return Err(format!(
+                "The start chance {} is above the maximum {}",
+                start, max
+            ));
+        }
+        Ok(Self { start, max, secs })
+    }
+
+    /// Parses the `START:MAX:SECS` format of
+    /// [`crate::config::DATAFLOW_RAMP_VAR`], e.g. `10:90:3600`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let [start, max, secs] = parts[..] else {
+            return Err(format!("Not in START:MAX:SECS format: '{}'", s));
+        };
+        let parse = |s: &str| {
+            s.trim()
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid number '{}'", s))
+        };
+        Self::new(parse(start)?, parse(max)?, parse(secs)?)
+    }
+
+    /// The chance after `elapsed` time, `max` once the ramp is over.
+    pub fn chance_at(&self, elapsed: Duration) -> u64 {
+        if elapsed.as_secs() >= self.secs {
+            return self.max;
+        }
+        let rise = (self.max - self.start) as u128 * elapsed.as_secs() as u128;
+        self.start + (rise / self.secs as u128) as u64
+    }
+}
+
+impl fmt::Display for DataflowRamp {
+    /// The [`crate::config::DATAFLOW_RAMP_VAR`] format.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.start, self.max, self.secs)
+    }
+}
+
+/// How often an instruction's arguments are regenerated to avoid a reserved
+/// encoding.
+const MAX_RESERVED_TRIES: u32 = 100;
+
+/// Parses a comma separated list of category names, e.g. `branch,system`.
+pub fn parse_categories(list: &str) -> Result<Vec<InstructionCategory>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            InstructionCategory::from_name(name).ok_or_else(|| {
+                let known: Vec<_> = InstructionCategory::ALL.iter().map(|c| c.name()).collect();
+                format!("Unknown category '{}'. Known: {}", name, known.join(", "))
+            })
+        })
+        .collect()
+}
+
+/// The templates of the set that aren't in one of the excluded categories.
+pub fn without_categories(
+    set: Vec<&'static InstructionTemplate>,
+    excluded: &[InstructionCategory],
+) -> Vec<&'static InstructionTemplate> {
+    set.into_iter()
+        .filter(|template| !excluded.contains(&template.category()))
+        .collect()
+}
+
+/// Store instructions, the load instructions reading the same width and the
+/// width in bytes.
+static STORE_LOAD_PAIRS: [(&InstructionTemplate, &InstructionTemplate, u64); 4] =
+    [(&SB, &LB, 1), (&SH, &LH, 2), (&SW, &LW, 4), (&SD, &LD, 8)];
+
+/// Returns `true` for the register operands of shift instructions.
+fn is_shift_register(spec: &ArgumentSpec) -> bool {
+    matches!(spec.name(), "rd" | "rs1")
+}
+
+/// `reg = reg OP 32` for the shift instruction with the given name. Picks the
+/// RV64 variant whose shift amount is wide enough.
+fn shift_by_32(name: &str, reg: u32) -> Instruction {
+    let template = sets::riscv_g()
+        .into_iter()
+        .find(|t| {
+            t.name() == name
+                && t.operands()
+                    .all(|spec| is_shift_register(spec) || spec.mask() >= 32)
+        })
+        .expect("Missing RV64 shift instruction");
+    let args = template
+        .operands()
+        .map(|spec| Argument::new(*spec, if is_shift_register(spec) { reg } else { 32 }))
+        .collect();
+    Instruction::new(template, args)
+}
 
 /// Generates random RISC-V instructions.
-#[derive(Default)]
 pub struct InstGenerator {
     /// List of known arguments the generator should try to reuse.
     known_args: Vec<Argument>,
     // Chance (0-100) of reusing a known arg value in the program.
     reuse_chance: u64,
+    /// Immediates from (and around) the values of saved crashes.
+    crash_args: Vec<Argument>,
+    // Chance (0-100) of using a crash immediate for a matching argument.
+    crash_chance: u64,
     // Chance (0-100) of choosing a power of two as arg value.
     power_of_two_chance: u64,
+    // Chance (0-100) of emitting a store followed by a load from the same
+    // address instead of a single instruction.
+    paired_mem_chance: u64,
+    // Inclusive value ranges that generated arguments are restricted to.
+    arg_ranges: HashMap<&'static ArgumentSpec, (u32, u32)>,
+    // Whether to regenerate arguments that form a reserved encoding.
+    avoid_reserved: bool,
+    // Whether programs start with a register fill prologue.
+    register_fill: bool,
+    // The region generated memory accesses target, if any.
+    scratch: Option<ScratchRegion>,
+    // Whether registers are 64 bits wide, so addresses built with a `lui`
+    // have to be zero-extended.
+    rv64: bool,
+    // Whether the prologue points `MEM_BASE_REG` into the scratch region for
+    // the generated memory accesses.
+    mem_base: bool,
+    // The alignment of generated branch and jump offsets.
+    target_alignment: u32,
+    // How many of the last destination registers are remembered when
+    // generating multiple instructions.
+    recent_regs: usize,
+    // Chance (0-100) of reading a remembered register in a source operand.
+    recent_reg_chance: u64,
+    // Chance (0-100) of reading a register that an earlier instruction of
+    // the generated sequence wrote in a source operand.
+    dataflow_chance: u64,
+    // The category generated instructions are more often taken from.
+    emphasis: Option<InstructionCategory>,
+    // The most instructions `generate_instructions` returns.
+    max_program_len: usize,
+    // Relative weights of templates and categories for choosing a template.
+    // Templates without either have a weight of `DEFAULT_WEIGHT`.
+    template_weights: HashMap<&'static InstructionTemplate, u64>,
+    category_weights: HashMap<InstructionCategory, u64>,
+}
+
+impl Default for InstGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the length of the prologue at the start of the program, or 0 if
+/// it has none. The prologue is a register fill (see
+/// [`InstGenerator::generate_register_fill`]), a memory base setup (see
+/// [`InstGenerator::mem_base_setup`]) or the fill followed by the setup. It
+/// is recognized by its shape, so it survives the round trip through the
+/// corpus on disk.
+pub fn register_fill_len(insts: &[Instruction]) -> usize {
+    let fill = fill_len(insts);
+    fill + mem_base_setup_len(&insts[fill..])
+}
+
+/// The length of the register fill at the start of the program, 0 or
+/// [`REGISTER_FILL_LEN`].
+fn fill_len(insts: &[Instruction]) -> usize {
+    if insts.len() < REGISTER_FILL_LEN {
+        return 0;
+    }
+    let is_fill = |(i, inst): (usize, &Instruction)| {
+        let value = |name: &str| {
+            inst.arguments()
+                .iter()
+                .find(|arg| arg.spec().name() == name)
+                .map(|arg| arg.value())
+        };
+        let writes_reg = value("rd") == Some(i as u32 + 1);
+        let template = inst.template();
+        writes_reg && (template == &LUI || (template == &ADDI && value("rs1") == Some(0)))
+    };
+    if insts[..REGISTER_FILL_LEN].iter().enumerate().all(is_fill) {
+        REGISTER_FILL_LEN
+    } else {
+        0
+    }
+}
+
+/// The length of the memory base setup at the start of the program, or 0.
+/// It has the zero-extending shifts on RV64 only.
+fn mem_base_setup_len(insts: &[Instruction]) -> usize {
+    let on_base_reg = |(name, inst): (&&str, &Instruction)| {
+        let value = |arg_name: &str| {
+            inst.arguments()
+                .iter()
+                .find(|arg| arg.spec().name() == arg_name)
+                .map(|arg| arg.value())
+        };
+        let reads_base = *name == "lui" || value("rs1") == Some(MEM_BASE_REG);
+        inst.template().name() == *name && value("rd") == Some(MEM_BASE_REG) && reads_base
+    };
+    let shapes: [&[&str]; 2] = [&["lui", "slli", "srli", "addi"], &["lui", "addi"]];
+    shapes
+        .iter()
+        .find(|shape| shape.len() <= insts.len() && shape.iter().zip(insts).all(on_base_reg))
+        .map_or(0, |shape| shape.len())
+}
+
+/// The length of the program in bytes. Compressed instructions take 2 bytes,
+/// all others 4.
+pub fn program_byte_len(insts: &[Instruction]) -> usize {
+    insts.iter().map(Instruction::size).sum()
+}
+
+/// Generates `count` programs of `len` instructions from the configuration,
+/// each with the register fill the clients add to their seeds. For looking
+/// at the kind of programs a configuration produces without starting the
+/// target. A client with the RNG seed `seed` starts from its seeds and
+/// mutates them instead, so it won't run these exact programs.
+pub fn preview_programs(
+    config: &DriverConfig,
+    seed: u64,
+    count: usize,
+    len: u32,
+) -> Vec<ProgramInput> {
+    let mut rand = StdRand::with_seed(seed);
+    let generator = InstGenerator::with_config(config);
+    let isa = config.instructions();
+    (0..count)
+        .map(|_| {
+            let insts = generator.generate_instructions(&mut rand, &isa, len);
+            ProgramInput::try_new(generator.add_register_fill(&mut rand, insts))
+                .expect("Generated an argument that doesn't fit into its field")
+        })
+        .collect()
+}
+
+/// Parses a list of argument value ranges in the `NAME=MIN-MAX,...` format.
+/// Names are the argument names used in the instruction templates.
+pub fn parse_arg_ranges(list: &str) -> Result<Vec<(&'static ArgumentSpec, u32, u32)>, String> {
+    let mut result = vec![];
+    for part in list.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (name, range) = part
+            .split_once('=')
+            .ok_or(format!("Not in NAME=MIN-MAX format: '{}'", part))?;
+        let (min, max) = range
+            .split_once('-')
+            .ok_or(format!("Not in NAME=MIN-MAX format: '{}'", part))?;
+        let parse = |s: &str| parse_u32(s).ok_or(format!("Invalid number '{}'", s));
+        let (min, max) = (parse(min)?, parse(max)?);
+
+        let spec = sets::all()
+            .iter()
+            .find_map(|t| t.op_with_name(name.to_owned()))
+            .ok_or(format!("Unknown argument '{}'", name))?;
+        if min > max || max > spec.mask() {
+            return Err(format!(
+                "Invalid range {}-{} for '{}' (max {})",
+                min,
+                max,
+                name,
+                spec.mask()
+            ));
+        }
+        result.push((spec, min, max));
+    }
+    Ok(result)
+}
+
+/// Splits a list in the `NAME=WEIGHT,...` format.
+fn parse_weights(list: &str) -> Result<Vec<(&str, u64)>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let (name, weight) = part
+                .split_once('=')
+                .ok_or(format!("Not in NAME=WEIGHT format: '{}'", part))?;
+            let weight = weight
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid weight '{}' for {}", weight.trim(), name.trim()))?;
+            Ok((name.trim(), weight))
+        })
+        .collect()
+}
+
+/// Parses template weights in the `NAME=WEIGHT,...` format relative to
+/// [`DEFAULT_WEIGHT`], e.g. `addi=1000,ecall=0`. A name stands for all
+/// templates with it, e.g. the RV32 and RV64 `slli`.
+pub fn parse_template_weights(
+    list: &str,
+) -> Result<Vec<(&'static InstructionTemplate, u64)>, String> {
+    let mut result = vec![];
+    for (name, weight) in parse_weights(list)? {
+        let templates: Vec<_> = sets::all()
+            .into_iter()
+            .filter(|t| t.name() == name)
+            .collect();
+        if templates.is_empty() {
+            return Err(format!("Unknown instruction '{}'", name));
+        }
+        result.extend(templates.into_iter().map(|template| (template, weight)));
+    }
+    Ok(result)
+}
+
+/// Parses category weights (see [`InstructionCategory::name`]) in the
+/// `NAME=WEIGHT,...` format, e.g. `mem=300,float=0`.
+pub fn parse_category_weights(list: &str) -> Result<Vec<(InstructionCategory, u64)>, String> {
+    parse_weights(list)?
+        .into_iter()
+        .map(|(name, weight)| {
+            InstructionCategory::from_name(name)
+                .map(|category| (category, weight))
+                .ok_or(format!("Unknown category '{}'", name))
+        })
+        .collect()
+}
+
+/// Parses the `K:CHANCE` format of [`crate::config::RECENT_REGS_VAR`], e.g. `4:30`.
+pub fn parse_recent_regs(s: &str) -> Result<(usize, u64), String> {
+    let (len, chance) = s
+        .split_once(':')
+        .ok_or(format!("Not in K:CHANCE format: '{}'", s))?;
+    let len = len
+        .trim()
+        .parse::<usize>()
+        .map_err(|_| format!("Invalid register count '{}'", len))?;
+    let chance = chance
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|chance| *chance <= 100)
+        .ok_or(format!("Invalid chance '{}', must be 0-100", chance))?;
+    Ok((len, chance))
+}
+
+/// Parses a decimal or `0x` prefixed hexadecimal number.
+fn parse_u32(s: &str) -> Option<u32> {
+    match s.trim().strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => s.trim().parse::<u32>().ok(),
+    }
+}
+
+/// Like [`parse_u32`], for addresses.
+pub fn parse_u64(s: &str) -> Option<u64> {
+    match s.trim().strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.trim().parse::<u64>().ok(),
+    }
+}
+
+/// The register the instruction writes, if any. Writes to x0 are discarded,
+/// so they don't define anything.
+fn destination(inst: &Instruction) -> Option<(RegisterFile, u32)> {
+    let rd = inst
+        .arguments()
+        .iter()
+        .find(|arg| arg.spec().name() == "rd")?;
+    let rd = (register_file(inst.template(), "rd")?, rd.value());
+    (rd != (RegisterFile::Int, 0)).then_some(rd)
+}
+
+/// The registers the instructions write, for [`InstGenerator::generate_insertion`].
+pub fn written_registers(insts: &[Instruction]) -> Vec<(RegisterFile, u32)> {
+    let mut written = vec![];
+    for rd in insts.iter().filter_map(destination) {
+        if !written.contains(&rd) {
+            written.push(rd);
+        }
+    }
+    written
 }
 
 impl InstGenerator {
+    /// A generator with the default [`DriverConfig`].
     pub fn new() -> Self {
-        let reuse_args = !env::var("PHANTOM_TRAILS_NO_ARG_REUSE").is_ok();
+        Self::with_config(&DriverConfig::default())
+    }
 
-        Self {
+    /// A generator with the default [`DriverConfig`], except for the chances
+    /// (0-100) of reusing a known argument value and of choosing a power of
+    /// two.
+    pub fn with_chances(reuse: u64, pow2: u64) -> Self {
+        debug_assert!(reuse <= 100 && pow2 <= 100);
+        Self::with_config(&DriverConfig {
+            reuse_chance: Some(reuse),
+            pow2_chance: Some(pow2),
+            ..DriverConfig::default()
+        })
+    }
+
+    pub fn with_config(config: &DriverConfig) -> Self {
+        let arg_chance = |chance: Option<u64>| {
+            chance.unwrap_or(if config.arg_reuse {
+                DEFAULT_ARG_CHANCE
+            } else {
+                0
+            })
+        };
+        let mut result = Self {
             known_args: Vec::<Argument>::new(),
-            reuse_chance: if reuse_args { 50 } else { 0 },
-            power_of_two_chance: if reuse_args { 50 } else { 0 },
+            reuse_chance: arg_chance(config.reuse_chance),
+            crash_args: Vec::<Argument>::new(),
+            crash_chance: config.crash_chance,
+            power_of_two_chance: arg_chance(config.pow2_chance),
+            paired_mem_chance: config.paired_mem_chance,
+            arg_ranges: HashMap::new(),
+            avoid_reserved: config.avoid_reserved,
+            register_fill: config.register_fill,
+            scratch: config.scratch,
+            rv64: config.is_rv64(),
+            mem_base: config.mem_base,
+            target_alignment: if config.compressed {
+                COMPRESSED_ALIGNMENT
+            } else {
+                INST_ALIGNMENT
+            },
+            recent_regs: 0,
+            recent_reg_chance: 0,
+            dataflow_chance: match (config.dataflow, config.dataflow_ramp) {
+                (false, _) => 0,
+                (true, Some(ramp)) => ramp.start,
+                (true, None) => 50,
+            },
+            emphasis: config.emphasis,
+            max_program_len: config.max_program_len,
+            template_weights: HashMap::new(),
+            category_weights: HashMap::new(),
+        };
+        if let Some((len, chance)) = config.recent_regs {
+            result.set_recent_regs(len, chance);
+        }
+        for (spec, min, max) in &config.arg_ranges {
+            result.set_arg_range(spec, *min, *max);
+        }
+        result.template_weights = config.template_weights.iter().copied().collect();
+        result.category_weights = config.category_weights.iter().copied().collect();
+        result
+    }
+
+    /// Restricts all generated values of the given argument to `min..=max`.
+    pub fn set_arg_range(&mut self, spec: &'static ArgumentSpec, min: u32, max: u32) {
+        debug_assert!(min <= max && max <= spec.mask());
+        self.arg_ranges.insert(spec, (min, max));
+    }
+
+    /// Makes `generate_instruction` avoid reserved encodings.
+    pub fn set_avoid_reserved(&mut self, avoid: bool) {
+        self.avoid_reserved = avoid;
+    }
+
+    /// Makes the generated memory accesses target the given region.
+    pub fn set_scratch_region(&mut self, region: Option<ScratchRegion>) {
+        self.scratch = region;
+    }
+
+    /// Sets whether registers are 64 bits wide, i.e. the instruction set is
+    /// an RV64 one.
+    pub fn set_rv64(&mut self, rv64: bool) {
+        self.rv64 = rv64;
+    }
+
+    /// Makes `add_register_fill` add a setup that points [`MEM_BASE_REG`]
+    /// into the scratch region to the prologue, and `generate_instruction`
+    /// use it as the address of memory accesses and never write it. The
+    /// scratch region must have at least [`MEM_BASE_SPAN`] bytes.
+    pub fn set_mem_base(&mut self, mem_base: bool) {
+        debug_assert!(!mem_base || self.scratch.map_or(false, |r| r.size >= MEM_BASE_SPAN));
+        self.mem_base = mem_base;
+    }
+
+    /// Sets the alignment of generated branch and jump offsets, i.e.
+    /// [`COMPRESSED_ALIGNMENT`] if the core implements the C extension.
+    pub fn set_target_alignment(&mut self, alignment: u32) {
+        debug_assert!(alignment == COMPRESSED_ALIGNMENT || alignment == INST_ALIGNMENT);
+        self.target_alignment = alignment;
+    }
+
+    /// The alignment branch and jump targets must have.
+    pub fn target_alignment(&self) -> u32 {
+        self.target_alignment
+    }
+
+    /// Makes `generate_instructions` remember the last `len` destination
+    /// registers and read one of them in a source operand with the given
+    /// chance (0-100). This builds short def-use chains.
+    pub fn set_recent_regs(&mut self, len: usize, chance: u64) {
+        debug_assert!(chance <= 100);
+        self.recent_regs = len;
+        self.recent_reg_chance = chance;
+    }
+
+    /// Sets the chance (0-100) that `generate_instructions` and
+    /// `generate_insertion` read a register written earlier in a source
+    /// operand.
+    pub fn set_dataflow_chance(&mut self, chance: u64) {
+        debug_assert!(chance <= 100);
+        self.dataflow_chance = chance;
+    }
+
+    /// Makes `generate_instructions` return at most `max` instructions.
+    pub fn set_max_program_len(&mut self, max: usize) {
+        self.max_program_len = max;
+    }
+
+    /// The weight of the template when choosing one to generate. The weight
+    /// of a template takes precedence over the one of its category.
+    pub fn weight(&self, template: &'static InstructionTemplate) -> u64 {
+        self.template_weights
+            .get(template)
+            .or_else(|| self.category_weights.get(&template.category()))
+            .copied()
+            .unwrap_or(DEFAULT_WEIGHT)
+    }
+
+    /// Chooses one of the templates with a chance proportional to its
+    /// weight. Without any weights set, all templates are equally likely.
+    /// They must not all have a weight of 0, which
+    /// [`DriverConfig::validate`] rules out for the configured set.
+    fn choose_template<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+        templates: &[&'static InstructionTemplate],
+    ) -> &'static InstructionTemplate {
+        // Also keeps the random choices of seeded runs without weights.
+        if self.template_weights.is_empty() && self.category_weights.is_empty() {
+            return rand.choose(templates.iter().copied());
         }
+        let weights: Vec<u64> = templates
+            .iter()
+            .map(|template| self.weight(template))
+            .collect();
+        let total: u64 = weights.iter().sum();
+        assert!(total != 0, "All templates have a weight of 0");
+        let mut pick = rand.below(total);
+        for (template, weight) in templates.iter().zip(weights) {
+            if pick < weight {
+                return *template;
+            }
+            pick -= weight;
+        }
+        unreachable!("the pick is below the total weight")
+    }
+
+    /// Makes `generate_instruction` pick an instruction of the given category
+    /// with a chance of [`EMPHASIS_CHANCE`] percent, if the set has any.
+    pub fn set_emphasis(&mut self, category: Option<InstructionCategory>) {
+        self.emphasis = category;
+    }
+
+    /// Replaces source registers of the instruction by one of the `recent`
+    /// destination registers of the same register file with the configured
+    /// chance. Values outside of the argument ranges are not used.
+    pub fn bias_to_recent<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+        inst: Instruction,
+        recent: &[(RegisterFile, u32)],
+    ) -> Instruction {
+        self.bias_sources(rand, inst, recent, self.recent_reg_chance)
+    }
+
+    /// Like [`Self::bias_to_recent`] for all `written` registers and the
+    /// dataflow chance.
+    pub fn bias_to_written<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+        inst: Instruction,
+        written: &[(RegisterFile, u32)],
+    ) -> Instruction {
+        self.bias_sources(rand, inst, written, self.dataflow_chance)
+    }
+
+    /// Replaces each source register by one of `regs` in its register file
+    /// with the given chance.
+    fn bias_sources<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+        mut inst: Instruction,
+        regs: &[(RegisterFile, u32)],
+        chance: u64,
+    ) -> Instruction {
+        if regs.is_empty() || chance == 0 {
+            return inst;
+        }
+        let template = inst.template();
+        let sources: Vec<(&'static ArgumentSpec, RegisterFile)> = inst
+            .arguments()
+            .iter()
+            .filter(|arg| SOURCE_REGS.contains(&arg.spec().name()))
+            .filter_map(|arg| {
+                let file = register_file(template, arg.spec().name())?;
+                // Addresses relative to the memory base stay that way.
+                let mem_base = file == RegisterFile::Int && arg.value() == MEM_BASE_REG;
+                (!self.mem_base || !mem_base).then_some((arg.spec(), file))
+            })
+            .collect();
+        for (spec, file) in sources {
+            if rand.below(100) >= chance {
+                continue;
+            }
+            let (min, max) = self
+                .arg_ranges
+                .get(spec)
+                .copied()
+                .unwrap_or((0, spec.mask()));
+            let options: Vec<u32> = regs
+                .iter()
+                .filter(|(reg_file, reg)| *reg_file == file && (min..=max).contains(reg))
+                .map(|(_, reg)| *reg)
+                .collect();
+            if !options.is_empty() {
+                inst.set_arg(Argument::new(spec, *rand.choose(options.iter())));
+            }
+        }
+        inst
+    }
+
+    /// Makes `add_register_fill` prepend a register fill prologue.
+    pub fn set_register_fill(&mut self, register_fill: bool) {
+        self.register_fill = register_fill;
     }
 
     pub fn forward_args(&mut self, args: &[Argument]) {
         self.known_args.append(&mut args.to_vec())
     }
 
+    /// Adds immediates of saved crashes (see [`crate::immediates`]). Unlike
+    /// the known args, they are only used for the same kind of argument.
+    pub fn forward_crash_args(&mut self, args: &[Argument]) {
+        self.crash_args.append(&mut args.to_vec())
+    }
+
+    /// Generates a value for the argument. Branch and jump offsets are
+    /// always aligned to the target alignment.
     pub fn generate_argument<R: libafl::prelude::Rand>(
         &self,
         rand: &mut R,
         arg: &'static ArgumentSpec,
     ) -> Argument {
+        self.choose_argument(rand, arg)
+            .aligned_target(self.target_alignment)
+    }
+
+    fn choose_argument<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+        arg: &'static ArgumentSpec,
+    ) -> Argument {
+        let (min, max) = self.arg_ranges.get(arg).copied().unwrap_or((0, arg.mask()));
+
+        if !self.crash_args.is_empty() && rand.below(100) < self.crash_chance {
+            let options = self
+                .crash_args
+                .iter()
+                .filter(|x| x.spec().name() == arg.name() && (min..=max).contains(&x.value()))
+                .collect::<Vec<&Argument>>();
+            if !options.is_empty() {
+                return Argument::new(arg, rand.choose(options).value());
+            }
+        }
+
+        if arg == &zicsr::CSR && rand.below(100) < KNOWN_CSR_CHANCE {
+            let known: Vec<u32> = zicsr::KNOWN_CSRS
+                .iter()
+                .map(|(_, address)| *address)
+                .filter(|address| (min..=max).contains(address))
+                .collect();
+            if !known.is_empty() {
+                return Argument::new(arg, *rand.choose(known.iter()));
+            }
+        }
+
         if rand.below(100) < self.reuse_chance {
             let filtered = self
                 .known_args
                 .iter()
-                .filter(|x| x.spec().length() == arg.length());
+                .filter(|x| x.spec().length() == arg.length() && (min..=max).contains(&x.value()));
             let options = filtered.collect::<Vec<&Argument>>();
             if !options.is_empty() {
                 let chosen = rand.choose(options).clone();
@@ -45,10 +819,17 @@ impl InstGenerator {
         }
 
         if rand.below(100) < self.power_of_two_chance {
-            Argument::new(arg, 1 << rand.below(arg.length() as u64) as u32)
-        } else {
-            Argument::new(arg, rand.below(arg.max_value() as u64) as u32)
+            let powers = (0..arg.length())
+                .map(|bit| 1u32 << bit)
+                .filter(|value| (min..=max).contains(value))
+                .collect::<Vec<u32>>();
+            if !powers.is_empty() {
+                return Argument::new(arg, *rand.choose(powers.iter()));
+            }
         }
+        // The range has up to 2^32 values, which only fits in a u64.
+        let range = (max - min) as u64 + 1;
+        Argument::new(arg, min + rand.below(range) as u32)
     }
 
     pub fn generate_instruction<R: libafl::prelude::Rand>(
@@ -57,24 +838,448 @@ impl InstGenerator {
         insts: &Vec<&'static InstructionTemplate>,
     ) -> Instruction {
         assert!(!insts.is_empty());
-        let template = rand.choose(insts.iter());
+        let emphasized: Vec<_> = match self.emphasis {
+            Some(category) if rand.below(100) < EMPHASIS_CHANCE => insts
+                .iter()
+                .copied()
+                .filter(|template| template.category() == category && self.weight(template) != 0)
+                .collect(),
+            _ => vec![],
+        };
+        let template = if emphasized.is_empty() {
+            self.choose_template(rand, insts)
+        } else {
+            self.choose_template(rand, &emphasized)
+        };
 
         let mut arguments = Vec::<Argument>::new();
-        for arg in template.operands() {
-            arguments.push(self.generate_argument(rand, arg));
+        for _ in 0..MAX_RESERVED_TRIES {
+            arguments.clear();
+            for arg in template.operands() {
+                arguments.push(self.generate_argument(rand, arg));
+            }
+            if !self.avoid_reserved || !template.is_reserved(&arguments) {
+                break;
+            }
         }
-        Instruction::new(template, arguments)
+        self.use_mem_base(rand, Instruction::new(template, arguments))
     }
 
+    /// With a memory base, makes loads, stores and atomics address memory
+    /// relative to [`MEM_BASE_REG`] with a chance of [`MEM_BASE_CHANCE`]
+    /// percent, and moves writes to the register to another one so the
+    /// address stays valid.
+    fn use_mem_base<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+        mut inst: Instruction,
+    ) -> Instruction {
+        if !self.mem_base {
+            return inst;
+        }
+        let is_access = matches!(
+            inst.template().category(),
+            InstructionCategory::Mem | InstructionCategory::Atomic
+        );
+        let template = inst.template();
+        let regs: Vec<Argument> = inst
+            .arguments()
+            .iter()
+            .filter(|arg| register_file(template, arg.spec().name()) == Some(RegisterFile::Int))
+            .cloned()
+            .collect();
+        for arg in regs {
+            let name = arg.spec().name();
+            if name == "rs1" && is_access && rand.below(100) < MEM_BASE_CHANCE {
+                inst.set_arg(Argument::new(arg.spec(), MEM_BASE_REG));
+            } else if name.starts_with("rd") {
+                inst.set_arg(Argument::new(
+                    arg.spec(),
+                    self.keep_mem_base(rand, arg.value()),
+                ));
+            }
+        }
+        inst
+    }
+
+    /// Whether the instruction overwrites the address in [`MEM_BASE_REG`] of
+    /// a memory base, which mutations must not introduce.
+    pub fn clobbers_mem_base(&self, inst: &Instruction) -> bool {
+        self.mem_base
+            && inst.arguments().iter().any(|arg| {
+                arg.spec().name().starts_with("rd")
+                    && arg.value() == MEM_BASE_REG
+                    && register_file(inst.template(), arg.spec().name()) == Some(RegisterFile::Int)
+            })
+    }
+
+    /// Replaces a destination register that would overwrite the address in
+    /// [`MEM_BASE_REG`] by a random other one. That is never x0, which some
+    /// compressed instructions reserve.
+    fn keep_mem_base<R: libafl::prelude::Rand>(&self, rand: &mut R, rd: u32) -> u32 {
+        if self.mem_base && rd == MEM_BASE_REG {
+            1 + rand.below(MEM_BASE_REG as u64 - 1) as u32
+        } else {
+            rd
+        }
+    }
+
+    /// Points [`MEM_BASE_REG`] to the middle of the scratch region, so every
+    /// 12 bit offset from it stays inside: the [`Self::scratch_address`]
+    /// setup of the address, followed by an `addi` of its offset. Empty
+    /// without a memory base.
+    pub fn mem_base_setup(&self) -> Vec<Instruction> {
+        let Some(region) = self.scratch.filter(|_| self.mem_base) else {
+            return vec![];
+        };
+        let address = region.base + ((region.size / 2) & !(SCRATCH_ALIGNMENT - 1));
+        let (mut setup, offset) = self.address_setup(MEM_BASE_REG, address);
+        setup.push(Instruction::new(
+            &ADDI,
+            vec![
+                Argument::new(&args::RD, MEM_BASE_REG),
+                Argument::new(&args::RS1, MEM_BASE_REG),
+                Argument::new(&args::IMM12, offset),
+            ],
+        ));
+        setup
+    }
+
+    /// Generates instructions that set `reg` to a random address in the
+    /// region that is aligned to `width` bytes. Returns them with the 12 bit
+    /// offset the access has to add to `reg`.
+    ///
+    /// The address is built with a `lui` of the upper bits. `lui`
+    /// sign-extends on RV64, so there the register is zero-extended with a
+    /// shift left and right by 32 bits afterwards. This is why the region
+    /// must be in the lower 4 GiB.
+    pub fn scratch_address<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+        region: &ScratchRegion,
+        reg: u32,
+        width: u64,
+    ) -> (Vec<Instruction>, u32) {
+        let address = region.base + rand.below(region.size / width) * width;
+        self.address_setup(reg, address)
+    }
+
+    /// The setup of [`Self::scratch_address`] for the given address.
+    fn address_setup(&self, reg: u32, address: u64) -> (Vec<Instruction>, u32) {
+        // The offset is sign-extended, so round the upper part up if the
+        // offset is negative.
+        let upper = ((address + 0x800) >> 12) as u32 & args::IMM20.mask();
+        let offset = (address & 0xfff) as u32;
+        let mut setup = vec![Instruction::new(
+            &LUI,
+            vec![
+                Argument::new(&args::RD, reg),
+                Argument::new(&args::IMM20, upper),
+            ],
+        )];
+        if self.rv64 {
+            setup.extend([shift_by_32("slli", reg), shift_by_32("srli", reg)]);
+        }
+        (setup, offset)
+    }
+
+    /// The number of instructions [`Self::scratch_address`] sets up an
+    /// address with.
+    fn scratch_address_len(&self) -> usize {
+        if self.rv64 {
+            3
+        } else {
+            1
+        }
+    }
+
+    /// One of the [`STORE_LOAD_PAIRS`], without the doubleword accesses that
+    /// only exist on RV64 unless registers are 64 bits wide.
+    fn choose_store_load_pair<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+    ) -> (
+        &'static InstructionTemplate,
+        &'static InstructionTemplate,
+        u64,
+    ) {
+        let pairs: Vec<_> = STORE_LOAD_PAIRS
+            .iter()
+            .filter(|(store, _, _)| self.rv64 || !sets::is_rv64_only(store))
+            .collect();
+        *rand.choose(pairs)
+    }
+
+    /// The number of instructions of [`Self::generate_memory_pair`].
+    pub fn memory_pair_len(&self) -> usize {
+        match self.scratch {
+            Some(_) => self.scratch_address_len() + 2,
+            None => 2,
+        }
+    }
+
+    /// With a scratch region, prepends a [`Self::scratch_address`] setup of
+    /// the base register to a load or store, so it accesses the region.
+    /// Other instructions, and accesses that would take more than `room`
+    /// instructions with the setup, are returned as they are.
+    pub fn target_scratch<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+        mut inst: Instruction,
+        room: usize,
+    ) -> Vec<Instruction> {
+        let Some(region) = &self.scratch else {
+            return vec![inst];
+        };
+        let spec = |name: &str| {
+            inst.arguments()
+                .iter()
+                .map(|arg| arg.spec())
+                .find(|spec| spec.name() == name)
+        };
+        let (rs1, imm12) = (spec("rs1"), spec("imm12"));
+        let split = spec("imm12hi").zip(spec("imm12lo"));
+        let is_access = inst.template().category() == InstructionCategory::Mem;
+        let Some(rs1) = rs1.filter(|_| is_access && (imm12.is_some() || split.is_some())) else {
+            return vec![inst];
+        };
+        let base = inst
+            .arguments()
+            .iter()
+            .find(|arg| arg.spec() == rs1)
+            .unwrap()
+            .value();
+        // Accesses relative to the memory base already are inside.
+        if room <= self.scratch_address_len() || (self.mem_base && base == MEM_BASE_REG) {
+            return vec![inst];
+        }
+        // x0 can't hold the address. Aligning to the widest access keeps
+        // every access inside.
+        let base = self.keep_mem_base(rand, base.max(1));
+        let (mut result, offset) = self.scratch_address(rand, region, base, SCRATCH_ALIGNMENT);
+        inst.set_arg(Argument::new(rs1, base));
+        match (imm12, split) {
+            (Some(imm12), _) => inst.set_arg(Argument::new(imm12, offset)),
+            (None, Some((hi, lo))) => {
+                // Stores split their immediate into a high and a low part.
+                inst.set_arg(Argument::new(hi, offset >> lo.length()));
+                inst.set_arg(Argument::new(lo, offset & ((1 << lo.length()) - 1)));
+            }
+            (None, None) => unreachable!("accesses without an offset are returned above"),
+        }
+        result.push(inst);
+        result
+    }
+
+    /// Generates instructions to insert into a program: a store/load pair
+    /// with the configured chance, otherwise a single instruction, which
+    /// reads the `written` registers of the instructions before it with the
+    /// dataflow chance and accesses the scratch region if it is a load or
+    /// store (see [`Self::target_scratch`]).
+    pub fn generate_insertion<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+        insts: &Vec<&'static InstructionTemplate>,
+        written: &[(RegisterFile, u32)],
+    ) -> Vec<Instruction> {
+        if rand.below(100) < self.paired_mem_chance {
+            return self.generate_memory_pair(rand);
+        }
+        let inst = self.generate_instruction(rand, insts);
+        let inst = self.bias_to_written(rand, inst, written);
+        self.target_scratch(rand, inst, usize::MAX)
+    }
+
+    /// Generates a store immediately followed by a load of the same width from
+    /// the same base register and offset. This exercises store-to-load
+    /// forwarding. With a scratch region, the base register is set up to
+    /// point into it first.
+    pub fn generate_memory_pair<R: libafl::prelude::Rand>(&self, rand: &mut R) -> Vec<Instruction> {
+        let (store, load, width) = self.choose_store_load_pair(rand);
+
+        let mut base = self.generate_argument(rand, &args::RS1).value();
+        let mut offset = self.generate_argument(rand, &args::IMM12).value();
+        let mut result = vec![];
+        if let Some(region) = &self.scratch {
+            // x0 can't hold the address.
+            base = self.keep_mem_base(rand, base.max(1));
+            let (setup, scratch_offset) = self.scratch_address(rand, region, base, width);
+            result = setup;
+            offset = scratch_offset;
+        }
+        // Stores split their immediate into a high and a low part.
+        let offset_lo = offset & ((1 << args::IMM12LO.length()) - 1);
+        let offset_hi = offset >> args::IMM12LO.length();
+
+        result.extend([
+            Instruction::new(
+                store,
+                vec![
+                    Argument::new(&args::IMM12HI, offset_hi),
+                    Argument::new(&args::RS1, base),
+                    self.generate_argument(rand, &args::RS2),
+                    Argument::new(&args::IMM12LO, offset_lo),
+                ],
+            ),
+            Instruction::new(
+                load,
+                vec![
+                    Argument::new(
+                        &args::RD,
+                        self.keep_mem_base(rand, self.generate_argument(rand, &args::RD).value()),
+                    ),
+                    Argument::new(&args::RS1, base),
+                    Argument::new(&args::IMM12, offset),
+                ],
+            ),
+        ]);
+        result
+    }
+
+    /// Generates a `lui`/`auipc` that sets up an address in `base_reg`,
+    /// followed by a load or store relative to that register. With a scratch
+    /// region, the address is always in it (see [`Self::scratch_address`]).
+    pub fn gen_memory_access<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+        base_reg: u32,
+    ) -> Vec<Instruction> {
+        let upper = if rand.below(2) == 0 { &LUI } else { &AUIPC };
+        let (store, load, width) = self.choose_store_load_pair(rand);
+        let (setup, offset) = match &self.scratch {
+            Some(region) => self.scratch_address(rand, region, base_reg, width),
+            None => (
+                vec![Instruction::new(
+                    upper,
+                    vec![
+                        Argument::new(&args::RD, base_reg),
+                        self.generate_argument(rand, &args::IMM20),
+                    ],
+                )],
+                self.generate_argument(rand, &args::IMM12).value(),
+            ),
+        };
+
+        let access = if rand.below(2) == 0 {
+            Instruction::new(
+                load,
+                vec![
+                    self.generate_argument(rand, &args::RD),
+                    Argument::new(&args::RS1, base_reg),
+                    Argument::new(&args::IMM12, offset),
+                ],
+            )
+        } else {
+            Instruction::new(
+                store,
+                vec![
+                    Argument::new(&args::IMM12HI, offset >> args::IMM12LO.length()),
+                    Argument::new(&args::RS1, base_reg),
+                    self.generate_argument(rand, &args::RS2),
+                    Argument::new(&args::IMM12LO, offset & ((1 << args::IMM12LO.length()) - 1)),
+                ],
+            )
+        };
+
+        let mut result = setup;
+        result.push(access);
+        result
+    }
+
+    /// Generates a prologue that writes a random value to every register from
+    /// `x1` to `x31` in order, using either a `lui` or an `addi` from `x0`.
+    /// Programs starting with it run their body on diverse architectural
+    /// state. The mutators never change the prologue (see
+    /// [`register_fill_len`]).
+    pub fn generate_register_fill<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+    ) -> Vec<Instruction> {
+        (1..=REGISTER_FILL_LEN as u32)
+            .map(|reg| {
+                if rand.below(2) == 0 {
+                    Instruction::new(
+                        &LUI,
+                        vec![
+                            Argument::new(&args::RD, reg),
+                            self.generate_argument(rand, &args::IMM20),
+                        ],
+                    )
+                } else {
+                    Instruction::new(
+                        &ADDI,
+                        vec![
+                            Argument::new(&args::RD, reg),
+                            Argument::new(&args::RS1, 0),
+                            self.generate_argument(rand, &args::IMM12),
+                        ],
+                    )
+                }
+            })
+            .collect()
+    }
+
+    /// Prepends the parts of the prologue that are enabled and that the
+    /// program doesn't have yet: a register fill and, after it, the
+    /// [`Self::mem_base_setup`].
+    pub fn add_register_fill<R: libafl::prelude::Rand>(
+        &self,
+        rand: &mut R,
+        mut insts: Vec<Instruction>,
+    ) -> Vec<Instruction> {
+        let fill = fill_len(&insts);
+        let mut body = insts.split_off(fill);
+        if self.register_fill && fill == 0 {
+            insts = self.generate_register_fill(rand);
+        }
+        if mem_base_setup_len(&body) == 0 {
+            insts.extend(self.mem_base_setup());
+        }
+        insts.append(&mut body);
+        insts
+    }
+
+    /// Generates `number` instructions, or the configured maximum if that is
+    /// less.
     pub fn generate_instructions<R: libafl::prelude::Rand>(
         &self,
         rand: &mut R,
         insts: &Vec<&'static InstructionTemplate>,
         number: u32,
     ) -> Vec<Instruction> {
+        let number = number.min(self.max_program_len.try_into().unwrap_or(u32::MAX));
         let mut result = Vec::<Instruction>::new();
-        for _ in 0..number {
-            result.push(self.generate_instruction(rand, insts));
+        // The last destination registers, oldest first.
+        let mut recent = Vec::<(RegisterFile, u32)>::new();
+        // All destination registers so far.
+        let mut written = Vec::<(RegisterFile, u32)>::new();
+        while result.len() < number as usize {
+            let remaining = number as usize - result.len();
+            let start = result.len();
+            if remaining >= self.memory_pair_len() && rand.below(100) < self.paired_mem_chance {
+                result.append(&mut self.generate_memory_pair(rand));
+            } else {
+                let inst = self.generate_instruction(rand, insts);
+                // The recent registers are the narrower bias, so they win.
+                let inst = self.bias_to_written(rand, inst, &written);
+                let inst = self.bias_to_recent(rand, inst, &recent);
+                result.append(&mut self.target_scratch(rand, inst, remaining));
+            }
+            for inst in &result[start..] {
+                let Some(rd) = destination(inst) else {
+                    continue;
+                };
+                if !written.contains(&rd) {
+                    written.push(rd);
+                }
+                if self.recent_regs != 0 {
+                    recent.retain(|reg| *reg != rd);
+                    recent.push(rd);
+                }
+            }
+            if recent.len() > self.recent_regs {
+                recent.drain(..recent.len() - self.recent_regs);
+            }
         }
         result
     }
@@ -82,11 +1287,130 @@ impl InstGenerator {
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use libafl::prelude::{Rand, Xoshiro256StarRand};
 
-    use crate::instructions::{self, Argument};
+    use crate::{
+        abi::{register_file, RegisterFile},
+        assembler::assemble_instructions,
+        config::DriverConfig,
+        immediates::crash_neighbors,
+        instructions::{
+            self,
+            riscv::{args, rv_i::ADDI},
+            test_insts::inst_with,
+            Argument, Instruction, InstructionCategory, COMPRESSED_ALIGNMENT, INST_ALIGNMENT,
+        },
+    };
+
+    use super::{
+        parse_arg_ranges, parse_categories, parse_category_weights, parse_recent_regs,
+        parse_template_weights, preview_programs, program_byte_len, register_fill_len,
+        without_categories, written_registers, DataflowRamp, InstGenerator, ScratchRegion,
+        DEFAULT_WEIGHT, MEM_BASE_REG, MEM_BASE_SPAN, REGISTER_FILL_LEN,
+    };
+
+    #[test]
+    fn weighted_templates() {
+        use instructions::riscv::rv_i::{ADD, ADDI};
+
+        let config = DriverConfig {
+            template_weights: parse_template_weights("addi=1000").unwrap(),
+            category_weights: parse_category_weights("arith=0, float=0").unwrap(),
+            ..DriverConfig::default()
+        };
+        let generator = InstGenerator::with_config(&config);
+        assert_eq!(generator.weight(&ADDI), 1000);
+        assert_eq!(generator.weight(&ADD), 0);
+        assert_eq!(
+            generator.weight(&instructions::riscv::rv_i::LW),
+            DEFAULT_WEIGHT
+        );
+
+        let isa = instructions::sets::riscv_g();
+        let mut rng = Xoshiro256StarRand::default();
+        let mut addi = 0;
+        for _ in 0..2000 {
+            let inst = generator.generate_instruction(&mut rng, &isa);
+            let category = inst.template().category();
+            assert!(category != InstructionCategory::Float, "{}", inst);
+            if category == InstructionCategory::Arith {
+                assert_eq!(inst.template(), &ADDI);
+                addi += 1;
+            }
+        }
+        // At least half of the expected share of addi.
+        let rest = isa.iter().map(|t| generator.weight(t)).sum::<u64>() - 1000;
+        assert!(addi > 2000 * 1000 / (rest + 1000) / 2, "{} addi", addi);
+
+        // A name stands for the RV32 and the RV64 variant.
+        assert!(parse_template_weights("slli=5").unwrap().len() >= 2);
+        assert!(parse_template_weights("addx=5").is_err());
+        assert!(parse_template_weights("addi").is_err());
+        assert!(parse_category_weights("arith=-1").is_err());
+        assert!(parse_category_weights("arithmetic=1").is_err());
+    }
+
+    #[test]
+    fn generate_system_instructions() {
+        let system = instructions::sets::riscv_system();
+        let mut generator = InstGenerator::new();
+        generator.set_avoid_reserved(true);
+        let mut rng = Xoshiro256StarRand::default();
+        let mut names = std::collections::HashSet::new();
+        for _ in 0..1000 {
+            let inst = generator.generate_instruction(&mut rng, &system);
+            assert_eq!(inst.arguments().len(), inst.template().operands().count());
+            names.insert(inst.template().name().to_owned());
+        }
+        assert_eq!(names.len(), system.len());
+    }
+
+    #[test]
+    fn csr_instructions_use_known_csrs() {
+        let config = DriverConfig {
+            csr: true,
+            ..DriverConfig::default()
+        };
+        let csr = instructions::sets::riscv_zicsr();
+        assert!(csr.iter().all(|t| config.instructions().contains(t)));
+        assert!(!DriverConfig::default().instructions().contains(&csr[0]));
 
-    use super::InstGenerator;
+        let generator = InstGenerator::with_config(&config);
+        let mut rng = Xoshiro256StarRand::default();
+        let mut known = 0;
+        for _ in 0..1000 {
+            let inst = generator.generate_instruction(&mut rng, &csr);
+            let address = arg_value(&inst, &instructions::zicsr::CSR);
+            if instructions::zicsr::KNOWN_CSRS
+                .iter()
+                .any(|(_, known)| *known == address)
+            {
+                known += 1;
+            }
+        }
+        // Random addresses are almost never known ones.
+        assert!(known > 800, "{}", known);
+        assert!(known < 1000, "{}", known);
+    }
+
+    #[test]
+    fn generator_from_config() {
+        let config = DriverConfig {
+            compressed: true,
+            arg_ranges: vec![(&args::RD, 1, 7)],
+            ..DriverConfig::default()
+        };
+        let generator = InstGenerator::with_config(&config);
+        assert_eq!(generator.target_alignment(), COMPRESSED_ALIGNMENT);
+        assert_eq!(InstGenerator::new().target_alignment(), INST_ALIGNMENT);
+        let mut rng = Xoshiro256StarRand::default();
+        for _ in 0..1000 {
+            let rd = generator.generate_argument(&mut rng, &args::RD).value();
+            assert!((1..=7).contains(&rd));
+        }
+    }
 
     #[test]
     fn generate_random_instructions() {
@@ -102,6 +1426,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn generate_m_extension_instructions() {
+        let m = instructions::sets::riscv_m();
+        let names: Vec<_> = m.iter().map(|t| t.name()).collect();
+        for name in ["mul", "mulh", "div", "divu", "rem", "remu", "mulw"] {
+            assert!(names.contains(&name), "{} missing", name);
+        }
+        let g = instructions::sets::riscv_g();
+        assert!(m.iter().all(|t| g.contains(t)));
+
+        let mut rng = Xoshiro256StarRand::default();
+        let generator = InstGenerator::new();
+        let generated = (0..10000)
+            .filter(|_| m.contains(&generator.generate_instruction(&mut rng, &g).template()))
+            .count();
+        assert!(generated > 0, "no M extension instruction generated");
+    }
+
     #[test]
     fn generate_instructions_and_reuse_arguments() {
         for i in 0..20 {
@@ -136,4 +1478,748 @@ mod tests {
             assert!(found);
         }
     }
+
+    #[test]
+    fn configured_arg_chances() {
+        let mut rng = Xoshiro256StarRand::default();
+        let known = Argument::new(&args::IMM20, 0x12345);
+        let reused = |generator: &mut InstGenerator, rng: &mut Xoshiro256StarRand| {
+            generator.forward_args(&[known.clone()]);
+            (0..1000)
+                .filter(|_| generator.generate_argument(rng, &args::IMM20) == known)
+                .count()
+        };
+        assert_eq!(
+            reused(&mut InstGenerator::with_chances(100, 0), &mut rng),
+            1000
+        );
+        assert_eq!(reused(&mut InstGenerator::with_chances(0, 0), &mut rng), 0);
+
+        let powers = InstGenerator::with_chances(0, 100);
+        for _ in 0..1000 {
+            let imm = powers.generate_argument(&mut rng, &args::IMM12).value();
+            assert!(imm.is_power_of_two(), "{:#x}", imm);
+        }
+
+        // Explicit chances win over a disabled reuse.
+        let config = DriverConfig {
+            arg_reuse: false,
+            reuse_chance: Some(100),
+            ..DriverConfig::default()
+        };
+        assert_eq!(
+            reused(&mut InstGenerator::with_config(&config), &mut rng),
+            1000
+        );
+    }
+
+    /// Returns the value of the argument with the given spec.
+    fn arg_value(inst: &Instruction, spec: &'static instructions::ArgumentSpec) -> u32 {
+        inst.arguments()
+            .iter()
+            .find(|arg| arg.spec() == spec)
+            .expect("Missing argument")
+            .value()
+    }
+
+    #[test]
+    fn generate_memory_pair_same_address() {
+        for i in 0..1000 {
+            let mut rng = Xoshiro256StarRand::default();
+            rng.set_seed(i);
+
+            let pair = InstGenerator::new().generate_memory_pair(&mut rng);
+            assert_eq!(pair.len(), 2);
+            let (store, load) = (&pair[0], &pair[1]);
+
+            assert_eq!(arg_value(store, &args::RS1), arg_value(load, &args::RS1));
+            let store_offset = (arg_value(store, &args::IMM12HI) << args::IMM12LO.length())
+                | arg_value(store, &args::IMM12LO);
+            assert_eq!(store_offset, arg_value(load, &args::IMM12));
+        }
+    }
+
+    #[test]
+    fn generate_instructions_with_memory_pairs() {
+        let mut rng = Xoshiro256StarRand::default();
+        let generator = InstGenerator::with_config(&DriverConfig {
+            paired_mem_chance: 100,
+            ..DriverConfig::default()
+        });
+
+        for len in 0..10 {
+            let insts =
+                generator.generate_instructions(&mut rng, &instructions::sets::riscv_g(), len);
+            assert_eq!(insts.len(), len as usize);
+        }
+    }
+
+    #[test]
+    fn rv32_accesses_are_legal() {
+        let config = DriverConfig {
+            isa: "rv32g".to_owned(),
+            scratch: Some(ScratchRegion::parse("0x80000000+4096").unwrap()),
+            ..DriverConfig::default()
+        };
+        let generator = InstGenerator::with_config(&config);
+        let mut rng = Xoshiro256StarRand::default();
+        for _ in 0..1000 {
+            let mut insts = generator.generate_memory_pair(&mut rng);
+            assert_eq!(insts.len(), generator.memory_pair_len());
+            insts.extend(generator.gen_memory_access(&mut rng, 5));
+            for inst in insts {
+                assert!(
+                    !instructions::sets::is_rv64_only(inst.template()),
+                    "{}",
+                    inst
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gen_memory_access_uses_base_reg() {
+        for i in 0..1000 {
+            let mut rng = Xoshiro256StarRand::default();
+            rng.set_seed(i);
+
+            let base_reg = 1 + (i as u32 % 31);
+            let insts = InstGenerator::new().gen_memory_access(&mut rng, base_reg);
+            assert_eq!(insts.len(), 2);
+            assert_eq!(arg_value(&insts[0], &args::RD), base_reg);
+            assert_eq!(arg_value(&insts[1], &args::RS1), base_reg);
+            assert_eq!(insts[1].template().category(), InstructionCategory::Mem);
+        }
+    }
+
+    /// The address the scratch setup and the access offset compute. The
+    /// setup is a `lui`, zero-extended on RV64.
+    fn scratch_target(setup: &[Instruction], offset: u32) -> u64 {
+        assert_eq!(setup[0].template().name(), "lui");
+        match setup.len() {
+            1 => {}
+            3 => {
+                assert_eq!(setup[1].template().name(), "slli");
+                assert_eq!(setup[2].template().name(), "srli");
+            }
+            len => panic!("{} instructions of setup", len),
+        }
+        // The register is zero-extended, so only the lower 32 bits count.
+        let upper = arg_value(&setup[0], &args::IMM20) << 12;
+        let offset = ((offset << 20) as i32 >> 20) as u32;
+        upper.wrapping_add(offset) as u64
+    }
+
+    /// The offset of a load or store, which stores split into two parts.
+    fn access_offset(access: &Instruction) -> u32 {
+        match access
+            .arguments()
+            .iter()
+            .find(|a| a.spec().name() == "imm12")
+        {
+            Some(imm) => imm.value(),
+            None => {
+                (arg_value(access, &args::IMM12HI) << args::IMM12LO.length())
+                    | arg_value(access, &args::IMM12LO)
+            }
+        }
+    }
+
+    #[test]
+    fn scratch_region_accesses() {
+        assert!(ScratchRegion::parse("0x80000000+4096").is_ok());
+        assert_eq!(
+            ScratchRegion::parse("0x1000+16").unwrap().to_string(),
+            "0x1000+16"
+        );
+        assert!(ScratchRegion::parse("0x80000004+4096").is_err());
+        assert!(ScratchRegion::parse("0x80000000+12").is_err());
+        assert!(ScratchRegion::parse("0x80000000+0").is_err());
+        assert!(ScratchRegion::parse("0xfffff000+4096").is_err());
+        assert!(ScratchRegion::parse("4096").is_err());
+
+        // Crosses a 2 KiB boundary, so both offset signs are needed.
+        let region = ScratchRegion::parse("0x80000700+512").unwrap();
+        let mut generator = InstGenerator::new();
+        generator.set_scratch_region(Some(region));
+        for i in 0..1000 {
+            let mut rng = Xoshiro256StarRand::default();
+            rng.set_seed(i);
+
+            let insts = generator.gen_memory_access(&mut rng, 5);
+            assert_eq!(insts.len(), 4);
+            let access = &insts[3];
+            let address = scratch_target(&insts[..3], access_offset(access));
+            assert!(
+                (region.base..region.end()).contains(&address),
+                "{:#x}",
+                address
+            );
+            assert_eq!(arg_value(access, &args::RS1), 5);
+
+            let pair = generator.generate_memory_pair(&mut rng);
+            assert_eq!(pair.len(), generator.memory_pair_len());
+            let address = scratch_target(&pair[..3], arg_value(&pair[4], &args::IMM12));
+            assert!(
+                (region.base..region.end()).contains(&address),
+                "{:#x}",
+                address
+            );
+        }
+    }
+
+    #[test]
+    fn plain_accesses_target_scratch_region() {
+        let region = ScratchRegion::parse("0x80000700+512").unwrap();
+        let set: Vec<_> = instructions::sets::riscv_g()
+            .into_iter()
+            .filter(|t| ["lw", "sb", "flw", "fsd", "add"].contains(&t.name()))
+            .collect();
+        for (rv64, setup_len) in [(true, 3), (false, 1)] {
+            let mut generator = InstGenerator::new();
+            generator.set_scratch_region(Some(region));
+            generator.set_rv64(rv64);
+            let mut rng = Xoshiro256StarRand::default();
+            let program = generator.generate_instructions(&mut rng, &set, 500);
+            assert_eq!(program.len(), 500);
+            assert_eq!(
+                program.iter().any(|inst| inst.template().name() == "slli"),
+                rv64
+            );
+
+            let (mut targeted, mut untargeted) = (0, 0);
+            for (i, access) in program.iter().enumerate() {
+                if access.template().category() != InstructionCategory::Mem {
+                    continue;
+                }
+                let rs1 = arg_value(access, &args::RS1);
+                let setup = &program[i.saturating_sub(setup_len)..i];
+                let is_setup = setup.len() == setup_len
+                    && setup[0].template().name() == "lui"
+                    && setup.iter().all(|inst| arg_value(inst, &args::RD) == rs1);
+                if !is_setup {
+                    untargeted += 1;
+                    continue;
+                }
+                let address = scratch_target(setup, access_offset(access));
+                assert!(
+                    (region.base..region.end()).contains(&address),
+                    "{:#x}",
+                    address
+                );
+                targeted += 1;
+            }
+            // Only accesses without room for the setup at the end.
+            assert!(targeted > 100, "{}", targeted);
+            assert!(untargeted <= setup_len, "{}", untargeted);
+        }
+    }
+
+    #[test]
+    fn mem_base_accesses() {
+        let mut generator = InstGenerator::new();
+        // The middle of the region has a negative lower part.
+        let region = ScratchRegion::new(0x8000_0700, MEM_BASE_SPAN).unwrap();
+        generator.set_scratch_region(Some(region));
+        assert!(generator.mem_base_setup().is_empty());
+        generator.set_mem_base(true);
+        let setup = generator.mem_base_setup();
+        assert_eq!(setup.len(), 4);
+        assert_eq!(arg_value(&setup[3], &args::RS1), MEM_BASE_REG);
+        let address = scratch_target(&setup[..3], arg_value(&setup[3], &args::IMM12));
+        assert_eq!(address, region.base + MEM_BASE_SPAN / 2);
+        assert_eq!(register_fill_len(&setup), 4);
+        assert!(!generator.clobbers_mem_base(&setup[0]));
+        generator.set_rv64(false);
+        assert_eq!(generator.mem_base_setup().len(), 2);
+        assert_eq!(register_fill_len(&generator.mem_base_setup()), 2);
+        generator.set_rv64(true);
+
+        let isa = instructions::sets::riscv_g();
+        let mut rng = Xoshiro256StarRand::default();
+        let mut based = 0;
+        for _ in 0..20 {
+            let body = generator.generate_instructions(&mut rng, &isa, 200);
+            let program = generator.add_register_fill(&mut rng, body.clone());
+            // The setup is the prologue, which isn't added twice.
+            assert_eq!(program[..4], setup);
+            assert_eq!(register_fill_len(&program), 4);
+            assert_eq!(
+                generator.add_register_fill(&mut rng, program.clone()),
+                program
+            );
+            for inst in &body {
+                assert!(!generator.clobbers_mem_base(inst), "{}", inst);
+                let int_reg = |name: &str| {
+                    inst.arguments()
+                        .iter()
+                        .find(|arg| arg.spec().name() == name)
+                        .filter(|_| !inst.template().name().starts_with('f'))
+                        .map(|arg| arg.value())
+                };
+                assert_ne!(int_reg("rd"), Some(MEM_BASE_REG), "{}", inst);
+                let category = inst.template().category();
+                if category == InstructionCategory::Mem && int_reg("rs1") == Some(MEM_BASE_REG) {
+                    based += 1;
+                }
+            }
+        }
+        assert!(based > 100, "{}", based);
+
+        // After a register fill, which also writes x31.
+        generator.set_register_fill(true);
+        let program = generator.add_register_fill(&mut rng, vec![]);
+        assert_eq!(register_fill_len(&program), REGISTER_FILL_LEN + 4);
+        assert_eq!(program[REGISTER_FILL_LEN..], setup);
+    }
+
+    #[test]
+    fn crash_immediates_are_reused() {
+        let crash = Instruction::new(
+            &instructions::riscv::rv_i::ADDI,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 1),
+                Argument::new(&args::IMM12, 1000),
+            ],
+        );
+        let mut generator = InstGenerator::new();
+        generator.forward_crash_args(&crash_neighbors(&[crash]));
+
+        let mut rng = Xoshiro256StarRand::default();
+        let imms: Vec<u32> = (0..1000)
+            .map(|_| generator.generate_argument(&mut rng, &args::IMM12).value())
+            .collect();
+        assert!(imms.contains(&999) && imms.contains(&1001) && imms.contains(&1004));
+        let near = imms
+            .iter()
+            .filter(|imm| (996..=1004).contains(*imm))
+            .count();
+        assert!(near > 300, "only {} values near the crash", near);
+    }
+
+    #[test]
+    fn register_fill_writes_all_registers() {
+        let mut rng = Xoshiro256StarRand::default();
+        let mut generator = InstGenerator::new();
+        let fill = generator.generate_register_fill(&mut rng);
+        let mut written: Vec<u32> = fill.iter().map(|inst| arg_value(inst, &args::RD)).collect();
+        written.sort();
+        assert_eq!(written, (1..32).collect::<Vec<u32>>());
+        assert_eq!(register_fill_len(&fill), REGISTER_FILL_LEN);
+
+        let body = generator.generate_instructions(&mut rng, &instructions::sets::riscv_g(), 5);
+        assert_eq!(generator.add_register_fill(&mut rng, body.clone()), body);
+        generator.set_register_fill(true);
+        let program = generator.add_register_fill(&mut rng, body.clone());
+        assert_eq!(register_fill_len(&program), REGISTER_FILL_LEN);
+        assert_eq!(&program[REGISTER_FILL_LEN..], &body[..]);
+        // Programs that already have a prologue don't get a second one.
+        assert_eq!(
+            generator.add_register_fill(&mut rng, program.clone()),
+            program
+        );
+        assert_eq!(register_fill_len(&program[1..]), 0);
+    }
+
+    #[test]
+    fn previews_are_seeded() {
+        let config = DriverConfig {
+            register_fill: true,
+            ..DriverConfig::default()
+        };
+        let programs = preview_programs(&config, 7, 5, 10);
+        assert_eq!(programs.len(), 5);
+        for program in &programs {
+            assert_eq!(program.insts().len(), REGISTER_FILL_LEN + 10);
+            assert_eq!(register_fill_len(program.insts()), REGISTER_FILL_LEN);
+        }
+        assert_eq!(preview_programs(&config, 7, 5, 10), programs);
+        assert_ne!(preview_programs(&config, 8, 5, 10), programs);
+    }
+
+    #[test]
+    fn compressed_program_byte_len() {
+        let isa = instructions::sets::by_name("rv64gc").unwrap();
+        let generator = InstGenerator::new();
+        let mut rng = Xoshiro256StarRand::default();
+        let program = generator.generate_instructions(&mut rng, &isa, 200);
+        let compressed = program
+            .iter()
+            .filter(|inst| inst.template().is_compressed())
+            .count();
+        assert!(compressed > 0 && compressed < program.len());
+        assert_eq!(
+            program_byte_len(&program),
+            4 * program.len() - 2 * compressed
+        );
+        assert_eq!(
+            program_byte_len(&program),
+            assemble_instructions(&program).len()
+        );
+    }
+
+    #[test]
+    fn arg_ranges_respected() {
+        let mut generator = InstGenerator::new();
+        generator.set_arg_range(&args::IMM12, 4, 20);
+        generator.set_arg_range(&args::RD, 3, 3);
+        // Reused values outside of the range must be ignored.
+        generator.forward_args(&[Argument::new(&args::IMM12, 100)]);
+
+        let mut rng = Xoshiro256StarRand::default();
+        for _ in 0..10000 {
+            let imm = generator.generate_argument(&mut rng, &args::IMM12).value();
+            assert!((4..=20).contains(&imm), "{} out of range", imm);
+            assert_eq!(generator.generate_argument(&mut rng, &args::RD).value(), 3);
+        }
+    }
+
+    #[test]
+    fn avoid_reserved_rounding_modes() {
+        let fadd = instructions::sets::riscv_g()
+            .into_iter()
+            .find(|t| t.name() == "fadd.s")
+            .unwrap();
+        // Counts the generated instructions with the reserved modes 5 and 6.
+        let reserved_modes = |generator: &InstGenerator| {
+            let mut rng = Xoshiro256StarRand::default();
+            rng.set_seed(0);
+            (0..1000)
+                .filter(|_| {
+                    let inst = generator.generate_instruction(&mut rng, &vec![fadd]);
+                    let rm = inst.arguments().iter().find(|a| a.spec().name() == "rm");
+                    matches!(rm.unwrap().value(), 5 | 6)
+                })
+                .count()
+        };
+
+        let mut generator = InstGenerator::new();
+        assert!(reserved_modes(&generator) > 0);
+        generator.set_avoid_reserved(true);
+        assert_eq!(reserved_modes(&generator), 0);
+    }
+
+    #[test]
+    fn branch_targets_are_aligned() {
+        let templates: Vec<_> = instructions::sets::riscv_g()
+            .into_iter()
+            .filter(|t| t.name() == "beq" || t.name() == "jal")
+            .collect();
+        assert_eq!(templates.len(), 2);
+        let misaligned = |generator: &InstGenerator, alignment: u32| {
+            let mut rng = Xoshiro256StarRand::default();
+            rng.set_seed(0);
+            (0..1000)
+                .filter(|_| {
+                    !generator
+                        .generate_instruction(&mut rng, &templates)
+                        .has_aligned_targets(alignment)
+                })
+                .count()
+        };
+
+        // Without the C extension every target is 4-byte aligned.
+        let mut generator = InstGenerator::new();
+        assert_eq!(generator.target_alignment(), INST_ALIGNMENT);
+        assert_eq!(misaligned(&generator, INST_ALIGNMENT), 0);
+        // With it, 2-byte aligned targets are generated as well.
+        generator.set_target_alignment(COMPRESSED_ALIGNMENT);
+        assert!(misaligned(&generator, INST_ALIGNMENT) > 0);
+        assert_eq!(misaligned(&generator, COMPRESSED_ALIGNMENT), 0);
+    }
+
+    #[test]
+    fn recent_registers_are_reused() {
+        let add: Vec<_> = instructions::sets::riscv_g()
+            .into_iter()
+            .filter(|t| t.name() == "add")
+            .collect();
+        // How often an instruction reads the register the previous one wrote.
+        let reuse_rate = |generator: &InstGenerator| {
+            let mut rng = Xoshiro256StarRand::default();
+            rng.set_seed(0);
+            let program = generator.generate_instructions(&mut rng, &add, 2000);
+            let reuses = program
+                .windows(2)
+                .filter(|pair| {
+                    let rd = arg_value(&pair[0], &args::RD);
+                    rd != 0
+                        && (arg_value(&pair[1], &args::RS1) == rd
+                            || arg_value(&pair[1], &args::RS2) == rd)
+                })
+                .count();
+            reuses as f64 / (program.len() - 1) as f64
+        };
+
+        let mut generator = InstGenerator::new();
+        let uniform = reuse_rate(&generator);
+        generator.set_recent_regs(1, 50);
+        let biased = reuse_rate(&generator);
+        assert!(
+            biased > uniform * 3.0,
+            "uniform {} biased {}",
+            uniform,
+            biased
+        );
+
+        // The bias respects the argument ranges.
+        generator.set_arg_range(&args::RS1, 0, 0);
+        generator.set_arg_range(&args::RS2, 0, 0);
+        let mut rng = Xoshiro256StarRand::default();
+        rng.set_seed(0);
+        for inst in generator.generate_instructions(&mut rng, &add, 100) {
+            assert_eq!(arg_value(&inst, &args::RS1), 0);
+            assert_eq!(arg_value(&inst, &args::RS2), 0);
+        }
+
+        assert_eq!(parse_recent_regs("4:30"), Ok((4, 30)));
+        assert!(parse_recent_regs("4").is_err());
+        assert!(parse_recent_regs("4:101").is_err());
+        assert!(parse_recent_regs("x:1").is_err());
+    }
+
+    #[test]
+    fn written_registers_are_read() {
+        let add: Vec<_> = instructions::sets::riscv_g()
+            .into_iter()
+            .filter(|t| t.name() == "add")
+            .collect();
+        // The number of source operands reading a register written by an
+        // earlier instruction.
+        let dependencies = |program: &[Instruction]| {
+            (1..program.len())
+                .map(|i| {
+                    [&args::RS1, &args::RS2]
+                        .into_iter()
+                        .map(|spec| arg_value(&program[i], spec))
+                        .filter(|reg| {
+                            let written = |inst: &Instruction| arg_value(inst, &args::RD) == *reg;
+                            *reg != 0 && program[..i].iter().any(written)
+                        })
+                        .count()
+                })
+                .sum::<usize>()
+        };
+
+        let mut generator = InstGenerator::new();
+        let mut biased = 0;
+        for seed in 0..20 {
+            let mut rng = Xoshiro256StarRand::default();
+            rng.set_seed(seed);
+            let program = generator.generate_instructions(&mut rng, &add, 10);
+            let found = dependencies(&program);
+            assert!(found > 0, "no read-after-write dependency in {:?}", program);
+            biased += found;
+        }
+
+        generator.set_dataflow_chance(0);
+        let mut uniform = 0;
+        for seed in 0..20 {
+            let mut rng = Xoshiro256StarRand::default();
+            rng.set_seed(seed);
+            uniform += dependencies(&generator.generate_instructions(&mut rng, &add, 10));
+        }
+        assert!(biased > uniform, "uniform {} biased {}", uniform, biased);
+    }
+
+    #[test]
+    fn dataflow_stays_in_register_file() {
+        let names = ["add", "fadd.d", "fcvt.d.l", "fmv.x.d", "feq.d"];
+        let templates: Vec<_> = instructions::sets::riscv_g()
+            .into_iter()
+            .filter(|t| names.contains(&t.name()))
+            .collect();
+        assert_eq!(templates.len(), names.len());
+        let config = DriverConfig {
+            paired_mem_chance: 0,
+            ..DriverConfig::default()
+        };
+        let mut generator = InstGenerator::with_config(&config);
+        generator.set_dataflow_chance(100);
+
+        // The registers of the file written before the instruction at `i`.
+        let written = |program: &[Instruction], i: usize, file: RegisterFile| -> Vec<u32> {
+            program[..i]
+                .iter()
+                .filter(|producer| register_file(producer.template(), "rd") == Some(file))
+                .map(|producer| arg_value(producer, &args::RD))
+                .filter(|rd| file == RegisterFile::Float || *rd != 0)
+                .collect()
+        };
+        let mut biased = 0;
+        for seed in 0..20 {
+            let mut rng = Xoshiro256StarRand::default();
+            rng.set_seed(seed);
+            let program = generator.generate_instructions(&mut rng, &templates, 20);
+            for (i, inst) in program.iter().enumerate() {
+                for arg in inst.arguments() {
+                    let name = arg.spec().name();
+                    if name != "rs1" && name != "rs2" {
+                        continue;
+                    }
+                    let file = register_file(inst.template(), name).unwrap();
+                    let producers = written(&program, i, file);
+                    // With a chance of 100, every source with a producer in
+                    // its register file reads one.
+                    if !producers.is_empty() {
+                        assert!(
+                            producers.contains(&arg.value()),
+                            "{} of {:?} doesn't read one of {:?}",
+                            name,
+                            inst,
+                            producers
+                        );
+                        biased += 1;
+                    }
+                }
+            }
+        }
+        assert!(biased > 0);
+
+        let config = DriverConfig {
+            dataflow: false,
+            ..DriverConfig::default()
+        };
+        assert_eq!(InstGenerator::with_config(&config).dataflow_chance, 0);
+    }
+
+    #[test]
+    fn dataflow_ramp() {
+        let ramp = DataflowRamp::parse("10:90:100").unwrap();
+        assert_eq!(ramp.chance_at(Duration::ZERO), 10);
+        assert_eq!(ramp.chance_at(Duration::from_secs(50)), 50);
+        assert_eq!(ramp.chance_at(Duration::from_secs(1000)), 90);
+        assert_eq!(ramp.to_string(), "10:90:100");
+        // A ramp without a duration is at its maximum right away.
+        let instant = DataflowRamp::parse("10:90:0").unwrap();
+        assert_eq!(instant.chance_at(Duration::ZERO), 90);
+        assert!(DataflowRamp::parse("10:90").is_err());
+        assert!(DataflowRamp::parse("10:x:60").is_err());
+        assert!(DataflowRamp::parse("50:10:60").is_err());
+
+        let config = DriverConfig {
+            dataflow_ramp: Some(ramp),
+            ..DriverConfig::default()
+        };
+        assert_eq!(InstGenerator::with_config(&config).dataflow_chance, 10);
+    }
+
+    #[test]
+    fn insertions_read_written_registers() {
+        let program = vec![
+            inst_with(&ADDI, &[(&args::RD, 0), (&args::IMM12, 1)]),
+            inst_with(&ADDI, &[(&args::RD, 5), (&args::IMM12, 1)]),
+            inst_with(&ADDI, &[(&args::RD, 5), (&args::RS1, 5)]),
+        ];
+        // Writes to x0 don't count, and x5 is only listed once.
+        let written = written_registers(&program);
+        assert_eq!(written, vec![(RegisterFile::Int, 5)]);
+
+        let add: Vec<_> = instructions::sets::riscv_g()
+            .into_iter()
+            .filter(|t| t.name() == "add")
+            .collect();
+        let mut generator = InstGenerator::new();
+        generator.set_dataflow_chance(100);
+        let mut rng = Xoshiro256StarRand::default();
+        for _ in 0..100 {
+            let insts = generator.generate_insertion(&mut rng, &add, &written);
+            assert_eq!(insts.len(), 1);
+            assert_eq!(arg_value(&insts[0], &args::RS1), 5, "{}", insts[0]);
+            assert_eq!(arg_value(&insts[0], &args::RS2), 5, "{}", insts[0]);
+        }
+    }
+
+    #[test]
+    fn emphasized_category() {
+        let set = instructions::sets::riscv_g();
+        let share = |generator: &InstGenerator, category| {
+            let mut rng = Xoshiro256StarRand::default();
+            rng.set_seed(0);
+            let insts = generator.generate_instructions(&mut rng, &set, 2000);
+            let matching = insts
+                .iter()
+                .filter(|inst| inst.template().category() == category)
+                .count();
+            matching as f64 / insts.len() as f64
+        };
+
+        let mut generator = InstGenerator::new();
+        let uniform = share(&generator, InstructionCategory::Atomic);
+        generator.set_emphasis(Some(InstructionCategory::Atomic));
+        let emphasized = share(&generator, InstructionCategory::Atomic);
+        assert!(
+            emphasized > 0.5,
+            "uniform {} emphasized {}",
+            uniform,
+            emphasized
+        );
+        assert!(
+            emphasized > uniform * 2.0,
+            "uniform {} emphasized {}",
+            uniform,
+            emphasized
+        );
+
+        // Without instructions of the category the whole set is used.
+        let base = instructions::sets::riscv_base();
+        let mut rng = Xoshiro256StarRand::default();
+        let insts = generator.generate_instructions(&mut rng, &base, 100);
+        assert_eq!(insts.len(), 100);
+    }
+
+    #[test]
+    fn exclude_categories() {
+        let excluded = parse_categories("branch, system").unwrap();
+        assert_eq!(
+            excluded,
+            [InstructionCategory::Branch, InstructionCategory::System]
+        );
+        assert!(parse_categories("").unwrap().is_empty());
+        let err = parse_categories("branch,sytem").unwrap_err();
+        assert!(err.contains("'sytem'") && err.contains("system"), "{}", err);
+
+        let set = without_categories(instructions::sets::riscv_g(), &excluded);
+        assert!(!set.is_empty());
+        assert!(set.iter().all(|t| !excluded.contains(&t.category())));
+        assert!(set.iter().any(|t| t.name() == "add"));
+        assert!(!set.iter().any(|t| t.name() == "beq" || t.name() == "ecall"));
+    }
+
+    #[test]
+    fn parse_arg_range_list() {
+        let ranges = parse_arg_ranges("imm12=0-64, rd=0x1-0x7").unwrap();
+        assert_eq!(ranges, vec![(&args::IMM12, 0, 64), (&args::RD, 1, 7)]);
+        assert!(parse_arg_ranges("").unwrap().is_empty());
+        assert!(parse_arg_ranges("rd=1-32").is_err());
+        assert!(parse_arg_ranges("rd=5-1").is_err());
+        assert!(parse_arg_ranges("bogus=1-2").is_err());
+        assert!(parse_arg_ranges("rd").is_err());
+    }
+
+    /// Checks that values for the given field cover its whole range.
+    fn check_full_range(spec: &'static instructions::ArgumentSpec) {
+        let mut rng = Xoshiro256StarRand::default();
+        let generator = InstGenerator::new();
+        let mut max_seen = 0;
+        for _ in 0..10000 {
+            let value = generator.generate_argument(&mut rng, spec).value();
+            assert!(value <= spec.mask());
+            max_seen = max_seen.max(value);
+        }
+        // Values from the upper half of the range must show up.
+        assert!(
+            max_seen > spec.mask() / 2,
+            "{} never above half",
+            spec.name()
+        );
+    }
+
+    #[test]
+    fn generate_32_bit_argument() {
+        check_full_range(&args::IMM20);
+        let wide = Box::leak(Box::new(instructions::ArgumentSpec::new("imm32", 32, 0)));
+        check_full_range(wide);
+    }
 }
@@ -0,0 +1,67 @@
+//! User commands that are run when the fuzzer finds something.
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+/// A shell command that is run for every saved objective, with the path of
+/// the saved file appended as the last argument.
+#[derive(Clone, Debug)]
+pub struct CrashHook {
+    command: String,
+}
+
+impl CrashHook {
+    pub fn new(command: String) -> Self {
+        Self { command }
+    }
+
+    /// The command for the given crash file. The command line is passed to
+    /// `sh`, so it may contain its own arguments.
+    pub fn command(&self, crash_path: &Path) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(format!("{} \"$@\"", self.command))
+            .arg("sh")
+            .arg(crash_path)
+            .stdin(Stdio::null());
+        cmd
+    }
+
+    /// Starts the command without waiting for it. Failures are only logged.
+    pub fn run(&self, crash_path: &Path) {
+        let mut child = match self.command(crash_path).spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                log::error!("Failed to run crash hook '{}': {}", self.command, err);
+                return;
+            }
+        };
+        // Reap the child in the background so it doesn't become a zombie.
+        let command = self.command.clone();
+        thread::spawn(move || match child.wait() {
+            Ok(status) if !status.success() => {
+                log::error!("Crash hook '{}' failed with {}", command, status)
+            }
+            Ok(_) => {}
+            Err(err) => log::error!("Failed to wait for crash hook '{}': {}", command, err),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::CrashHook;
+
+    #[test]
+    fn hook_gets_crash_path() {
+        let exists = std::env::temp_dir();
+        let hook = CrashHook::new("test -e".to_string());
+        assert!(hook.command(&exists).status().unwrap().success());
+        let missing = Path::new("/nonexistent/crash with spaces");
+        assert!(!hook.command(missing).status().unwrap().success());
+    }
+}
@@ -0,0 +1,141 @@
+//! Immediate values taken from saved crashes, which the generator reuses to
+//! explore the neighborhood of crash-triggering constants.
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::instructions::{sets, Argument, Instruction};
+
+/// The offsets added to and subtracted from every crashing immediate.
+pub const NEIGHBOR_DISTANCES: [u32; 3] = [1, 2, 4];
+
+/// Upper bound for the number of remembered values. The oldest values are
+/// dropped first.
+pub const MAX_CRASH_IMMEDIATES: usize = 256;
+
+/// The immediates of the program and their neighbors within the field width,
+/// e.g. `imm12` 0 also yields 1, 2, 4 and 0xfff, 0xffe, 0xffc.
+pub fn crash_neighbors(insts: &[Instruction]) -> Vec<Argument> {
+    let mut result = vec![];
+    for arg in insts.iter().flat_map(|inst| inst.arguments()) {
+        let spec = arg.spec();
+        if !spec.is_immediate() {
+            continue;
+        }
+        result.push(arg.clone());
+        for distance in NEIGHBOR_DISTANCES {
+            result.push(Argument::new(
+                spec,
+                arg.value().wrapping_add(distance) & spec.mask(),
+            ));
+            result.push(Argument::new(
+                spec,
+                arg.value().wrapping_sub(distance) & spec.mask(),
+            ));
+        }
+    }
+    // Keeps the first of every value, e.g. of an immediate that is used
+    // twice or that is the neighbor of another.
+    let mut seen = HashSet::new();
+    result.retain(|arg| seen.insert((arg.spec().name(), arg.value())));
+    result
+}
+
+libafl::impl_serdeany!(CrashImmediatesMetadata);
+/// The crash neighborhood values in the state, as argument names and values.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct CrashImmediatesMetadata {
+    values: Vec<(String, u32)>,
+    /// Changes whenever values are added, so users can cache the arguments.
+    version: u64,
+}
+
+impl CrashImmediatesMetadata {
+    pub fn add(&mut self, args: &[Argument]) {
+        for arg in args {
+            let value = (arg.spec().name().to_owned(), arg.value());
+            if !self.values.contains(&value) {
+                self.values.push(value);
+            }
+        }
+        if self.values.len() > MAX_CRASH_IMMEDIATES {
+            let excess = self.values.len() - MAX_CRASH_IMMEDIATES;
+            self.values.drain(..excess);
+        }
+        self.version += 1;
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// The values as arguments of the known instruction sets.
+    pub fn arguments(&self) -> Vec<Argument> {
+        let templates = sets::all();
+        self.values
+            .iter()
+            .filter_map(|(name, value)| {
+                let spec = templates
+                    .iter()
+                    .find_map(|t| t.op_with_name(name.clone()))?;
+                Some(Argument::new(spec, *value))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crash_neighbors, CrashImmediatesMetadata, MAX_CRASH_IMMEDIATES};
+    use crate::instructions::{
+        riscv::{args, rv_i::ADDI},
+        Argument, Instruction,
+    };
+
+    fn addi(imm: u32) -> Instruction {
+        Instruction::new(
+            &ADDI,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 2),
+                Argument::new(&args::IMM12, imm),
+            ],
+        )
+    }
+
+    #[test]
+    fn neighbors_of_crash_immediates() {
+        let values: Vec<u32> = crash_neighbors(&[addi(100)])
+            .iter()
+            .map(|arg| arg.value())
+            .collect();
+        // Registers are not immediates.
+        assert_eq!(values, [100, 101, 99, 102, 98, 104, 96]);
+        // Neighbors wrap around within the field.
+        let values: Vec<u32> = crash_neighbors(&[addi(0)])
+            .iter()
+            .map(|arg| arg.value())
+            .collect();
+        assert!(values.contains(&0xfff) && values.contains(&0xffc));
+        // Values are only listed once, also if they aren't consecutive.
+        let values: Vec<u32> = crash_neighbors(&[addi(100), addi(5), addi(101)])
+            .iter()
+            .map(|arg| arg.value())
+            .collect();
+        assert_eq!(values.len(), 7 + 7 + 3);
+        assert_eq!(values.iter().filter(|value| **value == 100).count(), 1);
+
+        let mut meta = CrashImmediatesMetadata::default();
+        meta.add(&crash_neighbors(&[addi(100)]));
+        meta.add(&crash_neighbors(&[addi(100)]));
+        assert_eq!(meta.version(), 2);
+        let learned = meta.arguments();
+        assert_eq!(learned.len(), 7);
+        assert_eq!(learned[0], Argument::new(&args::IMM12, 100));
+
+        let many: Vec<Argument> = (0..1000).map(|v| Argument::new(&args::IMM12, v)).collect();
+        meta.add(&many);
+        assert_eq!(meta.arguments().len(), MAX_CRASH_IMMEDIATES);
+        assert_eq!(meta.arguments().last().unwrap().value(), 999);
+    }
+}
@@ -1,7 +1,19 @@
-use std::iter::{zip, Flatten};
+use std::{
+    fmt,
+    iter::{zip, Flatten},
+};
+
+use serde::{Deserialize, Serialize};
 
 pub type EncodedInstruction = u32;
 
+/// The alignment of branch and jump targets without the C extension.
+pub const INST_ALIGNMENT: u32 = 4;
+
+/// The alignment of branch and jump targets if the core implements the C
+/// extension. This is also the finest alignment the encoding can express.
+pub const COMPRESSED_ALIGNMENT: u32 = 2;
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct ArgumentSpec {
     name: &'static str,
@@ -20,8 +32,7 @@ impl ArgumentSpec {
     }
 
     pub fn extract(&'static self, inst: EncodedInstruction) -> Argument {
-        let mask: u32 = 2u32.pow(self.length) - 1u32;
-        let value: u32 = (inst >> self.offset) & mask;
+        let value: u32 = (inst >> self.offset) & self.mask();
         Argument { spec: self, value }
     }
 
@@ -29,13 +40,131 @@ impl ArgumentSpec {
         self.length
     }
 
-    pub fn max_value(&self) -> u32 {
-        2u32.pow(self.length)
+    /// The number of values this argument can have. A `u64` as this doesn't
+    /// fit in a `u32` for 32 bit fields.
+    pub fn max_value(&self) -> u64 {
+        1u64 << self.length
+    }
+
+    /// The largest value of this argument, i.e., all bits set.
+    pub fn mask(&self) -> u32 {
+        (self.max_value() - 1) as u32
     }
 
     pub fn name(&self) -> &str {
         self.name
     }
+
+    /// Returns true for immediates and shift amounts, i.e. for all argument
+    /// values that are constants and not registers or encoding options.
+    pub fn is_immediate(&self) -> bool {
+        self.name.contains("imm") || self.name.starts_with("shamt")
+    }
+
+    /// For the fields of branch and jump offsets, the bit of the value that
+    /// holds bit 1 of the offset. Bit 0 is always zero and not encoded.
+    pub fn offset_bit_one(&self) -> Option<u32> {
+        match self.name {
+            // inst[11:8] hold offset[4:1], inst[7] holds offset[11].
+            "bimm12lo" => Some(1 << 1),
+            // inst[30:21] hold offset[10:1].
+            "jimm20" => Some(1 << 9),
+            // inst[5:3] of `c.j` and inst[4:3] of `c.beqz` hold offset[3:1]
+            // and offset[2:1].
+            "c_imm12" | "c_bimm9lo" => Some(1 << 1),
+            _ => None,
+        }
+    }
+}
+
+/// Coarse family of an instruction, used to group templates.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum InstructionCategory {
+    #[default]
+    Unknown,
+    // Integer computation (including LUI/AUIPC).
+    Arith,
+    // Loads, stores and fences.
+    Mem,
+    // Branches and jumps.
+    Branch,
+    // Environment calls and CSR accesses.
+    System,
+    // Floating point computation.
+    Float,
+    // Atomic memory operations.
+    Atomic,
+}
+
+impl InstructionCategory {
+    /// All categories that instructions can actually be tagged with.
+    pub const ALL: [InstructionCategory; 6] = [
+        InstructionCategory::Arith,
+        InstructionCategory::Mem,
+        InstructionCategory::Branch,
+        InstructionCategory::System,
+        InstructionCategory::Float,
+        InstructionCategory::Atomic,
+    ];
+
+    /// Returns the category for the given major opcode (the lowest 7 bits).
+    pub fn from_opcode(opcode: u32) -> Self {
+        match opcode & 0x7f {
+            0x13 | 0x1b | 0x33 | 0x3b | 0x37 | 0x17 => InstructionCategory::Arith,
+            0x03 | 0x07 | 0x23 | 0x27 | 0x0f => InstructionCategory::Mem,
+            0x63 | 0x67 | 0x6f => InstructionCategory::Branch,
+            0x73 => InstructionCategory::System,
+            0x43 | 0x47 | 0x4b | 0x4f | 0x53 => InstructionCategory::Float,
+            0x2f => InstructionCategory::Atomic,
+            _ => InstructionCategory::Unknown,
+        }
+    }
+
+    /// Returns the category of a compressed instruction, which depends on
+    /// the quadrant (the lowest 2 bits) and funct3 (the highest 3 bits).
+    pub fn from_compressed(pattern: u32, mask: u32) -> Self {
+        match (pattern & 0b11, (pattern >> 13) & 0b111) {
+            // c.addi4spn
+            (0b00, 0b000) => InstructionCategory::Arith,
+            (0b00, _) => InstructionCategory::Mem,
+            // c.jal, c.j, c.beqz and c.bnez
+            (0b01, 0b001 | 0b101 | 0b110 | 0b111) => InstructionCategory::Branch,
+            (0b01, _) => InstructionCategory::Arith,
+            // c.slli
+            (0b10, 0b000) => InstructionCategory::Arith,
+            // c.ebreak
+            (0b10, 0b100) if mask == 0xffff => InstructionCategory::System,
+            // c.jr and c.jalr have a zero rs2, c.mv and c.add don't.
+            (0b10, 0b100) if mask & 0x7c != 0 => InstructionCategory::Branch,
+            (0b10, 0b100) => InstructionCategory::Arith,
+            // The loads and stores relative to the stack pointer.
+            (0b10, _) => InstructionCategory::Mem,
+            _ => InstructionCategory::Unknown,
+        }
+    }
+
+    /// The category with the given [`InstructionCategory::name`], out of
+    /// [`InstructionCategory::ALL`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|category| category.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            InstructionCategory::Unknown => "unknown",
+            InstructionCategory::Arith => "arith",
+            InstructionCategory::Mem => "mem",
+            InstructionCategory::Branch => "branch",
+            InstructionCategory::System => "system",
+            InstructionCategory::Float => "float",
+            InstructionCategory::Atomic => "atomic",
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -94,6 +223,31 @@ impl InstructionTemplate {
         self.name
     }
 
+    /// The instruction family this template belongs to.
+    pub fn category(&self) -> InstructionCategory {
+        if self.is_compressed() {
+            InstructionCategory::from_compressed(self.match_pattern, self.mask_pattern)
+        } else {
+            InstructionCategory::from_opcode(self.match_pattern)
+        }
+    }
+
+    /// Returns true for the 16 bit instructions of the C extension. All
+    /// other instructions have `0b11` in their lowest 2 bits, except for
+    /// [`raw::UNKNOWN`], which doesn't fix them at all.
+    pub fn is_compressed(&self) -> bool {
+        self.mask_pattern & 0b11 == 0b11 && self.match_pattern & 0b11 != 0b11
+    }
+
+    /// The length of the encoding in bytes.
+    pub fn size(&self) -> usize {
+        if self.is_compressed() {
+            2
+        } else {
+            4
+        }
+    }
+
     pub fn op_with_name(&self, name: String) -> Option<&'static ArgumentSpec> {
         for op in self.operands() {
             if op.name() == name {
@@ -103,6 +257,23 @@ impl InstructionTemplate {
         None
     }
 
+    /// Returns true if the given arguments for this template form a reserved
+    /// encoding that is decoded as an illegal instruction.
+    pub fn is_reserved(&self, args: &[Argument]) -> bool {
+        args.iter().any(|arg| match arg.spec.name() {
+            // Rounding modes 5 and 6 are reserved, 7 is the dynamic mode.
+            "rm" => arg.value == 5 || arg.value == 6,
+            // Only the normal and the TSO fence modes are defined.
+            "fm" => self.name == "fence" && arg.value != 0 && arg.value != 0b1000,
+            // The all-zero encoding is defined to be illegal.
+            "c_nzuimm10" => arg.value == 0,
+            // Compressed encodings with a zero register here are reserved,
+            // hints or other instructions (e.g. `c.jr` for `c.mv`).
+            name if name.ends_with("_n0") => arg.value == 0,
+            _ => false,
+        })
+    }
+
     pub fn matches(&self, data: EncodedInstruction) -> bool {
         data & self.mask_pattern == self.match_pattern
     }
@@ -125,9 +296,760 @@ impl InstructionTemplate {
 
 include!(concat!(env!("OUT_DIR"), "/raw_instructions.rs"));
 
+/// Templates for a subset of the Zbb (basic bit-manipulation) extension.
+/// Not part of the generated tables, so they are listed here by hand.
+pub mod zbb {
+    use super::{riscv::args, InstructionTemplate};
+
+    pub static ANDN: InstructionTemplate = InstructionTemplate {
+        name: "andn",
+        match_pattern: 0x40007033,
+        mask_pattern: 0xfe00707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: Some(&args::RS2),
+        operand4: None,
+        operand5: None,
+    };
+    pub static ORN: InstructionTemplate = InstructionTemplate {
+        name: "orn",
+        match_pattern: 0x40006033,
+        mask_pattern: 0xfe00707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: Some(&args::RS2),
+        operand4: None,
+        operand5: None,
+    };
+    pub static XNOR: InstructionTemplate = InstructionTemplate {
+        name: "xnor",
+        match_pattern: 0x40004033,
+        mask_pattern: 0xfe00707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: Some(&args::RS2),
+        operand4: None,
+        operand5: None,
+    };
+    pub static MIN: InstructionTemplate = InstructionTemplate {
+        name: "min",
+        match_pattern: 0x0a004033,
+        mask_pattern: 0xfe00707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: Some(&args::RS2),
+        operand4: None,
+        operand5: None,
+    };
+    pub static MINU: InstructionTemplate = InstructionTemplate {
+        name: "minu",
+        match_pattern: 0x0a005033,
+        mask_pattern: 0xfe00707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: Some(&args::RS2),
+        operand4: None,
+        operand5: None,
+    };
+    pub static MAX: InstructionTemplate = InstructionTemplate {
+        name: "max",
+        match_pattern: 0x0a006033,
+        mask_pattern: 0xfe00707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: Some(&args::RS2),
+        operand4: None,
+        operand5: None,
+    };
+    pub static MAXU: InstructionTemplate = InstructionTemplate {
+        name: "maxu",
+        match_pattern: 0x0a007033,
+        mask_pattern: 0xfe00707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: Some(&args::RS2),
+        operand4: None,
+        operand5: None,
+    };
+    pub static ROL: InstructionTemplate = InstructionTemplate {
+        name: "rol",
+        match_pattern: 0x60001033,
+        mask_pattern: 0xfe00707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: Some(&args::RS2),
+        operand4: None,
+        operand5: None,
+    };
+    pub static ROR: InstructionTemplate = InstructionTemplate {
+        name: "ror",
+        match_pattern: 0x60005033,
+        mask_pattern: 0xfe00707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: Some(&args::RS2),
+        operand4: None,
+        operand5: None,
+    };
+    pub static ROLW: InstructionTemplate = InstructionTemplate {
+        name: "rolw",
+        match_pattern: 0x6000103b,
+        mask_pattern: 0xfe00707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: Some(&args::RS2),
+        operand4: None,
+        operand5: None,
+    };
+    pub static RORW: InstructionTemplate = InstructionTemplate {
+        name: "rorw",
+        match_pattern: 0x6000503b,
+        mask_pattern: 0xfe00707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: Some(&args::RS2),
+        operand4: None,
+        operand5: None,
+    };
+    pub static CLZ: InstructionTemplate = InstructionTemplate {
+        name: "clz",
+        match_pattern: 0x60001013,
+        mask_pattern: 0xfff0707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static CTZ: InstructionTemplate = InstructionTemplate {
+        name: "ctz",
+        match_pattern: 0x60101013,
+        mask_pattern: 0xfff0707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static CPOP: InstructionTemplate = InstructionTemplate {
+        name: "cpop",
+        match_pattern: 0x60201013,
+        mask_pattern: 0xfff0707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static CLZW: InstructionTemplate = InstructionTemplate {
+        name: "clzw",
+        match_pattern: 0x6000101b,
+        mask_pattern: 0xfff0707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static CTZW: InstructionTemplate = InstructionTemplate {
+        name: "ctzw",
+        match_pattern: 0x6010101b,
+        mask_pattern: 0xfff0707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static CPOPW: InstructionTemplate = InstructionTemplate {
+        name: "cpopw",
+        match_pattern: 0x6020101b,
+        mask_pattern: 0xfff0707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static RORI: InstructionTemplate = InstructionTemplate {
+        name: "rori",
+        match_pattern: 0x60005013,
+        mask_pattern: 0xfc00707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: Some(&args::SHAMTD),
+        operand4: None,
+        operand5: None,
+    };
+    pub static RORIW: InstructionTemplate = InstructionTemplate {
+        name: "roriw",
+        match_pattern: 0x6000501b,
+        mask_pattern: 0xfe00707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&args::RS1),
+        operand3: Some(&args::SHAMTW),
+        operand4: None,
+        operand5: None,
+    };
+
+    pub static INSTS: [&InstructionTemplate; 19] = [
+        &ANDN, &ORN, &XNOR, &MIN, &MINU, &MAX, &MAXU, &ROL, &ROR, &ROLW, &RORW, &CLZ, &CTZ, &CPOP,
+        &CLZW, &CTZW, &CPOPW, &RORI, &RORIW,
+    ];
+}
+
+/// Templates for the privileged trap-return, wait and fence instructions
+/// (M and S mode). Not part of the generated tables either.
+pub mod privileged {
+    use super::{riscv::args, InstructionTemplate};
+
+    pub static MRET: InstructionTemplate = InstructionTemplate {
+        name: "mret",
+        match_pattern: 0x30200073,
+        mask_pattern: 0xffffffff,
+        operand1: None,
+        operand2: None,
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static SRET: InstructionTemplate = InstructionTemplate {
+        name: "sret",
+        match_pattern: 0x10200073,
+        mask_pattern: 0xffffffff,
+        operand1: None,
+        operand2: None,
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static WFI: InstructionTemplate = InstructionTemplate {
+        name: "wfi",
+        match_pattern: 0x10500073,
+        mask_pattern: 0xffffffff,
+        operand1: None,
+        operand2: None,
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static SFENCE_VMA: InstructionTemplate = InstructionTemplate {
+        name: "sfence.vma",
+        match_pattern: 0x12000073,
+        mask_pattern: 0xfe007fff,
+        operand1: Some(&args::RS1),
+        operand2: Some(&args::RS2),
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+
+    pub static INSTS: [&InstructionTemplate; 4] = [&MRET, &SRET, &WFI, &SFENCE_VMA];
+}
+
+/// Templates for the CSR instructions of the Zicsr extension, which read and
+/// modify a control and status register in one go. Operands are in assembly
+/// order, e.g. `csrrw rd, csr, rs1`.
+pub mod zicsr {
+    use super::{riscv::args, ArgumentSpec, InstructionTemplate};
+
+    /// The address of the CSR.
+    pub static CSR: ArgumentSpec = ArgumentSpec {
+        name: "csr",
+        length: 12,
+        offset: 20,
+    };
+    /// The zero-extended 5 bit immediate of the `i` variants, in the field
+    /// of `rs1`.
+    pub static ZIMM: ArgumentSpec = ArgumentSpec {
+        name: "zimm",
+        length: 5,
+        offset: 15,
+    };
+
+    pub static CSRRW: InstructionTemplate = InstructionTemplate {
+        name: "csrrw",
+        match_pattern: 0x1073,
+        mask_pattern: 0x707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&CSR),
+        operand3: Some(&args::RS1),
+        operand4: None,
+        operand5: None,
+    };
+    pub static CSRRS: InstructionTemplate = InstructionTemplate {
+        name: "csrrs",
+        match_pattern: 0x2073,
+        mask_pattern: 0x707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&CSR),
+        operand3: Some(&args::RS1),
+        operand4: None,
+        operand5: None,
+    };
+    pub static CSRRC: InstructionTemplate = InstructionTemplate {
+        name: "csrrc",
+        match_pattern: 0x3073,
+        mask_pattern: 0x707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&CSR),
+        operand3: Some(&args::RS1),
+        operand4: None,
+        operand5: None,
+    };
+    pub static CSRRWI: InstructionTemplate = InstructionTemplate {
+        name: "csrrwi",
+        match_pattern: 0x5073,
+        mask_pattern: 0x707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&CSR),
+        operand3: Some(&ZIMM),
+        operand4: None,
+        operand5: None,
+    };
+    pub static CSRRSI: InstructionTemplate = InstructionTemplate {
+        name: "csrrsi",
+        match_pattern: 0x6073,
+        mask_pattern: 0x707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&CSR),
+        operand3: Some(&ZIMM),
+        operand4: None,
+        operand5: None,
+    };
+    pub static CSRRCI: InstructionTemplate = InstructionTemplate {
+        name: "csrrci",
+        match_pattern: 0x7073,
+        mask_pattern: 0x707f,
+        operand1: Some(&args::RD),
+        operand2: Some(&CSR),
+        operand3: Some(&ZIMM),
+        operand4: None,
+        operand5: None,
+    };
+
+    pub static INSTS: [&InstructionTemplate; 6] =
+        [&CSRRW, &CSRRS, &CSRRC, &CSRRWI, &CSRRSI, &CSRRCI];
+
+    /// CSRs that exist on most cores, which the generator prefers over
+    /// arbitrary addresses (see [`crate::generator::KNOWN_CSR_CHANCE`]).
+    /// Accesses to the others usually just trap as illegal instructions.
+    pub static KNOWN_CSRS: [(&str, u32); 33] = [
+        // Unprivileged floating point and counters.
+        ("fflags", 0x001),
+        ("frm", 0x002),
+        ("fcsr", 0x003),
+        ("cycle", 0xc00),
+        ("time", 0xc01),
+        ("instret", 0xc02),
+        // Supervisor trap setup, handling and address translation.
+        ("sstatus", 0x100),
+        ("sie", 0x104),
+        ("stvec", 0x105),
+        ("scounteren", 0x106),
+        ("sscratch", 0x140),
+        ("sepc", 0x141),
+        ("scause", 0x142),
+        ("stval", 0x143),
+        ("sip", 0x144),
+        ("satp", 0x180),
+        // Machine trap setup and handling.
+        ("mstatus", 0x300),
+        ("misa", 0x301),
+        ("medeleg", 0x302),
+        ("mideleg", 0x303),
+        ("mie", 0x304),
+        ("mtvec", 0x305),
+        ("mcounteren", 0x306),
+        ("mscratch", 0x340),
+        ("mepc", 0x341),
+        ("mcause", 0x342),
+        ("mtval", 0x343),
+        ("mip", 0x344),
+        // Machine counters and information.
+        ("mcycle", 0xb00),
+        ("minstret", 0xb02),
+        ("mvendorid", 0xf11),
+        ("marchid", 0xf12),
+        ("mhartid", 0xf14),
+    ];
+}
+
+/// Templates for a subset of the C (compressed) extension that is the same on
+/// RV32 and RV64, without the floating point loads and stores. These have 16
+/// bit encodings, so their patterns only use the lower half of the word (see
+/// [`InstructionTemplate::is_compressed`]).
+///
+/// `c.nop`, `c.ebreak` and `c.jalr` are left out, as they are `c.addi` and
+/// `c.add` with zero operands and would decode as those. Operands that must
+/// not be zero end in `_n0` (see [`InstructionTemplate::is_reserved`]), the
+/// `_p` registers are `x8` to `x15`.
+pub mod rv_c {
+    use super::{ArgumentSpec, InstructionTemplate};
+
+    // The immediates are scattered over the encoding, the fields hold the
+    // bits in encoding order like `imm12hi` and `imm12lo` do.
+    pub static RD_RS1_N0: ArgumentSpec = ArgumentSpec {
+        name: "rd_rs1_n0",
+        length: 5,
+        offset: 7,
+    };
+    pub static RD_N0: ArgumentSpec = ArgumentSpec {
+        name: "rd_n0",
+        length: 5,
+        offset: 7,
+    };
+    pub static RS1_N0: ArgumentSpec = ArgumentSpec {
+        name: "rs1_n0",
+        length: 5,
+        offset: 7,
+    };
+    pub static C_RS2_N0: ArgumentSpec = ArgumentSpec {
+        name: "c_rs2_n0",
+        length: 5,
+        offset: 2,
+    };
+    pub static C_RS2: ArgumentSpec = ArgumentSpec {
+        name: "c_rs2",
+        length: 5,
+        offset: 2,
+    };
+    pub static RD_RS1_P: ArgumentSpec = ArgumentSpec {
+        name: "rd_rs1_p",
+        length: 3,
+        offset: 7,
+    };
+    pub static RS1_P: ArgumentSpec = ArgumentSpec {
+        name: "rs1_p",
+        length: 3,
+        offset: 7,
+    };
+    pub static RD_P: ArgumentSpec = ArgumentSpec {
+        name: "rd_p",
+        length: 3,
+        offset: 2,
+    };
+    pub static RS2_P: ArgumentSpec = ArgumentSpec {
+        name: "rs2_p",
+        length: 3,
+        offset: 2,
+    };
+    pub static C_NZIMM6LO: ArgumentSpec = ArgumentSpec {
+        name: "c_nzimm6lo",
+        length: 5,
+        offset: 2,
+    };
+    pub static C_NZIMM6HI: ArgumentSpec = ArgumentSpec {
+        name: "c_nzimm6hi",
+        length: 1,
+        offset: 12,
+    };
+    pub static C_IMM6LO: ArgumentSpec = ArgumentSpec {
+        name: "c_imm6lo",
+        length: 5,
+        offset: 2,
+    };
+    pub static C_IMM6HI: ArgumentSpec = ArgumentSpec {
+        name: "c_imm6hi",
+        length: 1,
+        offset: 12,
+    };
+    pub static C_NZUIMM10: ArgumentSpec = ArgumentSpec {
+        name: "c_nzuimm10",
+        length: 8,
+        offset: 5,
+    };
+    pub static C_UIMM7LO: ArgumentSpec = ArgumentSpec {
+        name: "c_uimm7lo",
+        length: 2,
+        offset: 5,
+    };
+    pub static C_UIMM7HI: ArgumentSpec = ArgumentSpec {
+        name: "c_uimm7hi",
+        length: 3,
+        offset: 10,
+    };
+    pub static C_UIMM8SPLO: ArgumentSpec = ArgumentSpec {
+        name: "c_uimm8splo",
+        length: 5,
+        offset: 2,
+    };
+    pub static C_UIMM8SPHI: ArgumentSpec = ArgumentSpec {
+        name: "c_uimm8sphi",
+        length: 1,
+        offset: 12,
+    };
+    pub static C_UIMM8SP_S: ArgumentSpec = ArgumentSpec {
+        name: "c_uimm8sp_s",
+        length: 6,
+        offset: 7,
+    };
+    pub static C_IMM12: ArgumentSpec = ArgumentSpec {
+        name: "c_imm12",
+        length: 11,
+        offset: 2,
+    };
+    pub static C_BIMM9LO: ArgumentSpec = ArgumentSpec {
+        name: "c_bimm9lo",
+        length: 5,
+        offset: 2,
+    };
+    pub static C_BIMM9HI: ArgumentSpec = ArgumentSpec {
+        name: "c_bimm9hi",
+        length: 3,
+        offset: 10,
+    };
+
+    pub static ADD: InstructionTemplate = InstructionTemplate {
+        name: "c.add",
+        match_pattern: 0x9002,
+        mask_pattern: 0xf003,
+        operand1: Some(&RD_RS1_N0),
+        operand2: Some(&C_RS2_N0),
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static ADDI: InstructionTemplate = InstructionTemplate {
+        name: "c.addi",
+        match_pattern: 0x0001,
+        mask_pattern: 0xe003,
+        operand1: Some(&RD_RS1_N0),
+        operand2: Some(&C_NZIMM6LO),
+        operand3: Some(&C_NZIMM6HI),
+        operand4: None,
+        operand5: None,
+    };
+    pub static ADDI4SPN: InstructionTemplate = InstructionTemplate {
+        name: "c.addi4spn",
+        match_pattern: 0x0000,
+        mask_pattern: 0xe003,
+        operand1: Some(&RD_P),
+        operand2: Some(&C_NZUIMM10),
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static AND: InstructionTemplate = InstructionTemplate {
+        name: "c.and",
+        match_pattern: 0x8c61,
+        mask_pattern: 0xfc63,
+        operand1: Some(&RD_RS1_P),
+        operand2: Some(&RS2_P),
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static ANDI: InstructionTemplate = InstructionTemplate {
+        name: "c.andi",
+        match_pattern: 0x8801,
+        mask_pattern: 0xec03,
+        operand1: Some(&RD_RS1_P),
+        operand2: Some(&C_IMM6LO),
+        operand3: Some(&C_IMM6HI),
+        operand4: None,
+        operand5: None,
+    };
+    pub static BEQZ: InstructionTemplate = InstructionTemplate {
+        name: "c.beqz",
+        match_pattern: 0xc001,
+        mask_pattern: 0xe003,
+        operand1: Some(&RS1_P),
+        operand2: Some(&C_BIMM9LO),
+        operand3: Some(&C_BIMM9HI),
+        operand4: None,
+        operand5: None,
+    };
+    pub static BNEZ: InstructionTemplate = InstructionTemplate {
+        name: "c.bnez",
+        match_pattern: 0xe001,
+        mask_pattern: 0xe003,
+        operand1: Some(&RS1_P),
+        operand2: Some(&C_BIMM9LO),
+        operand3: Some(&C_BIMM9HI),
+        operand4: None,
+        operand5: None,
+    };
+    pub static J: InstructionTemplate = InstructionTemplate {
+        name: "c.j",
+        match_pattern: 0xa001,
+        mask_pattern: 0xe003,
+        operand1: Some(&C_IMM12),
+        operand2: None,
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static JR: InstructionTemplate = InstructionTemplate {
+        name: "c.jr",
+        match_pattern: 0x8002,
+        mask_pattern: 0xf07f,
+        operand1: Some(&RS1_N0),
+        operand2: None,
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static LI: InstructionTemplate = InstructionTemplate {
+        name: "c.li",
+        match_pattern: 0x4001,
+        mask_pattern: 0xe003,
+        operand1: Some(&RD_N0),
+        operand2: Some(&C_IMM6LO),
+        operand3: Some(&C_IMM6HI),
+        operand4: None,
+        operand5: None,
+    };
+    pub static LW: InstructionTemplate = InstructionTemplate {
+        name: "c.lw",
+        match_pattern: 0x4000,
+        mask_pattern: 0xe003,
+        operand1: Some(&RD_P),
+        operand2: Some(&RS1_P),
+        operand3: Some(&C_UIMM7LO),
+        operand4: Some(&C_UIMM7HI),
+        operand5: None,
+    };
+    pub static LWSP: InstructionTemplate = InstructionTemplate {
+        name: "c.lwsp",
+        match_pattern: 0x4002,
+        mask_pattern: 0xe003,
+        operand1: Some(&RD_N0),
+        operand2: Some(&C_UIMM8SPHI),
+        operand3: Some(&C_UIMM8SPLO),
+        operand4: None,
+        operand5: None,
+    };
+    pub static MV: InstructionTemplate = InstructionTemplate {
+        name: "c.mv",
+        match_pattern: 0x8002,
+        mask_pattern: 0xf003,
+        operand1: Some(&RD_N0),
+        operand2: Some(&C_RS2_N0),
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static OR: InstructionTemplate = InstructionTemplate {
+        name: "c.or",
+        match_pattern: 0x8c41,
+        mask_pattern: 0xfc63,
+        operand1: Some(&RD_RS1_P),
+        operand2: Some(&RS2_P),
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static SUB: InstructionTemplate = InstructionTemplate {
+        name: "c.sub",
+        match_pattern: 0x8c01,
+        mask_pattern: 0xfc63,
+        operand1: Some(&RD_RS1_P),
+        operand2: Some(&RS2_P),
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static SW: InstructionTemplate = InstructionTemplate {
+        name: "c.sw",
+        match_pattern: 0xc000,
+        mask_pattern: 0xe003,
+        operand1: Some(&RS1_P),
+        operand2: Some(&RS2_P),
+        operand3: Some(&C_UIMM7LO),
+        operand4: Some(&C_UIMM7HI),
+        operand5: None,
+    };
+    pub static SWSP: InstructionTemplate = InstructionTemplate {
+        name: "c.swsp",
+        match_pattern: 0xc002,
+        mask_pattern: 0xe003,
+        operand1: Some(&C_RS2),
+        operand2: Some(&C_UIMM8SP_S),
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+    pub static XOR: InstructionTemplate = InstructionTemplate {
+        name: "c.xor",
+        match_pattern: 0x8c21,
+        mask_pattern: 0xfc63,
+        operand1: Some(&RD_RS1_P),
+        operand2: Some(&RS2_P),
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+
+    pub static INSTS: [&InstructionTemplate; 18] = [
+        &ADD, &ADDI, &ADDI4SPN, &AND, &ANDI, &BEQZ, &BNEZ, &J, &JR, &LI, &LW, &LWSP, &MV, &OR,
+        &SUB, &SW, &SWSP, &XOR,
+    ];
+}
+
+/// A placeholder for 32 bit words that no template decodes, e.g. in seeds
+/// from other tools (see [`crate::parser::parse_words`]). The whole word is
+/// its argument, so it encodes back to the same bits. It matches every word
+/// and is thus in none of the [`sets`].
+pub mod raw {
+    use super::{ArgumentSpec, InstructionTemplate};
+
+    pub static WORD: ArgumentSpec = ArgumentSpec {
+        name: "word",
+        length: 32,
+        offset: 0,
+    };
+
+    pub static UNKNOWN: InstructionTemplate = InstructionTemplate {
+        name: ".word",
+        match_pattern: 0,
+        mask_pattern: 0,
+        operand1: Some(&WORD),
+        operand2: None,
+        operand3: None,
+        operand4: None,
+        operand5: None,
+    };
+}
+
+/// The instruction sets the fuzzer can generate programs from.
+///
+/// Every set is sorted by name and then by match pattern, so it has the same
+/// order in every build. Otherwise, choosing a random template from a set
+/// could pick different instructions for the same seed.
 pub mod sets {
     use super::riscv::*;
-    use super::InstructionTemplate;
+    use super::{privileged, rv_c, zbb, zicsr, InstructionTemplate};
+
+    /// Brings the templates into the documented order.
+    fn sorted(mut insts: Vec<&'static InstructionTemplate>) -> Vec<&'static InstructionTemplate> {
+        insts.sort_by_key(|inst| (inst.name(), inst.base_pattern()));
+        insts
+    }
+
+    /// The instruction sets that can be passed to [`by_name`].
+    pub const NAMES: [&str; 9] = [
+        "rv64g",
+        "rv64i",
+        "rv64imfd",
+        "rv64g_zbb",
+        "rv64gc",
+        "rv32g",
+        "rv32i",
+        "rv32imfd",
+        "rv32gc",
+    ];
+
+    /// The templates of `insts` that aren't in `removed`.
+    fn without(
+        insts: Vec<&'static InstructionTemplate>,
+        removed: &[&'static InstructionTemplate],
+    ) -> Vec<&'static InstructionTemplate> {
+        insts
+            .into_iter()
+            .filter(|template| !removed.contains(template))
+            .collect()
+    }
 
     pub fn riscv_g() -> Vec<&'static InstructionTemplate> {
         let mut result = Vec::<&'static InstructionTemplate>::new();
@@ -141,14 +1063,153 @@ pub mod sets {
         result.append(&mut rv_d::INSTS.to_vec());
         result.append(&mut rv_f::INSTS.to_vec());
         result.append(&mut rv_m::INSTS.to_vec());
-        result
+        sorted(result)
     }
 
     pub fn riscv_base() -> Vec<&'static InstructionTemplate> {
         let mut result = Vec::<&'static InstructionTemplate>::new();
         result.append(&mut rv64_i::INSTS.to_vec());
         result.append(&mut rv_i::INSTS.to_vec());
-        result
+        sorted(result)
+    }
+
+    /// The multiply and divide instructions of the M extension, which are
+    /// part of [`riscv_g`].
+    pub fn riscv_m() -> Vec<&'static InstructionTemplate> {
+        let mut result = Vec::<&'static InstructionTemplate>::new();
+        result.append(&mut rv64_m::INSTS.to_vec());
+        result.append(&mut rv_m::INSTS.to_vec());
+        sorted(result)
+    }
+
+    /// The load-reserved/store-conditional and atomic memory operations of
+    /// the A extension, which are part of [`riscv_g`]. Excluding the `atomic`
+    /// category removes them from a set again.
+    pub fn riscv_a() -> Vec<&'static InstructionTemplate> {
+        let mut result = Vec::<&'static InstructionTemplate>::new();
+        result.append(&mut rv64_a::INSTS.to_vec());
+        result.append(&mut rv_a::INSTS.to_vec());
+        sorted(result)
+    }
+
+    pub fn riscv_zbb() -> Vec<&'static InstructionTemplate> {
+        sorted(zbb::INSTS.to_vec())
+    }
+
+    /// The CSR instructions, which are the same on RV32 and RV64.
+    pub fn riscv_zicsr() -> Vec<&'static InstructionTemplate> {
+        sorted(zicsr::INSTS.to_vec())
+    }
+
+    /// Adds the [`riscv_zicsr`] instructions that aren't in the set yet.
+    /// They aren't part of [`riscv_g`], as most CSR accesses trap or change
+    /// how the rest of the program runs, so they are only generated on
+    /// request.
+    pub fn with_zicsr(
+        mut insts: Vec<&'static InstructionTemplate>,
+    ) -> Vec<&'static InstructionTemplate> {
+        for template in riscv_zicsr() {
+            if !insts.contains(&template) {
+                insts.push(template);
+            }
+        }
+        sorted(insts)
+    }
+
+    /// The compressed instructions, which are the same on RV32 and RV64.
+    pub fn riscv_c() -> Vec<&'static InstructionTemplate> {
+        sorted(rv_c::INSTS.to_vec())
+    }
+
+    /// The instructions that trap or change the privilege level: `ecall`
+    /// and `ebreak` (also part of the base sets) and the privileged
+    /// instructions, which return from traps, wait for interrupts or fence
+    /// the address translation. Most of them end the program early, so they
+    /// are only generated on request.
+    pub fn riscv_system() -> Vec<&'static InstructionTemplate> {
+        let mut result = vec![&rv_i::ECALL, &rv_i::EBREAK];
+        result.append(&mut privileged::INSTS.to_vec());
+        sorted(result)
+    }
+
+    /// Adds the [`riscv_system`] instructions that aren't in the set yet.
+    pub fn with_system(
+        mut insts: Vec<&'static InstructionTemplate>,
+    ) -> Vec<&'static InstructionTemplate> {
+        for template in riscv_system() {
+            if !insts.contains(&template) {
+                insts.push(template);
+            }
+        }
+        sorted(insts)
+    }
+
+    /// RV32G, i.e. [`riscv_g`] without the RV64-only instructions.
+    pub fn riscv32_g() -> Vec<&'static InstructionTemplate> {
+        let mut result = Vec::<&'static InstructionTemplate>::new();
+        result.append(&mut rv_i::INSTS.to_vec());
+        result.append(&mut rv_a::INSTS.to_vec());
+        result.append(&mut rv_d::INSTS.to_vec());
+        result.append(&mut rv_f::INSTS.to_vec());
+        result.append(&mut rv_m::INSTS.to_vec());
+        sorted(result)
+    }
+
+    pub fn riscv32_base() -> Vec<&'static InstructionTemplate> {
+        sorted(rv_i::INSTS.to_vec())
+    }
+
+    /// Returns true for instructions that only exist on RV64, e.g. `ld`.
+    pub fn is_rv64_only(template: &InstructionTemplate) -> bool {
+        [
+            &rv64_i::INSTS[..],
+            &rv64_a::INSTS[..],
+            &rv64_d::INSTS[..],
+            &rv64_f::INSTS[..],
+            &rv64_m::INSTS[..],
+        ]
+        .iter()
+        .any(|insts| insts.iter().any(|inst| *inst == template))
+    }
+
+    /// Every known instruction, used for decoding inputs regardless of the
+    /// instruction set they were generated from.
+    pub fn all() -> Vec<&'static InstructionTemplate> {
+        let mut result = super::riscv::all();
+        result.append(&mut riscv_zbb());
+        result.append(&mut privileged::INSTS.to_vec());
+        result.append(&mut riscv_zicsr());
+        result.append(&mut riscv_c());
+        sorted(result)
+    }
+
+    /// Looks up one of the instruction sets in [`NAMES`].
+    pub fn by_name(name: &str) -> Option<Vec<&'static InstructionTemplate>> {
+        match name {
+            "rv64g" => Some(riscv_g()),
+            "rv64i" => Some(riscv_base()),
+            // G without A, for cores without atomics.
+            "rv64imfd" => Some(without(riscv_g(), &riscv_a())),
+            "rv64g_zbb" => {
+                let mut result = riscv_g();
+                result.append(&mut riscv_zbb());
+                Some(sorted(result))
+            }
+            "rv64gc" => {
+                let mut result = riscv_g();
+                result.append(&mut riscv_c());
+                Some(sorted(result))
+            }
+            "rv32g" => Some(riscv32_g()),
+            "rv32i" => Some(riscv32_base()),
+            "rv32imfd" => Some(without(riscv32_g(), &riscv_a())),
+            "rv32gc" => {
+                let mut result = riscv32_g();
+                result.append(&mut riscv_c());
+                Some(sorted(result))
+            }
+            _ => None,
+        }
     }
 }
 
@@ -172,6 +1233,30 @@ impl Argument {
     pub fn value(&self) -> u32 {
         self.value
     }
+
+    /// Returns true if the value fits into the bits of the field. Wider values
+    /// would spill into the neighbouring fields when encoded.
+    pub fn fits(&self) -> bool {
+        self.value <= self.spec.mask()
+    }
+
+    /// Returns false if this is a branch or jump offset that isn't a
+    /// multiple of `alignment` (see [`INST_ALIGNMENT`]).
+    pub fn is_aligned_target(&self, alignment: u32) -> bool {
+        match self.spec.offset_bit_one() {
+            Some(bit) if alignment >= INST_ALIGNMENT => self.value & bit == 0,
+            _ => true,
+        }
+    }
+
+    /// The argument with its offset rounded down to `alignment` if it is a
+    /// branch or jump offset.
+    pub fn aligned_target(&self, alignment: u32) -> Argument {
+        match self.spec.offset_bit_one() {
+            Some(bit) if alignment >= INST_ALIGNMENT => Argument::new(self.spec, self.value & !bit),
+            _ => self.clone(),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -213,6 +1298,39 @@ impl Instruction {
         self.template
     }
 
+    /// The length of the encoding in bytes, see [`InstructionTemplate::size`].
+    pub fn size(&self) -> usize {
+        self.template.size()
+    }
+
+    /// Returns true if this is a word no template decodes (see
+    /// [`raw::UNKNOWN`]).
+    pub fn is_raw_word(&self) -> bool {
+        self.template == &raw::UNKNOWN
+    }
+
+    /// Checks that every argument fits into its field (see [`Argument::fits`]).
+    pub fn check_fields(&self) -> Result<(), String> {
+        match self.arguments.iter().find(|arg| !arg.fits()) {
+            Some(arg) => Err(format!(
+                "{}: {} = {:#x} doesn't fit in {} bits",
+                self.template.name(),
+                arg.spec.name(),
+                arg.value,
+                arg.spec.length()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns true if the branch or jump target of this instruction (if
+    /// any) is aligned to `alignment`.
+    pub fn has_aligned_targets(&self, alignment: u32) -> bool {
+        self.arguments
+            .iter()
+            .all(|arg| arg.is_aligned_target(alignment))
+    }
+
     pub fn set_arg(&mut self, new_arg: Argument) {
         // Delete the old argument if it exists.
         self.arguments
@@ -222,10 +1340,62 @@ impl Instruction {
     }
 }
 
+/// Prints the instruction in the format understood by `inst-assembler`,
+/// e.g. `addi rd=0x1 rs1=0x1 imm12=0x3`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.template.name())?;
+        for arg in &self.arguments {
+            write!(f, " {}={:#x}", arg.spec.name(), arg.value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Instructions for the tests of the other modules.
+#[cfg(test)]
+pub(crate) mod test_insts {
+    use super::{sets, Argument, ArgumentSpec, Instruction, InstructionTemplate};
+
+    /// An instruction of `template` with the given argument values, all
+    /// other arguments are zero.
+    pub fn inst_with(
+        template: &'static InstructionTemplate,
+        values: &[(&'static ArgumentSpec, u32)],
+    ) -> Instruction {
+        let args = template
+            .operands()
+            .map(|spec| {
+                let value = values
+                    .iter()
+                    .find(|(arg, _)| arg == spec)
+                    .map_or(0, |(_, v)| *v);
+                Argument::new(*spec, value)
+            })
+            .collect();
+        Instruction::new(template, args)
+    }
+
+    /// An instruction of `template` with all arguments zero.
+    pub fn inst(template: &'static InstructionTemplate) -> Instruction {
+        inst_with(template, &[])
+    }
+
+    /// The RV64G instruction with the given name and all arguments zero.
+    pub fn named(name: &str) -> Instruction {
+        let template = sets::riscv_g()
+            .into_iter()
+            .find(|t| t.name() == name)
+            .unwrap();
+        inst(template)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::instructions::riscv::args;
     use crate::instructions::riscv::rv_i::*;
+    use crate::instructions::test_insts::inst_with;
     use crate::instructions::*;
 
     #[test]
@@ -241,6 +1411,18 @@ mod tests {
         assert_eq!(inst.encode(), 0x004100b3);
     }
 
+    #[test]
+    fn wide_field_masks() {
+        assert_eq!(args::IMM20.max_value(), 1 << 20);
+        assert_eq!(args::IMM20.mask(), 0xfffff);
+
+        // A field spanning the whole instruction must not overflow.
+        let wide: &'static ArgumentSpec = Box::leak(Box::new(ArgumentSpec::new("imm32", 32, 0)));
+        assert_eq!(wide.max_value(), 1 << 32);
+        assert_eq!(wide.mask(), u32::MAX);
+        assert_eq!(wide.extract(0xdeadbeef).value(), 0xdeadbeef);
+    }
+
     #[test]
     fn compare_inst() {
         let inst1 = Instruction::new(
@@ -277,4 +1459,438 @@ mod tests {
         // Do a whole decode-encode roundabout with this instruction.
         assert_eq!(ADD.decode(inst.encode()).unwrap(), inst);
     }
+
+    #[test]
+    fn display_inst() {
+        let inst = Instruction::new(
+            &ADDI,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 1),
+                Argument::new(&args::IMM12, 3),
+            ],
+        );
+        assert_eq!(inst.to_string(), "addi rd=0x1 rs1=0x1 imm12=0x3");
+    }
+
+    #[test]
+    fn all_templates_have_category() {
+        for inst in sets::all() {
+            assert_ne!(
+                inst.category(),
+                InstructionCategory::default(),
+                "{} has no category",
+                inst.name()
+            );
+        }
+    }
+
+    #[test]
+    fn encode_zbb() {
+        let andn = Instruction::new(
+            &zbb::ANDN,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 2),
+                Argument::new(&args::RS2, 3),
+            ],
+        );
+        assert_eq!(andn.encode(), 0x403170b3);
+
+        let clz = Instruction::new(
+            &zbb::CLZ,
+            vec![Argument::new(&args::RD, 5), Argument::new(&args::RS1, 6)],
+        );
+        assert_eq!(clz.encode(), 0x60031293);
+
+        // Rotates by all 6 bits of the shift amount.
+        let rori = Instruction::new(
+            &zbb::RORI,
+            vec![
+                Argument::new(&args::RD, 1),
+                Argument::new(&args::RS1, 2),
+                Argument::new(&args::SHAMTD, 63),
+            ],
+        );
+        assert_eq!(rori.encode(), 0x63f15093);
+        assert_eq!(zbb::RORI.decode(rori.encode()).unwrap(), rori);
+    }
+
+    #[test]
+    fn zbb_does_not_overlap() {
+        for inst in sets::riscv_zbb() {
+            let matching: Vec<_> = sets::all()
+                .into_iter()
+                .filter(|other| other.matches(inst.base_pattern()))
+                .collect();
+            assert_eq!(matching, vec![inst], "{} overlaps", inst.name());
+        }
+        assert_eq!(
+            sets::by_name("rv64g_zbb").unwrap().len(),
+            sets::riscv_g().len() + sets::riscv_zbb().len()
+        );
+        assert!(sets::by_name("rv32e").is_none());
+    }
+
+    #[test]
+    fn encode_privileged() {
+        for (template, encoding) in [
+            (&ECALL, 0x00000073),
+            (&EBREAK, 0x00100073),
+            (&privileged::MRET, 0x30200073),
+            (&privileged::SRET, 0x10200073),
+            (&privileged::WFI, 0x10500073),
+        ] {
+            let inst = Instruction::new(template, vec![]);
+            assert_eq!(inst.encode(), encoding, "{}", template.name());
+            assert_eq!(template.decode(encoding).unwrap(), inst);
+        }
+        let sfence = Instruction::new(
+            &privileged::SFENCE_VMA,
+            vec![Argument::new(&args::RS1, 1), Argument::new(&args::RS2, 2)],
+        );
+        assert_eq!(sfence.encode(), 0x12208073);
+        assert_eq!(privileged::SFENCE_VMA.decode(0x12208073).unwrap(), sfence);
+
+        // No other template decodes the privileged instructions.
+        for inst in privileged::INSTS {
+            let matching: Vec<_> = sets::all()
+                .into_iter()
+                .filter(|other| other.matches(inst.base_pattern()))
+                .collect();
+            assert_eq!(matching, vec![inst], "{} overlaps", inst.name());
+            assert_eq!(inst.category(), InstructionCategory::System);
+        }
+        assert_eq!(sets::riscv_system().len(), 6);
+        let with_system = sets::with_system(sets::riscv_g());
+        assert_eq!(with_system.len(), sets::riscv_g().len() + 4);
+    }
+
+    #[test]
+    fn encode_compressed() {
+        // c.addi x1, -1
+        let addi = Instruction::new(
+            &rv_c::ADDI,
+            vec![
+                Argument::new(&rv_c::RD_RS1_N0, 1),
+                Argument::new(&rv_c::C_NZIMM6LO, 0x1f),
+                Argument::new(&rv_c::C_NZIMM6HI, 1),
+            ],
+        );
+        assert_eq!(addi.encode(), 0x10fd);
+        assert_eq!(addi.size(), 2);
+        assert_eq!(ADD.size(), 4);
+        // c.sw x9, 4(x8)
+        let sw = Instruction::new(
+            &rv_c::SW,
+            vec![
+                Argument::new(&rv_c::RS1_P, 0),
+                Argument::new(&rv_c::RS2_P, 1),
+                Argument::new(&rv_c::C_UIMM7LO, 0b10),
+                Argument::new(&rv_c::C_UIMM7HI, 0),
+            ],
+        );
+        assert_eq!(sw.encode(), 0xc044);
+        assert_eq!(rv_c::J.category(), InstructionCategory::Branch);
+        assert_eq!(rv_c::JR.category(), InstructionCategory::Branch);
+        assert_eq!(rv_c::MV.category(), InstructionCategory::Arith);
+        assert_eq!(rv_c::LWSP.category(), InstructionCategory::Mem);
+        assert_eq!(rv_c::ADDI4SPN.category(), InstructionCategory::Arith);
+
+        // Only templates of the compressed set decode compressed encodings.
+        for inst in sets::riscv_c() {
+            assert!(inst.is_compressed(), "{}", inst.name());
+            let matching: Vec<_> = sets::all()
+                .into_iter()
+                .filter(|other| other.matches(inst.base_pattern()))
+                .collect();
+            assert!(
+                matching.iter().all(|other| other.is_compressed()),
+                "{}",
+                inst.name()
+            );
+        }
+        assert_eq!(
+            sets::by_name("rv32gc").unwrap().len(),
+            sets::riscv32_g().len() + sets::riscv_c().len()
+        );
+    }
+
+    #[test]
+    fn compressed_reserved_operands() {
+        let mv = |rd, rs2| {
+            vec![
+                Argument::new(&rv_c::RD_N0, rd),
+                Argument::new(&rv_c::C_RS2_N0, rs2),
+            ]
+        };
+        assert!(!rv_c::MV.is_reserved(&mv(1, 2)));
+        assert!(rv_c::MV.is_reserved(&mv(0, 2)));
+        // c.mv x1, x0 is c.jr x1.
+        assert!(rv_c::MV.is_reserved(&mv(1, 0)));
+        let encoding = Instruction::new(&rv_c::MV, mv(1, 0)).encode();
+        let decoded = sets::all()
+            .into_iter()
+            .find_map(|t| t.decode(encoding))
+            .unwrap();
+        assert_eq!(decoded.template(), &rv_c::JR);
+    }
+
+    #[test]
+    fn atomic_ordering_bits_round_trip() {
+        let atomics = sets::riscv_a();
+        assert!(atomics
+            .iter()
+            .all(|t| t.category() == InstructionCategory::Atomic));
+        assert!(atomics.iter().all(|t| sets::riscv_g().contains(t)));
+        let find = |name: &str| *atomics.iter().find(|t| t.name() == name).unwrap();
+
+        let names = [
+            "lr.w",
+            "sc.w",
+            "amoadd.w",
+            "amoswap.w",
+            "lr.d",
+            "sc.d",
+            "amoadd.d",
+            "amoswap.d",
+        ];
+        for name in names {
+            let template = find(name);
+            // funct3 is the width: 2 for words and 3 for double words.
+            let width = if name.ends_with(".w") { 0b010 } else { 0b011 };
+            assert_eq!((template.base_pattern() >> 12) & 0b111, width, "{}", name);
+            for (aq, rl) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+                let args = template
+                    .operands()
+                    .map(|spec| match spec.name() {
+                        "aq" => Argument::new(*spec, aq),
+                        "rl" => Argument::new(*spec, rl),
+                        _ => Argument::new(*spec, 5),
+                    })
+                    .collect();
+                let inst = Instruction::new(template, args);
+                let encoded = inst.encode();
+                assert_eq!((encoded >> 26) & 1, aq, "{}", inst);
+                assert_eq!((encoded >> 25) & 1, rl, "{}", inst);
+                let decoded = sets::all().into_iter().find_map(|t| t.decode(encoded));
+                assert_eq!(decoded, Some(inst));
+            }
+        }
+        assert!(sets::is_rv64_only(find("lr.d")));
+        assert!(!sets::is_rv64_only(find("lr.w")));
+
+        // The sets without A have everything else of G.
+        for (name, g) in [
+            ("rv64imfd", sets::riscv_g()),
+            ("rv32imfd", sets::riscv32_g()),
+        ] {
+            let set = sets::by_name(name).unwrap();
+            assert!(set
+                .iter()
+                .all(|t| t.category() != InstructionCategory::Atomic));
+            assert_eq!(
+                set.len() + g.iter().filter(|t| atomics.contains(t)).count(),
+                g.len()
+            );
+        }
+    }
+
+    #[test]
+    fn argument_field_widths() {
+        let addi = |imm| {
+            inst_with(
+                &ADDI,
+                &[(&args::RD, 1), (&args::RS1, 2), (&args::IMM12, imm)],
+            )
+        };
+        assert!(Argument::new(&args::RD, 31).fits());
+        assert!(!Argument::new(&args::RD, 32).fits());
+        assert!(Argument::new(&args::IMM20, 0xfffff).fits());
+        assert_eq!(addi(0xfff).check_fields(), Ok(()));
+        assert_eq!(
+            addi(0x1000).check_fields(),
+            Err("addi: imm12 = 0x1000 doesn't fit in 12 bits".to_owned())
+        );
+    }
+
+    #[test]
+    fn encode_csr() {
+        // csrrs x5, mstatus, x0, i.e. csrr x5, mstatus.
+        let read = Instruction::new(
+            &zicsr::CSRRS,
+            vec![
+                Argument::new(&args::RD, 5),
+                Argument::new(&zicsr::CSR, 0x300),
+                Argument::new(&args::RS1, 0),
+            ],
+        );
+        assert_eq!(read.encode(), 0x300022f3);
+        // csrrwi x0, fflags, 0x1f
+        let write = Instruction::new(
+            &zicsr::CSRRWI,
+            vec![
+                Argument::new(&args::RD, 0),
+                Argument::new(&zicsr::CSR, 0x001),
+                Argument::new(&zicsr::ZIMM, 0x1f),
+            ],
+        );
+        assert_eq!(write.encode(), 0x001fd073);
+
+        for (funct3, template) in [1, 2, 3, 5, 6, 7].into_iter().zip(zicsr::INSTS) {
+            assert_eq!(template.base_pattern() & 0x7f, 0x73, "{}", template.name());
+            assert_eq!(
+                (template.base_pattern() >> 12) & 0b111,
+                funct3,
+                "{}",
+                template.name()
+            );
+            assert_eq!(template.category(), InstructionCategory::System);
+            let decoded: Vec<_> = sets::all()
+                .into_iter()
+                .filter_map(|t| t.decode(template.base_pattern() | 0xfff0_0000))
+                .collect();
+            assert_eq!(decoded.len(), 1, "{}", template.name());
+        }
+        assert!(zicsr::KNOWN_CSRS
+            .iter()
+            .all(|(_, address)| *address <= zicsr::CSR.mask()));
+        assert!(!sets::riscv_g().contains(&&zicsr::CSRRW));
+        assert!(sets::with_zicsr(sets::riscv_g()).contains(&&zicsr::CSRRW));
+    }
+
+    #[test]
+    fn rv32_sets() {
+        for name in ["rv32g", "rv32i"] {
+            let set = sets::by_name(name).unwrap();
+            assert!(set.contains(&&ADDI), "{}", name);
+            assert!(!set.iter().any(|t| sets::is_rv64_only(t)), "{}", name);
+        }
+        assert!(sets::is_rv64_only(&crate::instructions::riscv::rv64_i::LD));
+        assert!(!sets::is_rv64_only(&LW));
+        assert_eq!(
+            sets::riscv_g()
+                .iter()
+                .filter(|t| !sets::is_rv64_only(t))
+                .count(),
+            sets::riscv32_g().len()
+        );
+    }
+
+    #[test]
+    fn load_widths_and_signedness() {
+        use crate::instructions::riscv::rv64_i::{LD, LWU};
+
+        // The loads only differ in funct3, bit 2 selects zero extension.
+        let loads: [(&'static InstructionTemplate, u32); 7] = [
+            (&LB, 0),
+            (&LH, 1),
+            (&LW, 2),
+            (&LD, 3),
+            (&LBU, 4),
+            (&LHU, 5),
+            (&LWU, 6),
+        ];
+        for (template, funct3) in loads {
+            assert_eq!(template.base_pattern() & 0x7f, 0x03, "{}", template.name());
+            assert_eq!(
+                (template.base_pattern() >> 12) & 0x7,
+                funct3,
+                "{}",
+                template.name()
+            );
+            let unsigned = template.name().ends_with('u');
+            assert_eq!(
+                template.base_pattern() & 0x4000 != 0,
+                unsigned,
+                "{}",
+                template.name()
+            );
+
+            let inst = Instruction::new(
+                template,
+                vec![
+                    Argument::new(&args::RD, 5),
+                    Argument::new(&args::RS1, 6),
+                    Argument::new(&args::IMM12, 0x800),
+                ],
+            );
+            let decoded = parse_one(inst.encode());
+            assert_eq!(decoded, inst);
+            // The 64 bit loads are part of the fuzzed rv64 sets.
+            assert!(sets::riscv_g().contains(&template));
+            assert!(sets::riscv_base().contains(&template));
+        }
+    }
+
+    fn parse_one(data: EncodedInstruction) -> Instruction {
+        let parsed = crate::parser::parse_instructions(&data.to_ne_bytes().to_vec(), &sets::all());
+        parsed.unwrap().pop().unwrap()
+    }
+
+    #[test]
+    fn branch_target_alignment() {
+        // beq x0, x0, 2 and 4
+        let beq_2 = parse_one(0x0000_0163);
+        let beq_4 = parse_one(0x0000_0263);
+        // jal x0, 2 and 4
+        let jal_2 = parse_one(0x0020_006f);
+        let jal_4 = parse_one(0x0040_006f);
+        for inst in [&beq_2, &jal_2] {
+            assert!(!inst.has_aligned_targets(INST_ALIGNMENT), "{}", inst);
+            assert!(inst.has_aligned_targets(COMPRESSED_ALIGNMENT), "{}", inst);
+        }
+        for inst in [&beq_4, &jal_4] {
+            assert!(inst.has_aligned_targets(INST_ALIGNMENT), "{}", inst);
+            assert!(inst.has_aligned_targets(COMPRESSED_ALIGNMENT), "{}", inst);
+        }
+
+        // Aligning clears only bit 1 of the offset.
+        let mut aligned = beq_2.clone();
+        for arg in beq_2.arguments() {
+            aligned.set_arg(arg.aligned_target(INST_ALIGNMENT));
+        }
+        assert_eq!(aligned.encode(), 0x0000_0063);
+        let odd = parse_one(0x0000_03e3);
+        let mut aligned = odd.clone();
+        for arg in odd.arguments() {
+            aligned.set_arg(arg.aligned_target(INST_ALIGNMENT));
+        }
+        assert_eq!(aligned.encode(), 0x0000_02e3);
+
+        // Other immediates are not offsets.
+        let addi = parse_one(0x0020_0093);
+        assert!(addi.has_aligned_targets(INST_ALIGNMENT));
+    }
+
+    #[test]
+    fn sets_are_sorted() {
+        for name in sets::NAMES {
+            let set = sets::by_name(name).unwrap();
+            let keys: Vec<_> = set.iter().map(|t| (t.name(), t.base_pattern())).collect();
+            let mut sorted_keys = keys.clone();
+            sorted_keys.sort();
+            assert_eq!(keys, sorted_keys, "{} is not sorted", name);
+        }
+        let names: Vec<_> = sets::riscv_g().iter().take(4).map(|t| t.name()).collect();
+        assert_eq!(names, ["add", "addi", "addiw", "addw"]);
+    }
+
+    #[test]
+    fn category_of_known_templates() {
+        assert_eq!(ADD.category(), InstructionCategory::Arith);
+        assert_eq!(LW.category(), InstructionCategory::Mem);
+        assert_eq!(JALR.category(), InstructionCategory::Branch);
+        assert_eq!(ECALL.category(), InstructionCategory::System);
+        assert_eq!(zbb::ROLW.category(), InstructionCategory::Arith);
+
+        for category in InstructionCategory::ALL {
+            assert_eq!(
+                InstructionCategory::from_name(category.name()),
+                Some(category)
+            );
+        }
+        assert_eq!(InstructionCategory::from_name("unknown"), None);
+        assert_eq!(InstructionCategory::from_name("branches"), None);
+    }
 }
@@ -0,0 +1,62 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Name of the user stat with the length summary reported to the monitor.
+pub const LENGTHS_STAT_NAME: &str = "lengths";
+
+/// Program lengths (in instructions) are grouped into buckets of this size.
+pub const LENGTH_BUCKET_WIDTH: usize = 4;
+
+libafl::impl_serdeany!(LengthHistogramMetadata);
+/// Histogram of the program lengths in the corpus, kept in the state.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct LengthHistogramMetadata {
+    /// Number of corpus entries per length bucket.
+    counts: BTreeMap<usize, u64>,
+}
+
+impl LengthHistogramMetadata {
+    pub fn bucket(len: usize) -> usize {
+        len / LENGTH_BUCKET_WIDTH
+    }
+
+    pub fn add(&mut self, len: usize) {
+        *self.counts.entry(Self::bucket(len)).or_insert(0) += 1;
+    }
+
+    /// How many corpus entries have a length in the same bucket as `len`.
+    pub fn count(&self, len: usize) -> u64 {
+        self.counts.get(&Self::bucket(len)).copied().unwrap_or(0)
+    }
+
+    /// A short one-line summary for the monitor.
+    pub fn summary(&self) -> String {
+        let min = self.counts.keys().next().copied().unwrap_or(0);
+        let max = self.counts.keys().last().copied().unwrap_or(0);
+        format!(
+            "{}-{} ({} sizes)",
+            min * LENGTH_BUCKET_WIDTH,
+            (max + 1) * LENGTH_BUCKET_WIDTH - 1,
+            self.counts.len()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LengthHistogramMetadata;
+
+    #[test]
+    fn length_histogram() {
+        let mut hist = LengthHistogramMetadata::default();
+        assert_eq!(hist.count(5), 0);
+        for len in [1, 2, 5, 6, 7, 40] {
+            hist.add(len);
+        }
+        assert_eq!(hist.count(0), 2);
+        assert_eq!(hist.count(4), 3);
+        assert_eq!(hist.count(41), 1);
+        assert_eq!(hist.summary(), "0-43 (3 sizes)");
+    }
+}
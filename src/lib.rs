@@ -1,10 +1,37 @@
+pub mod abi;
 pub mod assembler;
+pub mod bench;
+pub mod bundle;
 pub mod calibration;
 pub mod causes;
+pub mod classes;
+pub mod config;
+pub mod cosim;
+pub mod depths;
+pub mod disasm;
+pub mod driver;
+pub mod executors;
+pub mod feedbacks;
 pub mod fuzz_ui;
 pub mod generator;
+pub mod hooks;
+pub mod immediates;
 pub mod instructions;
+pub mod lengths;
+pub mod manifest;
 pub mod monitor;
 pub mod mutator;
+pub mod observers;
+pub mod pack;
 pub mod parser;
 pub mod program_input;
+pub mod report;
+pub mod scheduler;
+pub mod seeds;
+pub mod shutdown;
+pub mod single_run;
+pub mod snapshot;
+pub mod stability;
+pub mod throughput;
+pub mod tmin;
+pub mod triage;
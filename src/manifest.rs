@@ -0,0 +1,213 @@
+use std::{fmt, fs, path::Path};
+
+use serde::Serialize;
+
+use crate::{driver::TargetConfig, instructions::InstructionCategory};
+
+/// File name of the manifest in the output directory and crash bundles.
+pub const RUN_MANIFEST_NAME: &str = "run.json";
+
+/// The instruction set the mutator generates instructions from by default.
+pub const FUZZED_ISA: &str = "rv64g";
+
+/// The client seeds of different shards are this far apart, so shards never
+/// share a seed as long as nobody runs more clients than that on a machine.
+const SHARD_SEED_STRIDE: u64 = 1 << 32;
+
+/// One of `count` machines fuzzing the same target (`index/count` on the
+/// command line). Every shard derives its client seeds from a disjoint range
+/// (see [`client_seed`]) and can emphasize a different instruction category,
+/// so the shards overlap less without talking to each other.
+///
+/// Shards don't exchange inputs while they run. To cross-pollinate, every
+/// shard writes to its own output directory on storage shared by all
+/// machines, and shards are restarted now and then with the `queue`
+/// directories of the others (e.g. moved around with `pack` and `unpack`)
+/// as input directory. Writing to the same output directory from multiple
+/// shards is not supported, as the clients of different machines would use
+/// the same per-core directories.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: u32,
+    pub count: u32,
+}
+
+impl Shard {
+    /// Parses `i/n` with `i < n`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (index, count) = spec
+            .split_once('/')
+            .and_then(|(index, count)| {
+                Some((index.trim().parse().ok()?, count.trim().parse().ok()?))
+            })
+            .ok_or(format!("Not in INDEX/COUNT format: '{}'", spec))?;
+        if index >= count {
+            return Err(format!(
+                "Shard index {} must be below the count {}",
+                index, count
+            ));
+        }
+        Ok(Self { index, count })
+    }
+
+    /// The category this shard generates more often. Shards take turns in
+    /// the order of [`InstructionCategory::ALL`].
+    pub fn emphasis(&self) -> InstructionCategory {
+        InstructionCategory::ALL[self.index as usize % InstructionCategory::ALL.len()]
+    }
+}
+
+impl Default for Shard {
+    /// A campaign that isn't sharded.
+    fn default() -> Self {
+        Self { index: 0, count: 1 }
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// Everything needed to repeat a fuzzing campaign later on.
+#[derive(Serialize, Debug, Clone)]
+pub struct RunManifest {
+    pub crate_version: String,
+    pub isa: String,
+    /// The seed all client RNG seeds are derived from.
+    pub master_seed: u64,
+    pub shard: Shard,
+    /// The directory with the initial inputs.
+    pub seed_dir: String,
+    /// The full fuzzer command line, including the target.
+    pub command_line: Vec<String>,
+    pub target: String,
+    pub target_arguments: Vec<String>,
+    pub target_envs: Vec<(String, String)>,
+    pub timeout_ms: u128,
+    pub timeout_reruns: u32,
+    pub timeout_factor: Option<f64>,
+    /// The map size after resolving the target's size and the default.
+    pub map_size: usize,
+    /// The resolved list of cores the clients run on.
+    pub cores: Vec<usize>,
+    pub unix_start_time: u64,
+}
+
+impl RunManifest {
+    pub fn new(
+        isa: &str,
+        master_seed: u64,
+        shard: Shard,
+        seed_dir: &Path,
+        target: &TargetConfig,
+        cores: Vec<usize>,
+        unix_start_time: u64,
+    ) -> Self {
+        Self {
+            crate_version: env!("CARGO_PKG_VERSION").to_owned(),
+            isa: isa.to_owned(),
+            master_seed,
+            shard,
+            seed_dir: seed_dir.to_string_lossy().into_owned(),
+            command_line: std::env::args().collect(),
+            target: target.executable.clone(),
+            target_arguments: target.arguments.clone(),
+            target_envs: target.envs.clone(),
+            timeout_ms: target.timeout.as_millis(),
+            timeout_reruns: target.timeout_reruns,
+            timeout_factor: target.timeout_factor,
+            map_size: target.map_size,
+            cores,
+            unix_start_time,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed to serialize run manifest")
+    }
+
+    /// Writes the manifest as `run.json` into the given directory.
+    pub fn write_to(&self, dir: &Path) -> std::io::Result<()> {
+        fs::write(dir.join(RUN_MANIFEST_NAME), self.to_json())
+    }
+}
+
+/// Derives the RNG seed of a single client from the master seed. Without
+/// sharding, this is the master seed plus the core id.
+pub fn client_seed(master_seed: u64, shard: Shard, core_id: usize) -> u64 {
+    master_seed
+        .wrapping_add(shard.index as u64 * SHARD_SEED_STRIDE)
+        .wrapping_add(core_id as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+    use std::path::Path;
+
+    use nix::sys::signal::Signal;
+
+    use super::{client_seed, RunManifest, Shard, FUZZED_ISA};
+    use crate::{driver::TargetConfig, instructions::InstructionCategory};
+
+    #[test]
+    fn manifest_json() {
+        let target = TargetConfig {
+            executable: "./sim".to_string(),
+            arguments: vec!["+trace".to_string()],
+            envs: vec![("A".to_string(), "1".to_string())],
+            debug_child: false,
+            timeout: Duration::from_millis(500),
+            timeout_reruns: 0,
+            timeout_factor: None,
+            signal: Signal::SIGKILL,
+            map_size: 1024,
+        };
+        let manifest = RunManifest::new(
+            FUZZED_ISA,
+            42,
+            Shard::default(),
+            Path::new("in"),
+            &target,
+            vec![0, 1],
+            7,
+        );
+        let json: serde_json::Value = serde_json::from_str(&manifest.to_json()).unwrap();
+        assert_eq!(json["master_seed"], 42);
+        assert_eq!(json["isa"], "rv64g");
+        assert_eq!(json["target"], "./sim");
+        assert_eq!(json["timeout_ms"], 500);
+        assert_eq!(json["cores"][1], 1);
+        assert_eq!(json["shard"]["count"], 1);
+
+        let unsharded = Shard::default();
+        assert_ne!(client_seed(42, unsharded, 0), client_seed(42, unsharded, 1));
+        assert_eq!(client_seed(42, unsharded, 3), 45);
+    }
+
+    #[test]
+    fn shards() {
+        let shard = Shard::parse("2/4").unwrap();
+        assert_eq!(shard, Shard { index: 2, count: 4 });
+        assert_eq!(shard.to_string(), "2/4");
+        assert!(Shard::parse("4/4").is_err());
+        assert!(Shard::parse("1").is_err());
+        assert!(Shard::parse("a/b").is_err());
+
+        // The seeds of different shards don't overlap.
+        let seeds = |index| {
+            let shard = Shard { index, count: 4 };
+            (0..256).map(move |core| client_seed(42, shard, core))
+        };
+        let first: Vec<u64> = seeds(0).collect();
+        assert!(seeds(1).chain(seeds(3)).all(|seed| !first.contains(&seed)));
+
+        assert_eq!(Shard::default().emphasis(), InstructionCategory::ALL[0]);
+        assert_ne!(
+            Shard::parse("1/2").unwrap().emphasis(),
+            Shard::default().emphasis()
+        );
+    }
+}
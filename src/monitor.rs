@@ -1,12 +1,20 @@
 use core::time::Duration;
 use std::fs::OpenOptions;
 use std::io::Write;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use libafl::prelude::current_time;
-use libafl::prelude::{format_duration_hms, ClientId, ClientStats, Monitor};
+use libafl::prelude::{format_duration_hms, ClientId, ClientStats, Monitor, UserStats};
+use serde::{Deserialize, Serialize};
 
+use crate::calibration::STABILITY_STAT_NAME;
+use crate::classes::CLASSES_STAT_NAME;
+use crate::depths::DEPTHS_STAT_NAME;
 use crate::fuzz_ui::FuzzUI;
+use crate::lengths::LENGTHS_STAT_NAME;
+use crate::observers::MAP_DENSITY_STAT_NAME;
+use crate::throughput::CATEGORY_TIMES_STAT_NAME;
 
 /// Tracking monitor during fuzzing.
 #[derive(Clone)]
@@ -16,6 +24,44 @@ pub struct HWFuzzMonitor {
     ui: Arc<Mutex<FuzzUI>>,
     iterations_log_path: String,
     last_iterations_logged: u64,
+    /// Per client, the number of corpus entries and solutions it found and
+    /// when that number last grew.
+    last_finds: Vec<(u64, Duration)>,
+    /// The JSON lines file that a [`StatsRecord`] is appended to every
+    /// [`STATS_INTERVAL`], and when the last one was written.
+    stats_file: Option<PathBuf>,
+    last_stats: Duration,
+}
+
+/// How often a line is appended to the stats file.
+pub const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One line of the stats file (see [`HWFuzzMonitor::with_stats_file`]).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StatsRecord {
+    pub elapsed_secs: u64,
+    pub executions: u64,
+    pub execs_per_sec: f64,
+    pub corpus: u64,
+    pub objectives: u64,
+}
+
+impl StatsRecord {
+    /// The record as a single line of JSON, with the newline.
+    pub fn to_json_line(&self) -> String {
+        serde_json::to_string(self).expect("Failed to serialize stats") + "\n"
+    }
+}
+
+/// The row of a client in the UI, e.g.
+/// `client 2: 1.2k exec/sec, last find 0h-0m-12s ago`.
+pub fn format_client_row(id: usize, execs_per_sec: &str, since_last_find: Duration) -> String {
+    format!(
+        "client {}: {} exec/sec, last find {} ago",
+        id,
+        execs_per_sec,
+        format_duration_hms(&since_last_find)
+    )
 }
 
 impl Monitor for HWFuzzMonitor {
@@ -37,6 +83,7 @@ impl Monitor for HWFuzzMonitor {
     fn display(&mut self, _event_msg: String, sender_id: ClientId) {
         let execs = self.total_execs();
         let execs_per_sec = self.execs_per_sec_pretty();
+        let client_rows = self.client_rows(current_time());
         {
             let client = self.client_stats_mut_for(sender_id).clone();
 
@@ -44,8 +91,9 @@ impl Monitor for HWFuzzMonitor {
             let data = ui.data();
 
             data.add_corpus_size(self.corpus_size());
+            data.set_client_rows(client_rows);
 
-            let mut max_coverage : u64 = 0;
+            let mut max_coverage: u64 = 0;
             for (key, val) in &client.user_monitor {
                 if key == "shared_mem" {
                     // shared_mem has the form:
@@ -66,6 +114,18 @@ impl Monitor for HWFuzzMonitor {
                     let max_str = right_side.split(" (").nth(0).unwrap();
                     max_coverage = u64::from_str_radix(max_str, 10).unwrap();
                 }
+                if key == CATEGORY_TIMES_STAT_NAME {
+                    data.set_category_times(&val.to_string());
+                }
+                match (key.as_str(), val) {
+                    (MAP_DENSITY_STAT_NAME, UserStats::Ratio(filled, size)) => {
+                        data.set_map_density(*filled, *size)
+                    }
+                    (STABILITY_STAT_NAME, UserStats::Ratio(stable, filled)) => {
+                        data.set_stability(*stable, *filled)
+                    }
+                    _ => {}
+                }
             }
 
             let time_since_start = current_time() - self.start_time;
@@ -123,7 +183,17 @@ impl Monitor for HWFuzzMonitor {
                     execs,
                     execs_per_sec,
                 );
-                for (_key, val) in &client.user_monitor {
+                for (key, val) in &client.user_monitor {
+                    // The depth, length, class and category time summaries are
+                    // not numbers, so keep them out of the machine-readable
+                    // status line.
+                    if key == DEPTHS_STAT_NAME
+                        || key == LENGTHS_STAT_NAME
+                        || key == CLASSES_STAT_NAME
+                        || key == CATEGORY_TIMES_STAT_NAME
+                    {
+                        continue;
+                    }
                     // Remove bunch of undesired stuff from the key to make it
                     // fully space separated.
                     let mut val_str = format!(" {val}").as_str().to_owned();
@@ -137,6 +207,8 @@ impl Monitor for HWFuzzMonitor {
             }
         }
 
+        self.maybe_write_stats();
+
         let mut ui = self.ui.lock().unwrap();
         ui.try_tick();
     }
@@ -152,6 +224,102 @@ impl HWFuzzMonitor {
             ui,
             iterations_log_path: log_path,
             last_iterations_logged: 0,
+            last_finds: vec![],
+            stats_file: None,
+            last_stats: Duration::ZERO,
         }
     }
+
+    /// Also appends a [`StatsRecord`] line to the given file every
+    /// [`STATS_INTERVAL`], independent of the UI. The file is created if
+    /// needed and never truncated, so it can be followed while fuzzing.
+    pub fn with_stats_file(mut self, path: Option<PathBuf>) -> Self {
+        self.stats_file = path;
+        self
+    }
+
+    fn maybe_write_stats(&mut self) {
+        let Some(path) = self.stats_file.clone() else {
+            return;
+        };
+        let now = current_time();
+        if now - self.last_stats < STATS_INTERVAL {
+            return;
+        }
+        self.last_stats = now;
+        let record = StatsRecord {
+            elapsed_secs: (now - self.start_time).as_secs(),
+            executions: self.total_execs(),
+            execs_per_sec: self.execs_per_sec(),
+            corpus: self.corpus_size(),
+            objectives: self.objective_size(),
+        };
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|mut file| file.write_all(record.to_json_line().as_bytes()));
+        if let Err(err) = written {
+            log::error!("Failed to write stats to {:?}: {}", path, err);
+        }
+    }
+
+    /// One row per client that executed anything so far, to spot clients
+    /// whose simulator is stalling while the aggregate looks fine.
+    fn client_rows(&mut self, now: Duration) -> Vec<String> {
+        self.last_finds
+            .resize(self.client_stats.len(), (0, self.start_time));
+        let mut rows = vec![];
+        for (id, client) in self.client_stats.iter_mut().enumerate() {
+            if client.executions == 0 {
+                continue;
+            }
+            let found = client.corpus_size + client.objective_size;
+            let last_find = &mut self.last_finds[id];
+            if found > last_find.0 {
+                *last_find = (found, now);
+            }
+            let since_last_find = now.saturating_sub(last_find.1);
+            rows.push(format_client_row(
+                id,
+                &client.execs_per_sec_pretty(now),
+                since_last_find,
+            ));
+        }
+        rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::{format_client_row, StatsRecord};
+
+    #[test]
+    fn client_rows() {
+        assert_eq!(
+            format_client_row(3, "1.2k", Duration::from_secs(75)),
+            "client 3: 1.2k exec/sec, last find 0h-1m-15s ago"
+        );
+    }
+
+    #[test]
+    fn stats_lines() {
+        let record = StatsRecord {
+            elapsed_secs: 12,
+            executions: 3400,
+            execs_per_sec: 283.5,
+            corpus: 17,
+            objectives: 1,
+        };
+        let line = record.to_json_line();
+        assert_eq!(
+            line,
+            "{\"elapsed_secs\":12,\"executions\":3400,\"execs_per_sec\":283.5,\"corpus\":17,\
+             \"objectives\":1}\n"
+        );
+        assert_eq!(line.matches('\n').count(), 1);
+        assert_eq!(serde_json::from_str::<StatsRecord>(&line).unwrap(), record);
+    }
 }
@@ -1,30 +1,31 @@
-use std::cmp::max;
-use std::env;
+use std::{cmp::max, time::Instant};
 
-use libafl::prelude::*;
 use core::{
     fmt::{self, Debug},
     marker::PhantomData,
 };
+use libafl::prelude::*;
+use serde::Serialize;
 
 use crate::{
-    generator::InstGenerator,
+    config::DriverConfig,
+    generator::{register_fill_len, written_registers, InstGenerator},
+    immediates::CrashImmediatesMetadata,
     instructions::{
-        self,
         riscv::{
             args,
             rv_i::{ADDI, AUIPC, JALR},
         },
-        Argument, Instruction,
+        Argument, Instruction, InstructionCategory,
     },
-    program_input::HasProgramInput,
+    program_input::{check_insts, HasProgramInput},
 };
 
 #[cfg(test)]
-use crate::{assembler::assemble_instructions, parser::parse_instructions};
+use crate::{assembler::assemble_instructions, instructions::sets, parser::parse_instructions};
 
 /// Supported mutation strategies.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
 pub enum Mutation {
     // Adds a new instruction.
     Add,
@@ -32,6 +33,8 @@ pub enum Mutation {
     Replace,
     // Replaces an argument of an instruction with a different one.
     ReplaceArg,
+    // Flips a single bit in an argument of an instruction.
+    FlipArgBit,
     // Repeats one instruction several times.
     RepeatSeveral,
     // Swaps two single instructions.
@@ -41,19 +44,136 @@ pub enum Mutation {
     // Replaces an instruction with a nop.
     ReplaceWithNop,
     Snippet,
+    // Grafts part of another corpus entry onto the input, see
+    // `RiscVSpliceMutator`.
+    Splice,
+}
+
+impl Mutation {
+    /// The name used for `--mutator-weights`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Mutation::Add => "add",
+            Mutation::Replace => "replace",
+            Mutation::ReplaceArg => "replace-arg",
+            Mutation::FlipArgBit => "flip-arg-bit",
+            Mutation::RepeatSeveral => "repeat",
+            Mutation::SwapTwo => "swap",
+            Mutation::Remove => "remove",
+            Mutation::ReplaceWithNop => "replace-with-nop",
+            Mutation::Snippet => "snippet",
+            Mutation::Splice => "splice",
+        }
+    }
+}
+
+/// The mutations scheduled by [`all_riscv_mutations`].
+pub const SCHEDULED_MUTATIONS: [Mutation; 9] = [
+    Mutation::Add,
+    Mutation::Remove,
+    Mutation::ReplaceArg,
+    Mutation::FlipArgBit,
+    Mutation::Replace,
+    Mutation::RepeatSeveral,
+    Mutation::SwapTwo,
+    Mutation::Splice,
+    Mutation::Snippet,
+];
+
+/// How likely every scheduled mutation is picked relative to the others.
+/// All weights are 1 by default, which picks every mutation of
+/// [`all_riscv_mutations`] uniformly.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MutationWeights {
+    weights: Vec<(Mutation, u64)>,
+}
+
+impl MutationWeights {
+    /// Parses a comma separated list of `<mutation>=<weight>` with a weight
+    /// for every one of the [`SCHEDULED_MUTATIONS`], e.g.
+    /// `add=3,remove=1,replace-arg=2,...`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut weights = vec![];
+        for pair in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+        {
+            let (name, weight) = pair
+                .split_once('=')
+                .ok_or(format!("Not in <mutation>=<weight> format: '{}'", pair))?;
+            let mutation = SCHEDULED_MUTATIONS
+                .into_iter()
+                .find(|m| m.name() == name.trim())
+                .ok_or(format!("Unknown mutation '{}'", name.trim()))?;
+            let weight = weight
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid weight '{}' for {}", weight.trim(), name.trim()))?;
+            if weights.iter().any(|(m, _)| *m == mutation) {
+                return Err(format!("Duplicate weight for {}", mutation.name()));
+            }
+            weights.push((mutation, weight));
+        }
+        let missing: Vec<&str> = SCHEDULED_MUTATIONS
+            .iter()
+            .filter(|m| !weights.iter().any(|(w, _)| w == *m))
+            .map(|m| m.name())
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!(
+                "Missing mutation weights for {}",
+                missing.join(", ")
+            ));
+        }
+        if weights.iter().all(|(_, weight)| *weight == 0) {
+            return Err("At least one mutation weight must be positive".to_string());
+        }
+        Ok(Self { weights })
+    }
+
+    pub fn weight(&self, mutation: Mutation) -> u64 {
+        self.weights
+            .iter()
+            .find(|(m, _)| *m == mutation)
+            .map_or(1, |(_, weight)| *weight)
+    }
+}
+
+impl Default for MutationWeights {
+    fn default() -> Self {
+        Self {
+            weights: SCHEDULED_MUTATIONS.iter().map(|m| (*m, 1)).collect(),
+        }
+    }
 }
 
+/// How often `ReplaceArg` tries to generate a different argument value.
+const MAX_ARG_TRIES: u32 = 1000;
+
+/// How often `Replace` tries to generate a different instruction, e.g. the
+/// weights might leave a single template without operands.
+const MAX_REPLACE_TRIES: u32 = 100;
+
 /// Mutator for RISC-V instructions.
 /// Operates on byte vectors that are parsed as RISC-V vectors.
 /// Invalid instructions are just filtered from the input.
 pub struct RiscVInstructionMutator {
     /// This should be a const generic argument but Rust doesn't support that.
     mutation: Mutation,
+    /// The crash immediates of the state and the version they were read at.
+    crash_args: Vec<Argument>,
+    crash_args_version: u64,
+    config: DriverConfig,
+    /// When the mutator was created and the dataflow chance it reached on
+    /// the [`DriverConfig::dataflow_ramp`] since then.
+    started: Instant,
+    dataflow_chance: Option<u64>,
 }
 
 impl<I, S> Mutator<I, S> for RiscVInstructionMutator
 where
-    S: HasRand,
+    S: HasRand + HasMetadata,
     I: HasProgramInput,
 {
     fn mutate(
@@ -62,32 +182,110 @@ where
         input: &mut I,
         _stage_idx: i32,
     ) -> Result<MutationResult, Error> {
-        self.mutate_impl(state.rand_mut(), input.insts_mut())
+        // Only convert the crash immediates again after new crashes.
+        if let Some(meta) = state.metadata_map().get::<CrashImmediatesMetadata>() {
+            if meta.version() != self.crash_args_version {
+                self.crash_args = meta.arguments();
+                self.crash_args_version = meta.version();
+            }
+        }
+        self.ramp_dataflow();
+        // The register fill prologue is not part of the mutable program.
+        let insts = input.insts_mut();
+        let mut body = insts.split_off(register_fill_len(insts));
+        let original = body.clone();
+        let result = self.mutate_impl(state.rand_mut(), &mut body);
+        body.truncate(self.config.max_program_len);
+        // Don't run a program that isn't what it seems. This is a bug in the
+        // mutation, not in the target, so only the mutation is dropped.
+        let invalid = body
+            .iter()
+            .find_map(|inst| inst.check_fields().err().map(|err| (inst, err)));
+        if let Some((inst, err)) = invalid {
+            let name = self.mutation.name();
+            log::warn!(
+                "Dropping a {} mutation that produced {} ({})",
+                name,
+                inst,
+                err
+            );
+            insts.extend(original);
+            return Ok(MutationResult::Skipped);
+        }
+        insts.append(&mut body);
+        result
     }
 }
 
 impl Named for RiscVInstructionMutator {
     fn name(&self) -> &str {
-        "RiscVInstructionMutator"
+        self.mutation.name()
     }
 }
 
 pub struct EmptyProgramNotSupported;
 
 impl RiscVInstructionMutator {
-    pub fn new(mutation: Mutation) -> Self {
-        Self { mutation }
+    pub fn new(config: &DriverConfig, mutation: Mutation) -> Self {
+        Self {
+            mutation,
+            crash_args: vec![],
+            crash_args_version: 0,
+            config: config.clone(),
+            started: Instant::now(),
+            dataflow_chance: None,
+        }
     }
 
-    /// Generates a random instruction.
-    fn gen_inst<Rng: Rand>(&self, program: &Vec<Instruction>, rng: &mut Rng) -> Instruction {
-        let mut generator = InstGenerator::new();
+    /// Moves the dataflow chance along the ramp, if there is one. Only
+    /// insertions read it, so only their mutator logs the new value.
+    fn ramp_dataflow(&mut self) {
+        let Some(ramp) = self.config.dataflow_ramp else {
+            return;
+        };
+        let chance = ramp.chance_at(self.started.elapsed());
+        if self.dataflow_chance != Some(chance) {
+            if self.mutation == Mutation::Add {
+                log::info!("Dataflow chance is now {}%", chance);
+            }
+            self.dataflow_chance = Some(chance);
+        }
+    }
+
+    /// A generator that reuses the arguments of the program.
+    fn generator(&self, program: &Vec<Instruction>) -> InstGenerator {
+        let mut generator = InstGenerator::with_config(&self.config);
+        generator.forward_crash_args(&self.crash_args);
+        if let Some(chance) = self.dataflow_chance {
+            generator.set_dataflow_chance(chance);
+        }
 
         for inst in program {
             generator.forward_args(inst.arguments())
         }
+        generator
+    }
+
+    /// Generates a random instruction.
+    fn gen_inst<Rng: Rand>(&self, program: &Vec<Instruction>, rng: &mut Rng) -> Instruction {
+        self.generator(program)
+            .generate_instruction::<Rng>(rng, &self.config.instructions())
+    }
 
-        generator.generate_instruction::<Rng>(rng, &instructions::sets::riscv_g())
+    /// Generates instructions to insert at `pos`, see
+    /// [`InstGenerator::generate_insertion`].
+    fn gen_insertion<Rng: Rand>(
+        &self,
+        program: &Vec<Instruction>,
+        pos: usize,
+        rng: &mut Rng,
+    ) -> Vec<Instruction> {
+        let written = written_registers(&program[..pos]);
+        self.generator(program).generate_insertion::<Rng>(
+            rng,
+            &self.config.instructions(),
+            &written,
+        )
     }
 
     /// Interprets the input bytes as RISC-V opcodes and mutates them.
@@ -110,7 +308,7 @@ impl RiscVInstructionMutator {
         rng: &mut Rng,
         input: &mut Vec<u8>,
     ) -> Result<MutationResult, Error> {
-        let program_or_err = parse_instructions(input, &instructions::sets::riscv_g());
+        let program_or_err = parse_instructions(input, &sets::riscv_g());
         if program_or_err.is_err() {
             return Err(Error::illegal_argument(program_or_err.err().unwrap()));
         }
@@ -145,7 +343,7 @@ impl RiscVInstructionMutator {
                     vec![
                         Argument::new(&args::RD, 1),
                         Argument::new(&args::RS1, 2),
-                        Argument::new(&args::IMM12, raw_offset*4),
+                        Argument::new(&args::IMM12, raw_offset * 4),
                     ],
                 ),
             ]
@@ -193,36 +391,63 @@ impl RiscVInstructionMutator {
 
         match mutation {
             Mutation::Add => {
-                program.insert(add_pos(rng), self.gen_inst(program, rng));
+                let pos = add_pos(rng);
+                let insts = self.gen_insertion(program, pos, rng);
+                program.splice(pos..pos, insts);
             }
             Mutation::Replace => {
                 // Keep replacing until we actually changed something.
-                loop {
+                let (pos, new_inst) = (0..MAX_REPLACE_TRIES).find_map(|_| {
                     let pos = valid_pos(rng)?;
-                    let old_inst = program[pos].clone();
                     let new_inst = self.gen_inst(program, rng);
-                    if new_inst != old_inst {
-                        program[pos] = new_inst;
-                        break;
-                    }
-                }
+                    (new_inst != program[pos]).then_some((pos, new_inst))
+                })?;
+                program[pos] = new_inst;
             }
             Mutation::ReplaceArg => {
                 let pos = valid_pos(rng)?;
                 let mut inst = program[pos].clone();
-                if inst.arguments().is_empty() {
+                // Raw words are kept as given, their argument is the whole
+                // word and not an operand.
+                if inst.arguments().is_empty() || inst.is_raw_word() {
                     return None;
                 }
                 let old_arg = rng.choose(inst.arguments());
                 let arg_spec = old_arg.spec();
-                // Keep generating arguments until we find a new one.
-                loop {
-                    let new_arg = InstGenerator::new().generate_argument(rng, arg_spec);
-                    if &new_arg == old_arg {
-                        continue;
-                    }
-                    inst.set_arg(new_arg);
-                    break;
+                // Keep generating arguments until we find a new one. Give up
+                // eventually as the argument range might be restricted.
+                let mut generator = InstGenerator::with_config(&self.config);
+                generator.forward_crash_args(&self.crash_args);
+                let new_arg = (0..MAX_ARG_TRIES)
+                    .map(|_| generator.generate_argument(rng, arg_spec))
+                    .find(|new_arg| new_arg != old_arg)?;
+                inst.set_arg(new_arg);
+                if generator.clobbers_mem_base(&inst) {
+                    return None;
+                }
+                program[pos] = inst;
+            }
+            Mutation::FlipArgBit => {
+                let pos = valid_pos(rng)?;
+                let mut inst = program[pos].clone();
+                if inst.arguments().is_empty() || inst.is_raw_word() {
+                    return None;
+                }
+                let old_arg = rng.choose(inst.arguments()).clone();
+                let length = old_arg.spec().length();
+                if length == 0 {
+                    return None;
+                }
+                let bit = rng.below(length as u64) as u32;
+                let new_arg = flip_arg_bit(&old_arg, bit);
+                // Don't make a branch or jump target misaligned.
+                let generator = InstGenerator::with_config(&self.config);
+                if !new_arg.is_aligned_target(generator.target_alignment()) {
+                    return None;
+                }
+                inst.set_arg(new_arg);
+                if generator.clobbers_mem_base(&inst) {
+                    return None;
                 }
                 program[pos] = inst;
             }
@@ -257,7 +482,17 @@ impl RiscVInstructionMutator {
                 );
                 program[pos] = nop;
             }
+            // Needs the corpus, see `RiscVSpliceMutator`.
+            Mutation::Splice => return None,
             Mutation::Snippet => {
+                // The snippets are all calls and returns.
+                if self
+                    .config
+                    .excluded_categories
+                    .contains(&InstructionCategory::Branch)
+                {
+                    return None;
+                }
                 let pos = add_pos(rng);
                 let mut snippet = self.make_snippet(rng);
                 while !snippet.is_empty() {
@@ -269,6 +504,107 @@ impl RiscVInstructionMutator {
     }
 }
 
+/// Flips the given bit of the argument value. The bit is masked to the
+/// width of the argument so the result always fits the field.
+pub fn flip_arg_bit(arg: &Argument, bit: u32) -> Argument {
+    Argument::new(
+        arg.spec(),
+        (arg.value() ^ (1u32 << (bit % 32))) & arg.spec().mask(),
+    )
+}
+
+/// Grafts a suffix of another corpus entry onto a prefix of the input, both
+/// split at a random instruction. The register fill prologues of both
+/// programs are kept out of the split, the input keeps its own.
+pub struct RiscVSpliceMutator {
+    /// The most instructions of the result, without the prologue.
+    max_len: usize,
+}
+
+impl RiscVSpliceMutator {
+    pub fn new(config: &DriverConfig) -> Self {
+        Self {
+            max_len: config.max_program_len,
+        }
+    }
+}
+
+impl<I, S> Mutator<I, S> for RiscVSpliceMutator
+where
+    S: HasRand + HasCorpus,
+    <S as UsesInput>::Input: HasProgramInput,
+    I: HasProgramInput,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut I,
+        _stage_idx: i32,
+    ) -> Result<MutationResult, Error> {
+        if state.corpus().count() < 2 {
+            return Ok(MutationResult::Skipped);
+        }
+        let idx = libafl::random_corpus_id!(state.corpus(), state.rand_mut());
+        if *state.corpus().current() == Some(idx) {
+            return Ok(MutationResult::Skipped);
+        }
+        let other: Vec<Instruction> = {
+            let mut testcase = state.corpus().get(idx)?.borrow_mut();
+            let insts = testcase.load_input(state.corpus())?.insts();
+            insts[register_fill_len(insts)..].to_vec()
+        };
+        if other.is_empty() {
+            return Ok(MutationResult::Skipped);
+        }
+
+        let insts = input.insts_mut();
+        let body = insts.split_off(register_fill_len(insts));
+        let prefix_len = state.rand_mut().below(body.len() as u64 + 1) as usize;
+        let suffix_start = state.rand_mut().below(other.len() as u64) as usize;
+        let spliced = splice(&body, prefix_len, &other, suffix_start, self.max_len);
+        match spliced.map(|spliced| check_insts(&spliced).map(|()| spliced)) {
+            Some(Ok(mut spliced)) => {
+                insts.append(&mut spliced);
+                Ok(MutationResult::Mutated)
+            }
+            Some(Err(err)) => {
+                log::warn!("Dropping a splice: {}", err);
+                insts.extend(body);
+                Ok(MutationResult::Skipped)
+            }
+            None => {
+                insts.extend(body);
+                Ok(MutationResult::Skipped)
+            }
+        }
+    }
+}
+
+impl Named for RiscVSpliceMutator {
+    fn name(&self) -> &str {
+        Mutation::Splice.name()
+    }
+}
+
+/// The first `prefix_len` instructions of `prefix` followed by `suffix` from
+/// `suffix_start` on, cut off after `max_len` instructions. Returns `None`
+/// instead of an empty program.
+pub fn splice(
+    prefix: &[Instruction],
+    prefix_len: usize,
+    suffix: &[Instruction],
+    suffix_start: usize,
+    max_len: usize,
+) -> Option<Vec<Instruction>> {
+    let result: Vec<Instruction> = prefix[..prefix_len.min(prefix.len())]
+        .iter()
+        .chain(&suffix[suffix_start.min(suffix.len())..])
+        .take(max_len)
+        .cloned()
+        .collect();
+    (!result.is_empty()).then_some(result)
+}
+
 /// All the types of the function below repeated.
 /// (A memorial to Rust's generic programming capabilities).
 pub type RiscVMutationList = tuple_list_type!(
@@ -279,34 +615,44 @@ pub type RiscVMutationList = tuple_list_type!(
     RiscVInstructionMutator,
     RiscVInstructionMutator,
     RiscVInstructionMutator,
-    RiscVInstructionMutator,
-    RiscVInstructionMutator,
-    RiscVInstructionMutator,
-    RiscVInstructionMutator,
-    RiscVInstructionMutator,
+    RiscVSpliceMutator,
     RiscVInstructionMutator,
 );
 
-/// Provides a list of all supported RISC-V instruction mutators.
-pub fn all_riscv_mutations() -> RiscVMutationList {
+/// Provides a list of all supported RISC-V instruction mutators, which
+/// generate instructions as configured. Every mutation is listed once, their
+/// mix is set with [`RiscvScheduledMutator::with_weights`].
+pub fn all_riscv_mutations(config: &DriverConfig) -> RiscVMutationList {
     tuple_list!(
-        RiscVInstructionMutator::new(Mutation::Add),
-        RiscVInstructionMutator::new(Mutation::Add),
-        RiscVInstructionMutator::new(Mutation::Remove),
-        RiscVInstructionMutator::new(Mutation::Remove),
-        RiscVInstructionMutator::new(Mutation::ReplaceArg),
-        RiscVInstructionMutator::new(Mutation::ReplaceArg),
-        RiscVInstructionMutator::new(Mutation::Replace),
-        RiscVInstructionMutator::new(Mutation::Replace),
-        RiscVInstructionMutator::new(Mutation::RepeatSeveral),
-        RiscVInstructionMutator::new(Mutation::RepeatSeveral),
-        RiscVInstructionMutator::new(Mutation::SwapTwo),
-        RiscVInstructionMutator::new(Mutation::SwapTwo),
-        RiscVInstructionMutator::new(Mutation::Snippet),
+        RiscVInstructionMutator::new(config, Mutation::Add),
+        RiscVInstructionMutator::new(config, Mutation::Remove),
+        RiscVInstructionMutator::new(config, Mutation::ReplaceArg),
+        RiscVInstructionMutator::new(config, Mutation::FlipArgBit),
+        RiscVInstructionMutator::new(config, Mutation::Replace),
+        RiscVInstructionMutator::new(config, Mutation::RepeatSeveral),
+        RiscVInstructionMutator::new(config, Mutation::SwapTwo),
+        RiscVSpliceMutator::new(config),
+        // Must stay last, see `RiscvScheduledMutator::schedule`.
+        RiscVInstructionMutator::new(config, Mutation::Snippet),
     )
 }
 
+/// Up to `2^DEFAULT_STACK_POW` mutations are stacked on every input.
+const DEFAULT_STACK_POW: u64 = 7;
+
+/// The most mutations that are stacked while the corpus stalls.
+const MAX_STALLED_STACK_POW: u64 = 10;
+
 /// A [`Mutator`] that schedules one of the embedded mutations on each call.
+///
+/// With a stall threshold, it stacks more mutations whenever that many
+/// executions in a row didn't add anything to the corpus. Small changes to
+/// the corpus entries have stopped paying off by then, and every rejected
+/// input costs a full simulator run. The stacking goes back to normal as
+/// soon as an input is added again.
+///
+/// With [`MutationWeights`], every embedded mutation is picked with a
+/// likelihood proportional to its weight instead of uniformly.
 pub struct RiscvScheduledMutator<I, MT, S>
 where
     MT: MutatorsTuple<I, S>,
@@ -315,6 +661,12 @@ where
     mutations: MT,
     has_snippet: bool,
     max_stack_pow: u64,
+    /// Executions without a new corpus entry before stacking more, 0 never
+    /// changes the stacking.
+    stall_threshold: u64,
+    stalled_execs: u64,
+    /// The weight of every embedded mutation, empty for uniform picks.
+    weights: Vec<u64>,
     phantom: PhantomData<(I, S)>,
 }
 
@@ -348,6 +700,16 @@ where
     ) -> Result<MutationResult, Error> {
         self.scheduled_mutate(state, input, stage_idx)
     }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _stage_idx: i32,
+        corpus_idx: Option<CorpusId>,
+    ) -> Result<(), Error> {
+        self.record_execution(corpus_idx.is_some());
+        Ok(())
+    }
 }
 
 impl<I, MT, S> ComposedByMutations<I, MT, S> for RiscvScheduledMutator<I, MT, S>
@@ -391,7 +753,19 @@ where
             self.mutations.len() - 1
         };
 
-        state.rand_mut().below(len as u64).into()
+        let weights = &self.weights[..self.weights.len().min(len)];
+        let total: u64 = weights.iter().sum();
+        if total == 0 {
+            return state.rand_mut().below(len as u64).into();
+        }
+        let mut pick = state.rand_mut().below(total);
+        for (idx, weight) in weights.iter().enumerate() {
+            if pick < *weight {
+                return idx.into();
+            }
+            pick -= weight;
+        }
+        unreachable!("The pick is below the total weight")
     }
 }
 
@@ -404,12 +778,83 @@ where
     pub fn new(mutations: MT) -> Self {
         RiscvScheduledMutator {
             mutations,
-            has_snippet: !env::var("PHANTOM_TRAILS_NO_SNIPPET").is_ok(),
-            max_stack_pow: 7,
+            has_snippet: true,
+            max_stack_pow: DEFAULT_STACK_POW,
+            stall_threshold: 0,
+            stalled_execs: 0,
+            weights: vec![],
             phantom: PhantomData,
         }
     }
 
+    /// Picks the mutations with the given weights, see
+    /// [`RiscvScheduledMutator`]. All [`SCHEDULED_MUTATIONS`] have a weight,
+    /// other mutations of the list get weight 1.
+    pub fn with_weights(mut self, weights: &MutationWeights) -> Self
+    where
+        MT: NamedTuple,
+    {
+        self.weights = (0..self.mutations.len())
+            .map(|idx| {
+                let name = self.mutations.name(idx);
+                SCHEDULED_MUTATIONS
+                    .into_iter()
+                    .find(|m| Some(m.name()) == name)
+                    .map_or(1, |m| weights.weight(m))
+            })
+            .collect();
+        self
+    }
+
+    /// Never schedules the snippet mutation if `snippets` is false (see
+    /// [`DriverConfig::snippets`]).
+    pub fn with_snippets(mut self, snippets: bool) -> Self {
+        self.has_snippet = snippets;
+        self
+    }
+
+    /// Stacks more mutations after `execs` executions in a row without a new
+    /// corpus entry, see [`RiscvScheduledMutator`].
+    pub fn with_stall_threshold(mut self, execs: u64) -> Self {
+        self.stall_threshold = execs;
+        self
+    }
+
+    /// At most `2^max_stack_pow` mutations are currently stacked.
+    pub fn max_stack_pow(&self) -> u64 {
+        self.max_stack_pow
+    }
+
+    /// Tracks whether the corpus still grows. Every further `stall_threshold`
+    /// executions without a new entry double the stacked mutations, up to
+    /// `2^MAX_STALLED_STACK_POW`.
+    pub fn record_execution(&mut self, added_to_corpus: bool) {
+        if self.stall_threshold == 0 {
+            return;
+        }
+        if added_to_corpus {
+            if self.max_stack_pow != DEFAULT_STACK_POW {
+                log::info!(
+                    "Corpus grows again, back to up to {} stacked mutations",
+                    1u64 << DEFAULT_STACK_POW
+                );
+                self.max_stack_pow = DEFAULT_STACK_POW;
+            }
+            self.stalled_execs = 0;
+            return;
+        }
+        self.stalled_execs += 1;
+        if self.stalled_execs >= self.stall_threshold && self.max_stack_pow < MAX_STALLED_STACK_POW
+        {
+            self.max_stack_pow += 1;
+            self.stalled_execs = 0;
+            log::info!(
+                "No new corpus entry in {} executions, stacking up to {} mutations",
+                self.stall_threshold,
+                1u64 << self.max_stack_pow
+            );
+        }
+    }
 }
 
 #[cfg(test)]
@@ -419,18 +864,28 @@ mod tests {
     use libafl::prelude::MutationResult;
     use libafl::prelude::Rand;
     use libafl::prelude::Xoshiro256StarRand;
+    use libafl::prelude::{ComposedByMutations, NamedTuple, ScheduledMutator};
 
     use crate::assembler::assemble_instructions;
+    use crate::config::DriverConfig;
     use crate::generator::InstGenerator;
     use crate::instructions;
+    use crate::instructions::riscv::args;
     use crate::instructions::riscv::rv_i::AUIPC;
     use crate::instructions::riscv::rv_i::JALR;
+    use crate::instructions::Argument;
     use crate::instructions::Instruction;
     use crate::instructions::InstructionTemplate;
     use crate::parser::parse_instructions;
 
+    use super::flip_arg_bit;
+    use super::splice;
     use super::Mutation;
     use super::RiscVInstructionMutator;
+    use super::{
+        all_riscv_mutations, MutationWeights, RiscVMutationList, RiscvScheduledMutator,
+        DEFAULT_STACK_POW, MAX_STALLED_STACK_POW, SCHEDULED_MUTATIONS,
+    };
 
     /// The test harness.
     /// Contains all the data for the tests below and some utility code.
@@ -451,7 +906,7 @@ mod tests {
         fn new(mutation: Mutation) -> Self {
             Self {
                 rng: Xoshiro256StarRand::default(),
-                mutator: RiscVInstructionMutator::new(mutation),
+                mutator: RiscVInstructionMutator::new(&DriverConfig::default(), mutation),
                 data: Vec::<u8>::new(),
                 old_data: Vec::<u8>::new(),
                 changed_insts: 0,
@@ -524,6 +979,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn mutate_add_memory_pairs() {
+        let config = DriverConfig {
+            paired_mem_chance: 100,
+            ..DriverConfig::default()
+        };
+        let mut setup = TestSetup::new(Mutation::Add);
+        setup.mutator = RiscVInstructionMutator::new(&config, Mutation::Add);
+
+        for _ in 0..TRIES {
+            setup.fill_random_inst();
+            assert!(setup.mutate());
+            // A store and a load instead of a single instruction.
+            assert_eq!(setup.data.len(), setup.old_data.len() + 8);
+        }
+    }
+
     #[test]
     fn mutate_remove() {
         // Test that the 'Remove' mutation removes only one instruction.
@@ -618,6 +1090,252 @@ mod tests {
         }
     }
 
+    #[test]
+    fn mutate_flip_arg_bit() {
+        // Test that 'FlipArgBit' changes exactly one bit of one argument.
+        let mut setup = TestSetup::new(Mutation::FlipArgBit);
+
+        for _ in 0..TRIES {
+            setup.fill_one_inst(&instructions::riscv::rv_i::ADDI);
+            let original_inst = setup.parsed_insts()[0].clone();
+            assert!(setup.mutate());
+            let new_inst = setup.parsed_insts()[0].clone();
+            assert_eq!(new_inst.template(), &instructions::riscv::rv_i::ADDI);
+
+            let mut flipped_bits = 0;
+            for old_arg in original_inst.arguments() {
+                let new_arg = new_inst
+                    .arguments()
+                    .iter()
+                    .find(|arg| arg.spec() == old_arg.spec())
+                    .unwrap();
+                assert!(new_arg.value() <= new_arg.spec().mask());
+                flipped_bits += (old_arg.value() ^ new_arg.value()).count_ones();
+            }
+            assert_eq!(flipped_bits, 1);
+        }
+    }
+
+    #[test]
+    fn flip_arg_bit_masked() {
+        let arg = Argument::new(&args::RD, 0b10101);
+        assert_eq!(flip_arg_bit(&arg, 0).value(), 0b10100);
+        assert_eq!(flip_arg_bit(&arg, 4).value(), 0b00101);
+        // Bits outside of the 5 bit field are dropped.
+        assert_eq!(flip_arg_bit(&arg, 5).value(), 0b10101);
+    }
+
+    #[test]
+    fn splice_lengths() {
+        let mut rng = Xoshiro256StarRand::with_seed(0);
+        let generator = InstGenerator::new();
+        let isa = instructions::sets::riscv_g();
+        let first = generator.generate_instructions(&mut rng, &isa, 10);
+        let second = generator.generate_instructions(&mut rng, &isa, 7);
+
+        for prefix_len in 0..=first.len() {
+            for suffix_start in 0..second.len() {
+                let spliced = splice(&first, prefix_len, &second, suffix_start, 100).unwrap();
+                let suffix_len = second.len() - suffix_start;
+                assert_eq!(spliced.len(), prefix_len + suffix_len);
+                assert_eq!(spliced[..prefix_len], first[..prefix_len]);
+                assert_eq!(spliced[prefix_len..], second[suffix_start..]);
+            }
+        }
+
+        assert_eq!(splice(&first, 8, &second, 0, 12).unwrap().len(), 12);
+        assert_eq!(splice(&first, 0, &second, 7, 100), None);
+        assert_eq!(splice(&first, 5, &second, 0, 0), None);
+    }
+
+    #[test]
+    fn mutations_respect_max_program_len() {
+        use crate::program_input::{HasProgramInput, ProgramInput};
+        use libafl::prelude::{
+            Corpus, HasCorpus, HasRand, InMemoryCorpus, MutatorsTuple, StdRand, StdState, Testcase,
+        };
+
+        type TestState = StdState<
+            ProgramInput,
+            InMemoryCorpus<ProgramInput>,
+            StdRand,
+            InMemoryCorpus<ProgramInput>,
+        >;
+        let mut state = TestState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::new(),
+            InMemoryCorpus::new(),
+            &mut (),
+            &mut (),
+        )
+        .unwrap();
+        const MAX_LEN: usize = 8;
+        let config = DriverConfig {
+            max_program_len: MAX_LEN,
+            ..DriverConfig::default()
+        };
+        // Without the cap, so the splice mutation has long programs to graft.
+        let generator = InstGenerator::new();
+        let isa = instructions::sets::riscv_g();
+        for _ in 0..2 {
+            let insts = generator.generate_instructions(state.rand_mut(), &isa, 20);
+            state
+                .corpus_mut()
+                .add(Testcase::new(ProgramInput::new(insts)))
+                .unwrap();
+        }
+
+        let mut mutations = all_riscv_mutations(&config);
+        for idx in 0..mutations.len() {
+            for _ in 0..100 {
+                let insts = generator.generate_instructions(state.rand_mut(), &isa, 8);
+                let mut input = ProgramInput::new(insts);
+                mutations
+                    .get_and_mutate(idx.into(), &mut state, &mut input, 0)
+                    .unwrap();
+                assert!(input.insts().len() <= MAX_LEN, "{:?}", mutations.name(idx));
+                assert!(
+                    check_insts(input.insts()).is_ok(),
+                    "{:?}",
+                    mutations.name(idx)
+                );
+            }
+        }
+
+        let capped = InstGenerator::with_config(&config);
+        assert_eq!(
+            capped
+                .generate_instructions(state.rand_mut(), &isa, 20)
+                .len(),
+            MAX_LEN
+        );
+    }
+
+    #[test]
+    fn mutations_keep_mem_base() {
+        use crate::generator::{register_fill_len, ScratchRegion};
+        use crate::program_input::{HasProgramInput, ProgramInput};
+        use libafl::prelude::{
+            Corpus, HasCorpus, HasRand, InMemoryCorpus, MutatorsTuple, StdRand, StdState, Testcase,
+        };
+
+        type TestState = StdState<
+            ProgramInput,
+            InMemoryCorpus<ProgramInput>,
+            StdRand,
+            InMemoryCorpus<ProgramInput>,
+        >;
+        let mut state = TestState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::new(),
+            InMemoryCorpus::new(),
+            &mut (),
+            &mut (),
+        )
+        .unwrap();
+        let config = DriverConfig {
+            scratch: Some(ScratchRegion::parse("0x80000000+4096").unwrap()),
+            mem_base: true,
+            ..DriverConfig::default()
+        };
+        let generator = InstGenerator::with_config(&config);
+        let isa = config.instructions();
+        let program = |state: &mut TestState| {
+            let body = generator.generate_instructions(state.rand_mut(), &isa, 20);
+            ProgramInput::new(generator.add_register_fill(state.rand_mut(), body))
+        };
+        for _ in 0..2 {
+            let input = program(&mut state);
+            state.corpus_mut().add(Testcase::new(input)).unwrap();
+        }
+
+        let setup = generator.mem_base_setup();
+        let mut mutations = all_riscv_mutations(&config);
+        for idx in 0..mutations.len() {
+            for _ in 0..100 {
+                let mut input = program(&mut state);
+                mutations
+                    .get_and_mutate(idx.into(), &mut state, &mut input, 0)
+                    .unwrap();
+                let insts = input.insts();
+                assert_eq!(insts[..setup.len()], setup, "{:?}", mutations.name(idx));
+                assert_eq!(register_fill_len(insts), setup.len());
+                for inst in &insts[setup.len()..] {
+                    let name = mutations.name(idx);
+                    assert!(!generator.clobbers_mem_base(inst), "{:?}: {}", name, inst);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_mutations_are_skipped() {
+        use crate::instructions::riscv::rv_i::ADDI;
+        use crate::instructions::test_insts::inst_with;
+        use crate::program_input::ProgramInput;
+        use libafl::prelude::{InMemoryCorpus, Mutator, StdRand, StdState};
+
+        type TestState = StdState<
+            ProgramInput,
+            InMemoryCorpus<ProgramInput>,
+            StdRand,
+            InMemoryCorpus<ProgramInput>,
+        >;
+        let mut state = TestState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::new(),
+            InMemoryCorpus::new(),
+            &mut (),
+            &mut (),
+        )
+        .unwrap();
+        // x32 doesn't exist, so this doesn't fit into the field.
+        let invalid = inst_with(&ADDI, &[(&args::RD, 32)]);
+        let program = vec![invalid.clone(), invalid];
+        let mut mutator = RiscVInstructionMutator::new(&DriverConfig::default(), Mutation::SwapTwo);
+        let mut input = ProgramInput::new(program.clone());
+        assert_eq!(
+            mutator.mutate(&mut state, &mut input, 0).unwrap(),
+            MutationResult::Skipped
+        );
+        assert_eq!(input.insts(), program);
+    }
+
+    #[test]
+    fn raw_words_keep_their_bits() {
+        use crate::instructions::raw::{UNKNOWN, WORD};
+        use crate::program_input::ProgramInput;
+        use libafl::prelude::{InMemoryCorpus, Mutator, StdRand, StdState};
+
+        type TestState = StdState<
+            ProgramInput,
+            InMemoryCorpus<ProgramInput>,
+            StdRand,
+            InMemoryCorpus<ProgramInput>,
+        >;
+        let mut state = TestState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::new(),
+            InMemoryCorpus::new(),
+            &mut (),
+            &mut (),
+        )
+        .unwrap();
+        let program = vec![Instruction::new(
+            &UNKNOWN,
+            vec![Argument::new(&WORD, 0xffff_ffff)],
+        )];
+        for mutation in [Mutation::ReplaceArg, Mutation::FlipArgBit] {
+            let mut mutator = RiscVInstructionMutator::new(&DriverConfig::default(), mutation);
+            for _ in 0..TRIES {
+                let mut input = ProgramInput::new(program.clone());
+                let result = mutator.mutate(&mut state, &mut input, 0).unwrap();
+                assert_eq!(result, MutationResult::Skipped);
+                assert_eq!(input.insts(), program);
+            }
+        }
+    }
+
     #[test]
     fn mutate_repeat() {
         // Test that 'RepeatOne' only adds instructions.
@@ -661,4 +1379,133 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn snippet_needs_branches() {
+        let config = DriverConfig {
+            excluded_categories: vec![instructions::InstructionCategory::Branch],
+            ..DriverConfig::default()
+        };
+        let mut setup = TestSetup::new(Mutation::Snippet);
+        setup.mutator = RiscVInstructionMutator::new(&config, Mutation::Snippet);
+        for _ in 0..TRIES {
+            assert!(!setup.mutate());
+        }
+    }
+
+    #[test]
+    fn stalled_corpus_stacks_more_mutations() {
+        type TestState = libafl::prelude::StdState<
+            crate::program_input::ProgramInput,
+            libafl::prelude::InMemoryCorpus<crate::program_input::ProgramInput>,
+            libafl::prelude::StdRand,
+            libafl::prelude::InMemoryCorpus<crate::program_input::ProgramInput>,
+        >;
+        let mut mutator: RiscvScheduledMutator<_, RiscVMutationList, TestState> =
+            RiscvScheduledMutator::new(all_riscv_mutations(&DriverConfig::default()));
+        // Without a threshold, nothing changes.
+        for _ in 0..100 {
+            mutator.record_execution(false);
+        }
+        assert_eq!(mutator.max_stack_pow(), DEFAULT_STACK_POW);
+
+        let mut mutator = mutator.with_stall_threshold(10);
+        for _ in 0..9 {
+            mutator.record_execution(false);
+        }
+        assert_eq!(mutator.max_stack_pow(), DEFAULT_STACK_POW);
+        mutator.record_execution(false);
+        assert_eq!(mutator.max_stack_pow(), DEFAULT_STACK_POW + 1);
+        // A new entry resets the stall counter too.
+        mutator.record_execution(true);
+        assert_eq!(mutator.max_stack_pow(), DEFAULT_STACK_POW);
+        for _ in 0..9 {
+            mutator.record_execution(false);
+        }
+        mutator.record_execution(true);
+        assert_eq!(mutator.max_stack_pow(), DEFAULT_STACK_POW);
+
+        for _ in 0..1000 {
+            mutator.record_execution(false);
+        }
+        assert_eq!(mutator.max_stack_pow(), MAX_STALLED_STACK_POW);
+    }
+
+    #[test]
+    fn every_mutation_listed_once() {
+        let mutations = all_riscv_mutations(&DriverConfig::default());
+        let names: Vec<&str> = (0..mutations.len())
+            .filter_map(|idx| mutations.name(idx))
+            .collect();
+        assert_eq!(names.len(), mutations.len());
+        for mutation in SCHEDULED_MUTATIONS {
+            assert_eq!(
+                names
+                    .iter()
+                    .filter(|name| **name == mutation.name())
+                    .count(),
+                1
+            );
+        }
+        assert_eq!(names.len(), SCHEDULED_MUTATIONS.len());
+        assert_eq!(names.last(), Some(&Mutation::Snippet.name()));
+    }
+
+    #[test]
+    fn mutation_weights() {
+        let all =
+            "add=1,remove=1,replace-arg=1,flip-arg-bit=1,replace=1,repeat=1,swap=1,splice=1,snippet=1";
+        assert_eq!(
+            MutationWeights::parse(all).unwrap(),
+            MutationWeights::default()
+        );
+        assert!(MutationWeights::parse("add=3,remove=1").is_err());
+        assert!(MutationWeights::parse(&all.replace("add=1", "insert=1")).is_err());
+        assert!(MutationWeights::parse(&all.replace("add=1", "add=x")).is_err());
+        assert!(MutationWeights::parse(&format!("{},add=2", all)).is_err());
+        let zeros = all.replace("=1", "=0");
+        assert!(MutationWeights::parse(&zeros).is_err());
+        let weights = MutationWeights::parse(&zeros.replace("add=0", "add=5")).unwrap();
+        assert_eq!(weights.weight(Mutation::Add), 5);
+        assert_eq!(weights.weight(Mutation::Remove), 0);
+    }
+
+    #[test]
+    fn high_weighted_mutation_dominates() {
+        type TestState = libafl::prelude::StdState<
+            crate::program_input::ProgramInput,
+            libafl::prelude::InMemoryCorpus<crate::program_input::ProgramInput>,
+            libafl::prelude::StdRand,
+            libafl::prelude::InMemoryCorpus<crate::program_input::ProgramInput>,
+        >;
+        let mut state = TestState::new(
+            libafl::prelude::StdRand::with_seed(0),
+            libafl::prelude::InMemoryCorpus::new(),
+            libafl::prelude::InMemoryCorpus::new(),
+            &mut (),
+            &mut (),
+        )
+        .unwrap();
+        let weights = MutationWeights::parse(
+            "add=1,remove=1,replace-arg=100,flip-arg-bit=1,replace=1,repeat=1,swap=1,splice=0,\
+             snippet=0",
+        )
+        .unwrap();
+        let mutator: RiscvScheduledMutator<_, RiscVMutationList, TestState> =
+            RiscvScheduledMutator::new(all_riscv_mutations(&DriverConfig::default()))
+                .with_weights(&weights);
+        let input = crate::program_input::ProgramInput::new(vec![]);
+
+        let mut picks = std::collections::HashMap::<&str, u32>::new();
+        for _ in 0..10000 {
+            let idx = mutator.schedule(&mut state, &input);
+            let name = mutator.mutations().name(idx.into()).unwrap();
+            *picks.entry(name).or_default() += 1;
+        }
+        // 100 of the 106 weight units are on ReplaceArg.
+        assert!(picks["replace-arg"] > 9000, "{:?}", picks);
+        assert!(picks["add"] > 0);
+        assert!(!picks.contains_key("snippet"));
+        assert!(!picks.contains_key("splice"));
+    }
 }
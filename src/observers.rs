@@ -0,0 +1,583 @@
+use core::ops::Range;
+use std::{fs, io::ErrorKind, path::PathBuf};
+
+use libafl::{
+    bolts::{tuples::Named, AsIter, AsIterMut, AsMutSlice, AsSlice},
+    executors::ExitKind,
+    inputs::UsesInput,
+    observers::{MapObserver, Observer},
+    prelude::{HasLen, Truncate},
+    Error,
+};
+use nix::{
+    sys::{signal::Signal, wait::WaitStatus},
+    unistd::Pid,
+};
+use serde::{Deserialize, Serialize};
+
+/// The hit count thresholds of the classic AFL buckets.
+const AFL_THRESHOLDS: [u8; 8] = [1, 2, 3, 4, 8, 16, 32, 128];
+
+/// Maps raw hit counts to the value stored in the coverage map.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BucketTable {
+    /// The bucket for every possible hit count.
+    table: Vec<u8>,
+}
+
+impl BucketTable {
+    /// The classic AFL buckets (1, 2, 3, 4-7, 8-15, 16-31, 32-127, 128+).
+    pub fn afl() -> Self {
+        Self::from_thresholds(&AFL_THRESHOLDS).unwrap()
+    }
+
+    /// Keeps the raw hit counts.
+    pub fn raw() -> Self {
+        Self {
+            table: (0..=255).collect(),
+        }
+    }
+
+    /// Creates buckets starting at the given (ascending) hit counts. Each
+    /// bucket sets its own bit, so at most 8 buckets are supported. Counts
+    /// below the first threshold are treated as not hit.
+    pub fn from_thresholds(thresholds: &[u8]) -> Result<Self, String> {
+        if thresholds.is_empty() || thresholds.len() > 8 {
+            return Err(format!(
+                "Need 1 to 8 bucket thresholds, got {}",
+                thresholds.len()
+            ));
+        }
+        if thresholds[0] == 0 || thresholds.windows(2).any(|w| w[0] >= w[1]) {
+            return Err("Bucket thresholds must be ascending and non-zero".to_string());
+        }
+        let table = (0..=255u8)
+            .map(|count| match thresholds.iter().rposition(|t| *t <= count) {
+                Some(bucket) => 1 << bucket,
+                None => 0,
+            })
+            .collect();
+        Ok(Self { table })
+    }
+
+    /// Parses `afl`, `raw` or a comma separated list of thresholds.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        match spec.trim() {
+            "afl" => Ok(Self::afl()),
+            "raw" => Ok(Self::raw()),
+            list => {
+                let thresholds = list
+                    .split(',')
+                    .map(|t| t.trim().parse::<u8>())
+                    .collect::<Result<Vec<u8>, _>>()
+                    .map_err(|_| format!("Invalid bucket thresholds '{}'", list))?;
+                Self::from_thresholds(&thresholds)
+            }
+        }
+    }
+
+    pub fn bucket(&self, count: u8) -> u8 {
+        self.table[count as usize]
+    }
+
+    /// The smallest hit count above `count` that lands in a different
+    /// bucket, if there is one.
+    pub fn next_threshold(&self, count: u8) -> Option<u8> {
+        let bucket = self.bucket(count);
+        (count.checked_add(1)?..=255).find(|next| self.bucket(*next) != bucket)
+    }
+
+    /// For every hit count the bucket that is at most `distance` hits away,
+    /// or 0 if the next bucket is further away or the entry wasn't hit.
+    fn near_miss_buckets(&self, distance: u8) -> Vec<u8> {
+        (0..=255u8)
+            .map(|count| match self.next_threshold(count) {
+                Some(next) if count > 0 && next - count <= distance => self.bucket(next),
+                _ => 0,
+            })
+            .collect()
+    }
+}
+
+impl Default for BucketTable {
+    fn default() -> Self {
+        Self::afl()
+    }
+}
+
+/// The name of the user stat with the map density, as filled out of all map
+/// entries in the last execution.
+pub const MAP_DENSITY_STAT_NAME: &str = "map_density";
+
+/// The filled entries (see `MapObserver::count_bytes`) and the size of the
+/// map, for [`MAP_DENSITY_STAT_NAME`]. A map that fills up tends to hide new
+/// coverage in hash collisions.
+pub fn map_density<O: MapObserver>(observer: &O) -> (u64, u64) {
+    (observer.count_bytes(), observer.usable_count() as u64)
+}
+
+/// Parses a `START-END` range of map entries, where `END` is exclusive.
+pub fn parse_map_region(spec: &str) -> Result<Range<usize>, String> {
+    let parse = |s: &str| match s.trim().strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.trim().parse::<usize>().ok(),
+    };
+    let (start, end) = spec
+        .split_once('-')
+        .and_then(|(start, end)| Some((parse(start)?, parse(end)?)))
+        .ok_or(format!("Not in START-END format: '{}'", spec))?;
+    if start >= end {
+        return Err(format!("Empty map region '{}'", spec));
+    }
+    Ok(start..end)
+}
+
+/// Checks that the region lies within a map of the given size.
+pub fn check_map_region(region: &Range<usize>, map_size: usize) -> Result<(), String> {
+    if region.end > map_size {
+        return Err(format!(
+            "Map region {}-{} exceeds the map size {}",
+            region.start, region.end, map_size
+        ));
+    }
+    Ok(())
+}
+
+/// A map observer that classifies the hit counts of the wrapped map with a
+/// [`BucketTable`] after every execution. Like LibAFL's
+/// `HitcountsMapObserver`, but with configurable buckets.
+///
+/// If a region is set, all entries outside of it are cleared, so the
+/// feedbacks only see the coverage of that part of the map.
+///
+/// With a near-miss distance, the observer also records the entries whose
+/// raw hit count was at most that many hits short of the next bucket (see
+/// [`crate::feedbacks::FrontierFeedback`]).
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(bound = "M: serde::de::DeserializeOwned")]
+pub struct BucketedMapObserver<M>
+where
+    M: Serialize,
+{
+    base: M,
+    buckets: BucketTable,
+    region: Option<Range<usize>>,
+    /// The reachable bucket for every raw hit count, empty if near misses
+    /// aren't recorded.
+    near_miss_buckets: Vec<u8>,
+    /// The near misses of the last execution as map index and bucket.
+    near_misses: Vec<(usize, u8)>,
+}
+
+impl<M> BucketedMapObserver<M>
+where
+    M: Serialize + serde::de::DeserializeOwned,
+{
+    pub fn new(base: M, buckets: BucketTable) -> Self {
+        Self {
+            base,
+            buckets,
+            region: None,
+            near_miss_buckets: vec![],
+            near_misses: vec![],
+        }
+    }
+
+    /// Ignores all map entries outside of `region`.
+    pub fn with_region(mut self, region: Option<Range<usize>>) -> Self {
+        self.region = region;
+        self
+    }
+
+    /// Records the entries that were at most `distance` hits short of their
+    /// next bucket. Zero disables the tracking.
+    pub fn with_near_miss(mut self, distance: u8) -> Self {
+        self.near_miss_buckets = if distance == 0 {
+            vec![]
+        } else {
+            self.buckets.near_miss_buckets(distance)
+        };
+        self
+    }
+
+    /// The entries of the last execution that almost reached the returned
+    /// (bucketed) value.
+    pub fn near_misses(&self) -> &[(usize, u8)] {
+        &self.near_misses
+    }
+}
+
+impl<S, M> Observer<S> for BucketedMapObserver<M>
+where
+    M: MapObserver<Entry = u8> + Observer<S> + AsMutSlice<Entry = u8>,
+    S: UsesInput,
+{
+    #[inline]
+    fn pre_exec(&mut self, state: &mut S, input: &S::Input) -> Result<(), Error> {
+        self.base.pre_exec(state, input)
+    }
+
+    fn post_exec(
+        &mut self,
+        state: &mut S,
+        input: &S::Input,
+        exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        let region = self.region.clone().unwrap_or(0..usize::MAX);
+        let track_near_misses = !self.near_miss_buckets.is_empty();
+        self.near_misses.clear();
+        for (idx, entry) in self.base.as_mut_slice().iter_mut().enumerate() {
+            if track_near_misses && *entry != 0 && region.contains(&idx) {
+                let reachable = self.near_miss_buckets[*entry as usize];
+                if reachable != 0 {
+                    self.near_misses.push((idx, reachable));
+                }
+            }
+            *entry = if region.contains(&idx) {
+                self.buckets.table[*entry as usize]
+            } else {
+                0
+            };
+        }
+        self.base.post_exec(state, input, exit_kind)
+    }
+}
+
+impl<M> Named for BucketedMapObserver<M>
+where
+    M: Named + Serialize + serde::de::DeserializeOwned,
+{
+    #[inline]
+    fn name(&self) -> &str {
+        self.base.name()
+    }
+}
+
+impl<M> HasLen for BucketedMapObserver<M>
+where
+    M: MapObserver,
+{
+    #[inline]
+    fn len(&self) -> usize {
+        self.base.len()
+    }
+}
+
+impl<M> MapObserver for BucketedMapObserver<M>
+where
+    M: MapObserver<Entry = u8>,
+{
+    type Entry = u8;
+
+    #[inline]
+    fn initial(&self) -> u8 {
+        self.base.initial()
+    }
+
+    #[inline]
+    fn usable_count(&self) -> usize {
+        self.base.usable_count()
+    }
+
+    #[inline]
+    fn get(&self, idx: usize) -> &u8 {
+        self.base.get(idx)
+    }
+
+    #[inline]
+    fn get_mut(&mut self, idx: usize) -> &mut u8 {
+        self.base.get_mut(idx)
+    }
+
+    fn count_bytes(&self) -> u64 {
+        self.base.count_bytes()
+    }
+
+    #[inline]
+    fn reset_map(&mut self) -> Result<(), Error> {
+        self.base.reset_map()
+    }
+
+    fn hash(&self) -> u64 {
+        self.base.hash()
+    }
+
+    fn to_vec(&self) -> Vec<u8> {
+        self.base.to_vec()
+    }
+
+    fn how_many_set(&self, indexes: &[usize]) -> usize {
+        self.base.how_many_set(indexes)
+    }
+}
+
+impl<M> Truncate for BucketedMapObserver<M>
+where
+    M: Named + Serialize + serde::de::DeserializeOwned + Truncate,
+{
+    fn truncate(&mut self, new_len: usize) {
+        self.base.truncate(new_len);
+    }
+}
+
+impl<M> AsSlice for BucketedMapObserver<M>
+where
+    M: MapObserver + AsSlice,
+{
+    type Entry = <M as AsSlice>::Entry;
+
+    #[inline]
+    fn as_slice(&self) -> &[Self::Entry] {
+        self.base.as_slice()
+    }
+}
+
+impl<M> AsMutSlice for BucketedMapObserver<M>
+where
+    M: MapObserver + AsMutSlice,
+{
+    type Entry = <M as AsMutSlice>::Entry;
+
+    #[inline]
+    fn as_mut_slice(&mut self) -> &mut [Self::Entry] {
+        self.base.as_mut_slice()
+    }
+}
+
+impl<'it, M> AsIter<'it> for BucketedMapObserver<M>
+where
+    M: Named + Serialize + serde::de::DeserializeOwned + AsIter<'it, Item = u8>,
+{
+    type Item = u8;
+    type IntoIter = <M as AsIter<'it>>::IntoIter;
+
+    fn as_iter(&'it self) -> Self::IntoIter {
+        self.base.as_iter()
+    }
+}
+
+impl<'it, M> AsIterMut<'it> for BucketedMapObserver<M>
+where
+    M: Named + Serialize + serde::de::DeserializeOwned + AsIterMut<'it, Item = u8>,
+{
+    type Item = u8;
+    type IntoIter = <M as AsIterMut<'it>>::IntoIter;
+
+    fn as_iter_mut(&'it mut self) -> Self::IntoIter {
+        self.base.as_iter_mut()
+    }
+}
+
+/// Removes the files the harness creates before reporting an issue (see
+/// [`crate::feedbacks::CrashOriginFeedback`]) or exiting (see
+/// [`crate::feedbacks::ExitCodeFeedback`]) before every run. Runs the
+/// objective never looks at, e.g. calibration runs and re-runs of timeouts,
+/// could otherwise leave a marker that makes the next crash look like a
+/// reported one. Without markers this does nothing.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IssueMarkerObserver {
+    markers: Vec<PathBuf>,
+}
+
+impl IssueMarkerObserver {
+    pub fn new(markers: Vec<PathBuf>) -> Self {
+        Self { markers }
+    }
+}
+
+impl Named for IssueMarkerObserver {
+    fn name(&self) -> &str {
+        "IssueMarkerObserver"
+    }
+}
+
+impl<S> Observer<S> for IssueMarkerObserver
+where
+    S: UsesInput,
+{
+    fn pre_exec(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
+        for marker in &self.markers {
+            match fs::remove_file(marker) {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The name of the [`ExitStatusObserver`].
+pub const EXIT_STATUS_OBSERVER_NAME: &str = "exit_status";
+
+/// The signal that terminated a process with the given raw `waitpid`
+/// status, if any.
+pub fn terminating_signal(status: i32) -> Option<Signal> {
+    match WaitStatus::from_raw(Pid::from_raw(0), status) {
+        Ok(WaitStatus::Signaled(_, signal, _)) => Some(signal),
+        _ => None,
+    }
+}
+
+/// The raw `waitpid` status of the target in the last run, as reported by
+/// the forkserver. The executor records it after every run (see
+/// [`crate::executors::TimeoutRerunExecutor`]), so it is `None` if the
+/// forkserver didn't report one.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ExitStatusObserver {
+    status: Option<i32>,
+}
+
+impl ExitStatusObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, status: Option<i32>) {
+        self.status = status;
+    }
+
+    /// The signal that terminated the target in the last run, if any.
+    pub fn signal(&self) -> Option<Signal> {
+        self.status.and_then(terminating_signal)
+    }
+}
+
+impl Named for ExitStatusObserver {
+    fn name(&self) -> &str {
+        EXIT_STATUS_OBSERVER_NAME
+    }
+}
+
+impl<S> Observer<S> for ExitStatusObserver
+where
+    S: UsesInput,
+{
+    fn pre_exec(&mut self, _state: &mut S, _input: &S::Input) -> Result<(), Error> {
+        self.status = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use libafl::{
+        bolts::tuples::tuple_list,
+        observers::{ObserversTuple, StdMapObserver},
+    };
+
+    use nix::sys::signal::Signal;
+
+    use super::{
+        check_map_region, map_density, parse_map_region, terminating_signal, BucketTable,
+        IssueMarkerObserver,
+    };
+    use crate::{program_input::ProgramInput, single_run::single_run_state};
+
+    #[test]
+    fn afl_buckets() {
+        let table = BucketTable::afl();
+        let expected = [
+            (0, 0),
+            (1, 1),
+            (2, 2),
+            (3, 4),
+            (7, 8),
+            (8, 16),
+            (31, 32),
+            (127, 64),
+        ];
+        for (count, bucket) in expected {
+            assert_eq!(table.bucket(count), bucket, "count {}", count);
+        }
+        assert_eq!(table.bucket(255), 128);
+    }
+
+    #[test]
+    fn custom_buckets() {
+        let table = BucketTable::parse("2, 10, 100").unwrap();
+        assert_eq!(table.bucket(0), 0);
+        // Below the first threshold counts as not hit.
+        assert_eq!(table.bucket(1), 0);
+        assert_eq!(table.bucket(2), 1);
+        assert_eq!(table.bucket(9), 1);
+        assert_eq!(table.bucket(10), 2);
+        assert_eq!(table.bucket(99), 2);
+        assert_eq!(table.bucket(100), 4);
+        assert_eq!(table.bucket(255), 4);
+
+        assert_eq!(BucketTable::parse("raw").unwrap().bucket(77), 77);
+        assert!(BucketTable::parse("4,2").is_err());
+        assert!(BucketTable::parse("0,2").is_err());
+        assert!(BucketTable::parse("1,2,3,4,5,6,7,8,9").is_err());
+        assert!(BucketTable::parse("x").is_err());
+    }
+
+    #[test]
+    fn near_misses_of_buckets() {
+        let table = BucketTable::afl();
+        assert_eq!(table.next_threshold(0), Some(1));
+        assert_eq!(table.next_threshold(4), Some(8));
+        assert_eq!(table.next_threshold(127), Some(128));
+        assert_eq!(table.next_threshold(128), None);
+
+        let near = table.near_miss_buckets(2);
+        // Unhit entries are no near misses.
+        assert_eq!(near[0], 0);
+        assert_eq!(near[6], table.bucket(8));
+        assert_eq!(near[7], table.bucket(8));
+        assert_eq!(near[5], 0);
+        assert_eq!(near[126], table.bucket(128));
+        assert_eq!(near[200], 0);
+    }
+
+    #[test]
+    fn issue_marker_removed_before_runs() {
+        let marker = std::env::temp_dir().join(format!("marker-test-{}", std::process::id()));
+        fs::write(&marker, "").unwrap();
+        let mut state = single_run_state();
+        let input = ProgramInput::new(vec![]);
+        let exit_code = marker.with_extension("code");
+        fs::write(&exit_code, "1").unwrap();
+        let markers = vec![marker.clone(), exit_code.clone()];
+        let mut observers = tuple_list!(IssueMarkerObserver::new(markers));
+        observers.pre_exec_all(&mut state, &input).unwrap();
+        assert!(!marker.exists());
+        assert!(!exit_code.exists());
+        // Runs without a marker are fine.
+        observers.pre_exec_all(&mut state, &input).unwrap();
+        let mut disabled = tuple_list!(IssueMarkerObserver::new(vec![]));
+        disabled.pre_exec_all(&mut state, &input).unwrap();
+    }
+
+    #[test]
+    fn terminating_signals() {
+        // The waitpid statuses of a SIGSEGV, of a SIGKILL with a core dump
+        // and of `exit(1)`.
+        assert_eq!(terminating_signal(11), Some(Signal::SIGSEGV));
+        assert_eq!(terminating_signal(0x80 | 9), Some(Signal::SIGKILL));
+        assert_eq!(terminating_signal(1 << 8), None);
+        assert_eq!(terminating_signal(0), None);
+    }
+
+    #[test]
+    fn map_regions() {
+        assert_eq!(parse_map_region("16-0x100").unwrap(), 16..256);
+        assert!(parse_map_region("16").is_err());
+        assert!(parse_map_region("16-16").is_err());
+        assert!(parse_map_region("a-b").is_err());
+
+        assert!(check_map_region(&(0..1024), 1024).is_ok());
+        assert!(check_map_region(&(512..1025), 1024).is_err());
+    }
+
+    #[test]
+    fn density_counts_filled_entries() {
+        let mut map = vec![0u8; 64];
+        map[3] = 1;
+        map[40] = 128;
+        let observer = unsafe { StdMapObserver::new("map", &mut map) };
+        assert_eq!(map_density(&observer), (2, 64));
+    }
+}
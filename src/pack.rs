@@ -0,0 +1,136 @@
+//! A single-file archive format for corpora: every input is serialized with
+//! [`ProgramInput::to_bytes`] and prefixed with its length as a
+//! little-endian `u32`.
+use std::{fs, path::Path};
+
+use crate::program_input::ProgramInput;
+
+/// Size of the length prefix in front of every entry.
+const LENGTH_SIZE: usize = 4;
+
+/// Packs the given inputs into one archive.
+pub fn pack(inputs: &[ProgramInput]) -> Vec<u8> {
+    let mut result = Vec::new();
+    for input in inputs {
+        let bytes = input.to_bytes().expect("Failed to serialize input");
+        result.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        result.extend_from_slice(&bytes);
+    }
+    result
+}
+
+/// The result of unpacking an archive.
+#[derive(Debug, Default)]
+pub struct Unpacked {
+    pub inputs: Vec<ProgramInput>,
+    /// Entries that couldn't be deserialized.
+    pub corrupt: usize,
+    /// True if the archive ended in the middle of an entry.
+    pub truncated: bool,
+}
+
+/// Unpacks an archive created by [`pack`]. Empty or corrupt entries are
+/// skipped instead of aborting the whole archive.
+pub fn unpack(bytes: &[u8]) -> Unpacked {
+    let mut result = Unpacked::default();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if pos + LENGTH_SIZE > bytes.len() {
+            result.truncated = true;
+            break;
+        }
+        let len_bytes: [u8; LENGTH_SIZE] = bytes[pos..pos + LENGTH_SIZE].try_into().unwrap();
+        let len = u32::from_le_bytes(len_bytes) as usize;
+        pos += LENGTH_SIZE;
+        if pos + len > bytes.len() {
+            result.truncated = true;
+            break;
+        }
+        match ProgramInput::from_bytes(&bytes[pos..pos + len]) {
+            Ok(input) if !input.insts().is_empty() => result.inputs.push(input),
+            Ok(_) => {}
+            Err(_) => result.corrupt += 1,
+        }
+        pos += len;
+    }
+    result
+}
+
+/// Reads all serialized inputs in `dir` and its subdirectories.
+/// Hidden files (e.g. LibAFL's metadata and lock files) are ignored.
+/// Returns the inputs and the number of files that couldn't be read.
+pub fn read_corpus_dir(dir: &Path) -> std::io::Result<(Vec<ProgramInput>, usize)> {
+    let mut inputs = vec![];
+    let mut failed = 0;
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    // Always pack in the same order.
+    entries.sort_by_key(|entry| entry.path());
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        if path.is_dir() {
+            let (mut sub_inputs, sub_failed) = read_corpus_dir(&path)?;
+            inputs.append(&mut sub_inputs);
+            failed += sub_failed;
+            continue;
+        }
+        match ProgramInput::from_bytes(&fs::read(&path)?) {
+            Ok(input) if !input.insts().is_empty() => inputs.push(input),
+            _ => failed += 1,
+        }
+    }
+    Ok((inputs, failed))
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::prelude::{Rand, Xoshiro256StarRand};
+
+    use super::{pack, unpack};
+    use crate::{generator::InstGenerator, instructions, program_input::ProgramInput};
+
+    fn random_inputs(count: u32) -> Vec<ProgramInput> {
+        let mut rng = Xoshiro256StarRand::default();
+        rng.set_seed(0);
+        (1..=count)
+            .map(|len| {
+                ProgramInput::new(InstGenerator::new().generate_instructions(
+                    &mut rng,
+                    &instructions::sets::riscv_g(),
+                    len,
+                ))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pack_round_trip() {
+        let inputs = random_inputs(10);
+        let unpacked = unpack(&pack(&inputs));
+        assert_eq!(unpacked.inputs, inputs);
+        assert_eq!(unpacked.corrupt, 0);
+        assert!(!unpacked.truncated);
+
+        assert!(unpack(&pack(&[])).inputs.is_empty());
+    }
+
+    #[test]
+    fn unpack_skips_bad_entries() {
+        let inputs = random_inputs(3);
+        let mut archive = pack(&inputs[..1]);
+        // An entry with 3 bytes of instructions can't be decoded.
+        archive.extend_from_slice(&[4, 0, 0, 0, 3, 1, 2, 3]);
+        // An empty program.
+        archive.extend_from_slice(&pack(&[ProgramInput::new(vec![])]));
+        archive.extend_from_slice(&pack(&inputs[1..]));
+        // Cut off the last entry.
+        archive.pop();
+
+        let unpacked = unpack(&archive);
+        assert_eq!(unpacked.inputs, inputs[..2].to_vec());
+        assert_eq!(unpacked.corrupt, 1);
+        assert!(unpacked.truncated);
+    }
+}
@@ -1,4 +1,35 @@
-use crate::instructions::{Instruction, InstructionTemplate};
+use crate::instructions::{raw, EncodedInstruction, Instruction, InstructionTemplate};
+
+/// The length in bytes of the instruction that starts with `half`, the first
+/// 16 bits of it: 4 if its lowest 2 bits are `0b11`, otherwise it is a
+/// compressed instruction and 2.
+pub fn encoded_len(half: u16) -> usize {
+    if half & 0b11 == 0b11 {
+        4
+    } else {
+        2
+    }
+}
+
+/// Splits the input into the encodings of its instructions. Also returns the
+/// bytes after the last whole instruction, which are empty for valid input.
+pub fn split_encodings(input: &[u8]) -> (Vec<EncodedInstruction>, &[u8]) {
+    let mut result = Vec::new();
+    let mut rest = input;
+    while let [low, high, ..] = *rest {
+        let half = u16::from_ne_bytes([low, high]);
+        let len = encoded_len(half);
+        if rest.len() < len {
+            break;
+        }
+        result.push(match len {
+            2 => half as EncodedInstruction,
+            _ => u32::from_ne_bytes(rest[..4].try_into().unwrap()),
+        });
+        rest = &rest[len..];
+    }
+    (result, rest)
+}
 
 pub fn parse_instructions(
     input: &Vec<u8>,
@@ -6,16 +37,12 @@ pub fn parse_instructions(
 ) -> Result<Vec<Instruction>, String> {
     let mut result = Vec::<Instruction>::new();
 
-    if input.len() % 4 != 0 {
+    let (encodings, rest) = split_encodings(input);
+    if !rest.is_empty() {
         return Err(format!("Tailing garbage in instructions: {:?}", input));
     }
 
-    for i in (0..input.len()).step_by(4) {
-        if i + 4 > input.len() {
-            continue;
-        }
-        let data = u32::from_ne_bytes(input[i..i + 4].try_into().unwrap());
-
+    for data in encodings {
         let mut found = false;
         for inst in insts {
             let maybe_parsed = inst.decode(data);
@@ -35,13 +62,42 @@ pub fn parse_instructions(
     Ok(result)
 }
 
+/// Decodes the input as 32 bit little-endian words, the format of tools that
+/// don't know the C extension. Words that none of the (non-compressed)
+/// templates match become [`raw::UNKNOWN`], so the program encodes to the
+/// input again. Only fails if the length isn't a multiple of 4.
+pub fn parse_words(
+    input: &[u8],
+    insts: &[&'static InstructionTemplate],
+) -> Result<Vec<Instruction>, String> {
+    if input.len() % 4 != 0 {
+        return Err(format!(
+            "{} bytes are no whole number of words",
+            input.len()
+        ));
+    }
+    let words = input
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()));
+    Ok(words
+        .map(|word| {
+            insts
+                .iter()
+                .filter(|template| !template.is_compressed())
+                .find_map(|template| template.decode(word))
+                .or_else(|| raw::UNKNOWN.decode(word))
+                .unwrap()
+        })
+        .collect())
+}
+
 #[cfg(test)]
 mod tests {
     use libafl::prelude::{Rand, Xoshiro256StarRand};
 
-    use crate::instructions;
+    use crate::{assembler::assemble_instructions, instructions};
 
-    use super::parse_instructions;
+    use super::{parse_instructions, parse_words, split_encodings};
 
     #[test]
     fn parse_random_bytes() {
@@ -61,4 +117,44 @@ mod tests {
             assert_eq!(parsed.unwrap().len() * 4, input.len());
         }
     }
+
+    #[test]
+    fn split_mixed_lengths() {
+        // c.addi x1, 1 between two addi x1, x1, 1.
+        let input = vec![0x93, 0x80, 0x10, 0x00, 0x85, 0x00, 0x93, 0x80, 0x10, 0x00];
+        let (encodings, rest) = split_encodings(&input);
+        assert_eq!(encodings, [0x00108093, 0x0085, 0x00108093]);
+        assert!(rest.is_empty());
+        let parsed = parse_instructions(&input, &instructions::sets::all()).unwrap();
+        let names: Vec<_> = parsed.iter().map(|inst| inst.template().name()).collect();
+        assert_eq!(names, ["addi", "c.addi", "addi"]);
+
+        // A single byte, or half of a 32 bit instruction, is left over.
+        assert_eq!(
+            split_encodings(&input[..5]),
+            (vec![0x00108093], &input[4..5])
+        );
+        assert_eq!(
+            split_encodings(&input[..8]),
+            (vec![0x00108093, 0x0085], &input[6..8])
+        );
+        assert!(parse_instructions(&input[..8].to_vec(), &instructions::sets::all()).is_err());
+    }
+
+    #[test]
+    fn unknown_words_round_trip() {
+        // addi x1, x1, 1, an unknown 32 bit word and a word with the low
+        // bits of a compressed instruction.
+        let input = vec![
+            0x93, 0x80, 0x10, 0x00, 0xff, 0xff, 0xff, 0xff, 0x85, 0x00, 0x00, 0x00,
+        ];
+        let parsed = parse_words(&input, &instructions::sets::all()).unwrap();
+        let names: Vec<_> = parsed.iter().map(|inst| inst.template().name()).collect();
+        assert_eq!(names, ["addi", ".word", ".word"]);
+        assert!(parsed.iter().all(|inst| inst.size() == 4));
+        assert_eq!(assemble_instructions(&parsed), input);
+
+        assert!(parse_words(&input[..6], &instructions::sets::all()).is_err());
+        assert_eq!(parse_words(&[], &instructions::sets::all()), Ok(vec![]));
+    }
 }
@@ -1,28 +1,59 @@
 //! The gramatron grammar fuzzer
-use core::hash::{BuildHasher, Hasher};
+use core::hash::{BuildHasher, Hash, Hasher};
 use libafl::{
     prelude::{HasLen, HasTargetBytes, Input, OwnedSlice},
     Error,
 };
-use std::fmt;
+use std::{cell::RefCell, fmt, fs, path::Path};
 
 use ahash::RandomState;
 use serde::{de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::{
     assembler::assemble_instructions,
+    disasm::insts_to_asm,
     instructions::{self, Instruction},
-    parser::parse_instructions,
+    parser::{parse_instructions, parse_words},
 };
 
+/// Starts every serialized input since version 2, followed by the version
+/// byte. Version 1 inputs have no header and begin with the varint length of
+/// the machine code, which can't start like this: 0xff 'P' is an odd length
+/// and thus not a whole number of instructions.
+const FORMAT_MAGIC: [u8; 3] = [0xff, b'P', b'T'];
+
+/// The version of the format written by [`ProgramInput::to_bytes`].
+pub const FORMAT_VERSION: u8 = 3;
+
+/// Set in the flags byte that starts the serialized program since version 3
+/// if the program has [`instructions::raw::UNKNOWN`] words.
+const RAW_WORDS_FLAG: u8 = 1;
+
 pub trait HasProgramInput {
     fn insts(&self) -> &[Instruction];
     fn insts_mut(&mut self) -> &mut Vec<Instruction>;
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, Default)]
 pub struct ProgramInput {
     insts: Vec<Instruction>,
+    /// The encoded bytes of `insts`. Reset whenever the instructions might
+    /// have been changed, i.e., whenever `insts_mut` is called.
+    encoded: RefCell<Option<Vec<u8>>>,
+}
+
+impl PartialEq for ProgramInput {
+    fn eq(&self, other: &Self) -> bool {
+        self.insts == other.insts
+    }
+}
+
+impl Eq for ProgramInput {}
+
+impl Hash for ProgramInput {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.insts.hash(state);
+    }
 }
 
 impl Serialize for ProgramInput {
@@ -30,7 +61,14 @@ impl Serialize for ProgramInput {
     where
         S: Serializer,
     {
-        serializer.serialize_bytes(assemble_instructions(&self.insts).as_slice())
+        let flags = if self.has_raw_words() {
+            RAW_WORDS_FLAG
+        } else {
+            0
+        };
+        let mut bytes = vec![flags];
+        bytes.extend(self.encode_program());
+        serializer.serialize_bytes(&bytes)
     }
 }
 
@@ -43,10 +81,23 @@ impl<'de> Deserialize<'de> for ProgramInput {
     }
 }
 
+/// Decodes the machine code of a serialized input. Programs with the
+/// [`instructions::raw::UNKNOWN`] words of raw seeds (`raw_words`) are
+/// decoded as words only (see [`parse_words`]), as a word can also be two
+/// valid compressed instructions.
+fn decode_program(bytes: &[u8], raw_words: bool) -> Result<Vec<Instruction>, String> {
+    let all = instructions::sets::all();
+    if raw_words {
+        parse_words(bytes, &all)
+    } else {
+        parse_instructions(&bytes.to_vec(), &all)
+    }
+}
+
 impl HasTargetBytes for ProgramInput {
     fn target_bytes(&self) -> OwnedSlice<u8> {
-        let bytes = assemble_instructions(&self.insts);
-        debug_assert!(parse_instructions(&bytes.to_vec(), &instructions::riscv::all()).is_ok());
+        let bytes = self.encode_program();
+        debug_assert!(decode_program(&bytes, self.has_raw_words()).is_ok());
         OwnedSlice::<u8>::from(bytes.to_vec())
     }
 }
@@ -63,9 +114,15 @@ impl<'de> Visitor<'de> for ProgramInputVisitor {
     where
         E: serde::de::Error,
     {
-        Ok(ProgramInput {
-            insts: parse_instructions(&v.to_vec(), &instructions::riscv::all()).unwrap(),
-        })
+        let (flags, code) = v
+            .split_first()
+            .ok_or_else(|| E::custom("Missing the program flags"))?;
+        if flags & !RAW_WORDS_FLAG != 0 {
+            return Err(E::custom(format!("Unknown program flags {:#x}", flags)));
+        }
+        let insts = decode_program(code, flags & RAW_WORDS_FLAG != 0)
+            .map_err(|e| E::custom(format!("Failed to decode instructions: {}", e)))?;
+        ProgramInput::try_new(insts).map_err(|e| E::custom(format!("Invalid instructions: {}", e)))
     }
 }
 
@@ -73,9 +130,26 @@ impl Input for ProgramInput {
     /// Generate a name for this input
     #[must_use]
     fn generate_name(&self, _idx: usize) -> String {
-        let mut hasher = RandomState::with_seeds(0, 0, 0, 0).build_hasher();
-        hasher.write(assemble_instructions(&self.insts).as_slice());
-        format!("size:{}-hash:{:016x}", self.insts().len(), hasher.finish())
+        format!(
+            "size:{}-hash:{:016x}",
+            self.insts().len(),
+            self.content_hash()
+        )
+    }
+
+    /// Corpus files use the versioned format of [`ProgramInput::to_bytes`].
+    fn to_file<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(fs::write(path, self.to_bytes()?)?)
+    }
+
+    fn from_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Self::from_bytes(&fs::read(path)?)
     }
 }
 
@@ -91,15 +165,36 @@ impl HasProgramInput for ProgramInput {
     }
 
     fn insts_mut(&mut self) -> &mut Vec<Instruction> {
+        self.encoded.take();
         &mut self.insts
     }
 }
 
+/// Checks that all arguments of the instructions fit into their fields (see
+/// [`Instruction::check_fields`]), so the encoding is the program.
+pub fn check_insts(insts: &[Instruction]) -> Result<(), Error> {
+    for (idx, inst) in insts.iter().enumerate() {
+        inst.check_fields()
+            .map_err(|err| Error::illegal_argument(format!("Instruction {}: {}", idx, err)))?;
+    }
+    Ok(())
+}
+
 impl ProgramInput {
     /// Creates a new codes input using the given terminals
     #[must_use]
     pub fn new(insts: Vec<Instruction>) -> Self {
-        Self { insts }
+        Self {
+            insts,
+            encoded: RefCell::new(None),
+        }
+    }
+
+    /// Like [`Self::new`], but fails if an argument doesn't fit into its
+    /// field (see [`check_insts`]).
+    pub fn try_new(insts: Vec<Instruction>) -> Result<Self, Error> {
+        check_insts(&insts)?;
+        Ok(Self::new(insts))
     }
 
     pub fn insts(&self) -> &[Instruction] {
@@ -107,13 +202,85 @@ impl ProgramInput {
     }
 
     pub fn insts_mut(&mut self) -> &mut Vec<Instruction> {
+        self.encoded.take();
         &mut self.insts
     }
 
+    /// Returns true if the program has words no template decodes (see
+    /// [`Instruction::is_raw_word`]).
+    pub fn has_raw_words(&self) -> bool {
+        self.insts.iter().any(Instruction::is_raw_word)
+    }
+
+    /// Returns the encoded machine code of this program.
+    /// The encoding is cached until the instructions are modified.
+    pub fn encode_program(&self) -> Vec<u8> {
+        self.encoded
+            .borrow_mut()
+            .get_or_insert_with(|| assemble_instructions(&self.insts))
+            .clone()
+    }
+
+    /// The program as an assembly listing, one instruction per line (see
+    /// [`crate::disasm::inst_to_asm`]).
+    pub fn to_asm(&self) -> String {
+        insts_to_asm(&self.insts)
+    }
+
+    /// A stable hash of the encoded instructions.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = RandomState::with_seeds(0, 0, 0, 0).build_hasher();
+        hasher.write(self.encode_program().as_slice());
+        hasher.finish()
+    }
+
+    /// The serialized form used for corpus files on disk, in the current
+    /// [`FORMAT_VERSION`].
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = FORMAT_MAGIC.to_vec();
+        bytes.push(FORMAT_VERSION);
+        bytes.extend(postcard::to_allocvec(self)?);
+        Ok(bytes)
+    }
+
+    /// Deserializes an input previously serialized with [`Self::to_bytes`] by
+    /// this or an older version of the fuzzer.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        match bytes.strip_prefix(&FORMAT_MAGIC) {
+            Some([version, payload @ ..]) => Self::from_versioned_bytes(*version, payload),
+            Some([]) => Err(Error::serialize("Missing the input format version")),
+            None => Self::from_versioned_bytes(1, bytes),
+        }
+    }
+
+    /// Reads the payload of the given format version. New versions that
+    /// change the payload must keep a case to migrate the older ones.
+    fn from_versioned_bytes(version: u8, payload: &[u8]) -> Result<Self, Error> {
+        match version {
+            // Version 2 only added the header, version 3 the program flags.
+            1 | 2 => {
+                let code: &[u8] = postcard::from_bytes(payload)?;
+                let insts = decode_program(code, false).map_err(|e| {
+                    Error::serialize(format!("Failed to decode instructions: {}", e))
+                })?;
+                Self::try_new(insts)
+            }
+            3 => Ok(postcard::from_bytes(payload)?),
+            _ if version > FORMAT_VERSION => Err(Error::serialize(format!(
+                "Input format version {} is newer than the supported version {}",
+                version, FORMAT_VERSION
+            ))),
+            _ => Err(Error::serialize(format!(
+                "Unknown input format version {}",
+                version
+            ))),
+        }
+    }
+
     /// Create a bytes representation of this input
     pub fn unparse(&self, bytes: &mut Vec<u8>) {
         bytes.clear();
-        bytes.extend_from_slice(assemble_instructions(&self.insts).as_slice());
+        bytes.extend_from_slice(self.encode_program().as_slice());
     }
 
     /// Crop the value to the given length
@@ -121,9 +288,184 @@ impl ProgramInput {
         if from < to && to <= self.insts.len() {
             let mut insts = vec![];
             insts.clone_from_slice(&self.insts[from..to]);
-            Ok(Self { insts })
+            Ok(Self::new(insts))
         } else {
             Err(Error::illegal_argument("Invalid from or to argument"))
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use libafl::prelude::{AsSlice, HasTargetBytes, Rand, Xoshiro256StarRand};
+
+    use crate::assembler::assemble_instructions;
+    use crate::generator::InstGenerator;
+    use crate::instructions::{
+        self, raw,
+        riscv::{args, rv_i::ADDI},
+        test_insts::inst_with,
+    };
+
+    use super::{check_insts, ProgramInput, FORMAT_VERSION};
+
+    fn random_program(seed: u64, len: u32) -> ProgramInput {
+        let mut rng = Xoshiro256StarRand::default();
+        rng.set_seed(seed);
+        ProgramInput::try_new(InstGenerator::new().generate_instructions(
+            &mut rng,
+            &instructions::sets::riscv_g(),
+            len,
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn encoding_cache_invalidated_on_mutation() {
+        let mut program = random_program(0, 20);
+        assert_eq!(
+            program.encode_program(),
+            assemble_instructions(&program.insts)
+        );
+
+        // Change the program after the encoding has been cached.
+        program.insts_mut().pop();
+        assert_eq!(program.encode_program().len(), 19 * 4);
+        assert_eq!(
+            program.encode_program(),
+            assemble_instructions(&program.insts)
+        );
+    }
+
+    #[test]
+    fn overflowing_arguments_are_rejected() {
+        let addi = |rd| {
+            inst_with(
+                &ADDI,
+                &[(&args::RD, rd), (&args::RS1, 2), (&args::IMM12, 3)],
+            )
+        };
+        assert!(ProgramInput::try_new(vec![addi(1), addi(31)]).is_ok());
+        // x32 would set the lowest bit of funct3 and encode `slli`.
+        let err = ProgramInput::try_new(vec![addi(1), addi(32)]).unwrap_err();
+        assert!(
+            err.to_string().contains("Instruction 1: addi: rd = 0x20"),
+            "{}",
+            err
+        );
+        assert!(check_insts(&[]).is_ok());
+    }
+
+    #[test]
+    fn serialized_round_trip() {
+        let program = random_program(1, 20);
+        let bytes = program.to_bytes().unwrap();
+        assert_eq!(ProgramInput::from_bytes(&bytes).unwrap(), program);
+        // Undecodable instructions (here 3 bytes) are an error and not a panic.
+        assert!(ProgramInput::from_bytes(&[3, 1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn assembly_listing() {
+        let program = random_program(2, 20);
+        let asm = program.to_asm();
+        assert_eq!(asm.lines().count(), program.insts().len());
+        for (line, inst) in asm.lines().zip(program.insts()) {
+            assert!(line.starts_with(inst.template().name()), "{}", line);
+        }
+    }
+
+    #[test]
+    fn read_older_format_versions() {
+        let addi = inst_with(&ADDI, &[(&args::RD, 1), (&args::RS1, 2), (&args::IMM12, 3)]);
+        // `addi x1, x2, 3` as written by version 1: the length of the
+        // machine code and the code itself, without a header.
+        let v1 = [4, 0x93, 0x00, 0x31, 0x00];
+        let program = ProgramInput::from_bytes(&v1).unwrap();
+        assert_eq!(program.insts(), &[addi]);
+
+        // Version 2 added the header.
+        let v2 = [&[0xff, b'P', b'T', 2][..], &v1[..]].concat();
+        assert_eq!(ProgramInput::from_bytes(&v2).unwrap(), program);
+
+        // Version 3 the flags before the machine code.
+        let v3 = program.to_bytes().unwrap();
+        assert_eq!(v3[3], FORMAT_VERSION);
+        assert_eq!(&v3[4..], &[5, 0, 0x93, 0x00, 0x31, 0x00]);
+        assert_eq!(ProgramInput::from_bytes(&v3).unwrap(), program);
+
+        // Inputs from a newer fuzzer are rejected.
+        let mut future = v3.clone();
+        future[3] = FORMAT_VERSION + 1;
+        let err = ProgramInput::from_bytes(&future).unwrap_err();
+        assert!(format!("{:?}", err).contains("newer"), "{:?}", err);
+        assert!(ProgramInput::from_bytes(&v3[..3]).is_err());
+        let mut unknown_flags = v3.clone();
+        unknown_flags[5] = 0x80;
+        assert!(ProgramInput::from_bytes(&unknown_flags).is_err());
+    }
+
+    #[test]
+    fn raw_words_only_decode_if_flagged() {
+        // No template decodes an all-ones word.
+        let word = inst_with(&raw::UNKNOWN, &[(&raw::WORD, 0xffff_ffff)]);
+        let program = ProgramInput::new(vec![word]);
+        assert!(program.has_raw_words());
+        let bytes = program.to_bytes().unwrap();
+        assert_eq!(ProgramInput::from_bytes(&bytes).unwrap(), program);
+
+        // Without the flag, the word is no instruction.
+        let mut unflagged = bytes.clone();
+        unflagged[5] = 0;
+        assert!(ProgramInput::from_bytes(&unflagged).is_err());
+        assert!(ProgramInput::from_bytes(&[&[4][..], &[0xff; 4][..]].concat()).is_err());
+    }
+
+    #[test]
+    fn raw_words_of_compressed_halves_round_trip() {
+        // Both halves of the word are a `c.nop`.
+        let word = inst_with(&raw::UNKNOWN, &[(&raw::WORD, 0x0001_0001)]);
+        let addi = inst_with(&ADDI, &[(&args::RD, 1), (&args::IMM12, 3)]);
+        let program = ProgramInput::new(vec![addi, word]);
+        let bytes = program.to_bytes().unwrap();
+        assert_eq!(ProgramInput::from_bytes(&bytes).unwrap(), program);
+    }
+
+    #[test]
+    fn target_bytes_are_encoded_program() {
+        // The forkserver writes the target bytes to the input file for '@@',
+        // so they must be the raw instructions and not the corpus format.
+        let mut program = random_program(2, 10);
+        let bytes = program.target_bytes().as_slice().to_vec();
+        assert_eq!(bytes, assemble_instructions(&program.insts));
+        assert_ne!(bytes, program.to_bytes().unwrap());
+
+        // Changes are visible in the next execution's bytes.
+        program.insts_mut().truncate(3);
+        assert_eq!(program.target_bytes().as_slice().len(), 3 * 4);
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_encoding_cache() {
+        let program = random_program(0, 200);
+        let iterations = 10000;
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            assemble_instructions(&program.insts);
+        }
+        let uncached = start.elapsed();
+
+        let start = Instant::now();
+        for _ in 0..iterations {
+            program.encode_program();
+        }
+        let cached = start.elapsed();
+
+        println!("uncached: {:?}, cached: {:?}", uncached, cached);
+        assert!(cached < uncached);
+    }
+}
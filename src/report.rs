@@ -0,0 +1,249 @@
+//! A machine-readable summary of the objectives of a campaign for triage
+//! dashboards, written when the fuzzer shuts down.
+//!
+//! Objectives are grouped by their origin label and signature. The signature
+//! is the [`crash_signature`] of the covered map entries if the crash has a
+//! bundle with coverage (see [`crate::bundle::CrashBundleConfig`]), otherwise
+//! the triage bucket `<trigger>:<last instruction>`.
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{feedbacks::crash_signature, program_input::ProgramInput, triage::CrashTriageMetadata};
+
+/// Changes whenever the layout of the report changes.
+pub const FINDINGS_REPORT_VERSION: u32 = 1;
+
+/// The parts of an objective file name, see
+/// [`crate::feedbacks::ObjectiveNameFeedback`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectiveName {
+    pub label: String,
+    pub unix_time: u64,
+    pub core: usize,
+    pub hash: String,
+}
+
+impl ObjectiveName {
+    /// Parses `<label>_<unixtime>_core<id>_<hash>`.
+    pub fn parse(name: &str) -> Option<Self> {
+        let mut parts = name.rsplitn(4, '_');
+        let hash = parts.next()?;
+        let core = parts.next()?.strip_prefix("core")?.parse().ok()?;
+        let unix_time = parts.next()?.parse().ok()?;
+        let label = parts.next()?;
+        Some(Self {
+            label: label.to_owned(),
+            unix_time,
+            core,
+            hash: hash.to_owned(),
+        })
+    }
+}
+
+/// All objectives with the same origin and signature.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// `<label>/<trigger>`, e.g. `crash/mem`.
+    pub category: String,
+    pub signature: String,
+    /// The first objective that was saved with this signature.
+    pub representative: PathBuf,
+    /// Unix time of the representative.
+    pub first_seen: u64,
+    pub count: usize,
+}
+
+/// The contents of the report file.
+#[derive(Serialize, Debug)]
+pub struct FindingsReport {
+    pub version: u32,
+    pub findings: Vec<Finding>,
+}
+
+impl FindingsReport {
+    pub fn new(findings: Vec<Finding>) -> Self {
+        Self {
+            version: FINDINGS_REPORT_VERSION,
+            findings,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed to serialize findings report")
+    }
+}
+
+/// The saved objectives in `dir` and in its per-client subdirectories.
+/// Hidden files (e.g. the corpus metadata) are skipped.
+fn objective_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    let mut result = vec![];
+    for entry in entries {
+        let entry = entry.map_err(|e| e.to_string())?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if path.is_dir() {
+            result.extend(objective_files(&path)?);
+        } else {
+            result.push(path);
+        }
+    }
+    Ok(result)
+}
+
+/// The signature of the crash from the coverage in its bundle, if any.
+fn bundle_signature(bundle_dir: &Path, name: &str) -> Option<String> {
+    let coverage = fs::read_to_string(bundle_dir.join(name).join("coverage")).ok()?;
+    let covered = coverage
+        .lines()
+        .filter_map(|line| line.split_whitespace().next()?.parse::<usize>().ok());
+    Some(format!("{:016x}", crash_signature(covered)))
+}
+
+/// Groups the objectives in `objective_dir` into findings, ordered by the
+/// time they were first seen. Files that aren't named like objectives or
+/// can't be read as inputs are skipped.
+pub fn collect_findings(objective_dir: &Path, bundle_dir: &Path) -> Result<Vec<Finding>, String> {
+    let mut objectives = vec![];
+    for path in objective_files(objective_dir)? {
+        let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let name = match ObjectiveName::parse(&file_name) {
+            Some(name) => name,
+            None => continue,
+        };
+        let input = match fs::read(&path).map(|bytes| ProgramInput::from_bytes(&bytes)) {
+            Ok(Ok(input)) => input,
+            _ => {
+                log::warn!("Skipping unreadable objective {}", path.display());
+                continue;
+            }
+        };
+        objectives.push((name, file_name, path, input));
+    }
+    // Oldest first so the first objective of every group represents it.
+    objectives.sort_by(|a, b| a.0.unix_time.cmp(&b.0.unix_time).then(a.1.cmp(&b.1)));
+
+    let mut groups = BTreeMap::<(String, String), Finding>::new();
+    for (name, file_name, path, input) in objectives {
+        let triage = CrashTriageMetadata::classify(input.insts());
+        let signature = bundle_signature(bundle_dir, &file_name).unwrap_or_else(|| {
+            format!(
+                "{}:{}",
+                triage.family.name(),
+                triage.last_inst.as_deref().unwrap_or("none")
+            )
+        });
+        groups
+            .entry((name.label.clone(), signature.clone()))
+            .and_modify(|finding| finding.count += 1)
+            .or_insert(Finding {
+                category: format!("{}/{}", name.label, triage.family.name()),
+                signature,
+                representative: path,
+                first_seen: name.unix_time,
+                count: 1,
+            });
+    }
+
+    let mut findings: Vec<Finding> = groups.into_values().collect();
+    findings.sort_by(|a, b| {
+        a.first_seen
+            .cmp(&b.first_seen)
+            .then(a.representative.cmp(&b.representative))
+    });
+    Ok(findings)
+}
+
+/// Collects the findings and writes the report to `path`.
+pub fn write_findings_report(
+    path: &Path,
+    objective_dir: &Path,
+    bundle_dir: &Path,
+) -> Result<usize, String> {
+    let findings = collect_findings(objective_dir, bundle_dir)?;
+    let count = findings.len();
+    fs::write(path, FindingsReport::new(findings).to_json())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{collect_findings, FindingsReport, ObjectiveName};
+    use crate::{instructions::test_insts::named, program_input::ProgramInput};
+
+    #[test]
+    fn objective_names() {
+        let name = ObjectiveName::parse("simcrash_1700000000_core3_00000000deadbeef").unwrap();
+        assert_eq!(name.label, "simcrash");
+        assert_eq!(name.unix_time, 1700000000);
+        assert_eq!(name.core, 3);
+        assert_eq!(name.hash, "00000000deadbeef");
+        assert!(ObjectiveName::parse("id:000001").is_none());
+        assert!(ObjectiveName::parse("crash_now_core0_1").is_none());
+    }
+
+    #[test]
+    fn findings_of_objectives() {
+        let dir = std::env::temp_dir().join(format!("findings-test-{}", std::process::id()));
+        let core_dir = dir.join("found").join("0");
+        let bundle_dir = dir.join("bundles");
+        fs::create_dir_all(&core_dir).unwrap();
+        fs::create_dir_all(&bundle_dir).unwrap();
+
+        let store = ProgramInput::new(vec![named("add"), named("sw")]);
+        let ecall = ProgramInput::new(vec![named("ecall")]);
+        let write = |name: &str, input: &ProgramInput| {
+            fs::write(core_dir.join(name), input.to_bytes().unwrap()).unwrap();
+        };
+        write("crash_200_core0_0000000000000002", &store);
+        write("crash_100_core0_0000000000000001", &store);
+        write("simcrash_150_core0_0000000000000003", &store);
+        write("crash_300_core0_0000000000000004", &ecall);
+        fs::write(
+            core_dir.join(".crash_100_core0_0000000000000001.metadata"),
+            "{}",
+        )
+        .unwrap();
+
+        let findings = collect_findings(&dir.join("found"), &bundle_dir).unwrap();
+        assert_eq!(findings.len(), 3);
+        assert_eq!(findings[0].category, "crash/mem");
+        assert_eq!(findings[0].signature, "mem:sw");
+        assert_eq!(findings[0].count, 2);
+        assert_eq!(findings[0].first_seen, 100);
+        assert!(findings[0]
+            .representative
+            .ends_with("crash_100_core0_0000000000000001"));
+        // The same program with a different origin is a separate finding.
+        assert_eq!(findings[1].category, "simcrash/mem");
+        assert_eq!(findings[2].signature, "system:ecall");
+
+        // Bundled coverage takes precedence over the triage bucket.
+        let bundle = bundle_dir.join("crash_200_core0_0000000000000002");
+        fs::create_dir_all(&bundle).unwrap();
+        fs::write(bundle.join("coverage"), "5 1\n9 3\n").unwrap();
+        let findings = collect_findings(&dir.join("found"), &bundle_dir).unwrap();
+        assert_eq!(findings.len(), 4);
+        assert!(findings
+            .iter()
+            .any(|f| f.signature.len() == 16 && f.first_seen == 200));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&FindingsReport::new(findings).to_json()).unwrap();
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["findings"][0]["count"], 1);
+
+        fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,605 @@
+use core::marker::PhantomData;
+
+use libafl::{
+    bolts::tuples::{MatchName, Named},
+    corpus::{Corpus, CorpusId, Testcase},
+    feedbacks::MapIndexesMetadata,
+    inputs::UsesInput,
+    observers::{MapObserver, ObserversTuple},
+    prelude::Rand,
+    random_corpus_id,
+    schedulers::Scheduler,
+    state::{HasCorpus, HasExecutions, HasMetadata, HasRand, UsesState},
+    Error,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    classes::{program_class, ProgramClassMetadata},
+    program_input::ProgramInput,
+};
+
+/// Resolution of the epsilon when comparing it against random numbers.
+const EPSILON_RESOLUTION: u64 = 1_000_000;
+
+/// Returns true if a random pick should be made for the given epsilon.
+pub fn should_pick_random<R: Rand>(rand: &mut R, epsilon: f64) -> bool {
+    let threshold = (epsilon * EPSILON_RESOLUTION as f64) as u64;
+    rand.below(EPSILON_RESOLUTION) < threshold
+}
+
+/// A [`Scheduler`] that usually defers to the wrapped scheduler, but with a
+/// chance of `epsilon` picks a uniformly random corpus entry instead.
+///
+/// This helps escaping local optima where the weighted scheduler keeps
+/// focusing on a few (fast) inputs.
+#[derive(Debug, Clone)]
+pub struct EpsilonScheduler<CS, S> {
+    base: CS,
+    epsilon: f64,
+    phantom: PhantomData<S>,
+}
+
+impl<CS, S> EpsilonScheduler<CS, S>
+where
+    CS: Scheduler<State = S>,
+    S: HasCorpus + HasRand,
+{
+    /// Creates a new scheduler. `epsilon` must be between 0 and 1.
+    pub fn new(base: CS, epsilon: f64) -> Self {
+        debug_assert!((0.0..=1.0).contains(&epsilon));
+        Self {
+            base,
+            epsilon,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<CS, S> UsesState for EpsilonScheduler<CS, S>
+where
+    S: UsesInput,
+{
+    type State = S;
+}
+
+impl<CS, S> Scheduler for EpsilonScheduler<CS, S>
+where
+    CS: Scheduler<State = S>,
+    S: HasCorpus + HasRand,
+{
+    fn on_add(&mut self, state: &mut S, idx: CorpusId) -> Result<(), Error> {
+        self.base.on_add(state, idx)
+    }
+
+    fn on_replace(
+        &mut self,
+        state: &mut S,
+        idx: CorpusId,
+        prev: &Testcase<<S as UsesInput>::Input>,
+    ) -> Result<(), Error> {
+        self.base.on_replace(state, idx, prev)
+    }
+
+    fn on_remove(
+        &mut self,
+        state: &mut S,
+        idx: CorpusId,
+        testcase: &Option<Testcase<<S as UsesInput>::Input>>,
+    ) -> Result<(), Error> {
+        self.base.on_remove(state, idx, testcase)
+    }
+
+    fn on_evaluation<OT>(
+        &mut self,
+        state: &mut S,
+        input: &<S as UsesInput>::Input,
+        observers: &OT,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        self.base.on_evaluation(state, input, observers)
+    }
+
+    fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        if state.corpus().count() == 0 || !should_pick_random(state.rand_mut(), self.epsilon) {
+            return self.base.next(state);
+        }
+        let id = random_corpus_id!(state.corpus(), state.rand_mut());
+        *state.corpus_mut().current_mut() = Some(id);
+        Ok(id)
+    }
+}
+
+/// A [`Scheduler`] that goes round-robin through the corpus for the first
+/// `warmup_execs` executions and defers to the wrapped scheduler afterwards.
+///
+/// The weighted scheduler makes poor choices while it has little data, which
+/// hurts most on slow targets. The wrapped scheduler still sees all corpus
+/// changes during the warmup.
+#[derive(Debug, Clone)]
+pub struct WarmupScheduler<CS, S> {
+    base: CS,
+    warmup_execs: usize,
+    warmed_up: bool,
+    phantom: PhantomData<S>,
+}
+
+impl<CS, S> WarmupScheduler<CS, S>
+where
+    CS: Scheduler<State = S>,
+    S: HasCorpus + HasExecutions,
+{
+    pub fn new(base: CS, warmup_execs: usize) -> Self {
+        Self {
+            base,
+            warmup_execs,
+            warmed_up: warmup_execs == 0,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns true once the warmup is over.
+    fn check_warmed_up(&mut self, executions: usize) -> bool {
+        if !self.warmed_up && executions >= self.warmup_execs {
+            log::info!("Warmup done after {} executions", executions);
+            self.warmed_up = true;
+        }
+        self.warmed_up
+    }
+}
+
+impl<CS, S> UsesState for WarmupScheduler<CS, S>
+where
+    S: UsesInput,
+{
+    type State = S;
+}
+
+impl<CS, S> Scheduler for WarmupScheduler<CS, S>
+where
+    CS: Scheduler<State = S>,
+    S: HasCorpus + HasExecutions,
+{
+    fn on_add(&mut self, state: &mut S, idx: CorpusId) -> Result<(), Error> {
+        self.base.on_add(state, idx)
+    }
+
+    fn on_replace(
+        &mut self,
+        state: &mut S,
+        idx: CorpusId,
+        prev: &Testcase<<S as UsesInput>::Input>,
+    ) -> Result<(), Error> {
+        self.base.on_replace(state, idx, prev)
+    }
+
+    fn on_remove(
+        &mut self,
+        state: &mut S,
+        idx: CorpusId,
+        testcase: &Option<Testcase<<S as UsesInput>::Input>>,
+    ) -> Result<(), Error> {
+        self.base.on_remove(state, idx, testcase)
+    }
+
+    fn on_evaluation<OT>(
+        &mut self,
+        state: &mut S,
+        input: &<S as UsesInput>::Input,
+        observers: &OT,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        self.base.on_evaluation(state, input, observers)
+    }
+
+    fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        if state.corpus().count() == 0 || self.check_warmed_up(*state.executions()) {
+            return self.base.next(state);
+        }
+        let corpus = state.corpus();
+        let id = corpus
+            .current()
+            .and_then(|cur| corpus.next(cur))
+            .or_else(|| corpus.first())
+            .unwrap();
+        *state.corpus_mut().current_mut() = Some(id);
+        Ok(id)
+    }
+}
+
+/// Upper bound for the entries of the edge frequency table. Larger maps share
+/// entries, which keeps the table at 16 MiB at most.
+pub const MAX_TRACKED_EDGES: usize = 1 << 22;
+
+/// How many random corpus entries compete for being the rarest.
+const RARITY_CANDIDATES: usize = 8;
+
+libafl::impl_serdeany!(EdgeFrequencyMetadata);
+/// How often each map entry was hit over all executions.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct EdgeFrequencyMetadata {
+    counts: Vec<u32>,
+}
+
+impl EdgeFrequencyMetadata {
+    fn slot(idx: usize) -> usize {
+        idx % MAX_TRACKED_EDGES
+    }
+
+    /// Records one execution that covered the given map entries.
+    pub fn record<I: IntoIterator<Item = usize>>(&mut self, indexes: I) {
+        for idx in indexes {
+            let slot = Self::slot(idx);
+            if slot >= self.counts.len() {
+                self.counts.resize(slot + 1, 0);
+            }
+            self.counts[slot] = self.counts[slot].saturating_add(1);
+        }
+    }
+
+    /// How often the given entry was hit.
+    pub fn frequency(&self, idx: usize) -> u32 {
+        self.counts.get(Self::slot(idx)).copied().unwrap_or(0)
+    }
+
+    /// The frequency of the rarest of the given entries.
+    /// Lower values mean the entries cover rarer edges.
+    pub fn rarity(&self, indexes: &[usize]) -> u32 {
+        indexes
+            .iter()
+            .map(|idx| self.frequency(*idx))
+            .min()
+            .unwrap_or(u32::MAX)
+    }
+}
+
+/// A [`Scheduler`] that with a chance of `rarity_chance` prefers corpus
+/// entries covering edges that are rarely hit by any execution. Otherwise
+/// it defers to the wrapped scheduler.
+///
+/// The global hit frequency of each edge is kept in an
+/// [`EdgeFrequencyMetadata`] in the state. To keep picks cheap on large
+/// corpora, only a few random entries are compared on each pick. Requires
+/// the map feedback to track indexes (`MapIndexesMetadata`).
+#[derive(Debug, Clone)]
+pub struct RarityScheduler<CS, O, S> {
+    base: CS,
+    observer_name: String,
+    rarity_chance: f64,
+    phantom: PhantomData<(O, S)>,
+}
+
+impl<CS, O, S> RarityScheduler<CS, O, S>
+where
+    CS: Scheduler<State = S>,
+    O: MapObserver,
+    S: HasCorpus + HasRand + HasMetadata,
+{
+    /// Creates a new scheduler. `rarity_chance` must be between 0 and 1.
+    pub fn new(base: CS, map_observer: &O, rarity_chance: f64) -> Self {
+        debug_assert!((0.0..=1.0).contains(&rarity_chance));
+        Self {
+            base,
+            observer_name: map_observer.name().to_string(),
+            rarity_chance,
+            phantom: PhantomData,
+        }
+    }
+
+    fn frequencies_mut(state: &mut S) -> &mut EdgeFrequencyMetadata {
+        if !state.has_metadata::<EdgeFrequencyMetadata>() {
+            state.add_metadata(EdgeFrequencyMetadata::default());
+        }
+        state
+            .metadata_map_mut()
+            .get_mut::<EdgeFrequencyMetadata>()
+            .unwrap()
+    }
+
+    /// Picks the entry covering the rarest edge out of a few random ones.
+    fn pick_rare(&self, state: &mut S) -> Result<CorpusId, Error> {
+        let mut best: Option<(CorpusId, u32)> = None;
+        for _ in 0..RARITY_CANDIDATES {
+            let id = random_corpus_id!(state.corpus(), state.rand_mut());
+            let rarity = {
+                let testcase = state.corpus().get(id)?.borrow();
+                let indexes = testcase.metadata_map().get::<MapIndexesMetadata>();
+                match (indexes, state.metadata_map().get::<EdgeFrequencyMetadata>()) {
+                    (Some(indexes), Some(freqs)) => freqs.rarity(&indexes.list),
+                    _ => u32::MAX,
+                }
+            };
+            if best.map_or(true, |(_, best_rarity)| rarity < best_rarity) {
+                best = Some((id, rarity));
+            }
+        }
+        Ok(best.unwrap().0)
+    }
+}
+
+impl<CS, O, S> UsesState for RarityScheduler<CS, O, S>
+where
+    S: UsesInput,
+{
+    type State = S;
+}
+
+impl<CS, O, S> Scheduler for RarityScheduler<CS, O, S>
+where
+    CS: Scheduler<State = S>,
+    O: MapObserver,
+    S: HasCorpus + HasRand + HasMetadata,
+{
+    fn on_add(&mut self, state: &mut S, idx: CorpusId) -> Result<(), Error> {
+        // New entries are not passed to on_evaluation, so count them here.
+        if self.rarity_chance == 0.0 {
+            return self.base.on_add(state, idx);
+        }
+        let indexes = state
+            .corpus()
+            .get(idx)?
+            .borrow()
+            .metadata_map()
+            .get::<MapIndexesMetadata>()
+            .map(|meta| meta.list.clone());
+        if let Some(indexes) = indexes {
+            Self::frequencies_mut(state).record(indexes);
+        }
+        self.base.on_add(state, idx)
+    }
+
+    fn on_replace(
+        &mut self,
+        state: &mut S,
+        idx: CorpusId,
+        prev: &Testcase<<S as UsesInput>::Input>,
+    ) -> Result<(), Error> {
+        self.base.on_replace(state, idx, prev)
+    }
+
+    fn on_remove(
+        &mut self,
+        state: &mut S,
+        idx: CorpusId,
+        testcase: &Option<Testcase<<S as UsesInput>::Input>>,
+    ) -> Result<(), Error> {
+        self.base.on_remove(state, idx, testcase)
+    }
+
+    fn on_evaluation<OT>(
+        &mut self,
+        state: &mut S,
+        input: &<S as UsesInput>::Input,
+        observers: &OT,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        if self.rarity_chance > 0.0 {
+            let map = observers
+                .match_name::<O>(&self.observer_name)
+                .ok_or_else(|| Error::key_not_found("MapObserver not found".to_string()))?;
+            let initial = map.initial();
+            let covered = (0..map.usable_count()).filter(|idx| *map.get(*idx) != initial);
+            Self::frequencies_mut(state).record(covered);
+        }
+        self.base.on_evaluation(state, input, observers)
+    }
+
+    fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        if state.corpus().count() == 0 || !should_pick_random(state.rand_mut(), self.rarity_chance)
+        {
+            return self.base.next(state);
+        }
+        let id = self.pick_rare(state)?;
+        *state.corpus_mut().current_mut() = Some(id);
+        Ok(id)
+    }
+}
+
+/// A [`Scheduler`] that with a chance of `class_chance` prefers corpus
+/// entries whose [`program_class`] has few members. Otherwise it defers to
+/// the wrapped scheduler.
+///
+/// This favors structurally different programs independently of the edges
+/// they cover. The class sizes are kept in a [`ProgramClassMetadata`] in the
+/// state. Like the [`RarityScheduler`], only a few random entries compete on
+/// each pick.
+#[derive(Debug, Clone)]
+pub struct ClassScheduler<CS, S> {
+    base: CS,
+    class_chance: f64,
+    phantom: PhantomData<S>,
+}
+
+impl<CS, S> ClassScheduler<CS, S>
+where
+    CS: Scheduler<State = S>,
+    S: HasCorpus + HasRand + HasMetadata + UsesInput<Input = ProgramInput>,
+{
+    /// Creates a new scheduler. `class_chance` must be between 0 and 1.
+    pub fn new(base: CS, class_chance: f64) -> Self {
+        debug_assert!((0.0..=1.0).contains(&class_chance));
+        Self {
+            base,
+            class_chance,
+            phantom: PhantomData,
+        }
+    }
+
+    fn classes_mut(state: &mut S) -> &mut ProgramClassMetadata {
+        if !state.has_metadata::<ProgramClassMetadata>() {
+            state.add_metadata(ProgramClassMetadata::default());
+        }
+        state
+            .metadata_map_mut()
+            .get_mut::<ProgramClassMetadata>()
+            .unwrap()
+    }
+
+    fn class_of(testcase: &Testcase<ProgramInput>) -> Option<String> {
+        testcase
+            .input()
+            .as_ref()
+            .map(|input| program_class(input.insts()))
+    }
+
+    /// Picks the entry of the smallest class out of a few random ones.
+    fn pick_rare_class(&self, state: &mut S) -> Result<CorpusId, Error> {
+        let mut best: Option<(CorpusId, u64)> = None;
+        for _ in 0..RARITY_CANDIDATES {
+            let id = random_corpus_id!(state.corpus(), state.rand_mut());
+            let size = {
+                let class = Self::class_of(&state.corpus().get(id)?.borrow());
+                match (class, state.metadata_map().get::<ProgramClassMetadata>()) {
+                    (Some(class), Some(classes)) => classes.count(&class),
+                    _ => u64::MAX,
+                }
+            };
+            if best.map_or(true, |(_, best_size)| size < best_size) {
+                best = Some((id, size));
+            }
+        }
+        Ok(best.unwrap().0)
+    }
+}
+
+impl<CS, S> UsesState for ClassScheduler<CS, S>
+where
+    S: UsesInput,
+{
+    type State = S;
+}
+
+impl<CS, S> Scheduler for ClassScheduler<CS, S>
+where
+    CS: Scheduler<State = S>,
+    S: HasCorpus + HasRand + HasMetadata + UsesInput<Input = ProgramInput>,
+{
+    fn on_add(&mut self, state: &mut S, idx: CorpusId) -> Result<(), Error> {
+        if let Some(class) = Self::class_of(&state.corpus().get(idx)?.borrow()) {
+            Self::classes_mut(state).add(class);
+        }
+        self.base.on_add(state, idx)
+    }
+
+    fn on_replace(
+        &mut self,
+        state: &mut S,
+        idx: CorpusId,
+        prev: &Testcase<<S as UsesInput>::Input>,
+    ) -> Result<(), Error> {
+        if let Some(class) = Self::class_of(prev) {
+            Self::classes_mut(state).remove(&class);
+        }
+        if let Some(class) = Self::class_of(&state.corpus().get(idx)?.borrow()) {
+            Self::classes_mut(state).add(class);
+        }
+        self.base.on_replace(state, idx, prev)
+    }
+
+    fn on_remove(
+        &mut self,
+        state: &mut S,
+        idx: CorpusId,
+        testcase: &Option<Testcase<<S as UsesInput>::Input>>,
+    ) -> Result<(), Error> {
+        if let Some(class) = testcase.as_ref().and_then(Self::class_of) {
+            Self::classes_mut(state).remove(&class);
+        }
+        self.base.on_remove(state, idx, testcase)
+    }
+
+    fn on_evaluation<OT>(
+        &mut self,
+        state: &mut S,
+        input: &<S as UsesInput>::Input,
+        observers: &OT,
+    ) -> Result<(), Error>
+    where
+        OT: ObserversTuple<S>,
+    {
+        self.base.on_evaluation(state, input, observers)
+    }
+
+    fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        if state.corpus().count() == 0 || !should_pick_random(state.rand_mut(), self.class_chance) {
+            return self.base.next(state);
+        }
+        let id = self.pick_rare_class(state)?;
+        *state.corpus_mut().current_mut() = Some(id);
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::{
+        prelude::{Rand, Xoshiro256StarRand},
+        schedulers::QueueScheduler,
+    };
+
+    use super::{should_pick_random, EdgeFrequencyMetadata, WarmupScheduler, MAX_TRACKED_EDGES};
+    use crate::driver::FuzzState;
+
+    #[test]
+    fn random_picks_match_epsilon() {
+        for epsilon in [0.0, 0.05, 0.3, 1.0] {
+            let mut rng = Xoshiro256StarRand::default();
+            rng.set_seed(1);
+
+            let tries = 100000;
+            let mut picks = 0;
+            for _ in 0..tries {
+                if should_pick_random(&mut rng, epsilon) {
+                    picks += 1;
+                }
+            }
+            let rate = picks as f64 / tries as f64;
+            assert!(
+                (rate - epsilon).abs() < 0.01,
+                "rate {} too far from epsilon {}",
+                rate,
+                epsilon
+            );
+        }
+    }
+
+    #[test]
+    fn warmup_ends_after_execs() {
+        let mut sched =
+            WarmupScheduler::<QueueScheduler<FuzzState>, FuzzState>::new(QueueScheduler::new(), 10);
+        assert!(!sched.check_warmed_up(0));
+        assert!(!sched.check_warmed_up(9));
+        assert!(sched.check_warmed_up(10));
+        // Stays warmed up, even if the executions were reset.
+        assert!(sched.check_warmed_up(0));
+
+        let mut no_warmup =
+            WarmupScheduler::<QueueScheduler<FuzzState>, FuzzState>::new(QueueScheduler::new(), 0);
+        assert!(no_warmup.check_warmed_up(0));
+    }
+
+    #[test]
+    fn edge_frequencies() {
+        let mut freqs = EdgeFrequencyMetadata::default();
+        freqs.record([1, 2, 3]);
+        freqs.record([1, 2]);
+        freqs.record([1]);
+        assert_eq!(freqs.frequency(1), 3);
+        assert_eq!(freqs.frequency(3), 1);
+        assert_eq!(freqs.frequency(100), 0);
+
+        // The entry covering edge 3 is the rarest one.
+        assert!(freqs.rarity(&[1, 3]) < freqs.rarity(&[1, 2]));
+        assert_eq!(freqs.rarity(&[]), u32::MAX);
+
+        // Huge maps are folded into the bounded table.
+        freqs.record([MAX_TRACKED_EDGES + 3]);
+        assert_eq!(freqs.frequency(3), 2);
+    }
+}
@@ -0,0 +1,262 @@
+//! Loading of the initial inputs, either in the structured [`ProgramInput`]
+//! format or as raw instruction bytes (e.g. an AFL corpus), or as 32 bit
+//! words from other tools.
+use std::{fs, path::Path};
+
+use serde::Serialize;
+
+use crate::{
+    instructions::{
+        riscv::rv_i::ADDI, sets, Argument, Instruction, InstructionCategory, InstructionTemplate,
+    },
+    parser::{parse_instructions, parse_words},
+    program_input::ProgramInput,
+};
+
+/// How a seed file was interpreted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeedFormat {
+    /// A serialized `ProgramInput`.
+    Structured,
+    /// Encoded RISC-V instructions.
+    Raw,
+    /// 32 bit words, see [`parse_raw_seed`].
+    Words,
+}
+
+/// How the files of the seed directory are read, see `--seed-format`.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SeedDirFormat {
+    /// Serialized inputs or encoded instructions, see [`parse_seed`].
+    #[default]
+    Native,
+    /// 32 bit little-endian words, see [`parse_raw_seed`].
+    Raw,
+}
+
+impl SeedDirFormat {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim() {
+            "native" => Ok(SeedDirFormat::Native),
+            "raw" => Ok(SeedDirFormat::Raw),
+            _ => Err(format!(
+                "Unknown seed format '{}'. Supported: native, raw",
+                s
+            )),
+        }
+    }
+}
+
+/// Interprets the contents of a seed file. Structured inputs take precedence
+/// over raw instructions if the bytes happen to be valid as both.
+pub fn parse_seed(bytes: &[u8]) -> Option<(ProgramInput, SeedFormat)> {
+    if let Ok(input) = ProgramInput::from_bytes(bytes) {
+        if !input.insts().is_empty() {
+            return Some((input, SeedFormat::Structured));
+        }
+    }
+    match parse_instructions(&bytes.to_vec(), &sets::all()) {
+        Ok(insts) if !insts.is_empty() => {
+            Some((ProgramInput::try_new(insts).ok()?, SeedFormat::Raw))
+        }
+        _ => None,
+    }
+}
+
+/// Interprets the contents of a seed file as 32 bit little-endian words.
+/// Unlike [`parse_seed`], words that aren't known instructions are kept as
+/// [`crate::instructions::raw::UNKNOWN`], so the seed runs exactly as given.
+pub fn parse_raw_seed(bytes: &[u8]) -> Option<(ProgramInput, SeedFormat)> {
+    match parse_words(bytes, &sets::all()) {
+        Ok(insts) if !insts.is_empty() => {
+            Some((ProgramInput::try_new(insts).ok()?, SeedFormat::Words))
+        }
+        _ => None,
+    }
+}
+
+/// Reads all seeds in `dir` (not recursive) in the given format. Hidden
+/// files are ignored, and files that can't be read or are not in the format
+/// are skipped with a warning.
+pub fn read_seed_dir(dir: &Path, format: SeedDirFormat) -> std::io::Result<Vec<ProgramInput>> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.path());
+    let mut seeds = vec![];
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_name().to_string_lossy().starts_with('.') || path.is_dir() {
+            continue;
+        }
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                log::warn!("Skipping seed {:?}: {}", path, err);
+                continue;
+            }
+        };
+        let parsed = match format {
+            SeedDirFormat::Native => parse_seed(&bytes),
+            SeedDirFormat::Raw => parse_raw_seed(&bytes),
+        };
+        match parsed {
+            Some((input, format)) => {
+                log::info!("Loaded seed {:?} as {:?}", path, format);
+                seeds.push(input);
+            }
+            None if format == SeedDirFormat::Raw => {
+                log::warn!("Skipping seed {:?}: not a whole number of words", path)
+            }
+            None => log::warn!(
+                "Skipping seed {:?}: neither a program nor instructions",
+                path
+            ),
+        }
+    }
+    Ok(seeds)
+}
+
+/// The seed used if the seed directory is empty: a single nop from the given
+/// instruction set, so it is legal for the configured core. That is
+/// `addi x0, x0, 0` if the set has it, otherwise another arithmetic
+/// instruction writing `x0`.
+pub fn fallback_seed(isa: &[&'static InstructionTemplate]) -> ProgramInput {
+    let template = isa
+        .iter()
+        .find(|template| ***template == ADDI)
+        .or_else(|| {
+            isa.iter()
+                .find(|template| template.category() == InstructionCategory::Arith)
+        })
+        .copied()
+        .expect("The instruction set has no arithmetic instructions");
+    let args = template
+        .operands()
+        .map(|spec| Argument::new(*spec, 0))
+        .collect();
+    ProgramInput::try_new(vec![Instruction::new(template, args)])
+        .expect("Zero doesn't fit into an argument")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{
+        fallback_seed, parse_raw_seed, parse_seed, read_seed_dir, SeedDirFormat, SeedFormat,
+    };
+    use crate::{
+        instructions::{
+            riscv::{args, rv_i::ADDI},
+            sets,
+            test_insts::inst_with,
+        },
+        program_input::ProgramInput,
+    };
+
+    #[test]
+    fn seed_formats() {
+        let input = ProgramInput::new(vec![inst_with(
+            &ADDI,
+            &[(&args::RD, 1), (&args::RS1, 2), (&args::IMM12, 3)],
+        )]);
+
+        let structured = input.to_bytes().unwrap();
+        assert_eq!(
+            parse_seed(&structured),
+            Some((input.clone(), SeedFormat::Structured))
+        );
+
+        let raw = input.encode_program();
+        assert_eq!(parse_seed(&raw), Some((input, SeedFormat::Raw)));
+
+        assert_eq!(parse_seed(&[1, 2, 3]), None);
+        assert_eq!(parse_seed(&[]), None);
+    }
+
+    #[test]
+    fn raw_word_seeds() {
+        assert_eq!(SeedDirFormat::parse("raw"), Ok(SeedDirFormat::Raw));
+        assert_eq!(SeedDirFormat::parse("native"), Ok(SeedDirFormat::Native));
+        assert!(SeedDirFormat::parse("hex").is_err());
+
+        // addi x1, x2, 3 and a word that is no instruction.
+        let bytes = [0x93, 0x00, 0x31, 0x00, 0xff, 0xff, 0xff, 0xff];
+        assert_eq!(parse_seed(&bytes), None);
+        let (input, format) = parse_raw_seed(&bytes).unwrap();
+        assert_eq!(format, SeedFormat::Words);
+        assert_eq!(input.insts()[0].template(), &ADDI);
+        assert_eq!(input.encode_program(), bytes);
+        // The placeholder survives the corpus format.
+        let restored = ProgramInput::from_bytes(&input.to_bytes().unwrap()).unwrap();
+        assert_eq!(restored, input);
+
+        assert_eq!(parse_raw_seed(&bytes[..6]), None);
+        assert_eq!(parse_raw_seed(&[]), None);
+    }
+
+    #[test]
+    fn seed_dir_skips_invalid_files() {
+        let dir = std::env::temp_dir().join(format!("seeds-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        let input = ProgramInput::new(vec![inst_with(&ADDI, &[(&args::RD, 1), (&args::IMM12, 7)])]);
+        fs::write(dir.join("a-structured"), input.to_bytes().unwrap()).unwrap();
+        fs::write(dir.join("b-raw"), input.encode_program()).unwrap();
+        fs::write(dir.join("c-garbage"), [1, 2, 3]).unwrap();
+        fs::write(dir.join(".hidden"), input.encode_program()).unwrap();
+
+        assert_eq!(
+            read_seed_dir(&dir, SeedDirFormat::Native).unwrap(),
+            vec![input.clone(), input.clone()]
+        );
+        // Only the raw file is a whole number of words.
+        assert_eq!(
+            read_seed_dir(&dir, SeedDirFormat::Raw).unwrap(),
+            vec![input]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(read_seed_dir(&dir, SeedDirFormat::Native).is_err());
+    }
+
+    #[test]
+    fn seed_dir_skips_unreadable_files() {
+        let dir = std::env::temp_dir().join(format!("seeds-unreadable-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = ProgramInput::new(vec![inst_with(&ADDI, &[(&args::RD, 1), (&args::IMM12, 7)])]);
+        fs::write(dir.join("a-seed"), input.to_bytes().unwrap()).unwrap();
+        // Reading a dangling link fails even as root, unlike a file without
+        // read permissions.
+        std::os::unix::fs::symlink(dir.join("missing"), dir.join("b-dangling")).unwrap();
+
+        assert_eq!(
+            read_seed_dir(&dir, SeedDirFormat::Native).unwrap(),
+            vec![input]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fallback_seed_is_legal() {
+        for name in sets::NAMES {
+            let isa = sets::by_name(name).unwrap();
+            let seed = fallback_seed(&isa);
+            assert_eq!(seed.insts().len(), 1);
+            assert!(seed
+                .insts()
+                .iter()
+                .all(|inst| isa.contains(&inst.template())));
+        }
+        let rv32 = fallback_seed(&sets::by_name("rv32g").unwrap());
+        assert!(!rv32
+            .insts()
+            .iter()
+            .any(|inst| sets::is_rv64_only(inst.template())));
+
+        // Without addi, another arithmetic instruction is used.
+        let isa: Vec<_> = sets::riscv32_base()
+            .into_iter()
+            .filter(|template| **template != ADDI)
+            .collect();
+        let seed = fallback_seed(&isa);
+        assert!(isa.contains(&seed.insts()[0].template()));
+    }
+}
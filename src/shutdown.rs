@@ -0,0 +1,49 @@
+//! Stopping a campaign on SIGINT. The first signal asks every client to
+//! finish its current execution and return, the second one exits right away.
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use nix::{
+    libc,
+    sys::signal::{signal, SigHandler, Signal},
+};
+
+/// Exit code of a process killed by SIGINT, used for the forced exit.
+const FORCED_EXIT_CODE: i32 = 128 + libc::SIGINT;
+
+/// Set by the SIGINT handler.
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: i32) {
+    if SHUTDOWN_REQUESTED.swap(true, Ordering::SeqCst) {
+        // SAFETY: `_exit` is async-signal-safe, unlike `std::process::exit`.
+        unsafe { libc::_exit(FORCED_EXIT_CODE) };
+    }
+}
+
+/// Installs the SIGINT handler in the calling process. The clients install
+/// it after LibAFL forked them, so the broker keeps its own handler.
+pub fn install_shutdown_handler() -> nix::Result<()> {
+    // SAFETY: The handler only touches an atomic and calls `_exit`.
+    unsafe { signal(Signal::SIGINT, SigHandler::Handler(on_interrupt)) }.map(|_| ())
+}
+
+/// Whether a SIGINT arrived since the handler was installed.
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::sys::signal::{raise, Signal};
+
+    use super::{install_shutdown_handler, shutdown_requested};
+
+    #[test]
+    fn interrupt_requests_shutdown() {
+        install_shutdown_handler().unwrap();
+        assert!(!shutdown_requested());
+        // Only once, the second signal would exit the test binary.
+        raise(Signal::SIGINT).unwrap();
+        assert!(shutdown_requested());
+    }
+}
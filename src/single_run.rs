@@ -0,0 +1,167 @@
+//! Runs single programs on the target outside of a fuzzing campaign, e.g. to
+//! reproduce a crash or from other tools that embed the driver. Nothing here
+//! depends on the `Launcher` or the event managers of a campaign.
+use core::time::Duration;
+
+use libafl::{
+    bolts::{
+        current_nanos,
+        rands::StdRand,
+        shmem::{ShMemProvider, UnixShMem, UnixShMemProvider},
+        tuples::MatchName,
+        AsMutSlice,
+    },
+    corpus::InMemoryCorpus,
+    events::NopEventManager,
+    executors::{Executor, ExitKind, HasObservers},
+    feedbacks::CrashFeedback,
+    fuzzer::NopFuzzer,
+    observers::{MapObserver, ObserversTuple, TimeObserver},
+    state::StdState,
+    Error,
+};
+use nix::sys::signal::Signal;
+
+use crate::{
+    causes::exit_signal,
+    cosim::RegisterStateObserver,
+    driver::{
+        build_executor, create_coverage_map, edges_observer, EdgesObserver, TargetConfig,
+        TargetExecutor, EDGES_OBSERVER_NAME,
+    },
+    feedbacks::map_crash_signature,
+    observers::{BucketTable, ExitStatusObserver, EXIT_STATUS_OBSERVER_NAME},
+    program_input::ProgramInput,
+};
+
+/// The state for running single inputs outside of a fuzzing campaign.
+pub type SingleRunState =
+    StdState<ProgramInput, InMemoryCorpus<ProgramInput>, StdRand, InMemoryCorpus<ProgramInput>>;
+
+pub fn single_run_state() -> SingleRunState {
+    // We never store anything, so a crash feedback is enough to build a state.
+    let mut feedback = CrashFeedback::new();
+    let mut objective = CrashFeedback::new();
+    StdState::new(
+        StdRand::with_seed(current_nanos()),
+        InMemoryCorpus::<ProgramInput>::new(),
+        InMemoryCorpus::new(),
+        &mut feedback,
+        &mut objective,
+    )
+    .unwrap()
+}
+
+/// The coverage of one execution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverageSummary {
+    /// The number of covered map entries.
+    pub covered: u64,
+    /// The number of usable map entries.
+    pub total: usize,
+    /// The [`crate::feedbacks::crash_signature`] of the covered entries.
+    pub signature: u64,
+}
+
+impl CoverageSummary {
+    pub fn percent(&self) -> u64 {
+        self.covered * 100 / (self.total.max(1) as u64)
+    }
+}
+
+/// What happened when the target ran a program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionResult {
+    pub exit_kind: ExitKind,
+    /// The signal that terminated the target, taken from the exit status
+    /// the forkserver reports (see [`ExitStatusObserver`]). Timeouts without
+    /// a status get the signal the executor kills them with.
+    pub signal: Option<Signal>,
+    pub exec_time: Option<Duration>,
+    pub coverage: CoverageSummary,
+    /// The bucketed coverage map.
+    pub map: Vec<u8>,
+}
+
+/// Runs programs one by one on a single forkserver, which is started once.
+pub struct SingleRunner {
+    // Declared before the map so it is dropped first.
+    executor: TargetExecutor<'static, SingleRunState>,
+    state: SingleRunState,
+    fuzzer: NopFuzzer<SingleRunState>,
+    mgr: NopEventManager<SingleRunState>,
+    signal: Signal,
+    _map: UnixShMem,
+}
+
+impl SingleRunner {
+    /// Allocates the coverage map and starts the target.
+    pub fn new(target: &TargetConfig) -> Result<Self, Error> {
+        let mut shmem_provider = UnixShMemProvider::new()?;
+        let mut map = create_coverage_map(&mut shmem_provider, target.map_size);
+        let map_ptr = map.as_mut_slice().as_mut_ptr();
+        let map_len = map.as_mut_slice().len();
+        // SAFETY: The shared memory isn't moved along with the handle and
+        // outlives the executor, the only user of the map.
+        let edges = edges_observer(
+            unsafe { std::slice::from_raw_parts_mut(map_ptr, map_len) },
+            BucketTable::afl(),
+            None,
+        );
+        let executor = build_executor(
+            target,
+            edges,
+            TimeObserver::new("time"),
+            RegisterStateObserver::disabled(),
+        )?;
+        Ok(Self {
+            executor,
+            state: single_run_state(),
+            fuzzer: NopFuzzer::new(),
+            mgr: NopEventManager::new(),
+            signal: target.signal,
+            _map: map,
+        })
+    }
+
+    pub fn run(&mut self, program: &ProgramInput) -> Result<ExecutionResult, Error> {
+        self.executor
+            .observers_mut()
+            .pre_exec_all(&mut self.state, program)?;
+        let exit_kind =
+            self.executor
+                .run_target(&mut self.fuzzer, &mut self.state, &mut self.mgr, program)?;
+        self.executor
+            .observers_mut()
+            .post_exec_all(&mut self.state, program, &exit_kind)?;
+
+        let observers = self.executor.observers();
+        let edges = observers
+            .match_name::<EdgesObserver>(EDGES_OBSERVER_NAME)
+            .ok_or_else(|| Error::key_not_found("Edges observer missing".to_string()))?;
+        let exec_time = *observers
+            .match_name::<TimeObserver>("time")
+            .ok_or_else(|| Error::key_not_found("Time observer missing".to_string()))?
+            .last_runtime();
+        let status_signal = observers
+            .match_name::<ExitStatusObserver>(EXIT_STATUS_OBSERVER_NAME)
+            .and_then(ExitStatusObserver::signal);
+        Ok(ExecutionResult {
+            signal: exit_signal(exit_kind, status_signal, self.signal),
+            exit_kind,
+            exec_time,
+            coverage: CoverageSummary {
+                covered: edges.count_bytes(),
+                total: edges.usable_count(),
+                signature: map_crash_signature(edges),
+            },
+            map: edges.to_vec(),
+        })
+    }
+}
+
+/// Starts the target, runs the program once and stops the target again.
+/// Use a [`SingleRunner`] to run several programs.
+pub fn run_one(program: &ProgramInput, target: &TargetConfig) -> Result<ExecutionResult, Error> {
+    SingleRunner::new(target)?.run(program)
+}
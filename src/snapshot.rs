@@ -0,0 +1,79 @@
+//! Periodic copies of the corpus for studying how it evolves over a campaign.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+};
+
+/// Name of the directory in the output directory holding all snapshots.
+pub const SNAPSHOTS_DIR_NAME: &str = "snapshots";
+
+/// Recreates `src` in `dest`, hardlinking every file. Files that can't be
+/// linked (e.g. because `dest` is on another file system) are copied.
+/// Hidden files (LibAFL's metadata and lock files) are skipped.
+/// Returns the number of snapshotted files.
+pub fn snapshot_dir(src: &Path, dest: &Path) -> io::Result<usize> {
+    fs::create_dir_all(dest)?;
+    let mut count = 0;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        let target = dest.join(entry.file_name());
+        if path.is_dir() {
+            count += snapshot_dir(&path, &target)?;
+            continue;
+        }
+        if fs::hard_link(&path, &target).is_err() {
+            fs::copy(&path, &target)?;
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// The directory of the snapshot taken at the given unix time.
+pub fn snapshot_path(snapshots_dir: &Path, unix_time: u64, name: &str) -> PathBuf {
+    snapshots_dir.join(format!("{}", unix_time)).join(name)
+}
+
+/// Snapshots `src` into `dest` on a background thread so the fuzzing loop
+/// doesn't wait on the file system. Errors are only logged.
+pub fn spawn_snapshot(src: PathBuf, dest: PathBuf) {
+    thread::spawn(move || match snapshot_dir(&src, &dest) {
+        Ok(count) => log::info!("Snapshotted {} files into {:?}", count, dest),
+        Err(err) => log::error!("Failed to snapshot {:?}: {}", src, err),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{snapshot_dir, snapshot_path};
+
+    #[test]
+    fn snapshot_links_files() {
+        let base = std::env::temp_dir().join(format!("snapshot-test-{}", std::process::id()));
+        let src = base.join("queue");
+        fs::create_dir_all(src.join("0")).unwrap();
+        fs::write(src.join("0").join("a"), "a").unwrap();
+        fs::write(src.join("0").join(".a.metadata"), "meta").unwrap();
+        fs::write(src.join("b"), "b").unwrap();
+
+        let dest = snapshot_path(&base.join("snapshots"), 1234, "queue");
+        assert!(dest.ends_with("snapshots/1234/queue"));
+        assert_eq!(snapshot_dir(&src, &dest).unwrap(), 2);
+        assert_eq!(fs::read_to_string(dest.join("0").join("a")).unwrap(), "a");
+        assert_eq!(fs::read_to_string(dest.join("b")).unwrap(), "b");
+        assert!(!dest.join("0").join(".a.metadata").exists());
+
+        // Later changes to the corpus don't affect the snapshot.
+        fs::remove_file(src.join("b")).unwrap();
+        assert!(dest.join("b").exists());
+
+        fs::remove_dir_all(base).ok();
+    }
+}
@@ -0,0 +1,35 @@
+//! Delta debugging of unstable coverage: finds the instructions that make the
+//! coverage of a program differ between runs (with [`crate::tmin::minimize`]).
+//! If even the empty program is unstable, the nondeterminism comes from the
+//! harness and not the input.
+use std::collections::HashSet;
+
+/// The map entries that differ between the first and any other run.
+pub fn unstable_entries<T: PartialEq>(maps: &[Vec<T>]) -> HashSet<usize> {
+    let mut result = HashSet::new();
+    if let Some((first, rest)) = maps.split_first() {
+        for map in rest {
+            for (idx, (a, b)) in first.iter().zip(map.iter()).enumerate() {
+                if a != b {
+                    result.insert(idx);
+                }
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unstable_entries;
+
+    #[test]
+    fn unstable_map_entries() {
+        let maps = vec![vec![0, 1, 2, 3], vec![0, 1, 2, 3], vec![0, 0, 2, 4]];
+        let mut unstable: Vec<usize> = unstable_entries(&maps).into_iter().collect();
+        unstable.sort();
+        assert_eq!(unstable, vec![1, 3]);
+        assert!(unstable_entries(&maps[..2]).is_empty());
+        assert!(unstable_entries(&[]).is_empty());
+    }
+}
@@ -0,0 +1,127 @@
+use core::time::Duration;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::instructions::{Instruction, InstructionCategory};
+
+/// Name of the user stat with the time per category reported to the monitor.
+pub const CATEGORY_TIMES_STAT_NAME: &str = "category_times";
+
+/// The most common category in the program. Ties go to the category that
+/// comes first in [`InstructionCategory::ALL`].
+pub fn dominant_category(insts: &[Instruction]) -> InstructionCategory {
+    let mut counts = BTreeMap::<InstructionCategory, usize>::new();
+    for inst in insts {
+        *counts.entry(inst.template().category()).or_insert(0) += 1;
+    }
+    let mut result = InstructionCategory::default();
+    let mut max = 0;
+    for (category, count) in counts {
+        if count > max {
+            result = category;
+            max = count;
+        }
+    }
+    result
+}
+
+/// Executions and total execution time of the programs with some dominant
+/// category.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CategoryTime {
+    pub execs: u64,
+    pub total: Duration,
+}
+
+impl CategoryTime {
+    /// The mean execution time, zero without executions.
+    pub fn mean(&self) -> Duration {
+        match u32::try_from(self.execs) {
+            Ok(execs) => self.total.checked_div(execs).unwrap_or(Duration::ZERO),
+            Err(_) => self.total.div_f64(self.execs as f64),
+        }
+    }
+}
+
+libafl::impl_serdeany!(CategoryTimeMetadata);
+/// Execution time per dominant instruction category, kept in the state.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct CategoryTimeMetadata {
+    times: BTreeMap<InstructionCategory, CategoryTime>,
+}
+
+impl CategoryTimeMetadata {
+    pub fn record(&mut self, category: InstructionCategory, time: Duration) {
+        let entry = self.times.entry(category).or_default();
+        entry.execs += 1;
+        entry.total += time;
+    }
+
+    pub fn get(&self, category: InstructionCategory) -> CategoryTime {
+        self.times.get(&category).copied().unwrap_or_default()
+    }
+
+    /// The mean execution time per category for the monitor, e.g.
+    /// `arith 120us/4000, mem 380us/900` (mean time/executions).
+    pub fn summary(&self) -> String {
+        self.times
+            .iter()
+            .map(|(category, time)| {
+                format!(
+                    "{} {}us/{}",
+                    category.name(),
+                    time.mean().as_micros(),
+                    time.execs
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::{dominant_category, CategoryTime, CategoryTimeMetadata};
+    use crate::instructions::{
+        riscv::rv_i::{ADD, LW},
+        test_insts::inst,
+        InstructionCategory,
+    };
+
+    #[test]
+    fn dominant_categories() {
+        assert_eq!(dominant_category(&[]), InstructionCategory::Unknown);
+        let program = [inst(&LW), inst(&ADD), inst(&LW)];
+        assert_eq!(dominant_category(&program), InstructionCategory::Mem);
+        // Arith comes before mem on a tie.
+        assert_eq!(dominant_category(&program[..2]), InstructionCategory::Arith);
+    }
+
+    #[test]
+    fn category_times() {
+        let mut times = CategoryTimeMetadata::default();
+        times.record(InstructionCategory::Arith, Duration::from_micros(100));
+        times.record(InstructionCategory::Arith, Duration::from_micros(300));
+        times.record(InstructionCategory::Mem, Duration::from_micros(600));
+        assert_eq!(
+            times.get(InstructionCategory::Arith).mean(),
+            Duration::from_micros(200)
+        );
+        assert_eq!(times.get(InstructionCategory::Float).execs, 0);
+        assert_eq!(times.summary(), "arith 200us/2, mem 600us/1");
+    }
+
+    #[test]
+    fn mean_of_many_execs() {
+        assert_eq!(CategoryTime::default().mean(), Duration::ZERO);
+        // More executions than fit in a u32 used to wrap around.
+        let time = CategoryTime {
+            execs: 1 << 32,
+            total: Duration::from_secs(1 << 32),
+        };
+        assert_eq!(time.mean(), Duration::from_secs(1));
+    }
+}
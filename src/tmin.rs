@@ -0,0 +1,128 @@
+//! Minimizing programs: instructions are removed as long as the rest still
+//! does what the original did, e.g. crashes the target the same way for a
+//! reproducer.
+use libafl::executors::ExitKind;
+
+use crate::single_run::ExecutionResult;
+
+/// Whether `result` is the same crash as `original`: the same exit kind and
+/// signal, and with `same_signature` also the same crash signature, so the
+/// minimization can't drift to a different crash.
+pub fn same_crash(
+    original: &ExecutionResult,
+    result: &ExecutionResult,
+    same_signature: bool,
+) -> bool {
+    result.exit_kind == original.exit_kind
+        && result.signal == original.signal
+        && (!same_signature || result.coverage.signature == original.coverage.signature)
+}
+
+/// Whether the result is worth minimizing at all, i.e. a crash or a timeout.
+pub fn is_crash(result: &ExecutionResult) -> bool {
+    matches!(result.exit_kind, ExitKind::Crash | ExitKind::Timeout)
+}
+
+/// Removes chunks of instructions as long as `keep` still holds on the rest,
+/// with shrinking chunk sizes (the `ddmin` algorithm). The result is
+/// 1-minimal: removing any single instruction makes `keep` fail.
+///
+/// `keep` should hold for `insts` itself, e.g. "still crashes the same way"
+/// (see [`same_crash`]) or "still has unstable coverage". If it only holds
+/// some of the time (like rare nondeterminism), the result can be larger than
+/// needed.
+pub fn minimize<T, F>(insts: &[T], mut keep: F) -> Vec<T>
+where
+    T: Clone,
+    F: FnMut(&[T]) -> bool,
+{
+    let mut current = insts.to_vec();
+    let mut chunks = 2;
+    while !current.is_empty() {
+        let chunk_len = (current.len() + chunks - 1) / chunks;
+        let reduced = (0..current.len()).step_by(chunk_len).find_map(|start| {
+            let end = (start + chunk_len).min(current.len());
+            let candidate = [&current[..start], &current[end..]].concat();
+            keep(&candidate).then_some(candidate)
+        });
+        match reduced {
+            Some(candidate) => {
+                current = candidate;
+                chunks = (chunks - 1).max(2);
+            }
+            None if chunk_len == 1 => break,
+            None => chunks = (chunks * 2).min(current.len()),
+        }
+    }
+    current
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl::executors::ExitKind;
+    use nix::sys::signal::Signal;
+
+    use super::{is_crash, minimize, same_crash};
+    use crate::single_run::{CoverageSummary, ExecutionResult};
+
+    fn result(exit_kind: ExitKind, signature: u64) -> ExecutionResult {
+        ExecutionResult {
+            exit_kind,
+            signal: (exit_kind == ExitKind::Timeout).then_some(Signal::SIGKILL),
+            exec_time: None,
+            coverage: CoverageSummary {
+                covered: 1,
+                total: 1,
+                signature,
+            },
+            map: vec![],
+        }
+    }
+
+    #[test]
+    fn crashes_are_compared() {
+        let crash = result(ExitKind::Crash, 1);
+        assert!(is_crash(&crash));
+        assert!(is_crash(&result(ExitKind::Timeout, 1)));
+        assert!(!is_crash(&result(ExitKind::Ok, 1)));
+
+        assert!(same_crash(&crash, &result(ExitKind::Crash, 2), false));
+        assert!(!same_crash(&crash, &result(ExitKind::Crash, 2), true));
+        assert!(same_crash(&crash, &result(ExitKind::Crash, 1), true));
+        assert!(!same_crash(&crash, &result(ExitKind::Timeout, 1), false));
+        assert!(!same_crash(&crash, &result(ExitKind::Ok, 1), false));
+    }
+
+    #[test]
+    fn finds_minimal_subsequence() {
+        let program: Vec<u32> = (0..20).collect();
+        let mut runs = 0;
+        let minimal = minimize(&program, |insts| {
+            runs += 1;
+            insts.contains(&3) && insts.contains(&17)
+        });
+        assert_eq!(minimal, vec![3, 17]);
+        assert!(runs < 100, "{} runs", runs);
+
+        // Harness nondeterminism: every program is unstable.
+        assert!(minimize(&program, |_| true).is_empty());
+        assert_eq!(minimize(&[5], |insts| insts == [5]), vec![5]);
+    }
+
+    #[test]
+    fn removes_instructions_not_needed_for_the_crash() {
+        // The "target" crashes if a 3 comes after a 7.
+        let program = [1, 7, 2, 2, 9, 3, 4, 3];
+        let crashes = |insts: &[u32]| {
+            let seven = insts.iter().position(|inst| *inst == 7);
+            seven.map_or(false, |seven| insts[seven..].contains(&3))
+        };
+        let minimal = minimize(&program, crashes);
+        assert_eq!(minimal, [7, 3]);
+        assert!(crashes(&minimal));
+        // No single removal keeps the crash.
+        for idx in 0..minimal.len() {
+            assert!(!crashes(&[&minimal[..idx], &minimal[idx + 1..]].concat()));
+        }
+    }
+}
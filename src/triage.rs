@@ -0,0 +1,98 @@
+//! Heuristic tagging of saved crashes with the instruction family that most
+//! likely triggered them, so crashes can be bucketed by root cause before
+//! anyone looks at a waveform.
+use serde::{Deserialize, Serialize};
+
+use crate::instructions::{Instruction, InstructionCategory};
+
+/// The categories a crash can be blamed on. Computation is rarely what makes
+/// a core misbehave, so arithmetic and floating point instructions are not
+/// considered.
+pub const TRIGGER_CATEGORIES: [InstructionCategory; 4] = [
+    InstructionCategory::Mem,
+    InstructionCategory::Branch,
+    InstructionCategory::System,
+    InstructionCategory::Atomic,
+];
+
+fn is_trigger(category: InstructionCategory) -> bool {
+    TRIGGER_CATEGORIES.contains(&category)
+}
+
+libafl::impl_serdeany!(CrashTriageMetadata);
+/// The likely trigger of a saved crash.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct CrashTriageMetadata {
+    /// The blamed category, `Unknown` if the program has no instruction of
+    /// any [`TRIGGER_CATEGORIES`].
+    pub family: InstructionCategory,
+    /// The name of the last instruction in the program.
+    pub last_inst: Option<String>,
+}
+
+impl CrashTriageMetadata {
+    /// Classifies the program. The last instruction wins if it is in one of
+    /// the [`TRIGGER_CATEGORIES`], as the crash most likely happened right
+    /// after it. Otherwise the most common trigger category is blamed, ties
+    /// go to the category that comes first in [`TRIGGER_CATEGORIES`].
+    pub fn classify(insts: &[Instruction]) -> Self {
+        let last = insts.last();
+        let last_inst = last.map(|inst| inst.template().name().to_owned());
+        if let Some(last) = last {
+            if is_trigger(last.template().category()) {
+                return Self {
+                    family: last.template().category(),
+                    last_inst,
+                };
+            }
+        }
+
+        let mut family = InstructionCategory::Unknown;
+        let mut max = 0;
+        for category in TRIGGER_CATEGORIES {
+            let count = insts
+                .iter()
+                .filter(|inst| inst.template().category() == category)
+                .count();
+            if count > max {
+                family = category;
+                max = count;
+            }
+        }
+        Self { family, last_inst }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CrashTriageMetadata;
+    use crate::instructions::{test_insts::named, Instruction, InstructionCategory};
+
+    fn family(program: &[Instruction]) -> InstructionCategory {
+        CrashTriageMetadata::classify(program).family
+    }
+
+    #[test]
+    fn classify_crashes() {
+        assert_eq!(family(&[]), InstructionCategory::Unknown);
+        assert_eq!(CrashTriageMetadata::classify(&[]).last_inst, None);
+        assert_eq!(
+            family(&[named("add"), named("add")]),
+            InstructionCategory::Unknown
+        );
+
+        // The last instruction is blamed even if others dominate.
+        let program = [named("lw"), named("sw"), named("lw"), named("ecall")];
+        let meta = CrashTriageMetadata::classify(&program);
+        assert_eq!(meta.family, InstructionCategory::System);
+        assert_eq!(meta.last_inst.as_deref(), Some("ecall"));
+
+        // Without a trigger at the end the most common family is blamed.
+        let program = [named("beq"), named("amoadd.d"), named("beq"), named("add")];
+        assert_eq!(family(&program), InstructionCategory::Branch);
+
+        // Memory comes before atomics on a tie.
+        let program = [named("amoadd.d"), named("sw"), named("add")];
+        assert_eq!(family(&program), InstructionCategory::Mem);
+    }
+}
@@ -0,0 +1,366 @@
+//! Runs the full fuzzing loop (executor, feedbacks, scheduler and stages)
+//! against a stub target that speaks the AFL++ forkserver protocol. The stub
+//! is a Python script, so no simulator or instrumented binary is needed.
+use core::time::Duration;
+use std::{fs, os::unix::fs::PermissionsExt, path::PathBuf, process::Command};
+
+use libafl::{
+    bolts::{
+        shmem::{ShMemProvider, UnixShMemProvider},
+        tuples::tuple_list,
+        AsMutSlice,
+    },
+    corpus::Corpus,
+    events::NopEventManager,
+    executors::ExitKind,
+    fuzzer::{Fuzzer, StdFuzzer},
+    observers::TimeObserver,
+    stages::{power::StdPowerMutationalStage, Stage},
+    state::{HasCorpus, HasMetadata},
+    Evaluator,
+};
+use nix::sys::signal::Signal;
+use riscv_mutator::{
+    bundle::{CrashBundleConfig, CrashBundleFeedback},
+    calibration::{SkipEvicted, UnstableEntriesMetadata},
+    causes::{CauseFeedback, CausesFormat},
+    config::DriverConfig,
+    cosim::RegisterStateObserver,
+    driver::{
+        build_executor, build_feedback, build_objective, build_scheduler, build_state,
+        create_coverage_map, edges_observer, FeedbackOptions, FuzzCalibration, FuzzFeedback,
+        FuzzObjective, FuzzObservers, FuzzScheduler, FuzzState, ObjectiveOptions, TargetConfig,
+        TargetExecutor,
+    },
+    feedbacks::{DuplicateCrashFilter, ExitCodeFeedback, KnownCrashFilter, LowCoverageFilter},
+    instructions::{
+        riscv::{args, rv_i::ADDI},
+        Argument, Instruction,
+    },
+    mutator::{all_riscv_mutations, RiscvScheduledMutator},
+    observers::BucketTable,
+    program_input::ProgramInput,
+    single_run::{run_one, SingleRunner},
+};
+
+const MAP_SIZE: usize = 4096;
+
+/// `addi x0, x0, 0x7ff`, which makes the stub target crash.
+const CRASH_WORD: u32 = 0x7ff0_0013;
+
+/// Handshakes with the fuzzer on the forkserver fds (198 control, 199
+/// status) and forks a child for every run. The child sets one map entry
+/// per instruction word of the input file, so new instructions mean new
+/// coverage. It segfaults on [`CRASH_WORD`].
+const STUB_TARGET: &str = r#"#!/usr/bin/env python3
+import ctypes, os, signal, struct, sys
+
+CTL_FD, ST_FD = 198, 199
+libc = ctypes.CDLL(None, use_errno=True)
+libc.shmat.restype = ctypes.c_void_p
+libc.shmat.argtypes = [ctypes.c_int, ctypes.c_void_p, ctypes.c_int]
+cov = libc.shmat(int(os.environ["__AFL_SHM_ID"]), None, 0)
+map_size = int(os.environ["AFL_MAP_SIZE"])
+
+os.write(ST_FD, struct.pack("<I", 0))
+while True:
+    if len(os.read(CTL_FD, 4)) != 4:
+        sys.exit(0)
+    pid = os.fork()
+    if pid == 0:
+        os.close(CTL_FD)
+        os.close(ST_FD)
+        with open(sys.argv[1], "rb") as f:
+            data = f.read()
+        for i in range(0, len(data) - 3, 4):
+            word = int.from_bytes(data[i:i + 4], "little")
+            if word == 0x7ff00013:
+                os.kill(os.getpid(), signal.SIGSEGV)
+            ctypes.c_uint8.from_address(cov + word % map_size).value = 1
+        os._exit(0)
+    os.write(ST_FD, struct.pack("<I", pid))
+    _, status = os.waitpid(pid, 0)
+    os.write(ST_FD, struct.pack("<i", status))
+"#;
+
+/// A fresh directory that is removed again when the test ends, also when it
+/// fails.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("fuzz-loop-test-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
+
+fn write_stub_target(dir: &PathBuf) -> String {
+    let path = dir.join("stub-target.py");
+    fs::write(&path, STUB_TARGET).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+fn addi(imm: u32) -> Instruction {
+    Instruction::new(
+        &ADDI,
+        vec![
+            Argument::new(&args::RD, 0u32),
+            Argument::new(&args::RS1, 0u32),
+            Argument::new(&args::IMM12, imm),
+        ],
+    )
+}
+
+fn stub_target(base_dir: &PathBuf) -> TargetConfig {
+    TargetConfig {
+        executable: write_stub_target(base_dir),
+        arguments: vec!["@@".to_string()],
+        envs: vec![],
+        debug_child: false,
+        timeout: Duration::from_secs(5),
+        timeout_reruns: 0,
+        timeout_factor: None,
+        signal: Signal::SIGKILL,
+        map_size: MAP_SIZE,
+    }
+}
+
+/// A fuzzer for the stub target with everything in a fresh directory.
+struct StubFuzzer<'a> {
+    base_dir: TempDir,
+    fuzzer: StdFuzzer<FuzzScheduler<'a>, FuzzFeedback<'a>, FuzzObjective<'a>, FuzzObservers<'a>>,
+    state: FuzzState,
+    executor: TargetExecutor<'a, FuzzState>,
+    calibration: FuzzCalibration<'a>,
+    mgr: NopEventManager<FuzzState>,
+}
+
+fn stub_fuzzer<'a>(name: &str, coverage: &'a mut [u8]) -> StubFuzzer<'a> {
+    require_python();
+    let temp_dir = TempDir::new(name);
+    let base_dir = &temp_dir.0;
+
+    let target = stub_target(base_dir);
+
+    let edges = edges_observer(coverage, BucketTable::afl(), None);
+    let time = TimeObserver::new("time");
+
+    let (mut feedback, calibration) = build_feedback(&edges, &time, FeedbackOptions::default());
+    let bundles = CrashBundleFeedback::new(
+        CrashBundleConfig::default(),
+        base_dir.join("bundles"),
+        target.clone(),
+        0,
+        &edges,
+    );
+    let known = KnownCrashFilter::new(&edges, Default::default(), None);
+    let low_coverage = LowCoverageFilter::new(&edges, 0, None);
+    let duplicates = DuplicateCrashFilter::new(&edges, None);
+    let causes = CauseFeedback::new(CausesFormat::Text, base_dir.join("causes"), Signal::SIGKILL);
+    let options = ObjectiveOptions {
+        core_id: 0,
+        issue_marker: None,
+        crash_immediates: false,
+        save_hangs: false,
+        crash_dedup: true,
+        known_crashes: known,
+        low_coverage,
+        duplicates,
+        bundles,
+        causes,
+        exit_codes: ExitCodeFeedback::new(None, vec![]),
+        label_dir: None,
+        trace: None,
+    };
+    let mut objective = build_objective(&time, options);
+    let mut state = build_state(
+        0,
+        base_dir.join("queue"),
+        base_dir.join("found"),
+        &mut feedback,
+        &mut objective,
+    )
+    .unwrap();
+    let scheduler = build_scheduler(&mut state, &edges, None, 0.0, 0.0, 0.0, 0);
+    let fuzzer = StdFuzzer::new(scheduler, feedback, objective);
+    let executor = build_executor(&target, edges, time, RegisterStateObserver::disabled()).unwrap();
+
+    StubFuzzer {
+        base_dir: temp_dir,
+        fuzzer,
+        state,
+        executor,
+        calibration,
+        mgr: NopEventManager::new(),
+    }
+}
+
+/// The stub target is a Python script, so without python3 the tests fail
+/// instead of passing without running anything.
+fn require_python() {
+    assert!(
+        Command::new("python3").arg("--version").output().is_ok(),
+        "python3 is needed for the stub target"
+    );
+}
+
+#[test]
+fn corpus_grows_against_stub_target() {
+    let mut shmem_provider = UnixShMemProvider::new().unwrap();
+    let mut shmem = create_coverage_map(&mut shmem_provider, MAP_SIZE);
+    let StubFuzzer {
+        base_dir: _base_dir,
+        mut fuzzer,
+        mut state,
+        mut executor,
+        calibration,
+        mut mgr,
+    } = stub_fuzzer("grow", shmem.as_mut_slice());
+
+    fuzzer
+        .add_input(
+            &mut state,
+            &mut executor,
+            &mut mgr,
+            ProgramInput::new(vec![addi(0)]),
+        )
+        .unwrap();
+    assert_eq!(state.corpus().count(), 1);
+
+    let mutations = all_riscv_mutations(&DriverConfig::default());
+    let power = StdPowerMutationalStage::new(RiscvScheduledMutator::new(mutations));
+    let mut stages = tuple_list!(calibration, SkipEvicted::new(power));
+    for _ in 0..20 {
+        fuzzer
+            .fuzz_one(&mut stages, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+    }
+    assert!(state.corpus().count() > 1, "no new coverage found");
+}
+
+#[test]
+fn calibration_evicts_crashing_seed() {
+    let mut shmem_provider = UnixShMemProvider::new().unwrap();
+    let mut shmem = create_coverage_map(&mut shmem_provider, MAP_SIZE);
+    let StubFuzzer {
+        base_dir: _base_dir,
+        mut fuzzer,
+        mut state,
+        mut executor,
+        mut calibration,
+        mut mgr,
+    } = stub_fuzzer("evict", shmem.as_mut_slice());
+
+    let good = fuzzer
+        .add_input(
+            &mut state,
+            &mut executor,
+            &mut mgr,
+            ProgramInput::new(vec![addi(0)]),
+        )
+        .unwrap();
+    let crashing = fuzzer
+        .add_input(
+            &mut state,
+            &mut executor,
+            &mut mgr,
+            ProgramInput::new(vec![addi(1), addi(CRASH_WORD >> 20)]),
+        )
+        .unwrap();
+    assert_eq!(state.corpus().count(), 2);
+
+    calibration
+        .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr, good)
+        .unwrap();
+    assert_eq!(state.corpus().count(), 2);
+    calibration
+        .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr, crashing)
+        .unwrap();
+    assert_eq!(state.corpus().count(), 1);
+    assert!(state.corpus().get(crashing).is_err());
+
+    // The remaining entry is still scheduled and fuzzed.
+    let mutations = all_riscv_mutations(&DriverConfig::default());
+    let power = StdPowerMutationalStage::new(RiscvScheduledMutator::new(mutations));
+    let mut stages = tuple_list!(calibration, SkipEvicted::new(power));
+    for _ in 0..5 {
+        let idx = fuzzer
+            .fuzz_one(&mut stages, &mut executor, &mut state, &mut mgr)
+            .unwrap();
+        assert_ne!(idx, crashing);
+    }
+}
+
+#[test]
+fn calibration_measures_stability() {
+    let mut shmem_provider = UnixShMemProvider::new().unwrap();
+    let mut shmem = create_coverage_map(&mut shmem_provider, MAP_SIZE);
+    let StubFuzzer {
+        base_dir: _base_dir,
+        mut fuzzer,
+        mut state,
+        mut executor,
+        calibration,
+        mut mgr,
+    } = stub_fuzzer("stability", shmem.as_mut_slice());
+    let mut calibration = calibration.with_stability_runs(3);
+
+    let idx = fuzzer
+        .add_input(
+            &mut state,
+            &mut executor,
+            &mut mgr,
+            ProgramInput::new(vec![addi(0), addi(1)]),
+        )
+        .unwrap();
+    calibration
+        .perform(&mut fuzzer, &mut executor, &mut state, &mut mgr, idx)
+        .unwrap();
+
+    // The stub target is deterministic.
+    let meta = state
+        .metadata_map()
+        .get::<UnstableEntriesMetadata>()
+        .unwrap();
+    assert!(meta.unstable_entries().is_empty());
+    assert_eq!(meta.filled_entries().len(), 1);
+    assert_eq!(meta.stability(), Some(1.0));
+}
+
+#[test]
+fn single_runs_against_stub_target() {
+    require_python();
+    let base_dir = TempDir::new("single");
+    let target = stub_target(&base_dir.0);
+
+    let result = run_one(&ProgramInput::new(vec![addi(0), addi(1)]), &target).unwrap();
+    assert_eq!(result.exit_kind, ExitKind::Ok);
+    assert_eq!(result.signal, None);
+    assert!(result.exec_time.is_some());
+    // Both words set the same map entry.
+    assert_eq!(result.coverage.covered, 1);
+    assert_eq!(result.coverage.total, MAP_SIZE);
+    assert_eq!(result.map[0x13], 1);
+
+    // A runner keeps the target running between programs.
+    let mut runner = SingleRunner::new(&target).unwrap();
+    let crash = runner
+        .run(&ProgramInput::new(vec![addi(1), addi(CRASH_WORD >> 20)]))
+        .unwrap();
+    assert_eq!(crash.exit_kind, ExitKind::Crash);
+    assert_eq!(crash.signal, Some(Signal::SIGSEGV));
+    assert_eq!(crash.coverage.covered, 1);
+    let again = runner
+        .run(&ProgramInput::new(vec![addi(0), addi(1)]))
+        .unwrap();
+    assert_eq!(again.coverage, result.coverage);
+}